@@ -2,5 +2,7 @@
 
 CURDIR="$(dirname "$0")"
 echo "Running migrations ..."
-sqlite3 verify.db < "$CURDIR/../repo-notifier/migrations/"*.sql
+for migration in "$CURDIR/../repo-notifier/migrations/"*.sql "$CURDIR/../repo-redirect/migrations/"*.sql; do
+    sqlite3 verify.db < "$migration"
+done
 echo "... Done."
@@ -0,0 +1,86 @@
+//! Splitting of the rendered updates into messages
+use defaultmap::DefaultHashMap;
+
+/// Rendered entries under each heading
+type EntryMapping = DefaultHashMap<String, Vec<String>>;
+
+/// A rendered entry of a batch (one or more updates) and the heading it goes under
+pub struct Line {
+    pub header: String,
+    pub text: String,
+    /// Number of the updates in the entry
+    pub count: usize,
+}
+
+/// Split the rendered entries into chunks (number of updates and the formatted content) of at
+/// most `max_lines` entries each, starting a new chunk once the entries took up `room`
+/// characters. The entries are only taken from the iterator when needed.
+pub fn split(
+    lines: impl IntoIterator<Item = Line>,
+    room: isize,
+    max_lines: usize,
+) -> Vec<(usize, String)> {
+    let mut chunks = Vec::new();
+    let mut lines = lines.into_iter().peekable();
+    while lines.peek().is_some() {
+        let mut mapping = EntryMapping::new();
+        let mut remaining = room;
+        let mut list_remaining = max_lines;
+        let mut count = 0;
+        mapping.reserve(max_lines);
+        while remaining > 0 && list_remaining > 0 {
+            let line = match lines.next() {
+                Some(line) => line,
+                None => break,
+            };
+            remaining -= line.text.len() as isize;
+            list_remaining -= 1;
+            count += line.count;
+            mapping[line.header].push(line.text);
+        }
+        chunks.push((count, format_sorted_mapping(mapping)));
+    }
+
+    chunks
+}
+
+fn format_sorted_mapping(mapping: EntryMapping) -> String {
+    let mut output = String::new();
+    output.reserve(4096);
+    for (k, v) in mapping.iter() {
+        output += k;
+        output += &v.join("\n");
+        output += "\n\n";
+    }
+
+    output
+}
+
+#[test]
+fn test_split() {
+    let lines = |count: usize, text: &str| {
+        (0..count)
+            .map(|i| Line {
+                header: "stable amd64\n".to_string(),
+                text: format!("{}{}", text, i),
+                count: 1,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(split(Vec::new(), 4000, 22).is_empty());
+    // limited by the number of the lines
+    let chunks = split(lines(5, "gtk-"), 4000, 2);
+    assert_eq!(chunks.iter().map(|c| c.0).collect::<Vec<_>>(), [2, 2, 1]);
+    assert_eq!(chunks[0].1, "stable amd64\ngtk-0\ngtk-1\n\n");
+    assert_eq!(chunks[2].1, "stable amd64\ngtk-4\n\n");
+    // limited by the length, the entry using up the room is still in the chunk
+    let chunks = split(lines(3, "x".repeat(10).as_str()), 15, 22);
+    assert_eq!(chunks.iter().map(|c| c.0).collect::<Vec<_>>(), [2, 1]);
+    // entries counting several updates, e.g. the collapsed rebuilds
+    let rebuilds = Line {
+        header: "stable amd64\n".to_string(),
+        text: "12 packages rebuilt".to_string(),
+        count: 12,
+    };
+    assert_eq!(split(vec![rebuilds], 4000, 22)[0].0, 12);
+}
@@ -0,0 +1,9 @@
+//! Updates published by p-vector and how the repository notifier batches them, without
+//! anything talking to Telegram
+
+pub mod chunks;
+pub mod message;
+pub mod priority;
+pub mod wire;
+
+pub use message::{PVError, PVMessage, PVMessageMethod};
@@ -0,0 +1,124 @@
+use serde::Deserialize;
+
+/// Error messages of p-vector are cut to this many characters
+const MAX_ERROR_LENGTH: usize = 300;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum PVMessageMethod {
+    Old(String),
+    New(u8),
+}
+
+impl PVMessageMethod {
+    pub fn as_new_type(&self) -> u8 {
+        match self {
+            PVMessageMethod::New(v) => *v,
+            PVMessageMethod::Old(v) => match v.as_str() {
+                "new" => b'+',
+                "upgrade" => b'^',
+                "delete" => b'-',
+                "overwrite" => b'*',
+                _ => b'?',
+            },
+        }
+    }
+}
+
+/// Details of an error event (`i`)
+#[derive(Deserialize, Clone, Debug)]
+pub struct PVError {
+    /// Stage of the processing that failed, e.g. `scan`
+    pub stage: Option<String>,
+    pub message: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PVMessage {
+    pub comp: String,
+    pub pkg: String,
+    pub arch: String,
+    pub method: PVMessageMethod,
+    pub from_ver: Option<String>,
+    pub to_ver: Option<String>,
+    /// What went wrong, only in the error events
+    #[serde(default)]
+    pub error: Option<PVError>,
+    /// Name of the repository the message came from (if there are multiple)
+    #[serde(skip)]
+    pub repo: Option<String>,
+    /// Whether the update is a security fix
+    #[serde(skip)]
+    pub security: bool,
+    /// Telegram usernames of the maintainers to notify
+    #[serde(skip)]
+    pub mentions: Vec<String>,
+    /// Short description of a new package
+    #[serde(skip)]
+    pub description: Option<String>,
+}
+
+impl PVMessage {
+    /// What went wrong in an error event, e.g. `failed at scan` and the (truncated) message
+    pub fn failure(&self) -> Option<(String, String)> {
+        let error = self.error.as_ref()?;
+        let what = match error.stage.as_deref() {
+            Some(stage) => format!("failed at {}", stage),
+            None => "failed".to_string(),
+        };
+        let mut message = error.message.trim().to_string();
+        if let Some((end, _)) = message.char_indices().nth(MAX_ERROR_LENGTH) {
+            message.truncate(end);
+            message.push('…');
+        }
+
+        Some((what, message))
+    }
+
+    /// An update in `stable` for the tests, with the method as a character (e.g. `b'^'`)
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn sample(
+        pkg: &str,
+        arch: &str,
+        method: u8,
+        from_ver: Option<&str>,
+        to_ver: Option<&str>,
+    ) -> PVMessage {
+        PVMessage {
+            comp: "stable".to_string(),
+            pkg: pkg.to_string(),
+            arch: arch.to_string(),
+            method: PVMessageMethod::New(method),
+            from_ver: from_ver.map(str::to_string),
+            to_ver: to_ver.map(str::to_string),
+            error: None,
+            repo: None,
+            security: false,
+            mentions: Vec::new(),
+            description: None,
+        }
+    }
+}
+
+#[test]
+fn test_failure() {
+    let legacy: Vec<PVMessage> = serde_json::from_str(
+        r#"[{"comp":"stable","pkg":"gtk-3","arch":"amd64","method":"delete","from_ver":"3.24.1","to_ver":null}]"#,
+    )
+    .unwrap();
+    assert_eq!(legacy[0].method.as_new_type(), b'-');
+    assert!(legacy[0].failure().is_none());
+
+    let failed = PVMessage {
+        method: PVMessageMethod::New(b'i'),
+        error: Some(PVError {
+            stage: None,
+            message: format!(" {} ", "é".repeat(MAX_ERROR_LENGTH + 1)),
+        }),
+        ..legacy[0].clone()
+    };
+    let (what, message) = failed.failure().unwrap();
+    assert_eq!(what, "failed");
+    assert_eq!(message.chars().count(), MAX_ERROR_LENGTH + 1);
+    assert!(message.ends_with("é…"));
+}
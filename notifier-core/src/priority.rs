@@ -0,0 +1,61 @@
+//! Order of the updates in a batch
+use std::collections::HashSet;
+
+use crate::PVMessage;
+
+/// Remove the repeated events (same component, package, architecture and versions)
+/// while keeping the order of their first occurrences
+pub fn dedup(pending: &mut Vec<PVMessage>) {
+    let mut seen = HashSet::new();
+    pending.retain(|p| {
+        seen.insert((
+            p.comp.clone(),
+            p.pkg.clone(),
+            p.arch.clone(),
+            p.from_ver.clone(),
+            p.to_ver.clone(),
+        ))
+    });
+}
+
+/// Sort the updates by priority (security fixes, then higher priorities first), keeping the
+/// order of the updates of the same priority
+pub fn sort(pending: &mut [PVMessage], priority_of: impl Fn(&PVMessage) -> i32) {
+    pending.sort_by_key(|p| std::cmp::Reverse((p.security, priority_of(p))));
+}
+
+#[test]
+fn test_priority() {
+    let message =
+        |pkg: &str, method: u8, to: &str| PVMessage::sample(pkg, "amd64", method, None, Some(to));
+    let mut pending = vec![
+        message("gtk-3", b'^', "3.24.2"),
+        message("curl", b'+', "8.0"),
+        message("gtk-3", b'^', "3.24.2"),
+        message("gtk-3", b'^', "3.24.3"),
+        message("openssl", b'^', "3.0.1"),
+        message("vim", b'-', "9.0"),
+    ];
+    pending[4].security = true;
+    dedup(&mut pending);
+    assert_eq!(pending.len(), 5);
+    sort(&mut pending, |p| match p.method.as_new_type() {
+        b'^' => 2,
+        b'+' => 1,
+        _ => 0,
+    });
+    let order = pending
+        .iter()
+        .map(|p| (p.pkg.as_str(), p.to_ver.as_deref().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        order,
+        [
+            ("openssl", "3.0.1"),
+            ("gtk-3", "3.24.2"),
+            ("gtk-3", "3.24.3"),
+            ("curl", "8.0"),
+            ("vim", "9.0")
+        ]
+    );
+}
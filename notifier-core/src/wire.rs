@@ -0,0 +1,245 @@
+//! Messages published by p-vector
+//!
+//! The legacy messages are plain JSON arrays of the updates. Since version 3, each message is
+//! an envelope (integers in big endian):
+//!
+//! | bytes | content                                                 |
+//! |-------|---------------------------------------------------------|
+//! | 2     | magic, `PV`                                             |
+//! | 1     | protocol version                                        |
+//! | 1     | flags, bit 0: the body is compressed with zstd          |
+//! |       | bit 1: the message is signed                            |
+//! | 8     | sequence number of the batch, increased by 1 each time  |
+//! | 4     | length of the body                                      |
+//! | ...   | body, a JSON object: `{"updates": [...]}`               |
+//! | 32    | signed messages only: HMAC-SHA256 of everything before  |
+//!
+//! With a secret shared with p-vector, only the signed messages with a valid HMAC are accepted,
+//! so nobody else on the network can inject updates. The HMAC covers the sequence number, and
+//! the signed messages not newer than the last accepted one are rejected, so the captured ones
+//! can not be replayed either: p-vector has to keep counting across its restarts when signing.
+//!
+//! Unknown fields in the body are ignored, so new ones can be added without bumping the version.
+//! The error events (method `i`) carry what went wrong in
+//! `"error": {"stage": "scan", "message": "..."}`, the stage being optional.
+//! The notifier advertises the highest version it understands in the Redis key
+//! [`PROTOCOL_KEY`], p-vector should use the lower one of that and its own (and the legacy
+//! format if the key does not exist).
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::convert::TryInto;
+
+use crate::{PVError, PVMessage, PVMessageMethod};
+
+/// Redis key holding the highest protocol version supported by the notifier
+pub const PROTOCOL_KEY: &str = "p-vector-notifier-protocol";
+pub const PROTOCOL_VERSION: u8 = 3;
+const MAGIC: &[u8] = b"PV";
+const HEADER_LENGTH: usize = 16;
+const FLAG_ZSTD: u8 = 1;
+const FLAG_SIGNED: u8 = 2;
+const TAG_LENGTH: usize = 32;
+/// Limit of the decompressed body, to keep a bad message from exhausting the memory
+const MAX_BODY_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Body of the version 3 messages
+#[derive(Deserialize)]
+struct Body {
+    updates: Vec<Update>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    comp: String,
+    pkg: String,
+    arch: String,
+    method: u8,
+    from_ver: Option<String>,
+    to_ver: Option<String>,
+    /// Only in the error events (`i`)
+    #[serde(default)]
+    error: Option<PVError>,
+}
+
+impl From<Update> for PVMessage {
+    fn from(update: Update) -> Self {
+        PVMessage {
+            comp: update.comp,
+            pkg: update.pkg,
+            arch: update.arch,
+            method: PVMessageMethod::New(update.method),
+            from_ver: update.from_ver,
+            to_ver: update.to_ver,
+            error: update.error,
+            repo: None,
+            security: false,
+            mentions: Vec::new(),
+            description: None,
+        }
+    }
+}
+
+/// A decoded message, the sequence number is only available since version 3
+pub struct Batch {
+    pub sequence: Option<u64>,
+    pub updates: Vec<PVMessage>,
+}
+
+/// Decode a message in either the legacy or the versioned format
+pub fn decode(payload: &[u8]) -> Result<Batch> {
+    if !payload.starts_with(MAGIC) {
+        return Ok(Batch {
+            sequence: None,
+            updates: serde_json::from_slice(payload)?,
+        });
+    }
+    if payload.len() < HEADER_LENGTH {
+        return Err(anyhow!("Truncated message header"));
+    }
+    let version = payload[2];
+    let flags = payload[3];
+    if version != PROTOCOL_VERSION {
+        return Err(anyhow!("Unsupported protocol version: {}", version));
+    }
+    if flags & !(FLAG_ZSTD | FLAG_SIGNED) != 0 {
+        return Err(anyhow!("Unknown flags in the message: {:#04x}", flags));
+    }
+    let sequence = u64::from_be_bytes(payload[4..12].try_into()?);
+    let length = u32::from_be_bytes(payload[12..16].try_into()?) as usize;
+    let body = if flags & FLAG_SIGNED != 0 {
+        payload
+            .get(HEADER_LENGTH..payload.len().saturating_sub(TAG_LENGTH))
+            .ok_or_else(|| anyhow!("Truncated signature"))?
+    } else {
+        &payload[HEADER_LENGTH..]
+    };
+    if body.len() != length {
+        return Err(anyhow!(
+            "Expected {} bytes in the message body, got {}",
+            length,
+            body.len()
+        ));
+    }
+    let body: Body = if flags & FLAG_ZSTD != 0 {
+        serde_json::from_slice(&zstd::bulk::decompress(body, MAX_BODY_LENGTH)?)?
+    } else {
+        serde_json::from_slice(body)?
+    };
+
+    Ok(Batch {
+        sequence: Some(sequence),
+        updates: body.updates.into_iter().map(PVMessage::from).collect(),
+    })
+}
+
+/// Check the HMAC at the end of a signed message
+fn verify(payload: &[u8], secret: &[u8]) -> bool {
+    if payload.len() < HEADER_LENGTH + TAG_LENGTH
+        || !payload.starts_with(MAGIC)
+        || payload[3] & FLAG_SIGNED == 0
+    {
+        return false;
+    }
+    let (signed, tag) = payload.split_at(payload.len() - TAG_LENGTH);
+    let mut mac = match Hmac::<Sha256>::new_from_slice(secret) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(signed);
+
+    mac.verify_slice(tag).is_ok()
+}
+
+/// Whether the message may be processed given the secret shared with p-vector, always
+/// without a secret. A signed message must have a sequence number greater than the one of the
+/// last accepted message (`last`).
+pub fn authentic(payload: &[u8], secret: Option<&[u8]>, last: Option<u64>) -> bool {
+    match secret {
+        Some(secret) => verify(payload, secret) && last.is_none_or(|last| sequence(payload) > last),
+        None => true,
+    }
+}
+
+/// Sequence number in the header of a versioned message
+fn sequence(payload: &[u8]) -> u64 {
+    payload
+        .get(4..12)
+        .and_then(|s| s.try_into().ok())
+        .map(u64::from_be_bytes)
+        .unwrap_or_default()
+}
+
+/// Number of the batches missed between the last received one and `sequence`
+/// (p-vector restarting from 0 is not counted)
+pub fn missed(last: Option<u64>, sequence: u64) -> u64 {
+    match last {
+        Some(last) if sequence > last => sequence - last - 1,
+        _ => 0,
+    }
+}
+
+#[test]
+fn test_decode() {
+    let body = br#"{"updates":[{"comp":"stable","pkg":"gtk-3","arch":"amd64","method":94,"from_ver":"3.24.1","to_ver":"3.24.2"}],"extra":1}"#;
+    let envelope = |flags: u8, body: &[u8]| {
+        let mut message = b"PV".to_vec();
+        message.extend([PROTOCOL_VERSION, flags]);
+        message.extend(42u64.to_be_bytes());
+        message.extend((body.len() as u32).to_be_bytes());
+        message.extend(body);
+        message
+    };
+    let batch = decode(&envelope(0, body)).unwrap();
+    assert_eq!(batch.sequence, Some(42));
+    assert_eq!(batch.updates[0].pkg, "gtk-3");
+    assert_eq!(batch.updates[0].method.as_new_type(), b'^');
+    let compressed = zstd::bulk::compress(body, 3).unwrap();
+    let batch = decode(&envelope(FLAG_ZSTD, &compressed)).unwrap();
+    assert_eq!(batch.updates[0].to_ver.as_deref(), Some("3.24.2"));
+
+    let failed = br#"{"updates":[{"comp":"stable","pkg":"gtk-3","arch":"amd64","method":105,"error":{"stage":"scan","message":"bad control file"}}]}"#;
+    let batch = decode(&envelope(0, failed)).unwrap();
+    let error = batch.updates[0].error.as_ref().unwrap();
+    assert_eq!(error.stage.as_deref(), Some("scan"));
+    assert_eq!(error.message, "bad control file");
+
+    let legacy = br#"[{"comp":"stable","pkg":"gtk-3","arch":"amd64","method":"upgrade","from_ver":"3.24.1","to_ver":"3.24.2"}]"#;
+    let batch = decode(legacy).unwrap();
+    assert_eq!(batch.sequence, None);
+    assert_eq!(batch.updates[0].method.as_new_type(), b'^');
+
+    assert!(decode(&envelope(0, body)[..20]).is_err());
+    assert!(decode(&envelope(2, body)).is_err());
+    let mut future = envelope(0, body);
+    future[2] = 4;
+    assert!(decode(&future).is_err());
+
+    let mut signed = envelope(FLAG_SIGNED, body);
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+    mac.update(&signed);
+    signed.extend(mac.finalize().into_bytes());
+    assert!(verify(&signed, b"secret"));
+    assert!(!verify(&signed, b"guessed"));
+    assert!(!verify(&envelope(0, body), b"secret"));
+    assert!(!verify(legacy, b"secret"));
+    assert_eq!(decode(&signed).unwrap().updates[0].pkg, "gtk-3");
+    assert!(authentic(&signed, Some(b"secret"), None));
+    assert!(authentic(&signed, Some(b"secret"), Some(41)));
+    // replayed
+    assert!(!authentic(&signed, Some(b"secret"), Some(42)));
+    let mut forged = signed.clone();
+    forged[11] += 1;
+    assert!(!authentic(&forged, Some(b"secret"), Some(42)));
+    let last = signed.len() - 1;
+    signed[last] ^= 1;
+    assert!(!verify(&signed, b"secret"));
+    assert!(authentic(&signed, None, Some(42)));
+
+    assert_eq!(missed(None, 42), 0);
+    assert_eq!(missed(Some(41), 42), 0);
+    assert_eq!(missed(Some(39), 42), 2);
+    assert_eq!(missed(Some(100), 0), 0);
+}
@@ -0,0 +1,98 @@
+//! Canonical JSON form of the manifests: no whitespace, object keys sorted by their bytes,
+//! integers without exponents and floats in their shortest round-trip representation.
+//! The same manifest always has the same bytes (and hash).
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    fs::{rename, write},
+    path::Path,
+};
+
+use crate::scan::sha256sum;
+
+fn write_value(value: &Value, output: &mut String) -> Result<()> {
+    match value {
+        Value::Array(items) => {
+            output.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_value(item, output)?;
+            }
+            output.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_unstable_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+            output.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                output.push_str(&serde_json::to_string(key)?);
+                output.push(':');
+                write_value(item, output)?;
+            }
+            output.push('}');
+        }
+        // null, booleans, numbers and strings already have a single representation
+        _ => output.push_str(&serde_json::to_string(value)?),
+    }
+
+    Ok(())
+}
+
+/// Serialize the value in the canonical JSON form
+pub fn to_string<T: Serialize>(value: &T) -> Result<String> {
+    let value = serde_json::to_value(value)?;
+    let mut output = String::new();
+    write_value(&value, &mut output)?;
+
+    Ok(output)
+}
+
+/// Write the manifest, along with its checksum in `<name>.sha256` (in the `sha256sum` format)
+/// Write `contents` to a temporary file next to `path` and rename it over `path`, so readers
+/// never see a partially written file
+fn replace<C: AsRef<[u8]>>(path: &Path, contents: C) -> Result<()> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.tmp", name));
+    write(&temp, contents)?;
+    rename(&temp, path)?;
+
+    Ok(())
+}
+
+pub fn write_manifest(path: &Path, json: &str) -> Result<()> {
+    replace(path, json)?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let hash = sha256sum(json.as_bytes())?;
+    replace(
+        &path.with_file_name(format!("{}.sha256", name)),
+        format!("{}  {}\n", hash, name),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_canonical_json() {
+    let value = serde_json::json!({
+        "version": 2,
+        "b": [1.5, -3, {"z": null, "a": true}],
+        "a": "\u{4e2d}\"",
+    });
+    assert_eq!(
+        to_string(&value).unwrap(),
+        r#"{"a":"中\"","b":[1.5,-3,{"a":true,"z":null}],"version":2}"#
+    );
+}
@@ -0,0 +1,144 @@
+use crate::canonical;
+use crate::parser::{
+    assemble_livekit_manifest, generate_manifest, parse_livekit_manifest, parse_manifest,
+    LiveKitManifest, Recipe,
+};
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::{
+    fs::read,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Manifest entries removed because their files no longer exist
+#[derive(Default, Debug)]
+pub struct Report {
+    pub recipe: Vec<String>,
+    pub livekit: Vec<String>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.recipe.is_empty() && self.livekit.is_empty()
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Removed {} orphaned manifest entries:",
+            self.recipe.len() + self.livekit.len()
+        )?;
+        for path in self.recipe.iter() {
+            writeln!(f, "recipe.json: {}", path)?;
+        }
+        for path in self.livekit.iter() {
+            writeln!(f, "livekit.json: {}", path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Remove the entries pointing at missing files from the recipe, returns the removed paths
+pub fn prune_recipe(recipe: &mut Recipe, root_path: &Path) -> Vec<String> {
+    let mut removed = Vec::new();
+    recipe.retain_files(|t| {
+        let exists = root_path.join(&t.path).exists();
+        if !exists {
+            removed.push(t.path.clone());
+        }
+        exists
+    });
+
+    removed
+}
+
+/// Remove the images pointing at missing files from the LiveKit manifest, returns the removed paths
+pub fn prune_livekit(manifest: &mut LiveKitManifest, root_path: &Path) -> Vec<String> {
+    let mut removed = Vec::new();
+    manifest.images.retain(|i| {
+        let exists = root_path.join(&i.tarball.path).exists();
+        if !exists {
+            removed.push(i.tarball.path.clone());
+        }
+        exists
+    });
+
+    removed
+}
+
+/// Remove the orphaned entries from the manifests under `root_path` and rewrite them if needed
+pub fn collect_garbage(root_path: &str, retro_arches: &[String]) -> Result<Report> {
+    let root = Path::new(root_path);
+    let manifest_dir = root.join("manifest");
+    let mut report = Report::default();
+
+    let recipe_path = manifest_dir.join("recipe.json");
+    match read(&recipe_path) {
+        Ok(data) => {
+            let mut recipe = parse_manifest(&data)?;
+            report.recipe = prune_recipe(&mut recipe, root);
+            if !report.recipe.is_empty() {
+                canonical::write_manifest(&recipe_path, &generate_manifest(&recipe)?)?;
+            }
+        }
+        Err(e) => warn!("Could not read {}: {}", recipe_path.display(), e),
+    }
+
+    let livekit_path = manifest_dir.join("livekit.json");
+    match read(&livekit_path) {
+        Ok(data) => {
+            // the legacy format is upgraded when rewritten
+            let images = parse_livekit_manifest(&data)?;
+            let mut manifest = assemble_livekit_manifest(images, retro_arches);
+            report.livekit = prune_livekit(&mut manifest, root);
+            if !report.livekit.is_empty() {
+                canonical::write_manifest(&livekit_path, &canonical::to_string(&manifest)?)?;
+            }
+        }
+        Err(e) => warn!("Could not read {}: {}", livekit_path.display(), e),
+    }
+
+    if report.is_empty() {
+        info!("No orphaned manifest entries found.");
+    } else {
+        warn!("{}", report.to_string().trim_end());
+    }
+
+    Ok(report)
+}
+
+/// Run the notification command with the report on its standard input
+pub fn notify<R: std::fmt::Display>(command: &str, report: &R) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(report.to_string().as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("Notification command exited with {}", status));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_livekit() {
+    let manifest = br#"[{"arch":"amd64","date":"20210614","downloadSize":1,"instSize":1,"path":"Cargo.toml","sha256sum":"00"},{"arch":"amd64","date":"20210614","downloadSize":1,"instSize":1,"path":"os-amd64/livekit/aosc-os_livekit_20210614_amd64.iso","sha256sum":"00"}]"#;
+    let images = parse_livekit_manifest(manifest).unwrap();
+    let mut manifest = assemble_livekit_manifest(images, &[]);
+    let removed = prune_livekit(&mut manifest, Path::new(env!("CARGO_MANIFEST_DIR")));
+    assert_eq!(
+        removed,
+        vec!["os-amd64/livekit/aosc-os_livekit_20210614_amd64.iso".to_string()]
+    );
+    assert_eq!(manifest.images.len(), 1);
+}
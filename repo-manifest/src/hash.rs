@@ -0,0 +1,171 @@
+//! SHA-256 checksums of the media files
+//!
+//! The method is selected with the `HASH_METHOD` environment variable:
+//! - `auto` (default): memory-mapped for the files larger than 16 MiB, buffered reads otherwise
+//! - `mmap`: hash the memory-mapped file, with `madvise(MADV_SEQUENTIAL)`
+//! - `direct`: read with `O_DIRECT`, bypassing the page cache (Linux only)
+//! - `buffered`: plain buffered reads
+//!
+//! When the file can not be mapped, `O_DIRECT` reads are used instead, then buffered reads.
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    str::FromStr,
+    time::Instant,
+};
+
+use crate::scan::sha256sum;
+
+/// Files larger than this are memory-mapped in the `auto` mode
+const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+const BUFFER_SIZE: usize = 1024 * 1024;
+/// Alignment of the buffer required by `O_DIRECT`
+#[cfg(target_os = "linux")]
+const DIRECT_ALIGNMENT: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Buffered,
+    Mmap,
+    Direct,
+}
+
+pub const METHODS: &[Method] = &[Method::Buffered, Method::Mmap, Method::Direct];
+
+impl FromStr for Method {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "buffered" => Ok(Method::Buffered),
+            "mmap" => Ok(Method::Mmap),
+            "direct" => Ok(Method::Direct),
+            _ => Err(anyhow!("Unknown hash method: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Method::Buffered => "buffered",
+            Method::Mmap => "mmap",
+            Method::Direct => "direct",
+        };
+
+        f.write_str(name)
+    }
+}
+
+fn hash_buffered(path: &Path) -> Result<String> {
+    sha256sum(BufReader::with_capacity(BUFFER_SIZE, File::open(path)?))
+}
+
+fn hash_mmap(path: &Path) -> Result<String> {
+    let f = File::open(path)?;
+    let map = unsafe { memmap2::Mmap::map(&f)? };
+    #[cfg(unix)]
+    map.advise(memmap2::Advice::Sequential)?;
+    let mut hasher = Sha256::new();
+    for chunk in map.chunks(BUFFER_SIZE) {
+        hasher.update(chunk);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(target_os = "linux")]
+fn hash_direct(path: &Path) -> Result<String> {
+    use std::{fs::OpenOptions, os::unix::fs::OpenOptionsExt};
+
+    let mut f = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)?;
+    let mut buffer = vec![0u8; BUFFER_SIZE + DIRECT_ALIGNMENT];
+    let offset = buffer.as_ptr().align_offset(DIRECT_ALIGNMENT);
+    let buffer = &mut buffer[offset..offset + BUFFER_SIZE];
+    let mut hasher = Sha256::new();
+    loop {
+        let size = f.read(buffer)?;
+        if size < 1 {
+            break;
+        }
+        hasher.update(&buffer[..size]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn hash_direct(_: &Path) -> Result<String> {
+    Err(anyhow!("O_DIRECT is only supported on Linux"))
+}
+
+/// Calculate the checksum of the file with the given method, falling back to the slower
+/// ones if it is not supported
+pub fn sha256_file_with(path: &Path, method: Method) -> Result<String> {
+    match method {
+        Method::Buffered => hash_buffered(path),
+        Method::Mmap => hash_mmap(path).or_else(|e| {
+            warn!("Could not map {}, reading directly: {}", path.display(), e);
+            sha256_file_with(path, Method::Direct)
+        }),
+        Method::Direct => hash_direct(path).or_else(|e| {
+            warn!("Could not read {} directly: {}", path.display(), e);
+            sha256_file_with(path, Method::Buffered)
+        }),
+    }
+}
+
+/// Calculate the checksum of the file with the method in `HASH_METHOD`
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let method = match std::env::var("HASH_METHOD") {
+        Ok(method) if method != "auto" => method.parse()?,
+        _ if path.metadata()?.len() > MMAP_THRESHOLD => Method::Mmap,
+        _ => Method::Buffered,
+    };
+
+    sha256_file_with(path, method)
+}
+
+/// Compare the throughput of the methods on the given file (the best of 3 runs each)
+pub fn bench(path: &Path) -> Result<()> {
+    let size = path.metadata()?.len() as f64 / 1024.0 / 1024.0;
+    let mut expected = None;
+    println!("Hashing {} ({:.1} MiB)...", path.display(), size);
+    for method in METHODS {
+        let mut best = f64::MAX;
+        for _ in 0..3 {
+            let start = Instant::now();
+            let hash = sha256_file_with(path, *method)?;
+            best = best.min(start.elapsed().as_secs_f64());
+            if *expected.get_or_insert_with(|| hash.clone()) != hash {
+                return Err(anyhow!("The {} method gave a different checksum", method));
+            }
+        }
+        println!(
+            "{:<10} {:>10.3} s {:>10.1} MiB/s",
+            method.to_string(),
+            best,
+            size / best
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hash_methods() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let expected = sha256sum(&std::fs::read(&path).unwrap()[..]).unwrap();
+    for method in METHODS {
+        assert_eq!(sha256_file_with(&path, *method).unwrap(), expected);
+    }
+    assert!("sha1".parse::<Method>().is_err());
+}
@@ -1,58 +1,205 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use log::{error, info, warn};
+use repokit_common::MediaDate;
 use std::{
-    fs::{create_dir_all, read, read_to_string, write},
+    collections::BTreeSet,
+    fs::{create_dir_all, read, read_to_string},
     path::Path,
     process,
+    thread::sleep,
+    time::Duration,
 };
 
-use crate::parser::Tarball;
-
+mod canonical;
+mod gc;
+mod hash;
 mod parser;
 mod scan;
 mod sqfs;
+mod upload;
 mod xz;
 
 #[derive(Parser, Debug)]
 #[clap(about, version, author)]
 struct Args {
     /// Specify the configuration file to use
-    #[clap(short, long)]
-    config: String,
+    #[clap(short, long, required_unless_present = "bench_hash")]
+    config: Option<String>,
+    /// Only remove the manifest entries whose files no longer exist, without scanning
+    #[clap(long)]
+    gc: bool,
+    /// Keep running and remove the orphaned manifest entries every given number of seconds
+    #[clap(long, value_name = "SECONDS")]
+    daemon: Option<u64>,
+    /// Shell command to run (with the report on its stdin) when orphaned entries are removed
+    /// or uploads are stalled
+    #[clap(long, value_name = "COMMAND")]
+    notify: Option<String>,
+    /// Only manifest the media of the dates marked with `UPLOAD_COMPLETE.<date>` files in the
+    /// root directory (the manifests are regenerated when new markers appear in daemon mode)
+    #[clap(long)]
+    upload_markers: bool,
+    /// Report the uploads still without markers after the given number of seconds
+    #[clap(long, value_name = "SECONDS", default_value_t = 21600)]
+    upload_timeout: u64,
+    /// Compare the speed of the checksum methods on the given file and exit
+    #[clap(long, value_name = "FILE")]
+    bench_hash: Option<String>,
 }
 
 fn main() {
     std::env::set_var("RUST_LOG", "info");
     env_logger::init();
     let matches = Args::parse();
-    let config = &matches.config;
+    if let Some(path) = matches.bench_hash.as_deref() {
+        if let Err(e) = hash::bench(Path::new(path)) {
+            error!("Could not benchmark the checksum methods: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let config = matches.config.as_deref().unwrap_or_default();
     info!("Reading config from {}...", config);
-    let config_data = read_to_string(config);
-    if let Err(e) = config_data {
-        error!("Could not read the config file {}: {}", config, e);
-        process::exit(1);
+    let config_data = match read_config(config) {
+        Ok(config_data) => config_data,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+    let root_path = parser::get_root_path(&config_data);
+    let retro_arches = parser::get_retro_arches(&config_data);
+    // dates of the complete uploads already manifested
+    let mut completed = BTreeSet::new();
+    if matches.upload_markers {
+        completed = upload::completed_dates(Path::new(&root_path)).unwrap_or_else(|e| {
+            error!("Could not read the upload markers: {}", e);
+            process::exit(1);
+        });
+    }
+    if !matches.gc {
+        let markers = if matches.upload_markers {
+            Some(&completed)
+        } else {
+            None
+        };
+        if generate_manifests(&root_path, &retro_arches, config_data, markers).is_err() {
+            process::exit(1);
+        }
+    }
+    // uploads already reported as stalled
+    let mut reported = BTreeSet::new();
+    if matches.upload_markers {
+        if let Err(e) = report_stalled(&matches, &root_path, &mut reported) {
+            error!("Could not check for the stalled uploads: {}", e);
+        }
+    }
+    if matches.gc || matches.daemon.is_some() {
+        if let Err(e) = collect_garbage(&root_path, &retro_arches, matches.notify.as_deref()) {
+            error!("Could not remove the orphaned manifest entries: {}", e);
+            if matches.daemon.is_none() {
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(interval) = matches.daemon {
+        info!(
+            "Checking for orphaned manifest entries every {} seconds.",
+            interval
+        );
+        loop {
+            sleep(Duration::from_secs(interval));
+            if matches.upload_markers {
+                if let Err(e) = check_uploads(&matches, &root_path, &mut completed) {
+                    error!("Could not regenerate the manifests: {}", e);
+                }
+                if let Err(e) = report_stalled(&matches, &root_path, &mut reported) {
+                    error!("Could not check for the stalled uploads: {}", e);
+                }
+            }
+            if let Err(e) = collect_garbage(&root_path, &retro_arches, matches.notify.as_deref()) {
+                error!("Could not remove the orphaned manifest entries: {}", e);
+            }
+        }
+    }
+}
+
+fn read_config(path: &str) -> Result<parser::UserConfig> {
+    let config_data = read_to_string(path)
+        .map_err(|e| anyhow!("Could not read the config file {}: {}", path, e))?;
+
+    parser::parse_config(&config_data)
+        .map_err(|e| anyhow!("Could not parse the config file {}: {}", path, e))
+}
+
+/// Regenerate the manifests when new uploads are marked as complete
+fn check_uploads(args: &Args, root_path: &str, completed: &mut BTreeSet<MediaDate>) -> Result<()> {
+    let markers = upload::completed_dates(Path::new(root_path))?;
+    if markers.is_subset(completed) {
+        *completed = markers;
+        return Ok(());
+    }
+    info!("New uploads completed, regenerating the manifests...");
+    let config_data = read_config(args.config.as_deref().unwrap_or_default())?;
+    let retro_arches = parser::get_retro_arches(&config_data);
+    generate_manifests(root_path, &retro_arches, config_data, Some(&markers))?;
+    *completed = markers;
+
+    Ok(())
+}
+
+/// Notify the admins about the uploads without markers after the timeout (once for each upload)
+fn report_stalled(args: &Args, root_path: &str, reported: &mut BTreeSet<MediaDate>) -> Result<()> {
+    let mut stalled = upload::stalled(root_path, Duration::from_secs(args.upload_timeout))?;
+    stalled.dates.retain(|(date, _, _)| reported.insert(*date));
+    if stalled.is_empty() {
+        return Ok(());
     }
-    let config_data = parser::parse_config(&config_data.unwrap());
-    if let Err(e) = config_data {
-        error!("Could not parse the config file {}: {}", config, e);
-        process::exit(1);
+    warn!("{}", stalled.to_string().trim_end());
+    if let Some(command) = args.notify.as_deref() {
+        gc::notify(command, &stalled)?;
+    }
+
+    Ok(())
+}
+
+/// Remove the orphaned manifest entries and notify the admins about them
+fn collect_garbage(root_path: &str, retro_arches: &[String], notify: Option<&str>) -> Result<()> {
+    info!("Checking for orphaned manifest entries...");
+    let report = gc::collect_garbage(root_path, retro_arches)?;
+    if let Some(command) = notify {
+        if !report.is_empty() {
+            gc::notify(command, &report)?;
+        }
     }
-    let config_data = config_data.unwrap();
+
+    Ok(())
+}
+
+/// Scan the files (only the ones of the given dates if any) and write the manifests,
+/// the errors are logged
+fn generate_manifests(
+    root_path: &str,
+    retro_arches: &[String],
+    config_data: parser::UserConfig,
+    completed: Option<&BTreeSet<MediaDate>>,
+) -> Result<()> {
     info!("Preflight scanning...");
-    let root_path = parser::get_root_path(&config_data);
-    let tarball_json = scan_tarballs(&root_path, config_data);
-    let image_json = scan_images(&root_path);
+    let tarball_json = scan_tarballs(root_path, config_data, completed);
+    let image_json = scan_images(root_path, retro_arches, completed);
     info!("Writing manifest...");
-    let manifest_dir = Path::new(&root_path).join("manifest");
+    let manifest_dir = Path::new(root_path).join("manifest");
     let mut error = false;
     if let Err(e) = create_dir_all(&manifest_dir) {
         error!("Could not create directory: {}", e);
-        process::exit(1);
+        return Err(anyhow!("Could not generate the manifests"));
     }
     match tarball_json {
         Ok(tarball_json) => {
-            if let Err(e) = write(manifest_dir.join("recipe.json"), tarball_json) {
+            if let Err(e) =
+                canonical::write_manifest(&manifest_dir.join("recipe.json"), &tarball_json)
+            {
                 error!("Could not write the manifest: {}", e);
                 error = true;
             }
@@ -65,7 +212,9 @@ fn main() {
 
     match image_json {
         Ok(image_json) => {
-            if let Err(e) = write(manifest_dir.join("livekit.json"), image_json) {
+            if let Err(e) =
+                canonical::write_manifest(&manifest_dir.join("livekit.json"), &image_json)
+            {
                 error!("Could not write the manifest: {}", e);
                 error = true;
             }
@@ -77,13 +226,22 @@ fn main() {
     }
 
     if error {
-        process::exit(1);
+        return Err(anyhow!("Could not generate the manifests"));
     }
     info!("Manifest generated successfully.");
+
+    Ok(())
 }
 
-fn scan_images(root_path: &str) -> Result<String> {
-    let files = scan::collect_iso(root_path)?;
+fn scan_images(
+    root_path: &str,
+    retro_arches: &[String],
+    completed: Option<&BTreeSet<MediaDate>>,
+) -> Result<String> {
+    let mut files = scan::collect_iso(root_path)?;
+    if let Some(completed) = completed {
+        files = upload::hold_incomplete(files, completed);
+    }
     if files.is_empty() {
         return Err(anyhow!("No image was found."));
     }
@@ -95,17 +253,24 @@ fn scan_images(root_path: &str) -> Result<String> {
         info!("Scanning {} images...", files.len());
         scan::scan_files(&files, root_path, true)?
     } else {
-        let existing_files: Vec<Tarball> =
-            serde_json::from_slice(previous_manifest.as_ref().unwrap())?;
+        let existing_files = parser::parse_livekit_manifest(previous_manifest.as_ref().unwrap())?;
         scan::increment_scan_files(files, existing_files, root_path, true)?
     };
     info!("Generating manifest...");
+    let manifest = parser::assemble_livekit_manifest(scanned, retro_arches);
 
-    Ok(serde_json::to_string(&scanned)?)
+    canonical::to_string(&manifest)
 }
 
-fn scan_tarballs(root_path: &str, config_data: parser::UserConfig) -> Result<String> {
-    let files = scan::collect_tarballs(root_path)?;
+fn scan_tarballs(
+    root_path: &str,
+    config_data: parser::UserConfig,
+    completed: Option<&BTreeSet<MediaDate>>,
+) -> Result<String> {
+    let mut files = scan::collect_tarballs(root_path)?;
+    if let Some(completed) = completed {
+        files = upload::hold_incomplete(files, completed);
+    }
     if files.is_empty() {
         return Err(anyhow!("No tarball was found."));
     }
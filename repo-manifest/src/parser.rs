@@ -1,7 +1,11 @@
 use anyhow::Result;
 use indexmap::IndexMap;
 use log::warn;
+use repokit_common::MediaName;
 use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+
+pub const LIVEKIT_MANIFEST_VERSION: usize = 2;
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub enum RootFSType {
@@ -9,14 +13,6 @@ pub enum RootFSType {
     SquashFs,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct FileNameParts<'a> {
-    pub arch: &'a str,
-    pub date: &'a str,
-    pub variant: &'a str,
-    pub type_: &'a str,
-}
-
 // mirror manifests
 #[derive(Serialize, Deserialize)]
 pub struct Mirror {
@@ -47,6 +43,63 @@ pub struct Tarball {
     pub inodes: Option<u32>,
 }
 
+/// LiveKit image manifest structure (`livekit.json`)
+#[derive(Serialize, Deserialize)]
+pub struct LiveKitManifest {
+    pub version: usize,
+    pub images: Vec<LiveKitImage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LiveKitImage {
+    pub variant: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub retro: bool,
+    #[serde(flatten)]
+    pub tarball: Tarball,
+}
+
+/// Aggregate download sizes of a variant, computed when the manifest is assembled
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct Sizes {
+    /// The latest tarball of each architecture
+    #[serde(rename = "latestTarball")]
+    pub latest_tarball: i64,
+    /// The latest squashfs image of each architecture
+    #[serde(rename = "latestSquashfs")]
+    pub latest_squashfs: i64,
+    /// All the tarballs and squashfs images kept on the mirrors
+    pub total: i64,
+}
+
+impl Sizes {
+    /// Sum the download sizes of the latest files of each architecture
+    fn latest(files: &[Tarball]) -> i64 {
+        let mut latest: HashMap<&str, &Tarball> = HashMap::new();
+        for file in files {
+            let entry = latest.entry(&file.arch).or_insert(file);
+            if file.date > entry.date {
+                *entry = file;
+            }
+        }
+
+        latest.values().map(|f| f.download_size).sum()
+    }
+
+    fn of(tarballs: &[Tarball], squashfs: &[Tarball]) -> Self {
+        Sizes {
+            latest_tarball: Self::latest(tarballs),
+            latest_squashfs: Self::latest(squashfs),
+            total: tarballs
+                .iter()
+                .chain(squashfs.iter())
+                .map(|f| f.download_size)
+                .sum(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Variant {
     name: String,
@@ -58,6 +111,8 @@ pub struct Variant {
     description_tr: String,
     tarballs: Vec<Tarball>,
     squashfs: Vec<Tarball>,
+    #[serde(default)]
+    sizes: Sizes,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -129,10 +184,26 @@ impl Variant {
             retro,
             description,
             description_tr: format!("{}{}-description", key, if retro { "-retro" } else { "" }),
+            sizes: Sizes::of(&tarballs, &squashfs),
             tarballs,
             squashfs,
         }
     }
+
+    fn update_sizes(&mut self) {
+        self.sizes = Sizes::of(&self.tarballs, &self.squashfs);
+    }
+}
+
+impl Recipe {
+    /// Keep only the tarballs and squashfs images for which `f` returns `true`
+    pub fn retain_files<F: FnMut(&Tarball) -> bool>(&mut self, mut f: F) {
+        for variant in self.variants.iter_mut() {
+            variant.tarballs.retain(&mut f);
+            variant.squashfs.retain(&mut f);
+            variant.update_sizes();
+        }
+    }
 }
 
 #[inline]
@@ -144,6 +215,41 @@ pub fn parse_manifest(data: &[u8]) -> Result<Recipe> {
     Ok(serde_json::from_slice(data)?)
 }
 
+/// Parse the LiveKit manifest, the legacy format (a plain list of tarballs) is also accepted
+pub fn parse_livekit_manifest(data: &[u8]) -> Result<Vec<Tarball>> {
+    if let Ok(manifest) = serde_json::from_slice::<LiveKitManifest>(data) {
+        return Ok(manifest.images.into_iter().map(|i| i.tarball).collect());
+    }
+
+    Ok(serde_json::from_slice(data)?)
+}
+
+pub fn assemble_livekit_manifest(images: Vec<Tarball>, retro_arches: &[String]) -> LiveKitManifest {
+    let images = images
+        .into_iter()
+        .map(|tarball| {
+            let names = Path::new(&tarball.path)
+                .file_name()
+                .and_then(|f| f.to_str())
+                .and_then(|f| MediaName::parse(f).ok());
+            let (variant, type_) = names
+                .map(|n| (n.full_variant(), n.ext))
+                .unwrap_or_else(|| ("livekit".to_string(), "iso".to_string()));
+            LiveKitImage {
+                variant,
+                type_,
+                retro: is_retro(retro_arches, &tarball.arch),
+                tarball,
+            }
+        })
+        .collect();
+
+    LiveKitManifest {
+        version: LIVEKIT_MANIFEST_VERSION,
+        images,
+    }
+}
+
 pub fn flatten_variants(recipe: Recipe) -> Vec<Tarball> {
     let mut results = Vec::with_capacity(128);
     for variant in recipe.variants {
@@ -158,12 +264,18 @@ pub fn get_root_path(config: &UserConfig) -> String {
     config.config.path.clone()
 }
 
+/// Whether the architecture is retro, ignoring the device part (e.g. `armhf_rpi`)
+fn is_retro(retro_arches: &[String], arch: &str) -> bool {
+    let arch = arch.split('_').next().unwrap_or(arch);
+    retro_arches.iter().any(|a| a == arch)
+}
+
 pub fn get_retro_arches(config: &UserConfig) -> Vec<String> {
     config.config.retro_arches.clone()
 }
 
 pub fn generate_manifest(manifest: &Recipe) -> Result<String> {
-    Ok(serde_json::to_string(manifest)?)
+    crate::canonical::to_string(manifest)
 }
 
 pub fn assemble_variants(config: &UserConfig, files: Vec<Tarball>) -> Vec<Variant> {
@@ -198,7 +310,7 @@ pub fn assemble_variants(config: &UserConfig, files: Vec<Tarball>) -> Vec<Varian
     }
     let retro_arches = &config.config.retro_arches;
     for file in files {
-        let v = if retro_arches.contains(&file.arch) {
+        let v = if is_retro(retro_arches, &file.arch) {
             variants_r.get_mut(&file.variant)
         } else {
             variants.get_mut(&file.variant)
@@ -213,10 +325,8 @@ pub fn assemble_variants(config: &UserConfig, files: Vec<Tarball>) -> Vec<Varian
             warn!("The variant `{}` is not in the config file.", file.variant);
         }
     }
-    for (_, variant) in variants {
-        results.push(variant);
-    }
-    for (_, variant) in variants_r {
+    for (_, mut variant) in variants.into_iter().chain(variants_r) {
+        variant.update_sizes();
         results.push(variant);
     }
 
@@ -232,47 +342,60 @@ pub fn assemble_manifest(config: UserConfig, variants: Vec<Variant>) -> Recipe {
     }
 }
 
-// parser combinators
-// AOSC OS tarball names have the following pattern:
-// aosc-os_<variant>_<date>_<arch>.<ext>
-// aosc-os_base_20200526_amd64.tar.xz
-pub fn get_splitted_name(name: &'_ str) -> Option<FileNameParts<'_>> {
-    let mut splitted = name.split('_');
-    splitted.next()?;
-    let variant = splitted.next()?;
-    let date = splitted.next()?;
-    let rest = splitted.next()?.split_once('.')?;
-    let arch = rest.0;
-    let rootfs_type = rest.1;
-
-    Some(FileNameParts {
-        arch,
-        date,
-        variant,
-        type_: rootfs_type,
-    })
+#[test]
+fn test_parse_livekit_manifest() {
+    let legacy = br#"[{"arch":"amd64","date":"20210614","downloadSize":1,"instSize":1,"path":"os-amd64/livekit/aosc-os_livekit_20210614_amd64.iso","sha256sum":"00"}]"#;
+    let images = parse_livekit_manifest(legacy).unwrap();
+    assert_eq!(images.len(), 1);
+    let manifest = assemble_livekit_manifest(images, &["i486".to_string()]);
+    assert_eq!(manifest.images[0].variant, "livekit");
+    assert_eq!(manifest.images[0].type_, "iso");
+    assert!(!manifest.images[0].retro);
+    let json = serde_json::to_vec(&manifest).unwrap();
+    let images = parse_livekit_manifest(&json).unwrap();
+    assert_eq!(
+        images[0].path,
+        "os-amd64/livekit/aosc-os_livekit_20210614_amd64.iso"
+    );
 }
 
 #[test]
-fn test_split_name() {
-    let names = get_splitted_name("aosc-os_base_20200526_amd64.tar.xz").unwrap();
+fn test_sizes() {
+    let tarball = |arch: &str, date: &str, size| Tarball {
+        arch: arch.to_string(),
+        date: date.to_string(),
+        variant: "base".to_string(),
+        type_: Some(RootFSType::Tarball),
+        download_size: size,
+        inst_size: size * 2,
+        path: String::new(),
+        sha256sum: String::new(),
+        inodes: None,
+    };
+    let tarballs = vec![
+        tarball("amd64", "20240101", 100),
+        tarball("amd64", "20240201", 120),
+        tarball("arm64", "20240101", 90),
+    ];
+    let squashfs = vec![tarball("amd64", "20240201", 80)];
     assert_eq!(
-        names,
-        FileNameParts {
-            arch: "amd64",
-            date: "20200526",
-            variant: "base",
-            type_: "tar.xz",
+        Sizes::of(&tarballs, &squashfs),
+        Sizes {
+            latest_tarball: 210,
+            latest_squashfs: 80,
+            total: 390,
         }
     );
-    let names = get_splitted_name("aosc-os_server_20230714_loongarch64.squashfs").unwrap();
-    assert_eq!(
-        names,
-        FileNameParts {
-            arch: "loongarch64",
-            date: "20230714",
-            variant: "server",
-            type_: "squashfs",
-        }
+    let mut variant = Variant::new(
+        "Base".to_string(),
+        "base".to_string(),
+        String::new(),
+        false,
+        tarballs,
+        squashfs,
     );
+    variant.tarballs.retain(|t| t.arch == "arm64");
+    variant.update_sizes();
+    assert_eq!(variant.sizes.latest_tarball, 90);
+    assert_eq!(variant.sizes.total, 170);
 }
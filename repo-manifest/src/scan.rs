@@ -1,6 +1,6 @@
+use crate::hash::sha256_file;
 use crate::parser::{
-    flatten_variants, get_retro_arches, get_splitted_name, parse_manifest, RootFSType, Tarball,
-    UserConfig,
+    flatten_variants, get_retro_arches, parse_manifest, RootFSType, Tarball, UserConfig,
 };
 use crate::sqfs::collect_squashfs_size_and_inodes;
 use crate::xz::calculate_xz_decompressed_size;
@@ -8,6 +8,7 @@ use anyhow::{anyhow, Result};
 use log::{error, info, warn};
 use parking_lot::Mutex;
 use rayon::prelude::*;
+use repokit_common::MediaName;
 use sha2::{Digest, Sha256};
 use std::{
     convert::TryInto,
@@ -167,9 +168,9 @@ pub fn increment_scan_files(
         let path = root_path_buf.join(&tarball.path);
         if files.contains(&path) {
             if let Some(filename) = PathBuf::from(&tarball.path).file_name() {
-                if let Some(names) = get_splitted_name(&filename.to_string_lossy()) {
-                    tarball.variant = names.variant.to_string();
-                    match names.type_ {
+                if let Ok(names) = MediaName::parse(&filename.to_string_lossy()) {
+                    tarball.variant = names.full_variant();
+                    match names.ext.as_str() {
                         "iso" | "img" => {
                             tarball.type_ = Some(RootFSType::Tarball);
                         }
@@ -180,7 +181,7 @@ pub fn increment_scan_files(
                             tarball.type_ = Some(RootFSType::SquashFs);
                         }
                         _ => {
-                            warn!("Unknown file type: {}", names.type_);
+                            warn!("Unknown file type: {}", names.ext);
                             continue;
                         }
                     }
@@ -216,22 +217,23 @@ pub fn filter_files(files: Vec<PathBuf>, config: &UserConfig) -> Vec<PathBuf> {
     let retro_arches = get_retro_arches(config);
     for file in files {
         if let Some(filename) = file.file_name() {
-            if let Some(names) = get_splitted_name(&filename.to_string_lossy()) {
-                if retro_arches.iter().any(|x| x == names.arch) {
-                    if config.distro.retro.contains_key(names.variant) {
+            if let Ok(names) = MediaName::parse(&filename.to_string_lossy()) {
+                let variant = names.full_variant();
+                if retro_arches.contains(&names.arch) {
+                    if config.distro.retro.contains_key(&variant) {
                         filtered_files.push(file);
                         continue;
                     }
                     warn!(
                         "The variant `{} (retro)` is not in the config file.",
-                        names.variant
+                        variant
                     );
-                } else if config.distro.mainline.contains_key(names.variant) {
+                } else if config.distro.mainline.contains_key(&variant) {
                     filtered_files.push(file);
                 } else {
                     warn!(
                         "The variant `{} (mainline)` is not in the config file.",
-                        names.variant
+                        variant
                     );
                 }
             }
@@ -281,7 +283,7 @@ pub fn scan_files(files: &[PathBuf], root_path: &str, raw: bool) -> Result<Vec<T
         let names = unwrap_or_show_error!(
             "Could not parse the filename {}: {}",
             p.display(),
-            get_splitted_name(&filename).ok_or_else(|| anyhow!("None value found"))
+            MediaName::parse(&filename)
         );
         let mut f = unwrap_or_show_error!("Could not open {}: {}", p.display(), File::open(p));
 
@@ -327,21 +329,16 @@ pub fn scan_files(files: &[PathBuf], root_path: &str, raw: bool) -> Result<Vec<T
             unwrap_or_show_error!("Could not read metadata {}: {}", p.display(), f.metadata());
         let download_size = f_metadata.len();
         let download_size: i64 = download_size.try_into().unwrap();
-        unwrap_or_show_error!(
-            "Could not seek() {}: {}",
-            p.display(),
-            f.seek(SeekFrom::Start(0))
-        );
         let sha256sum = unwrap_or_show_error!(
             "Could not update sha256sum of {}: {}",
             p.display(),
-            sha256sum(&f)
+            sha256_file(p)
         );
         let mut results = results_shared.lock();
         let result = Tarball {
-            arch: names.arch.to_string(),
+            arch: names.target(),
             date: names.date.to_string(),
-            variant: names.variant.to_string(),
+            variant: names.full_variant(),
             type_: Some(if is_squashfs {
                 RootFSType::SquashFs
             } else {
@@ -358,3 +355,39 @@ pub fn scan_files(files: &[PathBuf], root_path: &str, raw: bool) -> Result<Vec<T
 
     Ok(Arc::try_unwrap(results_shared).unwrap().into_inner())
 }
+
+#[test]
+fn test_scan_flavors_and_devices() {
+    let dir = std::env::temp_dir().join(format!("repo-manifest-scan-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let names = [
+        "aosc-os_desktop+nvidia_20240101_amd64.tar.xz",
+        "aosc-os_desktop_20240101_amd64.tar.xz",
+        "aosc-os_base_20240101_arm64_rpi64.img",
+        "aosc-os_base_20240101_arm64.tar.xz",
+    ];
+    let files = names
+        .iter()
+        .map(|name| {
+            let path = dir.join(name);
+            std::fs::write(&path, b"dummy").unwrap();
+            path
+        })
+        .collect::<Vec<_>>();
+    let mut tarballs = scan_files(&files, dir.to_str().unwrap(), true).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    tarballs.sort_by(|a, b| a.path.cmp(&b.path));
+    let keys = tarballs
+        .iter()
+        .map(|t| (t.variant.as_str(), t.arch.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        [
+            ("base", "arm64"),
+            ("base", "arm64_rpi64"),
+            ("desktop+nvidia", "amd64"),
+            ("desktop", "amd64"),
+        ]
+    );
+}
@@ -0,0 +1,152 @@
+//! Handshake with the release pipeline: the media of a date are only manifested once the
+//! pipeline drops an `UPLOAD_COMPLETE.<date>` marker in the root directory
+use anyhow::Result;
+use log::{info, warn};
+use repokit_common::{MediaDate, MediaName};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::read_dir,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::scan;
+
+pub const MARKER_PREFIX: &str = "UPLOAD_COMPLETE.";
+
+/// Uploads still without markers after the timeout
+#[derive(Default, Debug)]
+pub struct Stalled {
+    /// Date of the upload, number of the files and how long it has been waiting
+    pub dates: Vec<(MediaDate, usize, Duration)>,
+}
+
+impl Stalled {
+    pub fn is_empty(&self) -> bool {
+        self.dates.is_empty()
+    }
+}
+
+impl std::fmt::Display for Stalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} uploads are still waiting for their {}<date> markers:",
+            self.dates.len(),
+            MARKER_PREFIX
+        )?;
+        for (date, files, waiting) in self.dates.iter() {
+            writeln!(
+                f,
+                "{}: {} files, waiting for {} minutes",
+                date,
+                files,
+                waiting.as_secs() / 60
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the dates whose uploads are marked as complete
+pub fn completed_dates(root: &Path) -> Result<BTreeSet<MediaDate>> {
+    let mut dates = BTreeSet::new();
+    for entry in read_dir(root)? {
+        let name = entry?.file_name();
+        let date = match name.to_str().and_then(|n| n.strip_prefix(MARKER_PREFIX)) {
+            Some(date) => date,
+            None => continue,
+        };
+        match date.parse() {
+            Ok(date) => {
+                dates.insert(date);
+            }
+            Err(e) => warn!("Ignoring the upload marker {:?}: {}", name, e),
+        }
+    }
+
+    Ok(dates)
+}
+
+#[inline]
+fn date_of(path: &Path) -> Option<MediaDate> {
+    let filename = path.file_name()?.to_string_lossy();
+
+    MediaName::parse(&filename).ok().map(|n| n.date)
+}
+
+/// Group the files of the dates not marked as complete by their dates
+fn incomplete(
+    files: &[PathBuf],
+    completed: &BTreeSet<MediaDate>,
+) -> BTreeMap<MediaDate, Vec<PathBuf>> {
+    let mut uploads: BTreeMap<MediaDate, Vec<PathBuf>> = BTreeMap::new();
+    for file in files {
+        if let Some(date) = date_of(file).filter(|d| !completed.contains(d)) {
+            uploads.entry(date).or_default().push(file.clone());
+        }
+    }
+
+    uploads
+}
+
+/// Hold back the files of the dates not marked as complete (the files whose names can not
+/// be parsed are kept, they are reported when scanned)
+pub fn hold_incomplete(files: Vec<PathBuf>, completed: &BTreeSet<MediaDate>) -> Vec<PathBuf> {
+    for (date, held) in incomplete(&files, completed) {
+        info!(
+            "Skipping {} files of {}, the upload is not complete yet.",
+            held.len(),
+            date
+        );
+    }
+
+    files
+        .into_iter()
+        .filter(|f| date_of(f).is_none_or(|d| completed.contains(&d)))
+        .collect()
+}
+
+/// Find the uploads whose first file arrived more than `timeout` ago without a marker
+pub fn stalled(root_path: &str, timeout: Duration) -> Result<Stalled> {
+    let completed = completed_dates(Path::new(root_path))?;
+    let mut files = scan::collect_tarballs(root_path)?;
+    files.extend(scan::collect_iso(root_path)?);
+    let now = SystemTime::now();
+    let mut stalled = Stalled::default();
+    for (date, files) in incomplete(&files, &completed) {
+        let waiting = files
+            .iter()
+            .filter_map(|f| f.metadata().and_then(|m| m.modified()).ok())
+            .filter_map(|t| now.duration_since(t).ok())
+            .max()
+            .unwrap_or_default();
+        if waiting >= timeout {
+            stalled.dates.push((date, files.len(), waiting));
+        }
+    }
+
+    Ok(stalled)
+}
+
+#[test]
+fn test_hold_incomplete() {
+    let completed = std::iter::once("20240101".parse().unwrap()).collect();
+    let files = vec![
+        PathBuf::from("os-amd64/base/aosc-os_base_20240101_amd64.tar.xz"),
+        PathBuf::from("os-amd64/base/aosc-os_base_20240102_amd64.tar.xz"),
+        PathBuf::from("os-amd64/base/aosc-os_desktop_20240102_amd64.tar.xz"),
+        PathBuf::from("os-amd64/base/unknown.tar.xz"),
+    ];
+    let uploads = incomplete(&files, &completed);
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[&"20240102".parse().unwrap()].len(), 2);
+    assert_eq!(
+        hold_incomplete(files, &completed),
+        vec![
+            PathBuf::from("os-amd64/base/aosc-os_base_20240101_amd64.tar.xz"),
+            PathBuf::from("os-amd64/base/unknown.tar.xz"),
+        ]
+    );
+}
@@ -0,0 +1,5 @@
+-- Per-chat settings
+CREATE TABLE IF NOT EXISTS `chat_settings` (
+    chat_id INTEGER PRIMARY KEY NOT NULL,
+    lang TEXT NOT NULL DEFAULT 'en'
+);
@@ -0,0 +1,2 @@
+-- Per-chat message format (html, markdown or plain)
+ALTER TABLE `chat_settings` ADD COLUMN format TEXT NOT NULL DEFAULT 'html';
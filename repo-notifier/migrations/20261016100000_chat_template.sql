@@ -0,0 +1,4 @@
+-- Per-chat custom templates of the notification layout
+ALTER TABLE `chat_settings` ADD COLUMN template_header TEXT;
+ALTER TABLE `chat_settings` ADD COLUMN template_line TEXT;
+ALTER TABLE `chat_settings` ADD COLUMN template_footer TEXT;
@@ -0,0 +1,7 @@
+-- Per-chat filters of the updates (e.g. kind = 'repo', value = 'stable')
+CREATE TABLE IF NOT EXISTS `filters` (
+    chat_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (chat_id, kind, value)
+);
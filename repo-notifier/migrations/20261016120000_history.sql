@@ -0,0 +1,13 @@
+-- Package updates sent to the subscribers
+CREATE TABLE IF NOT EXISTS `history` (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp INTEGER NOT NULL,
+    repo TEXT,
+    comp TEXT NOT NULL,
+    pkg TEXT NOT NULL,
+    arch TEXT NOT NULL,
+    method TEXT NOT NULL,
+    from_ver TEXT,
+    to_ver TEXT
+);
+CREATE INDEX IF NOT EXISTS `history_pkg` ON `history` (pkg);
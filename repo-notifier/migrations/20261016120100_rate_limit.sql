@@ -0,0 +1,2 @@
+-- Maximum number of messages per hour (NULL: the default in the configuration, 0: unlimited)
+ALTER TABLE `chat_settings` ADD COLUMN rate_limit INTEGER;
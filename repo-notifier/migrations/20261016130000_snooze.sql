@@ -0,0 +1,4 @@
+-- Notifications are paused until this time (UNIX timestamp, NULL: not snoozed)
+ALTER TABLE `chat_settings` ADD COLUMN snoozed_until INTEGER;
+-- Number of the updates not sent while snoozed, summarized on resume
+ALTER TABLE `chat_settings` ADD COLUMN snoozed_updates INTEGER NOT NULL DEFAULT 0;
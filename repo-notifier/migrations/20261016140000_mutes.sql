@@ -0,0 +1,6 @@
+-- Packages whose updates are not sent to the chat
+CREATE TABLE IF NOT EXISTS `mutes` (
+    chat_id INTEGER NOT NULL,
+    pkg TEXT NOT NULL,
+    PRIMARY KEY (chat_id, pkg)
+);
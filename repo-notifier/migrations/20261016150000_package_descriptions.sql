@@ -0,0 +1,6 @@
+-- Cached descriptions of the packages (an empty description if the package has none)
+CREATE TABLE IF NOT EXISTS `package_descriptions` (
+    pkg TEXT PRIMARY KEY NOT NULL,
+    description TEXT NOT NULL,
+    fetched INTEGER NOT NULL
+);
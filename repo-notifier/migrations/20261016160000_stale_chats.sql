@@ -0,0 +1,20 @@
+-- Results of the periodic checks of the subscribed chats
+CREATE TABLE IF NOT EXISTS `chat_health` (
+    chat_id INTEGER PRIMARY KEY NOT NULL,
+    -- Time of the last check (UNIX timestamp)
+    checked INTEGER NOT NULL,
+    -- Time of the first failed check since the last successful one
+    failing_since INTEGER,
+    last_error TEXT
+);
+-- Changes of the subscriptions, e.g. the automatic unsubscriptions
+CREATE TABLE IF NOT EXISTS `audit` (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    -- Who made the change, `bot` for the automatic ones
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    detail TEXT
+);
+CREATE INDEX IF NOT EXISTS audit_chat ON audit (chat_id);
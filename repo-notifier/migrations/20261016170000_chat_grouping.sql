@@ -0,0 +1,2 @@
+-- Grouping of the updates in the messages (NULL: the default in the configuration)
+ALTER TABLE `chat_settings` ADD COLUMN grouping TEXT;
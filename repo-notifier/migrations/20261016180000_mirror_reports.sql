@@ -0,0 +1,14 @@
+-- Mirror issues reported by the users, the repeated reports are counted in the open one
+CREATE TABLE IF NOT EXISTS `mirror_reports` (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    mirror TEXT NOT NULL,
+    -- Chat and details of the first report
+    chat_id INTEGER NOT NULL,
+    details TEXT NOT NULL,
+    first_reported INTEGER NOT NULL,
+    last_reported INTEGER NOT NULL,
+    reports INTEGER NOT NULL DEFAULT 1,
+    -- Time the report was resolved by an operator (UNIX timestamp)
+    resolved INTEGER
+);
+CREATE INDEX IF NOT EXISTS mirror_reports_mirror ON mirror_reports (mirror);
@@ -0,0 +1,2 @@
+-- IANA time zone of the chat (NULL: UTC)
+ALTER TABLE `chat_settings` ADD COLUMN timezone TEXT;
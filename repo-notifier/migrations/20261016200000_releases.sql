@@ -0,0 +1,8 @@
+-- Whether the chat is told about the new releases of AOSC OS (/releases)
+ALTER TABLE `chat_settings` ADD COLUMN releases INTEGER NOT NULL DEFAULT 0;
+-- Tarballs and images of the releases already seen in the manifests of repo-manifest
+CREATE TABLE IF NOT EXISTS `releases` (
+    path TEXT PRIMARY KEY NOT NULL,
+    -- When it was first seen (UNIX timestamp)
+    seen INTEGER NOT NULL
+);
@@ -0,0 +1,9 @@
+-- The last chunk of the updates delivered to each chat, by the sequence number of the message
+-- of p-vector the updates came in
+CREATE TABLE IF NOT EXISTS `delivery_receipts` (
+    chat_id INTEGER PRIMARY KEY NOT NULL,
+    sequence INTEGER NOT NULL,
+    chunk INTEGER NOT NULL,
+    -- When it was delivered (UNIX timestamp)
+    delivered INTEGER NOT NULL
+);
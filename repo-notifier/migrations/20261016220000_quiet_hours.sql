@@ -0,0 +1,2 @@
+-- Daily window when the updates are held for the chat, in its time zone (e.g. `22:00-07:00`)
+ALTER TABLE `chat_settings` ADD COLUMN quiet_hours TEXT;
@@ -0,0 +1,2 @@
+-- Whether the chat chose its minimum severity with /severity, only then it gets the operational alerts
+ALTER TABLE `subbed` ADD COLUMN severity_chosen BOOLEAN NOT NULL DEFAULT 0;
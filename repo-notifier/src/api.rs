@@ -0,0 +1,161 @@
+//! HTTP API of the notifier (enabled with `API_LISTEN`), giving the web dashboards the same
+//! updates the subscribers receive, along with the dashboard of the notifier itself
+use actix_web::{get, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+
+use crate::audit;
+use crate::history::{self, Event};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Only return the events after this cursor
+    since: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct UpdatesQuery {
+    pkg: Option<String>,
+    arch: Option<String>,
+    /// Only return the updates recorded at or after this UNIX timestamp
+    since: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    chat_id: Option<i64>,
+    limit: Option<i64>,
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Events {
+    /// Cursor to poll the next events with
+    cursor: Option<i64>,
+    events: Vec<Event>,
+}
+
+/// Cursor to poll the events after the given ones with, the same cursor when there is nothing new
+fn next_cursor(since: Option<i64>, events: &[Event]) -> Option<i64> {
+    events.last().map(|e| e.id).or(since)
+}
+
+#[get("/api/v1/events")]
+async fn list_events(
+    pool: web::Data<SqlitePool>,
+    query: web::Query<EventsQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    match history::since(&pool, query.since, limit).await {
+        Ok(events) => HttpResponse::Ok().json(Events {
+            cursor: next_cursor(query.since, &events),
+            events,
+        }),
+        Err(e) => {
+            log::error!("Could not read the history: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Version transitions of the packages, e.g. for the "recently updated" widgets of
+/// packages.aosc.io
+#[get("/api/updates")]
+async fn list_updates(
+    pool: web::Data<SqlitePool>,
+    query: web::Query<UpdatesQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let pkg = query.pkg.as_deref().filter(|p| !p.is_empty());
+    let arch = query.arch.as_deref().filter(|a| !a.is_empty());
+    match history::updates(&pool, pkg, arch, query.since.unwrap_or(0), limit).await {
+        Ok(updates) => HttpResponse::Ok().json(updates),
+        Err(e) => {
+            log::error!("Could not read the history: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes of the subscriptions, for the operators (with the token of the dashboard)
+#[get("/api/audit")]
+async fn list_audit(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    if !crate::dashboard::is_admin(&req, query.token.as_deref()) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    match audit::entries(&pool, query.chat_id, limit).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => {
+            log::error!("Could not read the audit log: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Current probabilities of the injected faults
+#[cfg(feature = "chaos")]
+#[get("/api/v1/chaos")]
+async fn get_chaos() -> impl Responder {
+    crate::chaos::CHAOS.describe()
+}
+
+/// Replace the probabilities of the injected faults, the body is in the format of
+/// `CHAOS_FAULTS`
+#[cfg(feature = "chaos")]
+#[actix_web::put("/api/v1/chaos")]
+async fn put_chaos(spec: String) -> impl Responder {
+    match crate::chaos::CHAOS.set(&spec) {
+        Ok(described) => HttpResponse::Ok().body(described),
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
+    }
+}
+
+/// Serve the API on the given address
+pub async fn serve(listen: &str, pool: SqlitePool) -> anyhow::Result<()> {
+    log::info!("Serving the API on {}.", listen);
+    HttpServer::new(move || {
+        let app = App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(list_events)
+            .service(list_updates)
+            .service(list_audit)
+            .service(crate::dashboard::dashboard)
+            .service(crate::dashboard::subscribers);
+        #[cfg(feature = "chaos")]
+        let app = app.service(get_chaos).service(put_chaos);
+        app
+    })
+    .bind(listen)?
+    .run()
+    .await?;
+
+    Ok(())
+}
+
+#[test]
+fn test_next_cursor() {
+    let event = |id| Event {
+        id,
+        timestamp: 0,
+        repo: None,
+        comp: "stable".to_string(),
+        pkg: "gtk-3".to_string(),
+        arch: "amd64".to_string(),
+        method: "^".to_string(),
+        from_ver: None,
+        to_ver: None,
+    };
+    assert_eq!(next_cursor(Some(3), &[event(4), event(7)]), Some(7));
+    assert_eq!(next_cursor(Some(3), &[]), Some(3));
+    assert_eq!(next_cursor(None, &[]), None);
+}
@@ -0,0 +1,94 @@
+//! Log of the changes of the subscriptions (`/start`, `/stop`, the filters and the automatic
+//! unsubscriptions), to find out why a chat stopped getting the updates
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{query, query_as, sqlite::SqlitePool};
+use teloxide::types::User;
+
+use crate::snooze;
+
+/// Actor of the automatic changes
+pub const BOT: &str = "bot";
+
+/// A change as recorded in the log
+#[derive(Serialize, Debug)]
+pub struct Entry {
+    pub timestamp: i64,
+    pub chat_id: i64,
+    pub actor: String,
+    pub action: String,
+    pub detail: Option<String>,
+}
+
+/// Name the user making the change, e.g. `@username (12345)`
+pub fn actor(user: Option<&User>) -> String {
+    match user {
+        Some(User {
+            id,
+            username: Some(username),
+            ..
+        }) => format!("@{} ({})", username, id),
+        Some(user) => user.id.to_string(),
+        // sent on behalf of a channel
+        None => "channel".to_string(),
+    }
+}
+
+/// Record a change of the subscription of the chat
+pub async fn record(
+    pool: &SqlitePool,
+    chat_id: i64,
+    actor: &str,
+    action: &str,
+    detail: Option<&str>,
+) -> Result<()> {
+    let timestamp = snooze::now();
+    query!(
+        "INSERT INTO audit (timestamp, chat_id, actor, action, detail) VALUES (?, ?, ?, ?, ?)",
+        timestamp,
+        chat_id,
+        actor,
+        action,
+        detail
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the latest changes, of the given chat only if any, newest first
+pub async fn entries(pool: &SqlitePool, chat_id: Option<i64>, limit: i64) -> Result<Vec<Entry>> {
+    let entries = query_as!(
+        Entry,
+        "SELECT timestamp, chat_id, actor, action, detail FROM audit
+        WHERE (? IS NULL OR chat_id = ?) ORDER BY id DESC LIMIT ?",
+        chat_id,
+        chat_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+#[test]
+fn test_actor() {
+    use teloxide::types::UserId;
+
+    let mut user = User {
+        id: UserId(12345),
+        is_bot: false,
+        first_name: "Mingcong".to_string(),
+        last_name: None,
+        username: None,
+        language_code: None,
+        is_premium: false,
+        added_to_attachment_menu: false,
+    };
+    assert_eq!(actor(Some(&user)), "12345");
+    user.username = Some("aosc".to_string());
+    assert_eq!(actor(Some(&user)), "@aosc (12345)");
+    assert_eq!(actor(None), "channel");
+}
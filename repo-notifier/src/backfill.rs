@@ -0,0 +1,127 @@
+//! Backfill of the updates published while the notifier was down: on startup, the gap between
+//! the newest update in the history and the last write of the `last_update` file is fetched
+//! from p-vector (`[backfill]` in the configuration)
+use anyhow::Result;
+use notifier_core::PVMessage;
+use once_cell::sync::Lazy;
+use sqlx::sqlite::SqlitePool;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::{
+    config,
+    history::{self, Event},
+};
+
+/// Most updates of the history checked against the fetched ones
+const RECORDED_LIMIT: i64 = 10000;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Interval (UNIX timestamps, both included) of the missed updates after the newest one, if the
+/// gap is long enough; nothing is missed without any update in the history to start from
+fn gap(newest: Option<i64>, refreshed: i64, min_gap: i64) -> Option<(i64, i64)> {
+    let newest = newest?;
+    if refreshed - newest < min_gap {
+        return None;
+    }
+
+    Some((newest + 1, refreshed))
+}
+
+/// Drop the fetched updates already in the history, e.g. published in the same second as the
+/// newest one
+fn unseen(updates: Vec<PVMessage>, recorded: &[Event]) -> Vec<PVMessage> {
+    updates
+        .into_iter()
+        .filter(|p| {
+            let method = (p.method.as_new_type() as char).to_string();
+            !recorded.iter().any(|e| {
+                e.pkg == p.pkg
+                    && e.arch == p.arch
+                    && e.comp == p.comp
+                    && e.method == method
+                    && e.to_ver == p.to_ver
+            })
+        })
+        .collect()
+}
+
+/// When the file was last written (UNIX timestamp)
+fn modified(path: &str) -> Result<i64> {
+    let modified = std::fs::metadata(path)?.modified()?;
+
+    Ok(modified.duration_since(UNIX_EPOCH)?.as_secs() as i64)
+}
+
+/// Fetch the updates published since the newest one in the history, if the repository was
+/// refreshed (`last_update` written) long enough after it
+pub async fn missed(pool: &SqlitePool, last_update: &str) -> Result<Vec<PVMessage>> {
+    let config = &config::get().backfill;
+    if config.url.is_empty() {
+        return Ok(Vec::new());
+    }
+    let newest = history::newest(pool).await?;
+    let (from, to) = match gap(newest, modified(last_update)?, config.min_gap_minutes * 60) {
+        Some(gap) => gap,
+        None => return Ok(Vec::new()),
+    };
+    log::info!("Fetching the updates between {} and {}...", from, to);
+    let updates: Vec<PVMessage> = CLIENT
+        .get(config.url_of(from, to))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let recorded = history::updates(pool, None, None, from - 1, RECORDED_LIMIT).await?;
+
+    Ok(unseen(updates, &recorded))
+}
+
+#[test]
+fn test_gap() {
+    assert_eq!(gap(None, 1000, 600), None);
+    assert_eq!(gap(Some(500), 1000, 600), None);
+    assert_eq!(gap(Some(300), 1000, 600), Some((301, 1000)));
+    // refreshed before the last update was sent
+    assert_eq!(gap(Some(1200), 1000, 600), None);
+    assert_eq!(
+        config::Backfill {
+            url: "http://localhost/updates?from={from}&to={to}".to_string(),
+            min_gap_minutes: 10,
+        }
+        .url_of(300, 1000),
+        "http://localhost/updates?from=300&to=1000"
+    );
+}
+
+#[test]
+fn test_unseen() {
+    let event = |pkg: &str, to_ver: &str| Event {
+        id: 1,
+        timestamp: 300,
+        repo: None,
+        comp: "stable".to_string(),
+        pkg: pkg.to_string(),
+        arch: "amd64".to_string(),
+        method: "+".to_string(),
+        from_ver: None,
+        to_ver: Some(to_ver.to_string()),
+    };
+    let updates = vec![
+        PVMessage::sample("bash", "amd64", b'+', None, Some("5.2")),
+        PVMessage::sample("zsh", "amd64", b'+', None, Some("5.9")),
+        PVMessage::sample("bash", "arm64", b'+', None, Some("5.2")),
+    ];
+    let unseen = unseen(updates, &[event("bash", "5.2"), event("zsh", "5.8")]);
+    let unseen = unseen
+        .iter()
+        .map(|p| (p.pkg.as_str(), p.arch.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(unseen, [("zsh", "amd64"), ("bash", "arm64")]);
+}
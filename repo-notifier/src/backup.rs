@@ -0,0 +1,235 @@
+//! Export and import of the subscriptions as JSON, for moving the bot between hosts or
+//! databases (`repository-notifier export [FILE]` and `repository-notifier import FILE`)
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{query, sqlite::SqlitePool};
+use std::collections::BTreeMap;
+
+use crate::audit;
+
+const BACKUP_VERSION: usize = 1;
+/// Actor of the imported subscriptions in the audit log
+const ACTOR: &str = "import";
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Settings {
+    pub lang: String,
+    pub format: String,
+    pub template_header: Option<String>,
+    pub template_line: Option<String>,
+    pub template_footer: Option<String>,
+    pub rate_limit: Option<i64>,
+    pub snoozed_until: Option<i64>,
+    pub snoozed_updates: i64,
+    pub grouping: Option<String>,
+    pub timezone: Option<String>,
+    pub quiet_hours: Option<String>,
+    /// Whether the chat is told about the new releases
+    #[serde(default)]
+    pub releases: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: String,
+    pub value: String,
+}
+
+/// Everything stored for a chat
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Chat {
+    pub chat_id: i64,
+    /// Minimum severity level if subscribed
+    pub subscribed: Option<i64>,
+    /// Whether the minimum severity was chosen with /severity
+    #[serde(default)]
+    pub severity_chosen: bool,
+    pub settings: Option<Settings>,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub mutes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Backup {
+    pub version: usize,
+    pub chats: Vec<Chat>,
+}
+
+impl Backup {
+    pub fn parse(data: &str) -> Result<Self> {
+        let backup: Backup = serde_json::from_str(data)?;
+        if backup.version > BACKUP_VERSION {
+            return Err(anyhow!(
+                "Unsupported backup version {} (expected {} or older)",
+                backup.version,
+                BACKUP_VERSION
+            ));
+        }
+
+        Ok(backup)
+    }
+}
+
+/// Dump the subscriptions, settings, filters and mutes of all the chats
+pub async fn export(pool: &SqlitePool) -> Result<Backup> {
+    let mut chats: BTreeMap<i64, Chat> = BTreeMap::new();
+    for row in
+        query!(r#"SELECT chat_id, lvl, severity_chosen AS "severity_chosen: bool" FROM subbed"#)
+            .fetch_all(pool)
+            .await?
+    {
+        let chat = entry(&mut chats, row.chat_id);
+        chat.subscribed = Some(row.lvl);
+        chat.severity_chosen = row.severity_chosen;
+    }
+    for row in query!(
+        r#"SELECT chat_id, lang, format, template_header, template_line, template_footer,
+        rate_limit, snoozed_until, snoozed_updates, grouping, timezone,
+        quiet_hours, releases AS "releases: bool" FROM chat_settings"#
+    )
+    .fetch_all(pool)
+    .await?
+    {
+        entry(&mut chats, row.chat_id).settings = Some(Settings {
+            lang: row.lang,
+            format: row.format,
+            template_header: row.template_header,
+            template_line: row.template_line,
+            template_footer: row.template_footer,
+            rate_limit: row.rate_limit,
+            snoozed_until: row.snoozed_until,
+            snoozed_updates: row.snoozed_updates,
+            grouping: row.grouping,
+            timezone: row.timezone,
+            quiet_hours: row.quiet_hours,
+            releases: row.releases,
+        });
+    }
+    for row in query!("SELECT chat_id, kind, value FROM filters ORDER BY kind, value")
+        .fetch_all(pool)
+        .await?
+    {
+        entry(&mut chats, row.chat_id).filters.push(Filter {
+            kind: row.kind,
+            value: row.value,
+        });
+    }
+    for row in query!("SELECT chat_id, pkg FROM mutes ORDER BY pkg")
+        .fetch_all(pool)
+        .await?
+    {
+        entry(&mut chats, row.chat_id).mutes.push(row.pkg);
+    }
+
+    Ok(Backup {
+        version: BACKUP_VERSION,
+        chats: chats.into_values().collect(),
+    })
+}
+
+#[inline]
+fn entry(chats: &mut BTreeMap<i64, Chat>, chat_id: i64) -> &mut Chat {
+    chats.entry(chat_id).or_insert_with(|| Chat {
+        chat_id,
+        ..Default::default()
+    })
+}
+
+/// Restore the chats in the backup, replacing whatever is stored for them
+pub async fn import(pool: &SqlitePool, backup: &Backup) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for chat in backup.chats.iter() {
+        let chat_id = chat.chat_id;
+        query!("DELETE FROM subbed WHERE chat_id = ?", chat_id)
+            .execute(&mut *tx)
+            .await?;
+        query!("DELETE FROM chat_settings WHERE chat_id = ?", chat_id)
+            .execute(&mut *tx)
+            .await?;
+        query!("DELETE FROM filters WHERE chat_id = ?", chat_id)
+            .execute(&mut *tx)
+            .await?;
+        query!("DELETE FROM mutes WHERE chat_id = ?", chat_id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(lvl) = chat.subscribed {
+            query!(
+                "INSERT INTO subbed (chat_id, lvl, severity_chosen) VALUES (?, ?, ?)",
+                chat_id,
+                lvl,
+                chat.severity_chosen
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        if let Some(s) = &chat.settings {
+            query!(
+                "INSERT INTO chat_settings (chat_id, lang, format, template_header, template_line,
+                template_footer, rate_limit, snoozed_until, snoozed_updates, grouping, timezone,
+                quiet_hours, releases)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                chat_id,
+                s.lang,
+                s.format,
+                s.template_header,
+                s.template_line,
+                s.template_footer,
+                s.rate_limit,
+                s.snoozed_until,
+                s.snoozed_updates,
+                s.grouping,
+                s.timezone,
+                s.quiet_hours,
+                s.releases
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        for filter in chat.filters.iter() {
+            query!(
+                "INSERT INTO filters (chat_id, kind, value) VALUES (?, ?, ?)",
+                chat_id,
+                filter.kind,
+                filter.value
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        for pkg in chat.mutes.iter() {
+            query!(
+                "INSERT INTO mutes (chat_id, pkg) VALUES (?, ?)",
+                chat_id,
+                pkg
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await?;
+    for chat in backup.chats.iter() {
+        audit::record(pool, chat.chat_id, ACTOR, "import", None).await?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_backup() {
+    let backup = r#"{"version":1,"chats":[{"chat_id":-100,"subscribed":2,"settings":null,"mutes":["gtk-3"]}]}"#;
+    let backup = Backup::parse(backup).unwrap();
+    assert_eq!(
+        backup.chats,
+        vec![Chat {
+            chat_id: -100,
+            subscribed: Some(2),
+            // older backups do not tell, the alerts are opt-in
+            severity_chosen: false,
+            settings: None,
+            filters: Vec::new(),
+            mutes: vec!["gtk-3".to_string()],
+        }]
+    );
+    assert!(Backup::parse(r#"{"version":2,"chats":[]}"#).is_err());
+}
@@ -0,0 +1,78 @@
+//! Extra bots sharing the outgoing messages (`TELOXIDE_EXTRA_TOKENS`, comma-separated), so that
+//! huge batches stay under the global limit of each bot. Each chat is always served by the same
+//! bot, which must be able to post there, the main bot takes over if it can not (e.g. the group
+//! moved to a supergroup without it). Only the main bot receives the updates, so the messages
+//! with buttons are always sent by it.
+//!
+//! All the bots talk to the Bot API server in `TELOXIDE_API_URL` if set, e.g. a local one or
+//! a mock for testing.
+use once_cell::sync::{Lazy, OnceCell};
+use teloxide::{types::ChatId, Bot};
+
+static EXTRA_BOTS: OnceCell<Vec<Bot>> = OnceCell::new();
+
+static API_URL: Lazy<Option<reqwest::Url>> = Lazy::new(|| {
+    let url = std::env::var("TELOXIDE_API_URL").ok()?;
+    match reqwest::Url::parse(&url) {
+        Ok(url) => {
+            log::info!("Using the Bot API server at {}.", url);
+            Some(url)
+        }
+        Err(e) => {
+            log::error!("Invalid TELOXIDE_API_URL {:?}: {}", url, e);
+            None
+        }
+    }
+});
+
+/// Point the bot to the Bot API server in `TELOXIDE_API_URL`, if any
+pub fn with_api_url(bot: Bot) -> Bot {
+    match API_URL.as_ref() {
+        Some(url) => bot.set_api_url(url.clone()),
+        None => bot,
+    }
+}
+
+/// Set up the extra bots from the environment
+pub fn init() {
+    let bots = std::env::var("TELOXIDE_EXTRA_TOKENS")
+        .map(|tokens| {
+            tokens
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(|t| with_api_url(Bot::new(t)))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if !bots.is_empty() {
+        log::info!("Sharding the messages across {} bots.", bots.len() + 1);
+    }
+    EXTRA_BOTS.set(bots).ok();
+}
+
+/// Index of the bot serving the chat among `count` bots (0 is the main bot)
+fn shard(chat_id: ChatId, count: usize) -> usize {
+    chat_id.0.rem_euclid(count as i64) as usize
+}
+
+/// Get the extra bot serving the chat, `None` if it is the main bot
+pub fn for_chat(chat_id: ChatId) -> Option<Bot> {
+    let extra = EXTRA_BOTS.get().map(|b| b.as_slice()).unwrap_or_default();
+    match shard(chat_id, extra.len() + 1) {
+        0 => None,
+        i => Some(extra[i - 1].clone()),
+    }
+}
+
+#[test]
+fn test_shard() {
+    assert_eq!(shard(ChatId(7), 1), 0);
+    assert_eq!(shard(ChatId(7), 3), 1);
+    // supergroups, groups and private chats are spread evenly
+    let mut counts = [0; 4];
+    for id in (0..100).flat_map(|i| [-1001234567890 - i, -4000000 - i, 12345 + i]) {
+        counts[shard(ChatId(id), 4)] += 1;
+    }
+    assert!(counts.iter().all(|c| (70..=80).contains(c)), "{:?}", counts);
+}
@@ -0,0 +1,72 @@
+//! Buttons under the updates to manage the subscription without remembering the commands
+use std::time::Duration;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::i18n::{tr, Text};
+use crate::snooze;
+
+/// How long the "Mute 1 day" button snoozes the chat, as shown to the users
+pub const MUTE_FOR: &str = "1d";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Mute,
+    Settings,
+    Unsubscribe,
+}
+
+const ACTIONS: [(Action, &str, Text); 3] = [
+    (Action::Mute, "mute", Text::ButtonMute),
+    (Action::Settings, "settings", Text::ButtonSettings),
+    (Action::Unsubscribe, "stop", Text::ButtonUnsubscribe),
+];
+
+/// Length of the snooze of the "Mute 1 day" button
+pub fn mute_duration() -> Duration {
+    snooze::parse_duration(MUTE_FOR).expect("Invalid MUTE_FOR")
+}
+
+/// Row of the buttons, to go under the other buttons of a message
+pub fn row(lang: &str) -> Vec<InlineKeyboardButton> {
+    ACTIONS
+        .iter()
+        .map(|(_, name, text)| {
+            InlineKeyboardButton::callback(tr(lang, *text), format!("manage:{}", name))
+        })
+        .collect()
+}
+
+/// Inline keyboard with only the buttons
+pub fn keyboard(lang: &str) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([row(lang)])
+}
+
+/// Parse the callback data of the buttons
+pub fn parse_callback(data: &str) -> Option<Action> {
+    let name = data.strip_prefix("manage:")?;
+
+    ACTIONS
+        .iter()
+        .find(|(_, n, _)| *n == name)
+        .map(|(action, _, _)| *action)
+}
+
+#[test]
+fn test_buttons() {
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    let row = row("en");
+    assert_eq!(row.len(), ACTIONS.len());
+    assert_eq!(row[0].text, "🔕 Mute 1 day");
+    for (button, (action, _, _)) in row.iter().zip(ACTIONS.iter()) {
+        match &button.kind {
+            InlineKeyboardButtonKind::CallbackData(data) => {
+                assert_eq!(parse_callback(data), Some(*action))
+            }
+            kind => panic!("Unexpected button {:?}", kind),
+        }
+    }
+    assert_eq!(parse_callback("more:1:2"), None);
+    assert_eq!(parse_callback("manage:unknown"), None);
+    assert_eq!(mute_duration(), Duration::from_secs(24 * 3600));
+}
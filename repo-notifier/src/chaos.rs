@@ -0,0 +1,69 @@
+//! Failure injection for the resilience tests, only built with the `chaos` feature.
+//!
+//! `CHAOS_FAULTS` sets the probability of each fault, e.g.
+//! `telegram_429=0.2,telegram_500=0.1,disconnect=0.01,corrupt=0.05`, it can be changed at
+//! runtime with `PUT /api/v1/chaos` if the API is enabled.
+use once_cell::sync::Lazy;
+use repokit_common::chaos::{self, Chaos};
+use teloxide::{types::Seconds, ApiError, RequestError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fault {
+    /// Telegram asks to retry the request later
+    RateLimited,
+    /// Telegram fails with an internal error
+    ServerError,
+    /// The Redis subscription is lost
+    Disconnect,
+    /// The message from p-vector is mangled
+    Corrupt,
+}
+
+impl chaos::Fault for Fault {
+    const FAULTS: &'static [(&'static str, Self)] = &[
+        ("telegram_429", Fault::RateLimited),
+        ("telegram_500", Fault::ServerError),
+        ("disconnect", Fault::Disconnect),
+        ("corrupt", Fault::Corrupt),
+    ];
+}
+
+pub static CHAOS: Lazy<Chaos<Fault>> = Lazy::new(Chaos::from_env);
+
+/// Whether the fault should happen this time
+pub fn inject(fault: Fault) -> bool {
+    CHAOS.inject(fault)
+}
+
+/// A simulated failure of the Telegram request, if any
+pub fn telegram_error() -> Option<RequestError> {
+    if inject(Fault::RateLimited) {
+        return Some(RequestError::RetryAfter(Seconds::from_seconds(1)));
+    }
+    if inject(Fault::ServerError) {
+        return Some(RequestError::Api(ApiError::Unknown(
+            "Internal Server Error (injected)".to_string(),
+        )));
+    }
+
+    None
+}
+
+/// Mangle the payload if a corrupt message should be simulated
+pub fn corrupt(mut payload: Vec<u8>) -> Vec<u8> {
+    if inject(Fault::Corrupt) {
+        payload.truncate(payload.len() / 2);
+        payload.iter_mut().for_each(|b| *b = b.rotate_left(3));
+    }
+
+    payload
+}
+
+#[test]
+fn test_faults() {
+    let chaos = Chaos::<Fault>::new("telegram_429=0.5, corrupt=1").unwrap();
+    assert_eq!(chaos.describe(), "telegram_429=0.5,corrupt=1");
+    assert!(chaos.inject(Fault::Corrupt));
+    assert!(!chaos.inject(Fault::Disconnect));
+    assert!(Chaos::<Fault>::new("slow_reload=0.1").is_err());
+}
@@ -0,0 +1,931 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::{str::FromStr, time::Duration};
+
+use crate::filter::{Filters, Kind};
+use crate::{
+    format::Format, grouping::Grouping, schedule::Schedule, severity::Severity, sinks::Sink,
+};
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// Optional configuration file of the notifier (pointed to by `NOTIFIER_CONFIG`)
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub priority: PriorityPolicy,
+    pub batching: Batching,
+    pub severity: SeverityRouting,
+    pub repositories: Vec<Repository>,
+    pub rate_limit: RateLimit,
+    pub security: Security,
+    pub maintainers: Maintainers,
+    pub descriptions: Descriptions,
+    pub versions: Versions,
+    pub changelog: Changelog,
+    pub backfill: Backfill,
+    pub stale: StaleChats,
+    pub retention: Retention,
+    pub schedule: Jobs,
+    pub mirror_reports: MirrorReports,
+    pub releases: Releases,
+    pub arch_groups: ArchGroups,
+    pub sinks: Vec<Sink>,
+    pub database: Database,
+    pub alerts: Alerts,
+    pub channels: Vec<Channel>,
+}
+
+/// A chat listed in `[[channels]]`, notified whether it is subscribed or not (e.g. the official
+/// announcement channels), its settings in the database are not used
+#[derive(Deserialize, Debug)]
+pub struct Channel {
+    pub chat_id: i64,
+    /// Minimum severity of the messages, the updates are `routine`
+    #[serde(default = "default_channel_severity")]
+    pub severity: Severity,
+    #[serde(default = "default_channel_lang")]
+    pub lang: String,
+    #[serde(default)]
+    pub format: Format,
+    /// Only send the updates of these components, architectures, repositories and
+    /// architecture groups (all of them if empty)
+    #[serde(default)]
+    pub comps: Vec<String>,
+    #[serde(default)]
+    pub arches: Vec<String>,
+    #[serde(default)]
+    pub repos: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+fn default_channel_severity() -> Severity {
+    Severity::Routine
+}
+
+fn default_channel_lang() -> String {
+    crate::i18n::DEFAULT_LANG.to_string()
+}
+
+impl Config {
+    /// Whether the buttons managing the subscription are attached to the updates sent to the
+    /// chat, never in the `[[channels]]` as they are not managed by subscribing
+    pub fn buttons(&self, chat_id: i64) -> bool {
+        self.batching.buttons && !self.channels.iter().any(|c| c.chat_id == chat_id)
+    }
+}
+
+impl Channel {
+    pub fn filters(&self) -> Filters {
+        let mut filters = Filters::default();
+        for (kind, values) in [
+            (Kind::Comp, &self.comps),
+            (Kind::Arch, &self.arches),
+            (Kind::Repo, &self.repos),
+            (Kind::Group, &self.groups),
+        ] {
+            for value in values {
+                filters.add(kind, value.clone());
+            }
+        }
+
+        filters
+    }
+}
+
+/// Alert fired to a webhook when too many of the recent sends to Telegram fail
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Alerts {
+    /// URL the alert is posted to as JSON (empty: disabled)
+    pub webhook: String,
+    /// Fraction of the failed sends in the window to fire the alert at
+    pub failure_rate: f64,
+    pub window_minutes: i64,
+    /// Fewer sends in the window are not enough to tell
+    pub min_sends: usize,
+    /// Minutes before the alert can be fired again
+    pub cooldown_minutes: i64,
+}
+
+impl Alerts {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.failure_rate > 0.0 && self.failure_rate <= 1.0) {
+            return Err(anyhow!(
+                "failure_rate must be between 0 and 1, got {}",
+                self.failure_rate
+            ));
+        }
+        if self.window_minutes < 1 {
+            return Err(anyhow!("window_minutes must be positive"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Alerts {
+            webhook: String::new(),
+            failure_rate: 0.2,
+            window_minutes: 10,
+            min_sends: 20,
+            cooldown_minutes: 60,
+        }
+    }
+}
+
+/// Options of the SQLite database in `DATABASE_URL`
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Database {
+    /// Use the write-ahead log, so that reading does not block writing
+    pub wal: bool,
+    /// Milliseconds to wait for a locked database before giving up
+    pub busy_timeout_ms: u64,
+    pub max_connections: u32,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Database {
+            wal: true,
+            busy_timeout_ms: 5000,
+            max_connections: 5,
+        }
+    }
+}
+
+impl Database {
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=100).contains(&self.max_connections) {
+            return Err(anyhow!(
+                "max_connections must be between 1 and 100, got {}",
+                self.max_connections
+            ));
+        }
+        if self.busy_timeout_ms > 600_000 {
+            return Err(anyhow!(
+                "busy_timeout_ms must not exceed 10 minutes, got {}",
+                self.busy_timeout_ms
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Open the database with these options
+    pub async fn connect(&self, url: &str) -> Result<SqlitePool> {
+        let mut options = SqliteConnectOptions::from_str(url)?
+            .busy_timeout(Duration::from_millis(self.busy_timeout_ms));
+        if self.wal {
+            options = options
+                .journal_mode(SqliteJournalMode::Wal)
+                .synchronous(SqliteSynchronous::Normal);
+        }
+        let pool = SqlitePoolOptions::new()
+            .max_connections(self.max_connections)
+            .connect_with(options)
+            .await?;
+
+        Ok(pool)
+    }
+}
+
+/// Schedules of the periodic jobs
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Jobs {
+    /// Resuming the chats whose snooze has ended
+    pub snooze: Schedule,
+    /// Checking a few of the subscribed chats for the stale ones
+    pub stale_chats: Schedule,
+    /// Pruning the old history and audit entries and compacting the database
+    pub maintenance: Schedule,
+    /// Looking for the new releases in the manifests
+    pub releases: Schedule,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Jobs {
+            snooze: "* * * * *".parse().unwrap(),
+            stale_chats: "*/10 * * * *".parse().unwrap(),
+            maintenance: "0 4 * * 0".parse().unwrap(),
+            releases: "*/10 * * * *".parse().unwrap(),
+        }
+    }
+}
+
+/// Automatic cleanup of the subscribed chats the bot can no longer reach
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct StaleChats {
+    pub enabled: bool,
+    /// Each chat is checked once in this many days (the checks are spread over the period)
+    pub check_days: i64,
+    /// Chats failing the checks for this many days are unsubscribed
+    pub grace_days: i64,
+}
+
+impl Default for StaleChats {
+    fn default() -> Self {
+        StaleChats {
+            enabled: false,
+            check_days: 7,
+            grace_days: 14,
+        }
+    }
+}
+
+/// How long the entries are kept in the database, pruned by the maintenance job (0: forever)
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Retention {
+    pub history_days: i64,
+    pub audit_days: i64,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            history_days: 365,
+            audit_days: 730,
+        }
+    }
+}
+
+/// New releases of AOSC OS announced to the chats opting in with /releases
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Releases {
+    /// Directory of the manifests written by repo-manifest, with `recipe.json` and
+    /// `livekit.json` (empty: disabled)
+    pub manifest_dir: String,
+}
+
+/// Mirror issues reported by the users with /reportmirror
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct MirrorReports {
+    /// Names of the mirrors that can be reported (as in the mirrors of repo-redirect),
+    /// any name is accepted if empty
+    pub mirrors: Vec<String>,
+    /// Telegram usernames (without `@`) of the mirror operators, mentioned in the reports
+    /// and allowed to resolve them with /resolvemirror
+    pub operators: Vec<String>,
+    /// Reports of a mirror with an open report are added to it for this many hours
+    pub dedup_hours: i64,
+    /// Reported mirrors are put on probation in repo-redirect for this many hours after
+    /// the last report, unless resolved earlier (0: never)
+    pub probation_hours: i64,
+    /// File the mirrors on probation are written to (`MIRROR_PROBATION` of repo-redirect)
+    pub probation_file: Option<String>,
+}
+
+impl Default for MirrorReports {
+    fn default() -> Self {
+        MirrorReports {
+            mirrors: Vec::new(),
+            operators: Vec::new(),
+            dedup_hours: 6,
+            probation_hours: 6,
+            probation_file: None,
+        }
+    }
+}
+
+impl MirrorReports {
+    /// Find the configured mirror matching the name (case-insensitive)
+    pub fn find(&self, name: &str) -> Option<String> {
+        if self.mirrors.is_empty() {
+            return Some(name.to_string());
+        }
+
+        self.mirrors
+            .iter()
+            .find(|m| m.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+}
+
+/// Links to the changes of the upgraded packages
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Changelog {
+    /// URL template, `{pkg}`, `{from}` and `{to}` are replaced with the package name and
+    /// the old and new versions (empty: no links)
+    pub url: String,
+}
+
+/// Percent-encode everything but the unreserved characters
+fn encode_component(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    encoded
+}
+
+impl Changelog {
+    pub fn url_of(&self, pkg: &str, from: &str, to: &str) -> Option<String> {
+        if self.url.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.url
+                .replace("{pkg}", &encode_component(pkg))
+                .replace("{from}", &encode_component(from))
+                .replace("{to}", &encode_component(to)),
+        )
+    }
+}
+
+/// Updates published while the notifier was down, fetched on startup
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Backfill {
+    /// URL of the updates published between two UNIX timestamps, `{from}` and `{to}` are
+    /// replaced with them (empty: disabled)
+    pub url: String,
+    /// Shorter gaps between the newest update in the history and the last refresh are ignored
+    pub min_gap_minutes: i64,
+}
+
+impl Default for Backfill {
+    fn default() -> Self {
+        Backfill {
+            url: String::new(),
+            min_gap_minutes: 10,
+        }
+    }
+}
+
+impl Backfill {
+    pub fn url_of(&self, from: i64, to: i64) -> String {
+        self.url
+            .replace("{from}", &from.to_string())
+            .replace("{to}", &to.to_string())
+    }
+}
+
+/// Short descriptions of the new packages, fetched from the packages site
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Descriptions {
+    pub enabled: bool,
+    /// URL of the JSON information of a package, `{}` is replaced with the package name
+    pub url: String,
+    /// JSON pointer to the description in the response
+    pub pointer: String,
+    /// Days before a cached description is fetched again
+    pub cache_days: u64,
+    /// Longer descriptions are truncated
+    pub max_length: usize,
+}
+
+impl Default for Descriptions {
+    fn default() -> Self {
+        Descriptions {
+            enabled: false,
+            url: "https://packages.aosc.io/packages/{}?type=json".to_string(),
+            pointer: "/pkg/description".to_string(),
+            cache_days: 30,
+            max_length: 100,
+        }
+    }
+}
+
+/// Where `/pkg` looks up the current versions of the packages
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Versions {
+    /// URL of the JSON information of a package on the packages site, `{}` is replaced with the
+    /// package name. Only the recorded updates are used if empty.
+    pub url: String,
+}
+
+impl Default for Versions {
+    fn default() -> Self {
+        Versions {
+            url: "https://packages.aosc.io/packages/{}?type=json".to_string(),
+        }
+    }
+}
+
+/// Maintainers mentioned when their packages are removed (or fail)
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Maintainers {
+    /// Telegram usernames (without `@`) of the maintainers of the packages starting with
+    /// each prefix, the longest matching prefix wins
+    pub prefixes: HashMap<String, Vec<String>>,
+}
+
+impl Maintainers {
+    pub fn of(&self, pkg: &str) -> &[String] {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| pkg.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, users)| users.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// Named groups of the architectures, chats can follow a group with `/filter group`
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+pub struct ArchGroups(BTreeMap<String, Vec<String>>);
+
+impl Default for ArchGroups {
+    fn default() -> Self {
+        let group = |arches: &[&str]| arches.iter().map(|a| a.to_string()).collect();
+        ArchGroups(BTreeMap::from([
+            (
+                "mainline".to_string(),
+                group(&[
+                    "amd64",
+                    "arm64",
+                    "loongarch64",
+                    "loongson3",
+                    "ppc64el",
+                    "riscv64",
+                ]),
+            ),
+            (
+                "retro".to_string(),
+                group(&[
+                    "armv4",
+                    "armv6hf",
+                    "armv7hf",
+                    "i486",
+                    "loongson2f",
+                    "m68k",
+                    "powerpc",
+                    "ppc64",
+                ]),
+            ),
+        ]))
+    }
+}
+
+impl ArchGroups {
+    pub fn exists(&self, group: &str) -> bool {
+        self.0.contains_key(group)
+    }
+
+    /// All the architectures in the groups
+    pub fn arches(&self) -> BTreeSet<&str> {
+        self.0.values().flatten().map(|a| a.as_str()).collect()
+    }
+
+    /// Whether the architecture is in any of the groups
+    pub fn knows(&self, arch: &str) -> bool {
+        arch == "noarch"
+            || self
+                .0
+                .values()
+                .any(|arches| arches.iter().any(|a| a == arch))
+    }
+
+    /// Whether the architecture is in the group, `noarch` is in every group
+    pub fn contains(&self, group: &str, arch: &str) -> bool {
+        arch == "noarch"
+            || self
+                .0
+                .get(group)
+                .is_some_and(|arches| arches.iter().any(|a| a == arch))
+    }
+}
+
+/// Classification of the updates carrying security fixes
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Security {
+    /// Components of the security fixes, also matched against the last part of the
+    /// component (e.g. `bsp/security`)
+    pub components: Vec<String>,
+    /// Substrings of the new version marking a security fix (e.g. `+sec`)
+    pub version_patterns: Vec<String>,
+    /// Send the security updates to the snoozed chats right away
+    pub bypass_snooze: bool,
+}
+
+impl Default for Security {
+    fn default() -> Self {
+        Security {
+            components: vec!["security".to_string()],
+            version_patterns: vec!["+sec".to_string()],
+            bypass_snooze: false,
+        }
+    }
+}
+
+impl Security {
+    pub fn is_security_fix(&self, comp: &str, to_ver: Option<&str>) -> bool {
+        let comp_matches = self
+            .components
+            .iter()
+            .any(|c| comp == c || comp.rsplit_once('/').is_some_and(|(_, last)| last == c));
+        let version_matches = to_ver.is_some_and(|v| {
+            self.version_patterns
+                .iter()
+                .any(|p| !p.is_empty() && v.contains(p.as_str()))
+        });
+
+        comp_matches || version_matches
+    }
+}
+
+/// Default limit of the messages sent to each chat
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct RateLimit {
+    /// Maximum number of messages per hour (0: unlimited), chats may override it with /ratelimit
+    pub per_hour: u32,
+}
+
+/// A p-vector instance publishing the updates of a repository
+#[derive(Deserialize, Debug)]
+pub struct Repository {
+    /// Name of the repository shown in the messages and used in the filters
+    pub name: String,
+    /// Redis endpoint of the p-vector instance
+    pub endpoint: String,
+}
+
+/// Dedicated destinations of the messages of each severity
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct SeverityRouting {
+    /// Chat IDs that receive all the messages of the given severity. Operational alerts
+    /// (warnings and critical errors) with dedicated channels are only sent to those channels.
+    pub channels: HashMap<Severity, Vec<i64>>,
+}
+
+/// How the pending updates are delivered
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Batching {
+    /// Length of the accumulation window in seconds, counted from the last update
+    pub cooldown_seconds: usize,
+    /// Maximum number of the updates in each message
+    pub max_lines: usize,
+    /// Maximum length of each message, Telegram does not take more than 4096 characters
+    pub max_length: usize,
+    /// Only send the first page of a big batch, the remaining pages are shown on demand
+    /// using the "Show more" button
+    pub paginate: bool,
+    /// Send a small batch once no update arrived for this many seconds instead of waiting
+    /// for the whole accumulation window (0: always wait)
+    pub quiet_seconds: usize,
+    /// Batches with more updates than this are considered a large run and always wait
+    /// for the whole accumulation window
+    pub small_batch: usize,
+    /// Hold each batch for this many seconds after its first update, so that the updates of
+    /// a refresh right after it (e.g. of another branch) are sent along (0: never)
+    pub combine_seconds: usize,
+    /// Start the messages of the batches with at least this many updates with a summary
+    /// of the batch (0: never)
+    pub summary_threshold: usize,
+    /// Collapse the package rebuilds (only the release changed) in the same component and
+    /// architecture into a single line when there are at least this many of them (0: never)
+    pub rebuild_threshold: usize,
+    /// Send a line for each component with the number of the updates instead of the updates
+    /// when a batch has more than this many of them, e.g. during a mass rebuild (0: never)
+    pub flood_threshold: usize,
+    /// Full listing of the updates linked from the message sent instead of a flood
+    pub flood_url: String,
+    /// Attach the "Mute 1 day", "Settings" and "Unsubscribe" buttons to the updates, except in
+    /// the `[[channels]]`
+    pub buttons: bool,
+    /// Group the updates by component and architecture (`arch`), or by package with the
+    /// list of the architectures it changed on (`package`), unless set for the chat
+    pub group_by: Grouping,
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Batching {
+            cooldown_seconds: 20,
+            max_lines: 22,
+            // 4000 is just for the safety
+            max_length: 4000,
+            paginate: false,
+            quiet_seconds: 3,
+            small_batch: 10,
+            combine_seconds: 0,
+            summary_threshold: 5,
+            rebuild_threshold: 10,
+            flood_threshold: 500,
+            flood_url: "https://packages.aosc.io/updates".to_string(),
+            buttons: false,
+            group_by: Grouping::Arch,
+        }
+    }
+}
+
+impl Batching {
+    fn validate(&self) -> Result<()> {
+        if !(1..=3600).contains(&self.cooldown_seconds) {
+            return Err(anyhow!(
+                "cooldown_seconds must be between 1 and 3600, got {}",
+                self.cooldown_seconds
+            ));
+        }
+        if self.quiet_seconds > self.cooldown_seconds {
+            return Err(anyhow!(
+                "quiet_seconds ({}) must not exceed cooldown_seconds ({})",
+                self.quiet_seconds,
+                self.cooldown_seconds
+            ));
+        }
+        if self.combine_seconds > 3600 {
+            return Err(anyhow!(
+                "combine_seconds must not exceed 3600, got {}",
+                self.combine_seconds
+            ));
+        }
+        if !(1..=100).contains(&self.max_lines) {
+            return Err(anyhow!(
+                "max_lines must be between 1 and 100, got {}",
+                self.max_lines
+            ));
+        }
+        if !(1000..=4096).contains(&self.max_length) {
+            return Err(anyhow!(
+                "max_length must be between 1000 and 4096, got {}",
+                self.max_length
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the pending updates should be sent before the accumulation window ends
+    pub fn flush_early(&self, pending: usize, idle: usize) -> bool {
+        self.quiet_seconds > 0
+            && pending > 0
+            && pending <= self.small_batch
+            && idle >= self.quiet_seconds
+    }
+
+    /// Whether the batch started this many seconds ago is still waiting for another refresh
+    pub fn holds(&self, age: usize) -> bool {
+        age < self.combine_seconds
+    }
+
+    /// Whether a batch of this many updates is only sent as a count of each component
+    pub fn is_flood(&self, count: usize) -> bool {
+        self.flood_threshold > 0 && count > self.flood_threshold
+    }
+
+    /// Whether the messages of a batch of this many updates start with a summary
+    pub fn wants_summary(&self, count: usize) -> bool {
+        self.summary_threshold > 0 && count >= self.summary_threshold
+    }
+}
+
+/// Ordering policy of the operations in each digest
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct PriorityPolicy {
+    /// Operations (`+`, `-`, `^`, `*`, `i` and `?` for everything else) in the order they
+    /// should appear in the digest, the leading ones are sent first. The operations in the same
+    /// entry (e.g. `-i`) are ranked equally.
+    pub order: Vec<String>,
+    /// Extra priority for the updates in the given components
+    pub boost: HashMap<String, i32>,
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> Self {
+        PriorityPolicy {
+            order: ["?", "^", "*", "+", "-i"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            boost: HashMap::new(),
+        }
+    }
+}
+
+impl PriorityPolicy {
+    /// Priority of the given operation in the given component (higher ones are sent first)
+    pub fn priority_of(&self, method: u8, comp: &str) -> i32 {
+        let position = |op: u8| self.order.iter().position(|x| x.as_bytes().contains(&op));
+        let base = match position(method).or_else(|| position(b'?')) {
+            Some(index) => (self.order.len() - index) as i32,
+            None => 0,
+        };
+
+        base + self.boost.get(comp).copied().unwrap_or(0)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for op in self.order.iter() {
+            if op.is_empty() || !op.is_ascii() {
+                return Err(anyhow!("Invalid operation in the priority order: {:?}", op));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Load the configuration file specified by `NOTIFIER_CONFIG`, if any
+pub fn load() -> Result<()> {
+    let config = match std::env::var("NOTIFIER_CONFIG") {
+        Ok(path) => {
+            log::info!("Reading config from {}...", path);
+            let config: Config = toml::from_str(&std::fs::read_to_string(path)?)?;
+            config.priority.validate()?;
+            config.batching.validate()?;
+            config.database.validate()?;
+            config.alerts.validate()?;
+            for sink in config.sinks.iter() {
+                sink.validate()?;
+            }
+            config
+        }
+        Err(_) => Config::default(),
+    };
+    CONFIG
+        .set(config)
+        .map_err(|_| anyhow!("Configuration already loaded"))
+}
+
+/// Get the loaded configuration (or the default one if nothing was loaded)
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+#[test]
+fn test_changelog_url() {
+    let changelog = Changelog {
+        url: "https://example.com/{pkg}/compare/{from}...{to}".to_string(),
+    };
+    assert_eq!(
+        changelog
+            .url_of("gtk-3", "1:3.24.1", "3.24.2+sec1")
+            .as_deref(),
+        Some("https://example.com/gtk-3/compare/1%3A3.24.1...3.24.2%2Bsec1")
+    );
+    assert_eq!(Changelog::default().url_of("gtk-3", "1", "2"), None);
+}
+
+#[test]
+fn test_maintainers() {
+    let maintainers: Maintainers = toml::from_str(
+        r#"[prefixes]
+llvm = ["alice"]
+llvm-runtime = ["bob", "carol"]"#,
+    )
+    .unwrap();
+    assert_eq!(maintainers.of("llvm"), ["alice"]);
+    assert_eq!(maintainers.of("llvm-runtime-16"), ["bob", "carol"]);
+    assert!(maintainers.of("gcc").is_empty());
+}
+
+#[test]
+fn test_security_fix() {
+    let security = Security::default();
+    assert!(security.is_security_fix("security", None));
+    assert!(security.is_security_fix("bsp/security", Some("1.0")));
+    assert!(security.is_security_fix("stable", Some("1.2.3+sec1")));
+    assert!(!security.is_security_fix("security-tools", Some("1.0")));
+    assert!(!security.is_security_fix("stable", None));
+}
+
+#[test]
+fn test_priority_policy() {
+    let policy = PriorityPolicy::default();
+    let priority = |op| policy.priority_of(op, "stable");
+    assert!(priority(b'?') > priority(b'^'));
+    assert!(priority(b'^') > priority(b'*'));
+    assert!(priority(b'*') > priority(b'+'));
+    assert!(priority(b'+') > priority(b'-'));
+    // the info events rank with the deleted packages, not below them
+    assert_eq!(priority(b'i'), priority(b'-'));
+    assert_eq!(priority(b'x'), priority(b'?'));
+    let policy = PriorityPolicy {
+        order: vec!["-".to_string(), "+".to_string()],
+        boost: HashMap::from([("stable".to_string(), 5)]),
+    };
+    assert_eq!(policy.priority_of(b'-', "testing"), 2);
+    assert_eq!(policy.priority_of(b'+', "stable"), 6);
+    assert_eq!(policy.priority_of(b'^', "testing"), 0);
+    assert!(policy.validate().is_ok());
+    let policy = PriorityPolicy {
+        order: vec![String::new()],
+        ..PriorityPolicy::default()
+    };
+    assert!(policy.validate().is_err());
+}
+
+#[test]
+fn test_flush_early() {
+    let batching = Batching::default();
+    assert!(!batching.flush_early(0, 10));
+    assert!(!batching.flush_early(1, 2));
+    assert!(batching.flush_early(1, 3));
+    assert!(!batching.flush_early(11, 3));
+    let batching = Batching {
+        quiet_seconds: 0,
+        ..Batching::default()
+    };
+    assert!(!batching.flush_early(1, 10));
+    assert!(!batching.holds(0));
+    let batching = Batching {
+        combine_seconds: 300,
+        ..Batching::default()
+    };
+    assert!(batching.holds(299));
+    assert!(!batching.holds(300));
+}
+
+#[test]
+fn test_validate_batching() {
+    assert!(Batching::default().validate().is_ok());
+    let batching = |cooldown_seconds, max_lines, max_length| Batching {
+        cooldown_seconds,
+        max_lines,
+        max_length,
+        ..Batching::default()
+    };
+    assert!(batching(60, 50, 4096).validate().is_ok());
+    assert!(batching(0, 22, 4000).validate().is_err());
+    assert!(batching(2, 22, 4000).validate().is_err());
+    assert!(batching(20, 0, 4000).validate().is_err());
+    assert!(batching(20, 22, 5000).validate().is_err());
+}
+
+#[test]
+fn test_validate_database() {
+    assert!(Database::default().validate().is_ok());
+    let database: Database = toml::from_str("wal = false\nmax_connections = 0").unwrap();
+    assert!(!database.wal);
+    assert_eq!(database.busy_timeout_ms, 5000);
+    assert!(database.validate().is_err());
+}
+
+#[test]
+fn test_validate_alerts() {
+    assert!(Alerts::default().validate().is_ok());
+    let alerts: Alerts = toml::from_str("failure_rate = 1.5").unwrap();
+    assert!(alerts.validate().is_err());
+}
+
+#[test]
+fn test_channels() {
+    let config: Config = toml::from_str(
+        r#"
+        [[channels]]
+        chat_id = -1001234
+        format = "markdown"
+        comps = ["stable"]
+        arches = ["amd64", "arm64"]
+
+        [batching]
+        buttons = true
+        "#,
+    )
+    .unwrap();
+    let channel = &config.channels[0];
+    assert_eq!(channel.severity, Severity::Routine);
+    assert_eq!(channel.format, Format::Markdown);
+    assert_eq!(
+        channel.filters().to_string(),
+        "comp: stable\narch: amd64, arm64\n"
+    );
+    assert!(!config.buttons(-1001234));
+    assert!(config.buttons(-1005678));
+    assert!(!Config::default().buttons(-1005678));
+}
+
+#[test]
+fn test_arch_groups() {
+    let groups = ArchGroups::default();
+    assert!(groups.exists("retro"));
+    assert!(!groups.exists("ancient"));
+    assert!(groups.contains("retro", "i486"));
+    assert!(!groups.contains("retro", "amd64"));
+    assert!(groups.contains("retro", "noarch"));
+    assert!(!groups.contains("ancient", "i486"));
+    assert!(groups.knows("loongson3"));
+    assert!(!groups.knows("vax"));
+}
@@ -0,0 +1,183 @@
+//! Web dashboard served along with the API: the recent refreshes, a searchable history of the
+//! updates and the subscribed chats (only with the token in `DASHBOARD_TOKEN`)
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::sqlite::SqlitePool;
+use subtle::ConstantTimeEq;
+
+use crate::history::{self, Event};
+use crate::refresh::{self, Refresh};
+use crate::settings::{self, Subscriber};
+use crate::severity::Severity;
+use crate::{i18n::DEFAULT_LANG, status, summary};
+
+/// Number of the updates shown in the history
+const HISTORY_LIMIT: i64 = 200;
+
+static TEMPLATE: Lazy<Handlebars<'static>> = Lazy::new(|| {
+    let mut registry = Handlebars::new();
+    registry
+        .register_template_string("dashboard", include_str!("../assets/dashboard.hbs"))
+        .expect("Invalid dashboard template");
+    registry
+});
+
+#[derive(Deserialize)]
+struct DashboardQuery {
+    /// Part of the package names to search for
+    q: Option<String>,
+    token: Option<String>,
+}
+
+/// Format the UNIX timestamp for the tables
+fn time(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn refresh_row(refresh: &Refresh) -> serde_json::Value {
+    json!({
+        "finished": time(refresh.finished),
+        "duration": status::elapsed(refresh.duration.as_secs() as i64),
+        "updates": summary::describe(&refresh.counts, DEFAULT_LANG),
+    })
+}
+
+fn update_row(event: &Event) -> serde_json::Value {
+    let change = match (&event.from_ver, &event.to_ver) {
+        (Some(from), Some(to)) => format!("{} ⇒ {}", from, to),
+        (None, Some(ver)) | (Some(ver), None) => ver.clone(),
+        (None, None) => String::new(),
+    };
+    json!({
+        "time": time(event.timestamp),
+        "repo": event.repo,
+        "comp": event.comp,
+        "pkg": event.pkg,
+        "arch": event.arch,
+        "method": event.method,
+        "change": change,
+    })
+}
+
+fn subscriber_row(sub: &Subscriber) -> serde_json::Value {
+    let severity = Severity::from_level(sub.min_severity)
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| sub.min_severity.to_string());
+    let filters = sub.filters.to_string();
+    json!({
+        "chat_id": sub.chat_id,
+        "lang": sub.lang,
+        "severity": severity,
+        "format": sub.format.to_string(),
+        "filters": filters.trim_end().replace('\n', "; "),
+        "snoozed_until": sub.snoozed_until.filter(|_| sub.is_snoozed()).map(time),
+    })
+}
+
+/// Whether the request carries the admin token, either as `?token=` or a bearer token (compared
+/// in constant time)
+pub fn is_admin(req: &HttpRequest, token: Option<&str>) -> bool {
+    let expected = match std::env::var("DASHBOARD_TOKEN") {
+        Ok(expected) if !expected.is_empty() => expected,
+        _ => return false,
+    };
+    let bearer = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    token
+        .or(bearer)
+        .map(|given| bool::from(given.as_bytes().ct_eq(expected.as_bytes())))
+        .unwrap_or(false)
+}
+
+fn html(context: serde_json::Value) -> HttpResponse {
+    match TEMPLATE.render("dashboard", &context) {
+        Ok(page) => HttpResponse::Ok().content_type("text/html").body(page),
+        Err(e) => {
+            log::error!("Could not render the dashboard: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/")]
+async fn dashboard(
+    pool: web::Data<SqlitePool>,
+    query: web::Query<DashboardQuery>,
+) -> impl Responder {
+    let search = query.q.as_deref().unwrap_or_default().trim();
+    match history::search(&pool, search, HISTORY_LIMIT).await {
+        Ok(updates) => html(json!({
+            "refreshes": refresh::recent().iter().map(refresh_row).collect::<Vec<_>>(),
+            "query": search,
+            "updates": updates.iter().map(update_row).collect::<Vec<_>>(),
+        })),
+        Err(e) => {
+            log::error!("Could not read the history: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/subscribers")]
+async fn subscribers(
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+    query: web::Query<DashboardQuery>,
+) -> impl Responder {
+    if !is_admin(&req, query.token.as_deref()) {
+        return HttpResponse::Forbidden().finish();
+    }
+    match settings::subscribers(&pool).await {
+        Ok(subs) => html(json!({
+            "admin": true,
+            "subscribers": subs.iter().map(subscriber_row).collect::<Vec<_>>(),
+        })),
+        Err(e) => {
+            log::error!("Could not read the subscribers: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[test]
+fn test_dashboard() {
+    use std::time::Duration;
+
+    let refresh = Refresh {
+        finished: 86400,
+        duration: Duration::from_secs(75),
+        counts: [2, 0, 1, 0, 0],
+    };
+    let event = Event {
+        id: 1,
+        timestamp: 0,
+        repo: None,
+        comp: "stable".to_string(),
+        pkg: "<gtk-3>".to_string(),
+        arch: "amd64".to_string(),
+        method: "^".to_string(),
+        from_ver: Some("3.24.1".to_string()),
+        to_ver: Some("3.24.2".to_string()),
+    };
+    let page = TEMPLATE
+        .render(
+            "dashboard",
+            &json!({
+                "refreshes": [refresh_row(&refresh)],
+                "updates": [update_row(&event)],
+            }),
+        )
+        .unwrap();
+    assert!(page.contains("<td>1970-01-02 00:00 UTC</td><td>1m 15s</td><td>2 new, 1 removed</td>"));
+    assert!(page.contains("&lt;gtk-3&gt;"));
+    assert!(page.contains("3.24.1 ⇒ 3.24.2"));
+}
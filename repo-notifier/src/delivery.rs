@@ -0,0 +1,271 @@
+//! Accounting of the chunks that could not be delivered, they are resent on the next cycle
+//! instead of being dropped, of the chunks held in the quiet hours of the chats, and of the
+//! last chunk delivered to each chat (kept in the database)
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use sqlx::{query, sqlite::SqlitePool};
+use std::sync::Mutex;
+use teloxide::types::InlineKeyboardMarkup;
+
+use crate::format::Format;
+use crate::snooze::now;
+
+/// Give up on a chunk after this many failed cycles
+const MAX_ATTEMPTS: u32 = 3;
+
+/// What a chunk is part of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Batch {
+    /// The updates up to the message of p-vector with the sequence number, unknown for the
+    /// legacy messages and the updates missed while stopped
+    Updates(Option<u64>),
+    /// A message of the bot
+    Notice,
+}
+
+/// A chunk of a batch that could not be delivered to a chat, or is held for it
+pub struct Undelivered {
+    pub chat_id: i64,
+    pub batch: Batch,
+    pub chunk: usize,
+    pub content: String,
+    pub format: Format,
+    pub markup: Option<InlineKeyboardMarkup>,
+    /// Number of the cycles it failed in
+    attempts: u32,
+    /// Not sent before this time (UNIX timestamp), the end of the quiet hours of the chat
+    not_before: i64,
+}
+
+/// The last chunk of the updates delivered to a chat
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Receipt {
+    /// Sequence number of the message of p-vector
+    pub sequence: u64,
+    pub chunk: usize,
+    /// When it was delivered (UNIX timestamp)
+    pub time: i64,
+}
+
+/// The chunks waiting to be resent or held
+#[derive(Default)]
+pub struct Queue {
+    chunks: Mutex<Vec<Undelivered>>,
+}
+
+impl Queue {
+    fn push(&self, undelivered: Undelivered) {
+        self.chunks.lock().unwrap().push(undelivered);
+    }
+
+    /// Take the chunks to send at the time, in the order they failed or were held
+    pub fn take(&self, now: i64) -> Vec<Undelivered> {
+        let mut chunks = self.chunks.lock().unwrap();
+        let (due, held) = std::mem::take(&mut *chunks)
+            .into_iter()
+            .partition(|u| u.not_before <= now);
+        *chunks = held;
+
+        due
+    }
+
+    /// Send the chunks held for the chat on the next cycle
+    pub fn release(&self, chat_id: i64) {
+        self.chunks
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|u| u.chat_id == chat_id)
+            .for_each(|u| u.not_before = 0);
+    }
+
+    /// Keep the chunk for the next cycle after it failed again.
+    ///
+    /// Returns `false` if it failed too many times and was dropped.
+    pub fn retry_later(&self, mut undelivered: Undelivered) -> bool {
+        undelivered.attempts += 1;
+        if undelivered.attempts > MAX_ATTEMPTS {
+            return false;
+        }
+        self.push(undelivered);
+
+        true
+    }
+
+    /// Number of the chunks waiting to be resent or held
+    pub fn count(&self) -> usize {
+        self.chunks.lock().unwrap().len()
+    }
+
+    /// Number of the chunks waiting to be resent or held for the chat
+    pub fn count_for(&self, chat_id: i64) -> usize {
+        self.chunks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|u| u.chat_id == chat_id)
+            .count()
+    }
+}
+
+static UNDELIVERED: Lazy<Queue> = Lazy::new(Queue::default);
+
+/// Remember the chunk of the updates was delivered to the chat, unless a later one was
+pub async fn delivered(pool: &SqlitePool, chat_id: i64, batch: Batch, chunk: usize) -> Result<()> {
+    let sequence = match batch {
+        Batch::Updates(Some(sequence)) => sequence as i64,
+        _ => return Ok(()),
+    };
+    let chunk = chunk as i64;
+    let time = now();
+    query!(
+        "INSERT INTO delivery_receipts (chat_id, sequence, chunk, delivered) VALUES (?, ?, ?, ?)
+        ON CONFLICT(chat_id) DO UPDATE SET sequence = excluded.sequence, chunk = excluded.chunk, delivered = excluded.delivered
+        WHERE (excluded.sequence, excluded.chunk) > (delivery_receipts.sequence, delivery_receipts.chunk)",
+        chat_id,
+        sequence,
+        chunk,
+        time
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The last chunk of the updates delivered to the chat
+pub async fn receipt(pool: &SqlitePool, chat_id: i64) -> Result<Option<Receipt>> {
+    let receipt = query!(
+        "SELECT sequence, chunk, delivered FROM delivery_receipts WHERE chat_id = ?",
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(receipt.map(|r| Receipt {
+        sequence: r.sequence as u64,
+        chunk: r.chunk as usize,
+        time: r.delivered,
+    }))
+}
+
+/// Remember the chunk that could not be delivered to the chat
+pub fn failed(
+    chat_id: i64,
+    batch: Batch,
+    chunk: usize,
+    content: &str,
+    format: Format,
+    markup: Option<&InlineKeyboardMarkup>,
+) {
+    UNDELIVERED.push(Undelivered {
+        chat_id,
+        batch,
+        chunk,
+        content: content.to_string(),
+        format,
+        markup: markup.cloned(),
+        attempts: 1,
+        not_before: 0,
+    });
+}
+
+/// Hold the chunk for the chat until the time (UNIX timestamp)
+pub fn hold(
+    chat_id: i64,
+    batch: Batch,
+    chunk: usize,
+    content: &str,
+    format: Format,
+    markup: Option<&InlineKeyboardMarkup>,
+    until: i64,
+) {
+    UNDELIVERED.push(Undelivered {
+        chat_id,
+        batch,
+        chunk,
+        content: content.to_string(),
+        format,
+        markup: markup.cloned(),
+        attempts: 0,
+        not_before: until,
+    });
+}
+
+/// The chunks waiting to be resent or held
+pub fn queue() -> &'static Queue {
+    &UNDELIVERED
+}
+
+#[test]
+fn test_retry_later() {
+    let undelivered = |chat_id, batch, content: &str| Undelivered {
+        chat_id,
+        batch,
+        chunk: 0,
+        content: content.to_string(),
+        format: Format::Html,
+        markup: None,
+        attempts: 0,
+        not_before: 0,
+    };
+    let queue = Queue::default();
+    queue.retry_later(undelivered(1, Batch::Updates(Some(1)), "first"));
+    queue.retry_later(undelivered(2, Batch::Notice, "second"));
+    assert_eq!(queue.count(), 2);
+    assert_eq!(queue.count_for(2), 1);
+    let mut chunks = queue.take(now());
+    assert_eq!(queue.count(), 0);
+    assert_eq!(chunks[1].content, "second");
+    let chunk = chunks.remove(0);
+    assert!(queue.retry_later(chunk));
+    let chunk = queue.take(now()).remove(0);
+    assert_eq!(chunk.attempts, 2);
+    assert!(queue.retry_later(chunk));
+    assert!(!queue.retry_later(queue.take(now()).remove(0)));
+    assert_eq!(queue.count(), 0);
+
+    // the chunks held in the quiet hours are sent when they end
+    for (chat_id, content) in [(1, "held"), (2, "held too")] {
+        queue.push(Undelivered {
+            not_before: 1000,
+            ..undelivered(chat_id, Batch::Updates(Some(2)), content)
+        });
+    }
+    assert!(queue.take(999).is_empty());
+    assert_eq!(queue.count_for(1), 1);
+    let chunks = queue.take(1000);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].content, "held");
+    assert_eq!(queue.count(), 0);
+    // or when they are turned off
+    queue.push(Undelivered {
+        not_before: 1000,
+        ..undelivered(1, Batch::Updates(Some(3)), "held")
+    });
+    queue.release(1);
+    assert_eq!(queue.take(999).len(), 1);
+}
+
+#[tokio::test]
+async fn test_receipts() {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+    let delivered = |batch, chunk| delivered(&pool, -100, batch, chunk);
+    delivered(Batch::Updates(Some(42)), 1).await.unwrap();
+    // resent chunk of an earlier batch
+    delivered(Batch::Updates(Some(41)), 3).await.unwrap();
+    delivered(Batch::Updates(Some(42)), 0).await.unwrap();
+    delivered(Batch::Updates(None), 5).await.unwrap();
+    delivered(Batch::Notice, 5).await.unwrap();
+    let last = receipt(&pool, -100).await.unwrap().unwrap();
+    assert_eq!((last.sequence, last.chunk), (42, 1));
+    delivered(Batch::Updates(Some(43)), 0).await.unwrap();
+    let last = receipt(&pool, -100).await.unwrap().unwrap();
+    assert_eq!((last.sequence, last.chunk), (43, 0));
+    assert_eq!(receipt(&pool, -200).await.unwrap(), None);
+}
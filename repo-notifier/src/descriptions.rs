@@ -0,0 +1,121 @@
+use anyhow::Result;
+use futures_util::{stream, StreamExt};
+use notifier_core::PVMessage;
+use once_cell::sync::Lazy;
+use sqlx::{query, sqlite::SqlitePool};
+use std::{collections::HashMap, time::Duration};
+
+use crate::{config, snooze};
+
+/// Number of the descriptions fetched at the same time
+const CONCURRENCY: usize = 8;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Truncate the description to `max_length` characters
+fn truncate(description: &str, max_length: usize) -> String {
+    let description = description.trim();
+    match description.char_indices().nth(max_length) {
+        Some((end, _)) => format!("{}…", description[..end].trim_end()),
+        None => description.to_string(),
+    }
+}
+
+/// Fetch the description of the package, `None` if the package has none
+async fn fetch(pkg: &str) -> Result<Option<String>> {
+    let config = &config::get().descriptions;
+    let info: serde_json::Value = CLIENT
+        .get(config.url.replace("{}", pkg))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(info
+        .pointer(&config.pointer)
+        .and_then(|d| d.as_str())
+        .map(|d| d.to_string()))
+}
+
+/// Get the description of the package, from the cache if it is fresh enough
+async fn describe(pool: &SqlitePool, pkg: &str) -> Result<String> {
+    let config = &config::get().descriptions;
+    let now = snooze::now();
+    let expiry = now - (config.cache_days * 24 * 3600) as i64;
+    let cached = query!(
+        "SELECT description FROM package_descriptions WHERE pkg = ? AND fetched > ?",
+        pkg,
+        expiry
+    )
+    .fetch_optional(pool)
+    .await?;
+    if let Some(cached) = cached {
+        return Ok(cached.description);
+    }
+    // packages without a description are cached too, so they are not looked up every time,
+    // the failed lookups are retried next time
+    let description = fetch(pkg).await?.unwrap_or_default();
+    query!(
+        "INSERT INTO package_descriptions (pkg, description, fetched) VALUES (?, ?, ?)
+        ON CONFLICT(pkg) DO UPDATE SET description = excluded.description, fetched = excluded.fetched",
+        pkg,
+        description,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(description)
+}
+
+/// Add the descriptions to the new packages (if enabled)
+pub async fn enrich(pool: &SqlitePool, messages: &mut [PVMessage]) {
+    let config = &config::get().descriptions;
+    if !config.enabled {
+        return;
+    }
+    let mut packages = messages
+        .iter()
+        .filter(|p| p.method.as_new_type() == b'+')
+        .map(|p| p.pkg.clone())
+        .collect::<Vec<_>>();
+    packages.sort_unstable();
+    packages.dedup();
+    let descriptions = stream::iter(packages)
+        .map(|pkg| async move {
+            let description = describe(pool, &pkg).await;
+            (pkg, description)
+        })
+        .buffer_unordered(CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    let descriptions = descriptions
+        .into_iter()
+        .filter_map(|(pkg, description)| match description {
+            Ok(d) if !d.trim().is_empty() => Some((pkg, truncate(&d, config.max_length))),
+            Ok(_) => None,
+            Err(e) => {
+                log::error!("Could not look up the description of {}: {}", pkg, e);
+                None
+            }
+        })
+        .collect::<HashMap<_, _>>();
+    for p in messages.iter_mut() {
+        if p.method.as_new_type() == b'+' {
+            p.description = descriptions.get(&p.pkg).cloned();
+        }
+    }
+}
+
+#[test]
+fn test_truncate() {
+    assert_eq!(truncate(" GTK+ toolkit ", 20), "GTK+ toolkit");
+    assert_eq!(truncate("GNU Compiler Collection", 4), "GNU…");
+    assert_eq!(truncate("中文输入法框架", 4), "中文输入…");
+}
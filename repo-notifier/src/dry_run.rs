@@ -0,0 +1,71 @@
+//! Dry-run mode (`DRY_RUN`) to validate protocol or formatting changes against the live
+//! p-vector traffic: the messages for the chats are only logged (`DRY_RUN=log`) or all sent to
+//! a test chat (`DRY_RUN=<chat_id>`), and nothing is sent to the sinks
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicI32, Ordering};
+use teloxide::types::{ChatId, Message};
+
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    Log,
+    /// Send everything to this chat instead
+    Chat(ChatId),
+}
+
+static MODE: Lazy<Option<Mode>> = Lazy::new(|| {
+    let mode = parse(&std::env::var("DRY_RUN").unwrap_or_default());
+    if let Some(mode) = &mode {
+        log::warn!(
+            "Dry run: {:?}, the subscribers will not receive anything.",
+            mode
+        );
+    }
+    mode
+});
+
+/// Identifiers of the messages standing for the ones not sent
+static MESSAGE_ID: AtomicI32 = AtomicI32::new(1);
+
+fn parse(value: &str) -> Option<Mode> {
+    match value.trim() {
+        "" => None,
+        "log" => Some(Mode::Log),
+        value => match value.parse() {
+            Ok(chat_id) => Some(Mode::Chat(ChatId(chat_id))),
+            // better safe than broadcasting
+            Err(_) => {
+                log::warn!("Invalid DRY_RUN {:?}, only logging the messages.", value);
+                Some(Mode::Log)
+            }
+        },
+    }
+}
+
+pub fn mode() -> Option<&'static Mode> {
+    MODE.as_ref()
+}
+
+/// A message standing for the one that would have been sent to the chat
+pub fn message(chat_id: ChatId, text: &str) -> Message {
+    let message = serde_json::json!({
+        "message_id": MESSAGE_ID.fetch_add(1, Ordering::Relaxed),
+        "date": crate::snooze::now(),
+        "chat": { "id": chat_id.0, "type": "private" },
+        "text": text,
+    });
+
+    serde_json::from_value(message).expect("Invalid message of the dry run")
+}
+
+#[test]
+fn test_dry_run() {
+    assert_eq!(parse(""), None);
+    assert_eq!(parse("log"), Some(Mode::Log));
+    assert_eq!(parse("-1001234"), Some(Mode::Chat(ChatId(-1001234))));
+    assert_eq!(parse("everyone"), Some(Mode::Log));
+    let first = message(ChatId(-100), "<b>gtk-3</b>");
+    let second = message(ChatId(-100), "<b>gtk-4</b>");
+    assert_eq!(first.chat.id, ChatId(-100));
+    assert_eq!(first.text(), Some("<b>gtk-3</b>"));
+    assert_ne!(first.id, second.id);
+}
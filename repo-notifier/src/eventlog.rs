@@ -0,0 +1,120 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{rename, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Rotate the log when it grows beyond 16 MiB by default
+const DEFAULT_MAX_SIZE: u64 = 16 * 1024 * 1024;
+// Number of rotated logs to keep (`events.jsonl.1` ... `events.jsonl.5`)
+const ROTATE_KEEP: usize = 5;
+
+static EVENT_LOG: Lazy<Option<Mutex<EventLog>>> = Lazy::new(|| {
+    let path = std::env::var("EVENT_LOG").ok()?;
+    let max_size = std::env::var("EVENT_LOG_MAX_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE);
+    match EventLog::open(PathBuf::from(&path), max_size) {
+        Ok(log) => Some(Mutex::new(log)),
+        Err(e) => {
+            log::error!("Unable to open the event log {}: {}", path, e);
+            None
+        }
+    }
+});
+
+/// A single line in the event log
+#[derive(Serialize)]
+struct Event<'a> {
+    timestamp: u64,
+    chat_id: i64,
+    chunk: usize,
+    sha256: String,
+    outcome: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Append-only JSONL log of all the notifications sent to the chats
+struct EventLog {
+    path: PathBuf,
+    max_size: u64,
+    file: File,
+}
+
+impl EventLog {
+    fn open(path: PathBuf, max_size: u64) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(EventLog {
+            path,
+            max_size,
+            file,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        for i in (1..ROTATE_KEEP).rev() {
+            let from = self.rotated_path(i);
+            if from.exists() {
+                rename(&from, self.rotated_path(i + 1))?;
+            }
+        }
+        rename(&self.path, self.rotated_path(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        Ok(())
+    }
+
+    fn append(&mut self, event: &Event) -> Result<()> {
+        if self.file.metadata()?.len() >= self.max_size {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+
+        Ok(())
+    }
+}
+
+/// Record the outcome of sending `text` (the `chunk`-th message of a batch) to `chat_id`
+pub fn record(chat_id: i64, chunk: usize, text: &str, result: &Result<()>) {
+    let log = match EVENT_LOG.as_ref() {
+        Some(log) => log,
+        None => return,
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let event = Event {
+        timestamp,
+        chat_id,
+        chunk,
+        sha256: hex::encode(Sha256::digest(text.as_bytes())),
+        outcome: if result.is_ok() { "sent" } else { "failed" },
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    let mut log = log.lock().unwrap();
+    if let Err(e) = log.append(&event) {
+        log::error!("Unable to write to the event log: {}", e);
+    }
+}
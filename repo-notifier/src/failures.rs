@@ -0,0 +1,215 @@
+//! Accounting of the failed sends to Telegram by their causes, with an alert to a webhook when
+//! too many of the recent sends fail (`[alerts]` in the configuration)
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use teloxide::{ApiError, RequestError};
+
+use crate::config;
+use crate::i18n::{tr, Text};
+use crate::snooze::now;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cause {
+    RateLimit,
+    /// The bot was blocked, kicked or the chat is gone
+    Blocked,
+    Network,
+    Other,
+}
+
+const CAUSES: [Cause; 4] = [
+    Cause::RateLimit,
+    Cause::Blocked,
+    Cause::Network,
+    Cause::Other,
+];
+
+impl Cause {
+    /// Classify the error, `None` if it is not a failure (the chat moved)
+    fn of(error: &RequestError) -> Option<Cause> {
+        Some(match error {
+            RequestError::MigrateToChatId(_) => return None,
+            RequestError::RetryAfter(_) => Cause::RateLimit,
+            RequestError::Api(
+                ApiError::BotBlocked
+                | ApiError::BotKicked
+                | ApiError::BotKickedFromSupergroup
+                | ApiError::ChatNotFound
+                | ApiError::GroupDeactivated
+                | ApiError::UserDeactivated
+                | ApiError::CantInitiateConversation
+                | ApiError::NotEnoughRightsToPostMessages,
+            ) => Cause::Blocked,
+            RequestError::Network(_) | RequestError::Io(_) => Cause::Network,
+            _ => Cause::Other,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Cause::RateLimit => "rate_limit",
+            Cause::Blocked => "blocked",
+            Cause::Network => "network",
+            Cause::Other => "other",
+        }
+    }
+}
+
+/// Outcomes of the sends, oldest first
+#[derive(Default)]
+struct Outcomes {
+    /// UNIX timestamp and the cause of the failure (if failed) of the recent sends
+    recent: VecDeque<(i64, Option<Cause>)>,
+    /// Since the start
+    attempts: u64,
+    failures: [u64; CAUSES.len()],
+}
+
+impl Outcomes {
+    fn record(&mut self, time: i64, cause: Option<Cause>, window: i64) {
+        self.attempts += 1;
+        if let Some(cause) = cause {
+            self.failures[cause as usize] += 1;
+        }
+        self.recent.push_back((time, cause));
+        while self
+            .recent
+            .front()
+            .is_some_and(|(t, _)| *t <= time - window)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Number of the recent sends and the failed ones by cause
+    fn recent_failures(&self) -> (usize, [usize; CAUSES.len()]) {
+        let mut failures = [0; CAUSES.len()];
+        for cause in self.recent.iter().filter_map(|(_, c)| *c) {
+            failures[cause as usize] += 1;
+        }
+        (self.recent.len(), failures)
+    }
+}
+
+static OUTCOMES: Lazy<Mutex<Outcomes>> = Lazy::new(|| Mutex::new(Outcomes::default()));
+/// When the last alert was fired (UNIX timestamp)
+static LAST_ALERT: AtomicI64 = AtomicI64::new(0);
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+});
+
+pub fn succeeded() {
+    record(None);
+}
+
+pub fn failed(error: &RequestError) {
+    if let Some(cause) = Cause::of(error) {
+        record(Some(cause));
+    }
+}
+
+/// Whether the bot was blocked, kicked or the chat is gone
+pub fn blocked(error: &RequestError) -> bool {
+    Cause::of(error) == Some(Cause::Blocked)
+}
+
+fn record(cause: Option<Cause>) {
+    let alerts = &config::get().alerts;
+    let now = now();
+    let (attempts, failures) = {
+        let mut outcomes = OUTCOMES.lock().unwrap();
+        outcomes.record(now, cause, alerts.window_minutes * 60);
+        outcomes.recent_failures()
+    };
+    let failed = failures.iter().sum::<usize>();
+    if cause.is_none()
+        || alerts.webhook.is_empty()
+        || attempts < alerts.min_sends
+        || (failed as f64) < alerts.failure_rate * attempts as f64
+        || now - LAST_ALERT.load(Ordering::SeqCst) < alerts.cooldown_minutes * 60
+    {
+        return;
+    }
+    LAST_ALERT.store(now, Ordering::SeqCst);
+    let mut causes = serde_json::Map::new();
+    for (cause, count) in CAUSES.iter().zip(failures) {
+        causes.insert(cause.name().to_string(), json!(count));
+    }
+    let payload = json!({
+        "text": format!(
+            "⚠️ repo-notifier: {} of the last {} sends to Telegram failed in {} minutes.",
+            failed, attempts, alerts.window_minutes
+        ),
+        "attempts": attempts,
+        "failures": causes,
+    });
+    log::warn!("Send failure rate crossed the threshold: {}", payload);
+    tokio::spawn(async move {
+        let result = CLIENT
+            .post(&config::get().alerts.webhook)
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            log::error!("Could not fire the alert: {}", e);
+        }
+    });
+}
+
+/// Failures since the start, for /status
+pub fn report(lang: &str) -> String {
+    let outcomes = OUTCOMES.lock().unwrap();
+    let mut text = tr(lang, Text::StatusFailures).to_string();
+    for count in outcomes.failures.iter() {
+        text = text.replacen("{}", &count.to_string(), 1);
+    }
+
+    text.replacen("{}", &outcomes.attempts.to_string(), 1)
+}
+
+#[test]
+fn test_outcomes() {
+    use teloxide::types::{ChatId, Seconds};
+
+    assert_eq!(
+        Cause::of(&RequestError::RetryAfter(Seconds::from_seconds(3))),
+        Some(Cause::RateLimit)
+    );
+    assert_eq!(
+        Cause::of(&RequestError::Api(ApiError::BotBlocked)),
+        Some(Cause::Blocked)
+    );
+    assert!(blocked(&RequestError::Api(ApiError::BotKicked)));
+    assert_eq!(
+        Cause::of(&RequestError::Api(ApiError::MessageIsTooLong)),
+        Some(Cause::Other)
+    );
+    assert_eq!(
+        Cause::of(&RequestError::MigrateToChatId(ChatId(-100))),
+        None
+    );
+
+    let mut outcomes = Outcomes::default();
+    outcomes.record(0, Some(Cause::Network), 600);
+    outcomes.record(100, None, 600);
+    outcomes.record(200, Some(Cause::Blocked), 600);
+    assert_eq!(outcomes.recent_failures(), (3, [0, 1, 1, 0]));
+    // the first one is out of the window
+    outcomes.record(600, None, 600);
+    assert_eq!(outcomes.recent_failures(), (3, [0, 1, 0, 0]));
+    assert_eq!(outcomes.attempts, 4);
+    assert_eq!(outcomes.failures, [0, 1, 1, 0]);
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+/// Properties of the updates a chat can filter on
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Kind {
+    /// Source repository (name of the p-vector instance)
+    Repo,
+    /// Named group of the architectures (e.g. `retro`, see `[arch_groups]` in the config)
+    Group,
+    /// Component (branch) of the repository, e.g. `stable`
+    Comp,
+    Arch,
+}
+
+pub const KINDS: &[Kind] = &[Kind::Repo, Kind::Group, Kind::Comp, Kind::Arch];
+
+impl FromStr for Kind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KINDS
+            .iter()
+            .find(|x| x.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Repo => "repo",
+            Kind::Group => "group",
+            Kind::Comp => "comp",
+            Kind::Arch => "arch",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Filters of a chat, an update is sent only if it matches one of the values of every kind
+/// and its package is not muted
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Filters {
+    kinds: BTreeMap<Kind, BTreeSet<String>>,
+    muted: BTreeSet<String>,
+}
+
+impl Filters {
+    pub fn add(&mut self, kind: Kind, value: String) {
+        self.kinds.entry(kind).or_default().insert(value);
+    }
+
+    pub fn mute(&mut self, pkg: String) {
+        self.muted.insert(pkg);
+    }
+
+    pub fn is_muted(&self, pkg: &str) -> bool {
+        self.muted.contains(pkg)
+    }
+
+    /// Check whether the value of the given property passes the filter,
+    /// `None` (e.g. untagged updates) always passes
+    pub fn allows(&self, kind: Kind, value: Option<&str>) -> bool {
+        match (self.kinds.get(&kind), value) {
+            (Some(values), Some(value)) => values.contains(value),
+            _ => true,
+        }
+    }
+
+    /// Check whether one of the values of the given kind matches, passes if the chat
+    /// does not filter on it
+    pub fn matches(&self, kind: Kind, matches: impl Fn(&str) -> bool) -> bool {
+        self.kinds
+            .get(&kind)
+            .is_none_or(|values| values.iter().any(|v| matches(v)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty() && self.muted.is_empty()
+    }
+}
+
+impl fmt::Display for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (kind, values) in self.kinds.iter() {
+            let values = values.iter().cloned().collect::<Vec<_>>();
+            writeln!(f, "{}: {}", kind, values.join(", "))?;
+        }
+        if !self.muted.is_empty() {
+            let muted = self.muted.iter().cloned().collect::<Vec<_>>();
+            writeln!(f, "muted: {}", muted.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_filters() {
+    let mut filters = Filters::default();
+    assert!(filters.is_empty());
+    filters.add(Kind::Repo, "stable".to_string());
+    filters.mute("chromium".to_string());
+    assert!(filters.allows(Kind::Repo, Some("stable")));
+    assert!(!filters.allows(Kind::Repo, Some("testing")));
+    assert!(filters.allows(Kind::Repo, None));
+    assert!(filters.matches(Kind::Group, |_| false));
+    filters.add(Kind::Group, "retro".to_string());
+    assert!(filters.matches(Kind::Group, |g| g == "retro"));
+    assert_eq!("Comp".parse::<Kind>(), Ok(Kind::Comp));
+    assert!(!filters.matches(Kind::Group, |g| g == "mainline"));
+    assert!(filters.is_muted("chromium"));
+    assert!(!filters.is_muted("firefox"));
+    assert_eq!(
+        filters.to_string(),
+        "repo: stable\ngroup: retro\nmuted: chromium\n"
+    );
+}
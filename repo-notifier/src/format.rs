@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+use teloxide::{
+    types::ParseMode,
+    utils::{html, markdown},
+};
+
+/// Message format of a chat, some bridged chats (e.g. Telegram to IRC) mangle HTML
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, sqlx::Type, Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Html,
+    Markdown,
+    Plain,
+}
+
+pub const FORMATS: &[Format] = &[Format::Html, Format::Markdown, Format::Plain];
+/// Tags Telegram supports in the HTML messages
+const HTML_TAGS: &[&str] = &[
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "ins",
+    "s",
+    "strike",
+    "del",
+    "span",
+    "tg-spoiler",
+    "a",
+    "code",
+    "pre",
+    "blockquote",
+    "tg-emoji",
+];
+/// Characters to escape in MarkdownV2 outside of the entities
+const MARKDOWN_RESERVED: &str = "_*[]()~`>#+-=|{}.!";
+
+impl Format {
+    pub fn parse_mode(self) -> Option<ParseMode> {
+        match self {
+            Format::Html => Some(ParseMode::Html),
+            Format::Markdown => Some(ParseMode::MarkdownV2),
+            Format::Plain => None,
+        }
+    }
+
+    /// Escape the text so that it is displayed verbatim
+    pub fn escape(self, s: &str) -> String {
+        match self {
+            Format::Html => html::escape(s),
+            Format::Markdown => markdown::escape(s),
+            Format::Plain => s.to_string(),
+        }
+    }
+
+    /// Make the (already escaped) text italic
+    pub fn italic(self, s: &str) -> String {
+        match self {
+            Format::Html => format!("<i>{}</i>", s),
+            Format::Markdown => format!("_{}_", s),
+            Format::Plain => s.to_string(),
+        }
+    }
+
+    /// Link the (already escaped) text to the URL
+    pub fn link(self, s: &str, url: &str) -> String {
+        match self {
+            Format::Html => format!(r#"<a href="{}">{}</a>"#, html::escape(url), s),
+            Format::Markdown => format!("[{}]({})", s, markdown::escape_link_url(url)),
+            Format::Plain => format!("{} ({})", s, url),
+        }
+    }
+
+    /// Put the (already escaped) text in a quote collapsed by default
+    pub fn expandable(self, s: &str) -> String {
+        match self {
+            Format::Html => format!("<blockquote expandable>{}</blockquote>", s),
+            Format::Markdown => {
+                let quoted = s.lines().map(|l| format!(">{}", l)).collect::<Vec<_>>();
+                format!("**{}||", quoted.join("\n"))
+            }
+            Format::Plain => s.to_string(),
+        }
+    }
+
+    /// Check that Telegram accepts the text in the format
+    pub fn check(self, text: &str) -> Result<()> {
+        match self {
+            Format::Html => check_html(text),
+            Format::Markdown => check_markdown(text),
+            Format::Plain => Ok(()),
+        }
+    }
+
+    /// Make the (already escaped) text bold
+    pub fn bold(self, s: &str) -> String {
+        match self {
+            Format::Html => format!("<b>{}</b>", s),
+            Format::Markdown => format!("*{}*", s),
+            Format::Plain => s.to_string(),
+        }
+    }
+}
+
+/// The tags must be supported and balanced, `<`, `>` and `&` escaped elsewhere
+fn check_html(text: &str) -> Result<()> {
+    let mut open = Vec::new();
+    let mut rest = text;
+    while let Some(i) = rest.find(['<', '>', '&']) {
+        let c = rest.as_bytes()[i];
+        rest = &rest[i + 1..];
+        match c {
+            b'<' => {
+                let end = rest
+                    .find('>')
+                    .ok_or_else(|| anyhow!("unclosed tag, escape < as &lt;"))?;
+                let (tag, closing) = match rest[..end].strip_prefix('/') {
+                    Some(tag) => (tag, true),
+                    None => (&rest[..end], false),
+                };
+                let name = tag
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                rest = &rest[end + 1..];
+                if !HTML_TAGS.contains(&name.as_str()) {
+                    return Err(anyhow!("unsupported tag <{}>", name));
+                }
+                if !closing {
+                    open.push(name);
+                } else if open.pop().as_ref() != Some(&name) {
+                    return Err(anyhow!("unbalanced tag </{}>", name));
+                }
+            }
+            b'>' => return Err(anyhow!("unescaped >, escape it as &gt;")),
+            _ => {
+                let entity = rest.split(';').next().unwrap_or_default();
+                let numeric = entity
+                    .strip_prefix("#x")
+                    .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_hexdigit()))
+                    .or_else(|| {
+                        let n = entity.strip_prefix('#')?;
+                        Some(!n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+                    })
+                    .unwrap_or(false);
+                if !rest.contains(';')
+                    || !(numeric || ["lt", "gt", "amp", "quot"].contains(&entity))
+                {
+                    return Err(anyhow!("unescaped &, escape it as &amp;"));
+                }
+                rest = &rest[entity.len() + 1..];
+            }
+        }
+    }
+    match open.last() {
+        Some(tag) => Err(anyhow!("unclosed tag <{}>", tag)),
+        None => Ok(()),
+    }
+}
+
+/// Open or close the entity of the marker, they may be nested but not overlap
+fn toggle(open: &mut Vec<&'static str>, marker: &'static str) -> Result<()> {
+    if open.last() == Some(&marker) {
+        open.pop();
+    } else if open.contains(&marker) {
+        return Err(anyhow!("overlapping {} entities", marker));
+    } else {
+        open.push(marker);
+    }
+
+    Ok(())
+}
+
+/// The entities must be closed, the reserved characters escaped with `\` elsewhere
+fn check_markdown(text: &str) -> Result<()> {
+    let mut open = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        let at_line_start = line_start;
+        line_start = c == '\n';
+        match c {
+            '\\' => {
+                chars
+                    .next()
+                    .ok_or_else(|| anyhow!("nothing to escape after the trailing \\"))?;
+            }
+            '`' => {
+                // inline code or a block of code, only ` and \ are escaped inside
+                let mut fence = 1;
+                while fence < 3 && chars.peek() == Some(&'`') {
+                    chars.next();
+                    fence += 1;
+                }
+                let mut closed = 0;
+                while closed < fence {
+                    match chars.next() {
+                        Some('`') => closed += 1,
+                        Some('\\') => {
+                            chars.next();
+                            closed = 0;
+                        }
+                        Some(_) => closed = 0,
+                        None => return Err(anyhow!("unclosed `")),
+                    }
+                }
+            }
+            '*' => toggle(&mut open, "*")?,
+            '~' => toggle(&mut open, "~")?,
+            '_' if chars.peek() == Some(&'_') => {
+                chars.next();
+                toggle(&mut open, "__")?;
+            }
+            '_' => toggle(&mut open, "_")?,
+            '|' if chars.peek() == Some(&'|') => {
+                chars.next();
+                toggle(&mut open, "||")?;
+            }
+            '[' => open.push("["),
+            ']' if open.last() == Some(&"[") => {
+                open.pop();
+                if chars.next() != Some('(') {
+                    return Err(anyhow!("expected (URL) after the text of the link"));
+                }
+                loop {
+                    match chars.next() {
+                        Some(')') => break,
+                        Some('\\') => {
+                            chars.next();
+                        }
+                        Some(_) => (),
+                        None => return Err(anyhow!("unclosed URL of the link")),
+                    }
+                }
+            }
+            // quotation
+            '>' if at_line_start => (),
+            c if MARKDOWN_RESERVED.contains(c) => {
+                return Err(anyhow!("unescaped {}, escape it as \\{}", c, c));
+            }
+            _ => (),
+        }
+    }
+    match open.last() {
+        Some(marker) => Err(anyhow!("unclosed {}", marker)),
+        None => Ok(()),
+    }
+}
+
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FORMATS
+            .iter()
+            .find(|x| x.to_string().eq_ignore_ascii_case(s.trim()))
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Html => "html",
+            Format::Markdown => "markdown",
+            Format::Plain => "plain",
+        };
+
+        f.write_str(name)
+    }
+}
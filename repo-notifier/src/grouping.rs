@@ -0,0 +1,117 @@
+use notifier_core::PVMessage;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::format::Format;
+use crate::rebuilds::Entry;
+
+/// How the updates of a batch are grouped in the messages
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Grouping {
+    /// Under a heading for each component and architecture
+    #[default]
+    Arch,
+    /// Under a heading for each component, with the architectures listed after each package
+    Package,
+}
+
+pub const GROUPINGS: &[Grouping] = &[Grouping::Arch, Grouping::Package];
+
+impl FromStr for Grouping {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GROUPINGS
+            .iter()
+            .find(|x| x.to_string().eq_ignore_ascii_case(s.trim()))
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Grouping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Grouping::Arch => "arch",
+            Grouping::Package => "package",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Render the architectures an update was made on, after its line
+pub fn arches(packages: &[&PVMessage], format: Format) -> String {
+    let arches = packages
+        .iter()
+        .map(|p| p.arch.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format.italic(&format.escape(&format!("({})", arches)))
+}
+
+/// Merge the same update (repository, component, package, operation and versions) made on
+/// several architectures into a single entry, in place of its first occurrence
+pub fn by_package(entries: Vec<Entry<'_>>) -> Vec<Entry<'_>> {
+    let mut merged: Vec<Entry> = Vec::new();
+    let mut seen = HashMap::new();
+    for entry in entries {
+        let p = match entry {
+            Entry::Single(p) => p,
+            entry => {
+                merged.push(entry);
+                continue;
+            }
+        };
+        let key = (
+            p.repo.as_deref(),
+            p.comp.as_str(),
+            p.pkg.as_str(),
+            p.method.as_new_type(),
+            p.from_ver.as_deref(),
+            p.to_ver.as_deref(),
+        );
+        match seen.get(&key) {
+            Some(&i) => {
+                if let Entry::Arches(packages) = &mut merged[i] {
+                    packages.push(p);
+                }
+            }
+            None => {
+                seen.insert(key, merged.len());
+                merged.push(Entry::Arches(vec![p]));
+            }
+        }
+    }
+
+    merged
+}
+
+#[test]
+fn test_by_package() {
+    let message =
+        |pkg: &str, arch: &str, to: &str| PVMessage::sample(pkg, arch, b'^', Some("1.0"), Some(to));
+    assert_eq!("Package".parse(), Ok(Grouping::Package));
+    assert!("comp".parse::<Grouping>().is_err());
+    let messages = [
+        message("gtk-3", "amd64", "2.0"),
+        message("curl", "amd64", "2.0"),
+        message("gtk-3", "arm64", "2.0"),
+        message("gtk-3", "riscv64", "2.1"),
+    ];
+    let entries = by_package(messages.iter().map(Entry::Single).collect());
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries.iter().map(Entry::count).sum::<usize>(), 4);
+    match &entries[0] {
+        Entry::Arches(packages) => {
+            assert_eq!(arches(packages, Format::Plain), "(amd64, arm64)");
+            assert_eq!(arches(packages, Format::Html), "<i>(amd64, arm64)</i>");
+        }
+        _ => panic!("updates not merged"),
+    }
+    assert_eq!(entries[1].first().pkg, "curl");
+    assert_eq!(entries[2].first().arch, "riscv64");
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use notifier_core::{PVMessage, PVMessageMethod};
+use serde::Serialize;
+use sqlx::{query, query_as, sqlite::SqlitePool};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An update as recorded in the history, `id` is the cursor of the API
+#[derive(Serialize, Debug)]
+pub struct Event {
+    pub id: i64,
+    pub timestamp: i64,
+    pub repo: Option<String>,
+    pub comp: String,
+    pub pkg: String,
+    pub arch: String,
+    pub method: String,
+    pub from_ver: Option<String>,
+    pub to_ver: Option<String>,
+}
+
+/// Record the updates sent to the subscribers
+pub async fn record(pool: &SqlitePool, messages: &[PVMessage]) -> Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let mut tx = pool.begin().await?;
+    for p in messages {
+        let method = (p.method.as_new_type() as char).to_string();
+        query!(
+            "INSERT INTO history (timestamp, repo, comp, pkg, arch, method, from_ver, to_ver) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            timestamp,
+            p.repo,
+            p.comp,
+            p.pkg,
+            p.arch,
+            method,
+            p.from_ver,
+            p.to_ver
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Get the most recent updates (newest first)
+pub async fn recent(pool: &SqlitePool, limit: i64) -> Result<Vec<PVMessage>> {
+    let rows = query!(
+        "SELECT repo, comp, pkg, arch, method, from_ver, to_ver FROM history ORDER BY id DESC LIMIT ?",
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| PVMessage {
+            comp: r.comp,
+            pkg: r.pkg,
+            arch: r.arch,
+            method: PVMessageMethod::New(r.method.bytes().next().unwrap_or(b'?')),
+            from_ver: r.from_ver,
+            to_ver: r.to_ver,
+            error: None,
+            repo: r.repo,
+            security: false,
+            mentions: Vec::new(),
+            description: None,
+        })
+        .collect())
+}
+
+/// Get the updates recorded after the cursor (oldest first), or the latest ones without a cursor
+pub async fn since(pool: &SqlitePool, cursor: Option<i64>, limit: i64) -> Result<Vec<Event>> {
+    let events =
+        match cursor {
+            Some(cursor) => query_as!(
+                Event,
+                r#"SELECT id AS "id!", timestamp, repo, comp, pkg, arch, method, from_ver, to_ver
+                FROM history WHERE id > ? ORDER BY id LIMIT ?"#,
+                cursor,
+                limit
+            )
+            .fetch_all(pool)
+            .await?,
+            None => {
+                let mut events = query_as!(
+                Event,
+                r#"SELECT id AS "id!", timestamp, repo, comp, pkg, arch, method, from_ver, to_ver
+                FROM history ORDER BY id DESC LIMIT ?"#,
+                limit
+            )
+            .fetch_all(pool)
+            .await?;
+                events.reverse();
+                events
+            }
+        };
+
+    Ok(events)
+}
+
+/// Search the latest updates of the packages whose names contain `pkg` (newest first)
+pub async fn search(pool: &SqlitePool, pkg: &str, limit: i64) -> Result<Vec<Event>> {
+    let events = query_as!(
+        Event,
+        r#"SELECT id AS "id!", timestamp, repo, comp, pkg, arch, method, from_ver, to_ver
+        FROM history WHERE instr(pkg, ?) > 0 ORDER BY id DESC LIMIT ?"#,
+        pkg,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Get the components with the most recent updates, sorted by name
+pub async fn components(pool: &SqlitePool, limit: i64) -> Result<Vec<String>> {
+    let comps = query!(
+        r#"SELECT comp AS "comp!" FROM history GROUP BY comp ORDER BY MAX(id) DESC LIMIT ?"#,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut comps = comps.into_iter().map(|r| r.comp).collect::<Vec<_>>();
+    comps.sort();
+
+    Ok(comps)
+}
+
+/// When the newest update was recorded (UNIX timestamp), `None` if the history is empty
+pub async fn newest(pool: &SqlitePool) -> Result<Option<i64>> {
+    let newest = query!(r#"SELECT MAX(timestamp) AS "newest: i64" FROM history"#)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(newest.newest)
+}
+
+/// Get the updates of the package and architecture (any if not given) recorded at or after the
+/// UNIX timestamp `since` (newest first)
+pub async fn updates(
+    pool: &SqlitePool,
+    pkg: Option<&str>,
+    arch: Option<&str>,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<Event>> {
+    let events = query_as!(
+        Event,
+        r#"SELECT id AS "id!", timestamp, repo, comp, pkg, arch, method, from_ver, to_ver
+        FROM history WHERE (? IS NULL OR pkg = ?) AND (? IS NULL OR arch = ?) AND timestamp >= ?
+        ORDER BY id DESC LIMIT ?"#,
+        pkg,
+        pkg,
+        arch,
+        arch,
+        since,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Get the latest update of the package in each branch and architecture
+pub async fn latest(pool: &SqlitePool, pkg: &str) -> Result<Vec<Event>> {
+    let events = query_as!(
+        Event,
+        r#"SELECT id AS "id!", timestamp, repo, comp, pkg, arch, method, from_ver, to_ver
+        FROM history AS h WHERE pkg = ? AND id = (SELECT MAX(id) FROM history
+        WHERE pkg = h.pkg AND comp = h.comp AND arch = h.arch AND repo IS h.repo)
+        ORDER BY repo, comp, arch"#,
+        pkg
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
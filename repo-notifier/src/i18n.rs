@@ -0,0 +1,408 @@
+pub const LANGUAGES: &[&str] = &["en", "zh-CN"];
+pub const DEFAULT_LANG: &str = "en";
+
+/// Translatable texts sent by the bot
+#[derive(Clone, Copy, Debug)]
+pub enum Text {
+    Subscribed,
+    Unsubscribed,
+    Pong,
+    Refreshed,
+    /// `{}` is replaced with the duration of the refresh, then the counts of the updates
+    RefreshedIn,
+    /// Title of a batch in the push notifications, `{}` is replaced with the number of the updates
+    UpdateCount,
+    /// Title of a batch of a single update in the push notifications
+    UpdateCountOne,
+    /// Footer of a paginated batch, `{}` is replaced with the number of the remaining updates
+    MoreUpdates,
+    ShowMore,
+    BatchExpired,
+    /// Buttons under the updates
+    ButtonMute,
+    ButtonSettings,
+    ButtonUnsubscribe,
+    /// Steps of /setup
+    SetupComps,
+    SetupArches,
+    SetupDigest,
+    /// `{}` is replaced with the filters
+    SetupDone,
+    SetupCancelled,
+    SetupExpired,
+    ButtonNext,
+    ButtonCancel,
+    ButtonByArch,
+    ButtonByPackage,
+    /// `{}` is replaced with the language, the minimum severity, the format, the time zone,
+    /// then the filters
+    Settings,
+    /// `{}` is replaced with the new language
+    LangChanged,
+    /// `{}` is replaced with the list of the available languages
+    LangUnknown,
+    /// `{}` is replaced with the error
+    InvalidMessage,
+    Stopped,
+    /// `{}` is replaced with the new severity
+    SeverityChanged,
+    /// `{}` is replaced with the list of the available severities
+    SeverityUnknown,
+    NotSubscribed,
+    /// `{}` is replaced with the new format
+    FormatChanged,
+    /// `{}` is replaced with the list of the available formats
+    FormatUnknown,
+    /// `{}` is replaced with the new grouping
+    GroupingChanged,
+    GroupingUsage,
+    ReleasesOn,
+    ReleasesOff,
+    ReleasesUsage,
+    /// `{}` are replaced with the variant, the architectures and the date
+    Release,
+    AdminOnly,
+    /// `{}` is replaced with the changed part of the template
+    TemplateChanged,
+    TemplateReset,
+    /// `{}` is replaced with the error
+    TemplateInvalid,
+    TemplateUsage,
+    NoFilters,
+    /// `{}` is replaced with the kind of the filter
+    FilterChanged,
+    /// `{}` is replaced with the kind of the filter
+    FilterRemoved,
+    /// `{}` is replaced with the unknown value
+    FilterUnknown,
+    FilterUsage,
+    /// `{}` is replaced with the package
+    Muted,
+    /// `{}` is replaced with the package
+    Unmuted,
+    /// `{}` is replaced with the package
+    NotMuted,
+    MuteUsage,
+    /// `{}` is replaced with the number of the updates not sent
+    RateLimited,
+    NoRecent,
+    PkgUsage,
+    /// `{}` is replaced with the package name
+    PkgUnknown,
+    /// `{}` is replaced with the new limit
+    RateLimitChanged,
+    RateLimitUsage,
+    /// `{}` is replaced with the duration
+    Snoozed,
+    /// `{}` is replaced with the local time
+    SnoozedUntil,
+    SnoozeUsage,
+    NotSnoozed,
+    QuietHoursCurrent,
+    QuietHoursSet,
+    QuietHoursOff,
+    QuietHoursUsage,
+    /// `{}` is replaced with the time zone
+    TimezoneChanged,
+    TimezoneCurrent,
+    TimezoneUnknown,
+    Resumed,
+    /// `{}` is replaced with the number of the updates not sent
+    ResumedWithUpdates,
+    /// `{}` is replaced with the number of the updates published while the notifier was down
+    Backfill,
+    /// Counts in the summary of a batch, `{}` is replaced with the number of the updates
+    SummaryNew,
+    SummaryUpgraded,
+    SummaryRemoved,
+    SummaryOverwritten,
+    SummaryOther,
+    /// Sent instead of the updates of a mass rebuild, `{}` is replaced with the number of the
+    /// updates, then the component
+    Flooded,
+    /// Link to the full listing of the flooded updates
+    FloodedListing,
+    /// `{}` is replaced with the number of the rebuilt packages
+    Rebuilt,
+    /// `{}` is replaced with the number of the rebuilt packages, then the package they were
+    /// rebuilt against
+    RebuiltAgainst,
+    /// `{}` is replaced with the repository, its state, the time since its last event and
+    /// the number of the pending updates
+    StatusSource,
+    /// `{}` is replaced with the name of the source, then the number of the messages
+    StatusRejected,
+    StatusConnected,
+    StatusDisconnected,
+    /// `{}` is replaced with the number of the chunks waiting to be resent
+    StatusUndelivered,
+    /// The batch and the message last delivered to the chat, when, the last batch and the
+    /// number of the messages waiting to be resent to the chat
+    StatusChat,
+    /// The last batch and the number of the messages waiting to be resent to the chat
+    StatusChatNone,
+    /// `{}` are replaced with the failures by cause, then the number of the attempts
+    StatusFailures,
+    /// `{}` is replaced with the time since the last refresh
+    StatusRefresh,
+    /// `{}` is replaced with the elapsed time
+    StatusAgo,
+    StatusNever,
+    ReportMirrorUsage,
+    ResolveMirrorUsage,
+    /// `{}` is replaced with the list of the known mirrors
+    MirrorUnknown,
+    /// `{}` is replaced with the mirror, the details and the operators to notify
+    MirrorReported,
+    MirrorReportFiled,
+    /// `{}` is replaced with the number of the reports
+    MirrorReportCounted,
+    OperatorOnly,
+    /// `{}` is replaced with the mirror
+    MirrorResolved,
+    /// `{}` is replaced with the mirror
+    MirrorNotReported,
+}
+
+fn en(text: Text) -> &'static str {
+    match text {
+        Text::Subscribed => "Subscribed to updates.",
+        Text::Unsubscribed => "Unsubbed.",
+        Text::Pong => "Pong!",
+        Text::Refreshed => "🔄 Repository refreshed.",
+        Text::RefreshedIn => "🔄 Repository refreshed in {}: {}.",
+        Text::UpdateCount => "{} package updates",
+        Text::UpdateCountOne => "1 package update",
+        Text::MoreUpdates => "... and {} more updates.",
+        Text::ShowMore => "Show more",
+        Text::BatchExpired => "This batch is no longer available.",
+        Text::ButtonMute => "🔕 Mute 1 day",
+        Text::ButtonSettings => "⚙️ Settings",
+        Text::ButtonUnsubscribe => "Unsubscribe",
+        Text::SetupComps => "Step 1/3: choose the components (branches) to receive the updates of, none for all of them.",
+        Text::SetupArches => "Step 2/3: choose the architectures, none for all of them.",
+        Text::SetupDigest => "Step 3/3: choose the digest mode, how the updates are grouped in the messages.",
+        Text::SetupDone => "✅ Setup finished.\n\n{}",
+        Text::SetupCancelled => "Setup cancelled.",
+        Text::SetupExpired => "This setup is no longer active, start again with /setup.",
+        Text::ButtonNext => "Next ➡️",
+        Text::ButtonCancel => "Cancel",
+        Text::ButtonByArch => "By architecture",
+        Text::ButtonByPackage => "By package",
+        Text::Settings => "⚙️ Settings of this chat\nLanguage: {}\nMinimum severity: {}\nFormat: {}\nTime zone: {}\n\n{}\n\nChange them with /lang, /severity, /format, /tz and /filter.",
+        Text::LangChanged => "Language changed to {}.",
+        Text::LangUnknown => "Available languages: {}",
+        Text::InvalidMessage => "⚠️ Invalid message received from p-vector: {}",
+        Text::Stopped => "❌ Too many errors encountered. Stopped monitoring p-vector!",
+        Text::SeverityChanged => "Minimum severity changed to {}.",
+        Text::SeverityUnknown => "Available severities: {}",
+        Text::NotSubscribed => "This chat is not subscribed, use /start to subscribe first.",
+        Text::FormatChanged => "Message format changed to {}.",
+        Text::FormatUnknown => "Available formats: {}",
+        Text::GroupingChanged => "Updates are now grouped by {}.",
+        Text::GroupingUsage => "Usage: /group arch|package|default",
+        Text::ReleasesOn => "This chat will be told about the new releases of AOSC OS.",
+        Text::ReleasesOff => "This chat will no longer be told about the new releases of AOSC OS.",
+        Text::ReleasesUsage => "Usage: /releases on|off",
+        Text::Release => "💿 New AOSC OS release: {} {} {}",
+        Text::AdminOnly => "Only the administrators of this chat can do this.",
+        Text::TemplateChanged => "Template of the {} updated.",
+        Text::TemplateReset => "Templates reset to the default layout.",
+        Text::TemplateInvalid => "Invalid template: {}",
+        Text::RateLimited => "{} more packages updated, see /recent.",
+        Text::NoRecent => "No recent updates.",
+        Text::PkgUsage => "Usage: /pkg <package name>",
+        Text::PkgUnknown => "Package {} is not found.",
+        Text::RateLimitChanged => "Messages per hour limited to {} (0 means unlimited).",
+        Text::RateLimitUsage => "Usage: /ratelimit <messages per hour>|off|default",
+        Text::Snoozed => "🔕 Notifications snoozed for {}, use /snooze off to resume.",
+        Text::SnoozedUntil => "🔕 Notifications snoozed until {}, use /snooze off to resume.",
+        Text::SnoozeUsage => "Usage: /snooze <duration, e.g. 30m, 2h or 1d>|<time, e.g. 08:00>|off",
+        Text::NotSnoozed => "Notifications are not snoozed.",
+        Text::QuietHoursCurrent => "🌙 The updates are held every day from {}, use /quiet off to stop.",
+        Text::QuietHoursSet => "🌙 The updates will be held every day from {} and sent when the quiet hours end.",
+        Text::QuietHoursOff => "Quiet hours turned off.",
+        Text::QuietHoursUsage => "Usage: /quiet <start-end, e.g. 22:00-07:00, in the time zone set with /tz>|off",
+        Text::TimezoneChanged => "Time zone changed to {}.",
+        Text::TimezoneCurrent => "The time zone of this chat is {}, change it with /tz <name, e.g. Asia/Shanghai>.",
+        Text::TimezoneUnknown => "Unknown time zone {}, use a name of the tz database like Asia/Shanghai or UTC.",
+        Text::Resumed => "🔔 Notifications resumed.",
+        Text::ResumedWithUpdates => "🔔 Notifications resumed, {} packages were updated meanwhile, see /recent.",
+        Text::Backfill => "While I was away, {} packages were updated:",
+        Text::SummaryNew => "{} new",
+        Text::SummaryUpgraded => "{} upgraded",
+        Text::SummaryRemoved => "{} removed",
+        Text::SummaryOverwritten => "{} overwritten",
+        Text::SummaryOther => "{} other",
+        Text::Flooded => "📦 {} packages updated in {}",
+        Text::FloodedListing => "See the full list",
+        Text::Rebuilt => "{} packages rebuilt",
+        Text::StatusSource => "{}: {}, last event: {}, pending updates: {}",
+        Text::StatusRejected => "{}: {} unauthenticated messages dropped",
+        Text::StatusConnected => "connected",
+        Text::StatusDisconnected => "disconnected",
+        Text::StatusUndelivered => "Messages waiting to be resent: {}",
+        Text::StatusChat => "This chat: batch #{} received up to message {} {} (last batch: #{}), messages waiting to be resent: {}",
+        Text::StatusChatNone => "This chat: nothing received since the start (last batch: #{}), messages waiting to be resent: {}",
+        Text::StatusFailures => "Failed sends: {} rate limited, {} blocked, {} network, {} other (out of {})",
+        Text::StatusRefresh => "Last repository refresh: {}",
+        Text::StatusAgo => "{} ago",
+        Text::StatusNever => "never",
+        Text::ReportMirrorUsage => "Usage: /reportmirror <mirror> <details>",
+        Text::ResolveMirrorUsage => "Usage: /resolvemirror <mirror>",
+        Text::MirrorUnknown => "Unknown mirror, available mirrors: {}",
+        Text::MirrorReported => "⚠️ Mirror issue reported by a user, {}",
+        Text::MirrorReportFiled => "Thank you, the mirror operators have been notified.",
+        Text::MirrorReportCounted => "Thank you, this issue has been reported {} times and is being looked into.",
+        Text::OperatorOnly => "Only the mirror operators can do this.",
+        Text::MirrorResolved => "The reports of {} have been resolved.",
+        Text::MirrorNotReported => "There is no open report of {}.",
+        Text::RebuiltAgainst => "{} packages rebuilt against {}",
+        Text::NoFilters => "This chat receives all the updates.",
+        Text::FilterChanged => "Filter on {} updated.",
+        Text::FilterRemoved => "Filter on {} removed.",
+        Text::FilterUnknown => "Unknown value: {}",
+        Text::Muted => "Updates of {} muted, use /unmute to receive them again.",
+        Text::Unmuted => "Updates of {} unmuted.",
+        Text::NotMuted => "{} is not muted.",
+        Text::MuteUsage => "Usage: /mute <package> or /unmute <package>, /filter lists the muted packages.",
+        Text::FilterUsage => "Usage:\n/filter\n/filter repo [repository...]\n/filter group [architecture group...], e.g. mainline or retro\n/filter comp [component...], e.g. stable\n/filter arch [architecture...]\n\nAn empty list removes the filter.",
+        Text::TemplateUsage => "Usage:\n/template show\n/template reset\n/template header|line|footer [Handlebars template]\n\nVariables: {{repo}}, {{comp}} and {{arch}} in the header; {{repo}}, {{comp}}, {{arch}}, {{arches}} (when grouped by package), {{pkg}}, {{method}}, {{from_ver}}, {{to_ver}}, {{url}}, {{security}} (whether it is a security fix), {{mentions}} (maintainers to notify), {{description}} (of the new packages) and {{changelog}} (link to the changes of the upgrades) in the line; {{count}} in the footer. An empty template restores the default.",
+    }
+}
+
+fn zh_cn(text: Text) -> &'static str {
+    match text {
+        Text::Subscribed => "已订阅更新。",
+        Text::Unsubscribed => "已取消订阅。",
+        Text::Pong => "Pong！",
+        Text::Refreshed => "🔄 软件仓库已刷新。",
+        Text::RefreshedIn => "🔄 软件仓库已刷新，耗时 {}：{}。",
+        Text::UpdateCount => "{} 个软件包更新",
+        Text::UpdateCountOne => "1 个软件包更新",
+        Text::MoreUpdates => "……以及其他 {} 项更新。",
+        Text::ShowMore => "显示更多",
+        Text::BatchExpired => "该批更新已不可用。",
+        Text::ButtonMute => "🔕 静音 1 天",
+        Text::ButtonSettings => "⚙️ 设置",
+        Text::ButtonUnsubscribe => "取消订阅",
+        Text::SetupComps => "第 1/3 步：选择要接收更新的组件（分支），不选则接收全部组件。",
+        Text::SetupArches => "第 2/3 步：选择架构，不选则接收全部架构。",
+        Text::SetupDigest => "第 3/3 步：选择摘要模式，即消息中的更新如何分组。",
+        Text::SetupDone => "✅ 设置完成。\n\n{}",
+        Text::SetupCancelled => "已取消设置。",
+        Text::SetupExpired => "该设置已失效，请使用 /setup 重新开始。",
+        Text::ButtonNext => "下一步 ➡️",
+        Text::ButtonCancel => "取消",
+        Text::ButtonByArch => "按架构",
+        Text::ButtonByPackage => "按软件包",
+        Text::Settings => "⚙️ 本聊天的设置\n语言：{}\n最低严重程度：{}\n格式：{}\n时区：{}\n\n{}\n\n使用 /lang、/severity、/format、/tz 和 /filter 修改。",
+        Text::LangChanged => "语言已切换为 {}。",
+        Text::LangUnknown => "可用的语言：{}",
+        Text::InvalidMessage => "⚠️ 收到来自 p-vector 的无效消息：{}",
+        Text::Stopped => "❌ 错误过多，已停止监听 p-vector！",
+        Text::SeverityChanged => "最低通知级别已设置为 {}。",
+        Text::SeverityUnknown => "可用的通知级别：{}",
+        Text::NotSubscribed => "本聊天尚未订阅，请先使用 /start 订阅。",
+        Text::FormatChanged => "消息格式已设置为 {}。",
+        Text::FormatUnknown => "可用的消息格式：{}",
+        Text::GroupingChanged => "更新现按 {} 分组。",
+        Text::GroupingUsage => "用法：/group arch|package|default",
+        Text::ReleasesOn => "本聊天将收到 AOSC OS 新版本的通知。",
+        Text::ReleasesOff => "本聊天将不再收到 AOSC OS 新版本的通知。",
+        Text::ReleasesUsage => "用法：/releases on|off",
+        Text::Release => "💿 AOSC OS 新版本：{} {} {}",
+        Text::AdminOnly => "只有本聊天的管理员可以进行此操作。",
+        Text::TemplateChanged => "已更新 {} 模板。",
+        Text::TemplateReset => "已恢复默认消息模板。",
+        Text::TemplateInvalid => "无效的模板：{}",
+        Text::RateLimited => "另有 {} 个软件包已更新，详见 /recent。",
+        Text::NoRecent => "近期没有更新。",
+        Text::PkgUsage => "用法：/pkg <软件包名>",
+        Text::PkgUnknown => "未找到软件包 {}。",
+        Text::RateLimitChanged => "每小时最多发送 {} 条消息（0 表示不限制）。",
+        Text::RateLimitUsage => "用法：/ratelimit <每小时消息数>|off|default",
+        Text::Snoozed => "🔕 已暂停通知 {}，使用 /snooze off 恢复。",
+        Text::SnoozedUntil => "🔕 已暂停通知至 {}，使用 /snooze off 恢复。",
+        Text::SnoozeUsage => "用法：/snooze <时长，如 30m、2h 或 1d>|<时刻，如 08:00>|off",
+        Text::NotSnoozed => "通知未被暂停。",
+        Text::QuietHoursCurrent => "🌙 每天 {} 暂缓推送更新，使用 /quiet off 关闭。",
+        Text::QuietHoursSet => "🌙 每天 {} 将暂缓推送更新，并在免打扰时段结束时发送。",
+        Text::QuietHoursOff => "免打扰时段已关闭。",
+        Text::QuietHoursUsage => "用法：/quiet <起止时刻，如 22:00-07:00，按 /tz 设置的时区>|off",
+        Text::TimezoneChanged => "时区已切换为 {}。",
+        Text::TimezoneCurrent => "本聊天的时区为 {}，使用 /tz <时区名，如 Asia/Shanghai> 修改。",
+        Text::TimezoneUnknown => "未知的时区 {}，请使用时区数据库中的名称，如 Asia/Shanghai 或 UTC。",
+        Text::Resumed => "🔔 已恢复通知。",
+        Text::ResumedWithUpdates => "🔔 已恢复通知，暂停期间有 {} 个软件包更新，详见 /recent。",
+        Text::Backfill => "在我离线期间，有 {} 个软件包更新：",
+        Text::SummaryNew => "新增 {} 个",
+        Text::SummaryUpgraded => "更新 {} 个",
+        Text::SummaryRemoved => "移除 {} 个",
+        Text::SummaryOverwritten => "覆盖 {} 个",
+        Text::SummaryOther => "其他 {} 个",
+        Text::Flooded => "📦 {} 个软件包在 {} 中更新",
+        Text::FloodedListing => "查看完整列表",
+        Text::Rebuilt => "{} 个软件包已重新构建",
+        Text::StatusSource => "{}：{}，上次收到消息：{}，待发送更新：{} 个",
+        Text::StatusRejected => "{}：已丢弃 {} 条未通过验证的消息",
+        Text::StatusConnected => "已连接",
+        Text::StatusDisconnected => "未连接",
+        Text::StatusUndelivered => "等待重新发送的消息：{} 条",
+        Text::StatusChat => "本聊天：第 #{} 批已收到第 {} 条消息（{}，最新一批：#{}），等待重新发送的消息：{} 条",
+        Text::StatusChatNone => "本聊天：启动以来未收到任何消息（最新一批：#{}），等待重新发送的消息：{} 条",
+        Text::StatusFailures => "发送失败：限流 {} 次，被屏蔽 {} 次，网络错误 {} 次，其他 {} 次（共尝试 {} 次）",
+        Text::StatusRefresh => "上次刷新软件仓库：{}",
+        Text::StatusAgo => "{}前",
+        Text::StatusNever => "从未",
+        Text::ReportMirrorUsage => "用法：/reportmirror <镜像> <问题描述>",
+        Text::ResolveMirrorUsage => "用法：/resolvemirror <镜像>",
+        Text::MirrorUnknown => "未知的镜像，可用的镜像：{}",
+        Text::MirrorReported => "⚠️ 用户报告了镜像问题，{}",
+        Text::MirrorReportFiled => "感谢报告，已通知镜像维护者。",
+        Text::MirrorReportCounted => "感谢报告，该问题已被报告 {} 次，正在处理中。",
+        Text::OperatorOnly => "仅镜像维护者可执行此操作。",
+        Text::MirrorResolved => "{} 的问题报告已解决。",
+        Text::MirrorNotReported => "{} 没有未解决的问题报告。",
+        Text::RebuiltAgainst => "{} 个软件包已针对 {} 重新构建",
+        Text::NoFilters => "本聊天接收所有更新。",
+        Text::FilterChanged => "已更新 {} 过滤器。",
+        Text::FilterRemoved => "已移除 {} 过滤器。",
+        Text::FilterUnknown => "未知的值：{}",
+        Text::Muted => "已屏蔽 {} 的更新，使用 /unmute 恢复接收。",
+        Text::Unmuted => "已恢复接收 {} 的更新。",
+        Text::NotMuted => "{} 未被屏蔽。",
+        Text::MuteUsage => "用法：/mute <软件包> 或 /unmute <软件包>，/filter 可列出已屏蔽的软件包。",
+        Text::FilterUsage => "用法：\n/filter\n/filter repo [软件仓库...]\n/filter group [架构组...]，如 mainline 或 retro\n/filter comp [组件...]，如 stable\n/filter arch [架构...]\n\n列表留空即移除过滤器。",
+        Text::TemplateUsage => "用法：\n/template show\n/template reset\n/template header|line|footer [Handlebars 模板]\n\n变量：header 中可使用 {{repo}}、{{comp}} 和 {{arch}}；line 中可使用 {{repo}}、{{comp}}、{{arch}}、{{arches}}（按软件包分组时）、{{pkg}}、{{method}}、{{from_ver}}、{{to_ver}}、{{url}}、{{security}}（是否为安全更新）、{{mentions}}（需要提醒的维护者）、{{description}}（新软件包的简介）和 {{changelog}}（升级的变更链接）；footer 中可使用 {{count}}。模板留空即恢复默认。",
+    }
+}
+
+/// Get the translation of the text in the given language (falls back to English)
+pub fn tr(lang: &str, text: Text) -> &'static str {
+    match lang {
+        "zh-CN" => zh_cn(text),
+        _ => en(text),
+    }
+}
+
+/// Get the translation of the text with the placeholder replaced by `arg`
+pub fn tr_with(lang: &str, text: Text, arg: &str) -> String {
+    tr(lang, text).replacen("{}", arg, 1)
+}
+
+/// Get the title of a batch of `count` updates
+pub fn update_count(lang: &str, count: usize) -> String {
+    match count {
+        1 => tr(lang, Text::UpdateCountOne).to_string(),
+        _ => tr_with(lang, Text::UpdateCount, &count.to_string()),
+    }
+}
+
+/// Find the supported language matching the given language tag (case-insensitive)
+pub fn find_language(lang: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|l| l.eq_ignore_ascii_case(lang.trim()))
+        .copied()
+}
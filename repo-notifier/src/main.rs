@@ -1,41 +1,105 @@
 use anyhow::{anyhow, Result};
-use defaultmap::DefaultHashMap;
 use futures_util::StreamExt;
 use inotify::{Inotify, WatchMask};
-use serde::Deserialize;
+use notifier_core::{chunks, priority, wire, PVMessage};
+use once_cell::sync::Lazy;
+use serde_json::json;
 use sqlx::{migrate, query, sqlite};
-use std::sync::atomic::AtomicBool;
+use std::collections::HashMap;
+use std::sync::{atomic::AtomicBool, Arc};
 use std::{sync::atomic::Ordering, time::Duration};
 use teloxide::{
-    payloads::SendMessageSetters,
+    payloads::{EditMessageTextSetters, SendMessageSetters},
     prelude::*,
     respond,
-    types::{ChatId, ParseMode},
-    utils::command::BotCommands,
+    types::{Chat, ChatId, InlineKeyboardMarkup, MessageId, ReplyParameters, User},
+    utils::{command::BotCommands, html, markdown},
     RequestError,
 };
 use tokio::time::sleep;
 
-const LIST_MAX_SIZE: usize = 22;
-// The maximum size of a Telegram message is 4096 chars. 4000 is just for the safety.
-const LIST_MAX_LENGTH: isize = 4000;
-const COOLDOWN_TIME: usize = 20usize;
+use crate::filter::Filters;
+use crate::format::Format;
+use crate::grouping::Grouping;
+use crate::i18n::{tr, tr_with, Text};
+use crate::settings::Subscriber;
+use crate::severity::Severity;
+use crate::template::{Layout, Part};
 
-type EntryMapping = DefaultHashMap<String, Vec<String>>;
+// Number of the recent updates looked up for /recent before applying the filters
+const RECENT_LOOKUP: i64 = 200;
+// Number of the most recently updated components offered by /setup
+const SETUP_COMPS: i64 = 24;
+// Attempts to send a message before giving up
+const SEND_ATTEMPTS: usize = 5;
+// Longest wait before retrying after an unexpected error
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
 
+/// Messages sent to each chat in the current batch (the actual chat ID, message ID and its content)
+type BatchMessages = HashMap<i64, (ChatId, MessageId, String)>;
+
+/// Where a message goes in the chat
+#[derive(Clone, Copy)]
+enum Target {
+    New,
+    /// Replace the text of this message
+    Edit(MessageId),
+    /// Reply to this message
+    Reply(MessageId),
+}
+
+/// Secret shared with p-vector to authenticate its messages
+static SECRET: Lazy<Option<Vec<u8>>> = Lazy::new(|| {
+    std::env::var("PV_HMAC_SECRET")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(String::into_bytes)
+});
 static UPDATED: AtomicBool = AtomicBool::new(false);
 static MSGSENT: AtomicBool = AtomicBool::new(false);
 static WRITTEN: AtomicBool = AtomicBool::new(false);
 
-macro_rules! send_to_subscribers {
-    ($c:expr, $bot:ident, $subs:ident) => {
-        for sub in $subs.iter() {
-            if let Err(e) = send_with_retry($c, $bot, sub.chat_id).await {
-                log::error!("{}", e);
-            }
-        }
-    };
-}
+mod api;
+mod audit;
+mod backfill;
+mod backup;
+mod bots;
+mod buttons;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod config;
+mod dashboard;
+mod delivery;
+mod descriptions;
+mod dry_run;
+mod eventlog;
+mod failures;
+mod filter;
+mod format;
+mod grouping;
+mod history;
+mod i18n;
+mod maintenance;
+mod mirror_reports;
+#[cfg(test)]
+mod mock_api;
+mod pages;
+mod ratelimit;
+mod rebuilds;
+mod refresh;
+mod releases;
+mod schedule;
+mod settings;
+mod setup;
+mod severity;
+mod sinks;
+mod snooze;
+mod stale;
+mod status;
+mod summary;
+mod template;
+mod timezone;
+mod versions;
 
 #[derive(BotCommands, Clone)]
 #[command(
@@ -53,51 +117,79 @@ enum Command {
     Ping,
     #[command(description = "display the `chat_id` of this chat.")]
     ChatID,
+    #[command(description = "set the language of this chat (en, zh-CN).")]
+    Lang(String),
+    #[command(description = "set the time zone of this chat (e.g. Asia/Shanghai, or default).")]
+    Tz(String),
+    #[command(
+        description = "set the minimum severity of the messages (heartbeat, routine, warning, critical)."
+    )]
+    Severity(String),
+    #[command(description = "set the format of the messages (html, markdown, plain).")]
+    Format(String),
+    #[command(
+        description = "group the updates by architecture or by package (arch, package or default)."
+    )]
+    Group(String),
+    #[command(description = "customize the layout of the messages (admins only).")]
+    Template(String),
+    #[command(
+        description = "only receive the updates of the given repositories (/filter repo stable)."
+    )]
+    Filter(String),
+    #[command(
+        description = "choose the components, architectures and grouping step by step (admins only)."
+    )]
+    Setup,
+    #[command(description = "stop receiving the updates of a package.")]
+    Mute(String),
+    #[command(description = "receive the updates of a muted package again.")]
+    Unmute(String),
+    #[command(description = "show the recent updates.")]
+    Recent,
+    #[command(description = "show the current versions of a package.")]
+    Pkg(String),
+    #[command(
+        description = "show the health of the notification pipeline (and of the deliveries to the chat for the admins)."
+    )]
+    Status,
+    #[command(description = "get told about the new releases of AOSC OS (on or off).")]
+    Releases(String),
+    #[command(
+        description = "report an issue with a download mirror (/reportmirror <mirror> <details>)."
+    )]
+    ReportMirror(String),
+    #[command(
+        description = "resolve the reports of a mirror (/resolvemirror <mirror>, mirror operators only)."
+    )]
+    ResolveMirror(String),
+    #[command(
+        description = "limit the number of messages per hour (admins only; a number, off or default)."
+    )]
+    RateLimit(String),
+    #[command(
+        description = "pause the notifications for a while (admins only; e.g. 2h, 08:00, or off)."
+    )]
+    Snooze(String),
+    #[command(
+        description = "hold the updates every day in the time zone of this chat (admins only; e.g. 22:00-07:00, or off)."
+    )]
+    Quiet(String),
 }
 
-#[derive(Deserialize, Clone, Debug)]
-#[serde(untagged)]
-enum PVMessageMethod {
-    Old(String),
-    New(u8),
-}
-
-impl PVMessageMethod {
-    fn as_new_type(&self) -> u8 {
-        match self {
-            PVMessageMethod::New(v) => *v,
-            PVMessageMethod::Old(v) => match v.as_str() {
-                "new" => b'+',
-                "upgrade" => b'^',
-                "delete" => b'-',
-                "overwrite" => b'*',
-                _ => b'?',
-            },
-        }
-    }
-}
-
-#[derive(Deserialize, Clone, Debug)]
-struct PVMessage {
-    comp: String,
-    pkg: String,
-    arch: String,
-    method: PVMessageMethod,
-    from_ver: Option<String>,
-    to_ver: Option<String>,
-}
-
-#[derive(Deserialize, Clone, Debug)]
-struct PVMessageNew {
-    comp: String,
-    pkg: String,
-    arch: String,
-    method: u8,
-    from_ver: Option<String>,
-    to_ver: Option<String>,
+/// Rendering and filtering of the updates, which depend on the configuration
+trait PVMessageExt {
+    fn to_html(&self) -> String;
+    fn to_markdown(&self) -> String;
+    fn to_plain(&self) -> String;
+    fn allowed_by(&self, filters: &Filters) -> bool;
+    fn header(&self, format: Format, grouping: Grouping) -> String;
+    fn changelog_url(&self) -> Option<String>;
+    fn template_context(&self) -> serde_json::Value;
+    fn render(&self, format: Format) -> String;
 }
 
-impl PVMessage {
+impl PVMessageExt for PVMessage {
     fn to_html(&self) -> String {
         match self.method.as_new_type() {
             b'+' => format!(
@@ -125,13 +217,162 @@ impl PVMessage {
                 self.pkg,
                 self.from_ver.as_ref().unwrap_or(&"?".to_string())
             ),
-            b'i' => format!(r#"<code> i</code> {}"#, self.pkg),
+            b'i' => match self.failure() {
+                Some((what, message)) => format!(
+                    "<code> i</code> {} {}: <code>{}</code>",
+                    html::escape(&self.pkg),
+                    html::escape(&what),
+                    html::escape(&message)
+                ),
+                None => format!(r#"<code> i</code> {}"#, self.pkg),
+            },
             _ => format!(
                 r#"<code> ?</code> <a href="https://packages.aosc.io/packages/{}">{}</a> Unknown operation"#,
                 self.pkg, self.pkg,
             ),
         }
     }
+
+    fn to_markdown(&self) -> String {
+        let link = format!(
+            "[{}]({})",
+            markdown::escape(&self.pkg),
+            markdown::escape_link_url(&format!("https://packages.aosc.io/packages/{}", self.pkg))
+        );
+        let code = |v: &Option<String>| {
+            format!("`{}`", markdown::escape_code(v.as_deref().unwrap_or("?")))
+        };
+        match self.method.as_new_type() {
+            b'+' => format!("` +` {} {}", link, code(&self.to_ver)),
+            b'^' => format!(
+                "` ^` {} {} ⇒ {}",
+                link,
+                code(&self.from_ver),
+                code(&self.to_ver)
+            ),
+            b'-' => format!("` -` {} {}", link, code(&self.from_ver)),
+            b'*' => format!("` *` {} {}", link, code(&self.from_ver)),
+            b'i' => match self.failure() {
+                Some((what, message)) => format!(
+                    "` i` {} {}: `{}`",
+                    markdown::escape(&self.pkg),
+                    markdown::escape(&what),
+                    markdown::escape_code(&message)
+                ),
+                None => format!("` i` {}", markdown::escape(&self.pkg)),
+            },
+            _ => format!("` ?` {} Unknown operation", link),
+        }
+    }
+
+    fn to_plain(&self) -> String {
+        let ver = |v: &Option<String>| v.clone().unwrap_or_else(|| "?".to_string());
+        match self.method.as_new_type() {
+            b'+' => format!(" + {} {}", self.pkg, ver(&self.to_ver)),
+            b'^' => format!(
+                " ^ {} {} ⇒ {}",
+                self.pkg,
+                ver(&self.from_ver),
+                ver(&self.to_ver)
+            ),
+            b'-' => format!(" - {} {}", self.pkg, ver(&self.from_ver)),
+            b'*' => format!(" * {} {}", self.pkg, ver(&self.from_ver)),
+            b'i' => match self.failure() {
+                Some((what, message)) => format!(" i {} {}: {}", self.pkg, what, message),
+                None => format!(" i {}", self.pkg),
+            },
+            _ => format!(" ? {} Unknown operation", self.pkg),
+        }
+    }
+
+    /// Check whether the update passes the filters of a chat
+    fn allowed_by(&self, filters: &Filters) -> bool {
+        let groups = &config::get().arch_groups;
+        filters.allows(filter::Kind::Repo, self.repo.as_deref())
+            && filters.allows(filter::Kind::Comp, Some(&self.comp))
+            && filters.allows(filter::Kind::Arch, Some(&self.arch))
+            && filters.matches(filter::Kind::Group, |g| groups.contains(g, &self.arch))
+            && !filters.is_muted(&self.pkg)
+    }
+
+    /// Heading of the component and architecture (only component when grouped by package)
+    /// of the update
+    fn header(&self, format: Format, grouping: Grouping) -> String {
+        let comp = format.bold(&format.escape(&self.comp));
+        let header = match grouping {
+            Grouping::Arch => format!("{} {}", comp, format.escape(&self.arch)),
+            Grouping::Package => comp,
+        };
+        match self.repo.as_deref() {
+            Some(repo) => format!("{} {}", format.escape(&format!("[{}]", repo)), header),
+            None => header,
+        }
+    }
+
+    /// Link to the changes of an upgrade (if configured)
+    fn changelog_url(&self) -> Option<String> {
+        if self.method.as_new_type() != b'^' {
+            return None;
+        }
+        let (from, to) = (self.from_ver.as_deref()?, self.to_ver.as_deref()?);
+
+        config::get().changelog.url_of(&self.pkg, from, to)
+    }
+
+    /// Variables available to the custom line template
+    fn template_context(&self) -> serde_json::Value {
+        json!({
+            "repo": self.repo,
+            "comp": self.comp,
+            "arch": self.arch,
+            "pkg": self.pkg,
+            "method": (self.method.as_new_type() as char).to_string(),
+            "from_ver": self.from_ver,
+            "to_ver": self.to_ver,
+            "url": format!("https://packages.aosc.io/packages/{}", self.pkg),
+            "security": self.security,
+            "mentions": self.mentions,
+            "description": self.description,
+            "changelog": self.changelog_url(),
+        })
+    }
+
+    fn render(&self, format: Format) -> String {
+        let line = match format {
+            Format::Html => self.to_html(),
+            Format::Markdown => self.to_markdown(),
+            Format::Plain => self.to_plain(),
+        };
+        let line = if self.security {
+            format!("🔒{}", line)
+        } else {
+            line
+        };
+        let line = match self.changelog_url() {
+            Some(url) => format!(
+                "{} {}",
+                line,
+                format.link(&format.escape("changelog"), &url)
+            ),
+            None => line,
+        };
+        let line = match self.description.as_deref() {
+            Some(description) => {
+                format!("{} — {}", line, format.italic(&format.escape(description)))
+            }
+            None => line,
+        };
+        if self.mentions.is_empty() {
+            return line;
+        }
+        let mentions = self
+            .mentions
+            .iter()
+            .map(|user| format.escape(&format!("@{}", user)))
+            .collect::<Vec<_>>();
+
+        format!("{} {}", line, mentions.join(" "))
+    }
 }
 
 async fn connect_redis(endpoint: &str) -> Result<redis::Client> {
@@ -140,135 +381,915 @@ async fn connect_redis(endpoint: &str) -> Result<redis::Client> {
 }
 
 #[inline]
-fn method_to_priority(v: &PVMessage) -> u8 {
-    match v.method.as_new_type() {
-        b'i' | b'-' => 0,
-        b'+' => 1,
-        b'*' => 2,
-        b'^' => 3,
-        _ => 99,
-    }
+fn method_to_priority(v: &PVMessage) -> i32 {
+    config::get()
+        .priority
+        .priority_of(v.method.as_new_type(), &v.comp)
 }
 
-/// Sort the messages by priority and then truncate them to the given length
-fn sort_pending_messages_chunk(pending: &mut Vec<PVMessage>) -> EntryMapping {
-    let mut mapping: DefaultHashMap<String, Vec<String>> = DefaultHashMap::new();
-    let mut remaining = LIST_MAX_LENGTH;
-    let mut list_remaining = LIST_MAX_SIZE;
-    mapping.reserve(LIST_MAX_SIZE);
-    pending.sort_unstable_by_key(method_to_priority);
-    while !pending.is_empty() && remaining > 0 && list_remaining > 0 {
-        let p = pending.pop();
-        if p.is_none() {
-            break;
+/// Mark the security fixes according to the configured patterns, and mention the
+/// maintainers of the removed packages (or the ones with unknown operations)
+fn classify_messages(messages: &mut [PVMessage]) {
+    let config = config::get();
+    for p in messages.iter_mut() {
+        p.security = config
+            .security
+            .is_security_fix(&p.comp, p.to_ver.as_deref());
+        if matches!(p.method.as_new_type(), b'-' | b'?') {
+            p.mentions = config.maintainers.of(&p.pkg).to_vec();
         }
-        let p = p.unwrap();
-        let html = p.to_html();
-        let len = html.len();
-        mapping[format!("<b>{}</b> {}\n", p.comp, p.arch)].push(html);
-        remaining -= len as isize;
-        list_remaining -= 1;
     }
-
-    mapping
 }
 
-fn format_sorted_mapping(mapping: EntryMapping) -> String {
-    let mut output = String::new();
-    output.reserve(4096);
-    for (k, v) in mapping.iter() {
-        output += k;
-        output += &v.join("\n");
-        output += "\n\n";
+/// Render the sorted messages in the given layout and split them into chunks
+/// (number of updates and the formatted content) that fit in a Telegram message,
+/// leaving `reserved` characters for the content added afterwards
+fn split_into_chunks(
+    messages: &[PVMessage],
+    layout: &Layout,
+    lang: &str,
+    reserved: usize,
+) -> Vec<(usize, String)> {
+    let (format, template, grouping) = layout;
+    let (format, grouping) = (*format, *grouping);
+    let renderer = template::Renderer::new(format, template);
+    let footer = |count: usize| renderer.render(Part::Footer, &json!({ "count": count }));
+    let batching = &config::get().batching;
+    // leave room for the custom footer
+    let footer_length = footer(batching.max_lines).map(|f| f.len()).unwrap_or(0) as isize;
+    let mut entries = rebuilds::collapse(messages, batching.rebuild_threshold, grouping);
+    if grouping == Grouping::Package {
+        entries = grouping::by_package(entries);
+    }
+    let lines = entries.into_iter().map(|entry| {
+        let p = entry.first();
+        let text = match &entry {
+            rebuilds::Entry::Single(p) => renderer
+                .render(Part::Line, &p.template_context())
+                .unwrap_or_else(|| p.render(format)),
+            rebuilds::Entry::Arches(packages) => {
+                let mut context = p.template_context();
+                context["arches"] = json!(packages.iter().map(|p| &p.arch).collect::<Vec<_>>());
+                renderer.render(Part::Line, &context).unwrap_or_else(|| {
+                    format!(
+                        "{} {}",
+                        p.render(format),
+                        grouping::arches(packages, format)
+                    )
+                })
+            }
+            rebuilds::Entry::Rebuilds { packages, against } => {
+                rebuilds::render(packages, *against, lang, format)
+            }
+        };
+        let arch = (grouping == Grouping::Arch).then_some(&p.arch);
+        let header = renderer
+            .render(
+                Part::Header,
+                &json!({ "repo": p.repo, "comp": p.comp, "arch": arch }),
+            )
+            .unwrap_or_else(|| p.header(format, grouping))
+            + "\n";
+        chunks::Line {
+            header,
+            text,
+            count: entry.count(),
+        }
+    });
+    let room = batching.max_length as isize - footer_length - reserved as isize;
+    let mut chunks = chunks::split(lines, room, batching.max_lines);
+    for (count, formatted) in chunks.iter_mut() {
+        if let Some(footer) = footer(*count) {
+            *formatted += &footer;
+            *formatted += "\n\n";
+        }
     }
 
-    output
+    chunks
 }
 
 #[inline]
-async fn send_with_retry(msg: &str, bot: &Bot, chat_id: i64) -> Result<()> {
-    let mut retries = 5usize;
-    let mut chat_id = ChatId(chat_id);
+async fn send_with_retry(
+    msg: &str,
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+    mut chat_id: ChatId,
+    target: Target,
+    markup: Option<&InlineKeyboardMarkup>,
+    format: Format,
+) -> Result<Message> {
+    match dry_run::mode() {
+        Some(dry_run::Mode::Log) => {
+            log::info!("Dry run, message for {}:\n{}", chat_id, msg);
+            return Ok(dry_run::message(chat_id, msg));
+        }
+        Some(dry_run::Mode::Chat(test_chat)) if *test_chat != chat_id => {
+            log::info!(
+                "Dry run, sending the message for {} to {}.",
+                chat_id,
+                test_chat
+            );
+            chat_id = *test_chat;
+        }
+        _ => (),
+    }
+    // the messages with buttons are sent by the bot receiving the callbacks, the bot serving the
+    // chat is kept if it moves
+    let mut extra = match markup {
+        Some(_) => None,
+        None => bots::for_chat(chat_id),
+    };
+    let mut retries = SEND_ATTEMPTS;
     while retries > 0 {
-        let result = bot
-            .send_message(chat_id, msg)
-            .parse_mode(ParseMode::Html)
-            .await;
-        if let Err(e) = result {
-            retries -= 1;
-            match e {
-                RequestError::RetryAfter(t) => {
-                    log::warn!("Rate limited, will retry after {} seconds", t.seconds());
-                    sleep(t.duration()).await;
+        let bot = extra.as_ref().unwrap_or(bot);
+        #[cfg(feature = "chaos")]
+        let injected = chaos::telegram_error();
+        #[cfg(not(feature = "chaos"))]
+        let injected = None;
+        let result = match (injected, target) {
+            (Some(e), _) => Err(e),
+            (None, Target::Edit(message_id)) => {
+                let mut request = bot.edit_message_text(chat_id, message_id, msg);
+                if let Some(mode) = format.parse_mode() {
+                    request = request.parse_mode(mode);
+                }
+                if let Some(markup) = markup {
+                    request = request.reply_markup(markup.clone());
+                }
+                request.await
+            }
+            (None, Target::New | Target::Reply(_)) => {
+                let mut request = bot.send_message(chat_id, msg);
+                if let Some(mode) = format.parse_mode() {
+                    request = request.parse_mode(mode);
                 }
-                RequestError::MigrateToChatId(id) => {
-                    log::warn!("Chat ID {} changed to {}", chat_id, id);
-                    chat_id = id;
+                if let Target::Reply(message_id) = target {
+                    request = request.reply_parameters(
+                        ReplyParameters::new(message_id).allow_sending_without_reply(),
+                    );
                 }
-                _ => {
-                    log::warn!("Unexpected error occurred ({:?}), retrying ...", e);
-                    sleep(Duration::from_secs(10)).await;
+                if let Some(markup) = markup {
+                    request = request.reply_markup(markup.clone());
                 }
+                request.await
             }
-        } else {
-            return Ok(());
+        };
+        let e = match result {
+            Ok(message) => {
+                failures::succeeded();
+                return Ok(message);
+            }
+            Err(e) => e,
+        };
+        failures::failed(&e);
+        retries -= 1;
+        if extra.is_some() && failures::blocked(&e) {
+            log::warn!(
+                "The extra bot can not post to {} ({}), using the main bot",
+                chat_id,
+                e
+            );
+            extra = None;
+            continue;
+        }
+        match e {
+            RequestError::RetryAfter(t) => {
+                log::warn!("Rate limited, will retry after {} seconds", t.seconds());
+                sleep(t.duration()).await;
+            }
+            RequestError::MigrateToChatId(id) => {
+                log::warn!("Chat ID {} changed to {}", chat_id, id);
+                if let Err(e) = settings::migrate_chat(db, chat_id.0, id.0).await {
+                    log::error!("Could not save the new chat ID {}: {}", id, e);
+                }
+                let detail = id.to_string();
+                if let Err(e) =
+                    audit::record(db, chat_id.0, audit::BOT, "migrate", Some(&detail)).await
+                {
+                    log::error!("Could not record the migration of chat {}: {}", chat_id, e);
+                }
+                chat_id = id;
+            }
+            _ if retries > 0 => {
+                let backoff = backoff(SEND_ATTEMPTS - retries);
+                log::warn!(
+                    "Unexpected error occurred ({:?}), retrying in {} seconds ...",
+                    e,
+                    backoff.as_secs()
+                );
+                sleep(backoff).await;
+            }
+            _ => log::warn!("Unexpected error occurred ({:?}), giving up", e),
         }
     }
 
     Err(anyhow!("Failed to send message to {}", chat_id))
 }
 
-/// Send all the pending messages to the subscribers
+/// Wait before the retry after the `attempt`-th failure (from 1), doubling up to `MAX_BACKOFF`
+fn backoff(attempt: usize) -> Duration {
+    Duration::from_secs(1 << attempt.saturating_sub(1).min(8)).min(MAX_BACKOFF)
+}
+
+/// Send the message to the chat. If a message was already sent to this chat in the same batch
+/// and there is still enough room in it, the message is appended to the previous one using
+/// `editMessageText` instead, so that big batches do not flood the chat with notifications.
+async fn send_or_append(
+    msg: &str,
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+    chat_id: i64,
+    sent: &mut BatchMessages,
+    format: Format,
+    markup: Option<&InlineKeyboardMarkup>,
+) -> Result<()> {
+    if let Some((real_id, message_id, text)) = sent.get(&chat_id).cloned() {
+        if text.len() + msg.len() <= config::get().batching.max_length {
+            let combined = text + msg;
+            match send_with_retry(
+                &combined,
+                bot,
+                db,
+                real_id,
+                Target::Edit(message_id),
+                markup,
+                format,
+            )
+            .await
+            {
+                Ok(_) => {
+                    sent.insert(chat_id, (real_id, message_id, combined));
+                    return Ok(());
+                }
+                Err(e) => log::warn!("Could not append to the previous message: {}", e),
+            }
+        }
+    }
+    let message =
+        send_with_retry(msg, bot, db, ChatId(chat_id), Target::New, markup, format).await?;
+    sent.insert(chat_id, (message.chat.id, message.id, msg.to_string()));
+
+    Ok(())
+}
+
+/// Send (or append) the `chunk`-th message of a batch to the subscriber in its format and
+/// record the outcome in the event log
+async fn deliver(
+    msg: &str,
+    batch: delivery::Batch,
+    chunk: usize,
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+    sub: &Subscriber,
+    sent: &mut BatchMessages,
+) {
+    let keyboard = keyboard(batch, sub);
+    let result = send_or_append(
+        msg,
+        bot,
+        db,
+        sub.chat_id,
+        sent,
+        sub.format,
+        keyboard.as_ref(),
+    )
+    .await;
+    eventlog::record(sub.chat_id, chunk, msg, &result);
+    match result {
+        Ok(_) => record_delivery(db, sub.chat_id, batch, chunk).await,
+        Err(e) => {
+            log::error!("{}", e);
+            delivery::failed(
+                sub.chat_id,
+                batch,
+                chunk,
+                msg,
+                sub.format,
+                keyboard.as_ref(),
+            );
+        }
+    }
+}
+
+/// Deliver the chunk now, or hold it until the quiet hours of the chat end
+#[allow(clippy::too_many_arguments)]
+async fn deliver_or_hold(
+    msg: &str,
+    batch: delivery::Batch,
+    chunk: usize,
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+    sub: &Subscriber,
+    quiet_until: Option<i64>,
+    sent: &mut BatchMessages,
+) {
+    match quiet_until {
+        Some(until) => {
+            let keyboard = keyboard(batch, sub);
+            delivery::hold(
+                sub.chat_id,
+                batch,
+                chunk,
+                msg,
+                sub.format,
+                keyboard.as_ref(),
+                until,
+            );
+        }
+        None => deliver(msg, batch, chunk, bot, db, sub, sent).await,
+    }
+}
+
+/// Buttons under the chunk, only the updates come with them
+fn keyboard(batch: delivery::Batch, sub: &Subscriber) -> Option<InlineKeyboardMarkup> {
+    (batch != delivery::Batch::Notice && config::get().buttons(sub.chat_id))
+        .then(|| buttons::keyboard(&sub.lang))
+}
+
+async fn record_delivery(
+    db: &sqlite::SqlitePool,
+    chat_id: i64,
+    batch: delivery::Batch,
+    chunk: usize,
+) {
+    if let Err(e) = delivery::delivered(db, chat_id, batch, chunk).await {
+        log::error!("Could not record the delivery to {}: {}", chat_id, e);
+    }
+}
+
+/// Resend the chunks that could not be delivered in the previous cycles
+async fn resend_undelivered(bot: &Bot, db: &sqlite::SqlitePool) {
+    for undelivered in delivery::queue().take(snooze::now()) {
+        let (chat_id, chunk) = (undelivered.chat_id, undelivered.chunk);
+        let result = send_with_retry(
+            &undelivered.content,
+            bot,
+            db,
+            ChatId(chat_id),
+            Target::New,
+            undelivered.markup.as_ref(),
+            undelivered.format,
+        )
+        .await
+        .map(|_| ());
+        eventlog::record(chat_id, chunk, &undelivered.content, &result);
+        match result {
+            Ok(_) => record_delivery(db, chat_id, undelivered.batch, chunk).await,
+            Err(e) => {
+                if !delivery::queue().retry_later(undelivered) {
+                    log::error!("Giving up on chunk {} for {}: {}", chunk, chat_id, e);
+                }
+            }
+        }
+    }
+}
+
+/// Send all the pending messages to the subscribers, up to the message of p-vector with the
+/// sequence number if known
 async fn send_all_pending_messages(
     pending: &mut Vec<PVMessage>,
+    sequence: Option<u64>,
     bot: &Bot,
     db: &sqlite::SqlitePool,
 ) -> Result<()> {
     if pending.is_empty() {
         return Ok(());
     }
-    let subs = query!("SELECT chat_id FROM subbed").fetch_all(db).await?;
-    while !pending.is_empty() {
-        let sorted = sort_pending_messages_chunk(pending);
-        let formatted = format_sorted_mapping(sorted);
-        send_to_subscribers!(&formatted, bot, subs);
+    let subs = settings::recipients(db, Severity::Routine).await?;
+    priority::dedup(pending);
+    classify_messages(pending);
+    priority::sort(pending, method_to_priority);
+    let mut messages = std::mem::take(pending);
+    descriptions::enrich(db, &mut messages).await;
+    let batch = delivery::Batch::Updates(sequence);
+    let batching = &config::get().batching;
+    let paginate = batching.paginate;
+    let bypass_snooze = config::get().security.bypass_snooze;
+    // the snoozed chats only get the security fixes right away (if allowed to bypass)
+    let security_only = |sub: &Subscriber| bypass_snooze && sub.is_snoozed();
+    // render the messages only once for the chats sharing the same layout, language and filters
+    let mut views = HashMap::new();
+    let chunks: Vec<Arc<Vec<(usize, String)>>> = subs
+        .iter()
+        .map(|sub| {
+            views
+                .entry((
+                    sub.layout(),
+                    sub.lang.clone(),
+                    sub.filters.clone(),
+                    security_only(sub),
+                ))
+                .or_insert_with_key(|(layout, lang, filters, security_only)| {
+                    let messages = messages
+                        .iter()
+                        .filter(|p| p.allowed_by(filters) && (p.security || !security_only))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if batching.is_flood(messages.len()) {
+                        let text = summary::flood(&messages, lang, layout.0, &batching.flood_url);
+                        return Arc::new(vec![(messages.len(), text)]);
+                    }
+                    let summary = if batching.wants_summary(messages.len()) {
+                        Some(summary::summarize(&messages, lang, layout.0) + "\n\n")
+                    } else {
+                        None
+                    };
+                    let reserved = summary.as_ref().map_or(0, |s| s.len());
+                    let mut chunks = split_into_chunks(&messages, layout, lang, reserved);
+                    if paginate {
+                        chunks = pages::paginate(chunks, batching.max_length - reserved);
+                    }
+                    if let Some(summary) = summary {
+                        for (_, chunk) in chunks.iter_mut() {
+                            chunk.insert_str(0, &summary);
+                        }
+                    }
+                    Arc::new(chunks)
+                })
+                .clone()
+        })
+        .collect();
+    if let Err(e) = history::record(db, &messages).await {
+        log::error!("Could not record the updates: {}", e);
+    }
+    // the updates not sent to the snoozed chats are summarized on resume
+    for (sub, chunks) in subs.iter().zip(chunks.iter()) {
+        if !sub.is_snoozed() {
+            continue;
+        }
+        let mut count = messages
+            .iter()
+            .filter(|p| p.allowed_by(&sub.filters))
+            .count();
+        if security_only(sub) {
+            count -= chunks.iter().map(|c| c.0).sum::<usize>();
+        }
+        if count > 0 {
+            settings::add_snoozed_updates(db, sub.chat_id, count as i64).await?;
+        }
     }
+    // number of the leading messages each chat may receive now and the overflow to summarize
+    let limits = subs
+        .iter()
+        .zip(chunks.iter())
+        .map(|(sub, chunks)| {
+            if sub.is_snoozed() && !security_only(sub) {
+                return (0, None);
+            }
+            if paginate {
+                let counts = if chunks.is_empty() {
+                    vec![]
+                } else {
+                    vec![chunks.iter().map(|p| p.0).sum()]
+                };
+                return ratelimit::acquire(sub.chat_id, sub.messages_per_hour(), &counts);
+            }
+            // only the new messages are charged, not the chunks appended to them
+            let messages = ratelimit::messages(chunks, batching.max_length);
+            let counts = messages.iter().map(|m| m.1).collect::<Vec<_>>();
+            let (allowed, summary) =
+                ratelimit::acquire(sub.chat_id, sub.messages_per_hour(), &counts);
+
+            (messages[..allowed].iter().map(|m| m.0).sum(), summary)
+        })
+        .collect::<Vec<_>>();
+    // the messages to the chats in their quiet hours are held until they end there
+    let now = snooze::now();
+    let quiet = subs
+        .iter()
+        .map(|sub| sub.quiet_until(now))
+        .collect::<Vec<_>>();
+    let mut sent = BatchMessages::new();
+    if paginate {
+        let stored = pages::store(
+            subs.iter()
+                .zip(chunks.iter())
+                .map(|(sub, pages)| (sub.chat_id, pages.clone()))
+                .collect(),
+        );
+        for (((sub, pages), (allowed, _)), quiet) in subs
+            .iter()
+            .zip(chunks.iter())
+            .zip(limits.iter())
+            .zip(quiet.iter())
+        {
+            if *allowed == 0 {
+                continue;
+            }
+            if pages.len() < 2 {
+                if let Some((_, page)) = pages.first() {
+                    deliver_or_hold(page, batch, 0, bot, db, sub, *quiet, &mut sent).await;
+                }
+                continue;
+            }
+            let remaining = pages.iter().skip(1).map(|p| p.0).sum();
+            let first_page = pages[0].1.clone() + &pages::footer(remaining, &sub.lang, sub.format);
+            let mut keyboard = pages::keyboard(stored, 1, &sub.lang);
+            if config::get().buttons(sub.chat_id) {
+                keyboard = keyboard.append_row(buttons::row(&sub.lang));
+            }
+            if let Some(until) = quiet {
+                let (format, markup) = (sub.format, Some(&keyboard));
+                delivery::hold(sub.chat_id, batch, 0, &first_page, format, markup, *until);
+                continue;
+            }
+            let result = send_with_retry(
+                &first_page,
+                bot,
+                db,
+                ChatId(sub.chat_id),
+                Target::New,
+                Some(&keyboard),
+                sub.format,
+            )
+            .await
+            .map(|message| refresh::batch_sent(sub.chat_id, message.chat.id, message.id));
+            eventlog::record(sub.chat_id, 0, &first_page, &result);
+            match result {
+                Ok(_) => record_delivery(db, sub.chat_id, batch, 0).await,
+                Err(e) => {
+                    log::error!("{}", e);
+                    delivery::failed(
+                        sub.chat_id,
+                        batch,
+                        0,
+                        &first_page,
+                        sub.format,
+                        Some(&keyboard),
+                    );
+                }
+            }
+        }
+    } else {
+        let total = chunks.iter().map(|c| c.len()).max().unwrap_or(0);
+        for chunk in 0..total {
+            for (((sub, chunks), (allowed, _)), quiet) in subs
+                .iter()
+                .zip(chunks.iter())
+                .zip(limits.iter())
+                .zip(quiet.iter())
+            {
+                if chunk >= *allowed {
+                    continue;
+                }
+                if let Some((_, formatted)) = chunks.get(chunk) {
+                    deliver_or_hold(formatted, batch, chunk, bot, db, sub, *quiet, &mut sent).await;
+                }
+            }
+        }
+    }
+    for ((sub, (_, summary)), quiet) in subs.iter().zip(limits.iter()).zip(quiet.iter()) {
+        if let Some(count) = summary {
+            let message = overflow_summary(sub, *count);
+            let notice = delivery::Batch::Notice;
+            deliver_or_hold(&message, notice, 0, bot, db, sub, *quiet, &mut sent).await;
+        }
+    }
+    for (chat_id, (real_id, message_id, _)) in sent {
+        refresh::batch_sent(chat_id, real_id, message_id);
+    }
+    sinks::updates(&messages);
 
     Ok(())
 }
 
-/// Parse on-the-wire messages
-async fn parse_message(message: &str, pending: &mut Vec<PVMessage>) -> Result<()> {
-    let msg = serde_json::from_str::<Vec<PVMessage>>(message)?;
-    pending.extend(msg);
+/// Send the updates published while the notifier was down, after telling each chat how many
+/// of them it follows
+async fn send_missed(bot: &Bot, db: &sqlite::SqlitePool, last_update: &str) -> Result<()> {
+    let mut missed = backfill::missed(db, last_update).await?;
+    if missed.is_empty() {
+        return Ok(());
+    }
+    log::info!("Sending {} updates missed while stopped.", missed.len());
+    let subs = settings::recipients(db, Severity::Routine).await?;
+    let mut sent = BatchMessages::new();
+    let now = snooze::now();
+    for sub in subs.iter().filter(|s| !s.is_snoozed()) {
+        let count = missed.iter().filter(|p| p.allowed_by(&sub.filters)).count();
+        if count > 0 {
+            let message =
+                sub.format
+                    .escape(&tr_with(&sub.lang, Text::Backfill, &count.to_string()));
+            deliver_or_hold(
+                &message,
+                delivery::Batch::Notice,
+                0,
+                bot,
+                db,
+                sub,
+                sub.quiet_until(now),
+                &mut sent,
+            )
+            .await;
+        }
+    }
+
+    send_all_pending_messages(&mut missed, None, bot, db).await
+}
+
+/// Message telling the chat how many updates were not sent because of its rate limit
+fn overflow_summary(sub: &Subscriber, count: usize) -> String {
+    sub.format
+        .escape(&tr_with(&sub.lang, Text::RateLimited, &count.to_string()))
+}
+
+/// Send the summaries of the rate-limited chats whose limits allow a message again
+async fn flush_overflow_summaries(bot: &Bot, db: &sqlite::SqlitePool) -> Result<()> {
+    let overflowed = ratelimit::overflowed();
+    if overflowed.is_empty() {
+        return Ok(());
+    }
+    let subs = settings::subscribers(db).await?;
+    let mut sent = BatchMessages::new();
+    let now = snooze::now();
+    // the chats in their quiet hours get the summaries once they end
+    for sub in subs.iter().filter(|s| {
+        overflowed.contains(&s.chat_id) && !s.is_snoozed() && s.quiet_until(now).is_none()
+    }) {
+        if let (_, Some(count)) = ratelimit::acquire(sub.chat_id, sub.messages_per_hour(), &[]) {
+            let message = overflow_summary(sub, count);
+            deliver(
+                &message,
+                delivery::Batch::Notice,
+                0,
+                bot,
+                db,
+                sub,
+                &mut sent,
+            )
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Tell the chat its notifications are resumed, along with the number of the updates
+/// it missed
+async fn send_resumed(bot: &Bot, db: &sqlite::SqlitePool, chat_id: i64, missed: i64) -> Result<()> {
+    let lang = settings::get_lang(db, chat_id).await?;
+    let format = settings::get_format(db, chat_id).await?;
+    let text = if missed > 0 {
+        tr_with(&lang, Text::ResumedWithUpdates, &missed.to_string())
+    } else {
+        tr(&lang, Text::Resumed).to_string()
+    };
+    send_with_retry(
+        &format.escape(&text),
+        bot,
+        db,
+        ChatId(chat_id),
+        Target::New,
+        None,
+        format,
+    )
+    .await?;
+
     Ok(())
 }
 
-/// Monitor the Redis endpoint of p-vector
-async fn monitor_pv(client: redis::Client, bot: &Bot, db: &sqlite::SqlitePool) -> Result<()> {
+/// Resume the chats whose snooze has ended
+async fn resume_snoozes(bot: &Bot, db: &sqlite::SqlitePool) -> Result<()> {
+    for chat_id in settings::expired_snoozes(db, snooze::now()).await? {
+        if let Some(missed) = settings::resume(db, chat_id).await? {
+            if let Err(e) = send_resumed(bot, db, chat_id, missed).await {
+                log::error!("Could not resume {}: {}", chat_id, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the periodic jobs at the configured schedules
+async fn run_jobs(bot: &Bot, db: &sqlite::SqlitePool) -> Result<()> {
+    let jobs = &config::get().schedule;
+    let stale_interval = jobs.stale_chats.interval().num_seconds();
+    tokio::try_join!(
+        schedule::run("snooze", &jobs.snooze, || resume_snoozes(bot, db)),
+        schedule::run("maintenance", &jobs.maintenance, || maintenance::run(db)),
+        async {
+            if config::get().releases.manifest_dir.is_empty() {
+                return Ok(());
+            }
+            schedule::run("releases", &jobs.releases, || announce_releases(bot, db)).await
+        },
+        async {
+            if !config::get().stale.enabled {
+                return Ok(());
+            }
+            schedule::run("stale_chats", &jobs.stale_chats, || {
+                stale::check_some(bot, db, stale_interval)
+            })
+            .await
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Tell the chats opting in about the new releases in the manifests
+async fn announce_releases(bot: &Bot, db: &sqlite::SqlitePool) -> Result<()> {
+    let manifest_dir = std::path::Path::new(&config::get().releases.manifest_dir);
+    let new = releases::check(db, manifest_dir).await?;
+    if new.is_empty() {
+        return Ok(());
+    }
+    log::info!("Announcing {} new release files.", new.len());
+    for (chat_id, lang, format) in settings::release_chats(db).await? {
+        let message = format.escape(&releases::describe(&new, &lang));
+        let result = send_with_retry(
+            &message,
+            bot,
+            db,
+            ChatId(chat_id),
+            Target::New,
+            None,
+            format,
+        )
+        .await
+        .map(|_| ());
+        eventlog::record(chat_id, 0, &message, &result);
+        if let Err(e) = result {
+            log::error!("{}", e);
+            delivery::failed(chat_id, delivery::Batch::Notice, 0, &message, format, None);
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a single message of the given severity to the chats in their languages,
+/// `arg` replaces the placeholder in the text (if any)
+async fn notify(
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+    severity: Severity,
+    text: Text,
+    arg: &str,
+) -> Result<()> {
+    notify_with(bot, db, severity, |lang| tr_with(lang, text, arg)).await
+}
+
+/// Send the message rendered in the language of each recipient
+async fn notify_with<F: Fn(&str) -> String>(
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+    severity: Severity,
+    render: F,
+) -> Result<()> {
+    let subs = settings::recipients(db, severity).await?;
+    let mut sent = BatchMessages::new();
+    for sub in subs.iter() {
+        if sub.is_snoozed() && severity != Severity::Critical {
+            continue;
+        }
+        let message = sub.format.escape(&render(&sub.lang));
+        deliver(
+            &message,
+            delivery::Batch::Notice,
+            0,
+            bot,
+            db,
+            sub,
+            &mut sent,
+        )
+        .await;
+    }
+    sinks::notice(severity, render);
+
+    Ok(())
+}
+
+/// Send the "repository refreshed" notice, as a reply to the last batch message in each chat
+async fn notify_refreshed<F: Fn(&str) -> String>(
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+    render: F,
+) -> Result<()> {
+    let subs = settings::recipients(db, Severity::Heartbeat).await?;
+    let threads = refresh::take_threads();
+    let now = snooze::now();
+    for sub in subs
+        .iter()
+        .filter(|s| !s.is_snoozed() && s.quiet_until(now).is_none())
+    {
+        let message = sub.format.escape(&render(&sub.lang));
+        let (chat_id, target) = match threads.get(&sub.chat_id) {
+            Some((real_id, message_id)) => (*real_id, Target::Reply(*message_id)),
+            None => (ChatId(sub.chat_id), Target::New),
+        };
+        let result = send_with_retry(&message, bot, db, chat_id, target, None, sub.format)
+            .await
+            .map(|_| ());
+        eventlog::record(sub.chat_id, 0, &message, &result);
+        if let Err(e) = result {
+            log::error!("{}", e);
+            delivery::failed(
+                sub.chat_id,
+                delivery::Batch::Notice,
+                0,
+                &message,
+                sub.format,
+                None,
+            );
+        }
+    }
+    sinks::notice(Severity::Heartbeat, render);
+
+    Ok(())
+}
+
+/// Parse on-the-wire messages and tag them with the name of the repository
+async fn parse_message(
+    message: &[u8],
+    repo: Option<&str>,
+    last_sequence: &mut Option<u64>,
+    pending: &mut Vec<PVMessage>,
+) -> Result<()> {
+    let batch = wire::decode(message)?;
+    if let Some(sequence) = batch.sequence {
+        let missed = wire::missed(*last_sequence, sequence);
+        if missed > 0 {
+            log::warn!(
+                "Missed {} batches from p-vector ({}).",
+                missed,
+                repo.unwrap_or("default")
+            );
+        }
+        *last_sequence = Some(sequence);
+        status::set_sequence(repo, sequence);
+    }
+    refresh::received(&batch.updates);
+    pending.extend(batch.updates.into_iter().map(|p| PVMessage {
+        repo: repo.map(|r| r.to_string()),
+        ..p
+    }));
+    Ok(())
+}
+
+/// Monitor the Redis endpoint of p-vector (of the given repository)
+async fn monitor_pv(
+    client: redis::Client,
+    repo: Option<&str>,
+    bot: &Bot,
+    db: &sqlite::SqlitePool,
+) -> Result<()> {
+    let mut connection = client.get_multiplexed_async_connection().await?;
+    redis::cmd("SET")
+        .arg(wire::PROTOCOL_KEY)
+        .arg(wire::PROTOCOL_VERSION)
+        .query_async::<()>(&mut connection)
+        .await?;
     let mut pubsub = client.get_async_pubsub().await?;
     pubsub.subscribe("p-vector-publish").await?;
+    status::set_connected(repo, true);
 
     let mut fail_count = 0usize;
+    let mut last_sequence = None;
     let mut pending = Vec::new();
-    let mut pending_time = COOLDOWN_TIME;
+    let mut pending_time = config::get().batching.cooldown_seconds;
+    // seconds since the last update arrived
+    let mut idle = 0usize;
+    // seconds since the first pending update arrived
+    let mut age = 0usize;
     let mut stream = pubsub.on_message();
     loop {
         tokio::select! {
             Some(msg) = stream.next() => {
-                let payload: Result<String, _> = msg.get_payload();
+                let payload: Result<Vec<u8>, _> = msg.get_payload();
+                #[cfg(feature = "chaos")]
+                if chaos::inject(chaos::Fault::Disconnect) {
+                    return Err(anyhow!("Lost the subscription (injected)"));
+                }
                 match payload {
                     Ok(msg) => {
+                        #[cfg(feature = "chaos")]
+                        let msg = chaos::corrupt(msg);
+                        if !wire::authentic(&msg, SECRET.as_deref(), last_sequence) {
+                            // not counted as an error, or anyone could stop the monitoring
+                            log::warn!("Dropped an unauthenticated or replayed message ({}).", repo.unwrap_or("default"));
+                            status::rejected(repo);
+                            continue;
+                        }
                         UPDATED.fetch_or(true, Ordering::SeqCst);
-                        match parse_message(&msg, &mut pending).await {
-                            Ok(_) => pending_time = COOLDOWN_TIME,
+                        status::received(repo);
+                        let result = parse_message(&msg, repo, &mut last_sequence, &mut pending).await;
+                        status::set_pending(repo, pending.len());
+                        match result {
+                            Ok(_) => {
+                                pending_time = config::get().batching.cooldown_seconds;
+                                idle = 0;
+                            }
                             Err(err) => {
                                 log::warn!("Invalid message received: {}", err);
+                                notify(bot, db, Severity::Warning, Text::InvalidMessage, &err.to_string()).await.ok();
                                 fail_count += 1;
                                 if fail_count > 10 {
                                     log::error!("Too many errors encountered. Stopped monitoring Redis!");
                                     // Flush all the pending messages and then return
-                                    send_all_pending_messages(&mut pending, bot, db).await.ok();
+                                    send_all_pending_messages(&mut pending, last_sequence, bot, db).await.ok();
+                                    notify(bot, db, Severity::Critical, Text::Stopped, "").await.ok();
                                     return Err(anyhow!("Too many errors encountered"));
                                 }
                             }
@@ -284,7 +1305,8 @@ async fn monitor_pv(client: redis::Client, bot: &Bot, db: &sqlite::SqlitePool) -
                             if fail_count > 10 {
                                 log::error!("Too many errors encountered. Stopped monitoring Redis!");
                                 // Flush all the pending messages and then return
-                                send_all_pending_messages(&mut pending, bot, db).await.ok();
+                                send_all_pending_messages(&mut pending, last_sequence, bot, db).await.ok();
+                                notify(bot, db, Severity::Critical, Text::Stopped, "").await.ok();
                                 return Err(anyhow!("Too many errors encountered"));
                             }
                         }
@@ -292,20 +1314,37 @@ async fn monitor_pv(client: redis::Client, bot: &Bot, db: &sqlite::SqlitePool) -
                 }
             }
             _ = tokio::time::sleep(Duration::from_secs(1)) => {
-                if pending_time < 1 {
+                idle += 1;
+                if !pending.is_empty() {
+                    age += 1;
+                }
+                let batching = &config::get().batching;
+                // the updates of the next refresh may still join the batch
+                let held = !pending.is_empty() && batching.holds(age);
+                // small batches are sent as soon as the updates stop arriving
+                if !held && (pending_time < 1 || batching.flush_early(pending.len(), idle)) {
                     // check if pending messages list is empty
                     MSGSENT.fetch_or(!pending.is_empty(), Ordering::SeqCst);
+                    // the chunks missed in the previous cycles go first
+                    resend_undelivered(bot, db).await;
                     // accumulate enough pending messages to send
-                    send_all_pending_messages(&mut pending, bot, db).await.ok();
+                    send_all_pending_messages(&mut pending, last_sequence, bot, db).await.ok();
+                    status::set_pending(repo, pending.len());
+                    flush_overflow_summaries(bot, db).await.ok();
                     // check if "repository refreshed" needs to be sent
                     if WRITTEN.fetch_and(false, Ordering::SeqCst) {
-                        let subs = query!("SELECT chat_id FROM subbed").fetch_all(db).await?;
-                        send_to_subscribers!("🔄 Repository refreshed.", bot, subs);
+                        match refresh::take() {
+                            Some(refresh) => {
+                                notify_refreshed(bot, db, |lang| refresh.describe(lang)).await?
+                            }
+                            None => notify_refreshed(bot, db, |lang| tr(lang, Text::Refreshed).to_string()).await?,
+                        }
                     }
-                    pending_time = COOLDOWN_TIME; // reset the pending time
+                    pending_time = config::get().batching.cooldown_seconds; // reset the pending time
+                    age = 0;
                     continue;
                 }
-                pending_time -= 1;
+                pending_time = pending_time.saturating_sub(1);
             }
         };
     }
@@ -321,16 +1360,47 @@ async fn monitor_last_update(f: &str, _: &Bot, _: &sqlite::SqlitePool) -> Result
     let mut stream = inotify.into_event_stream(buffer)?;
     log::info!("Last update file monitoring started.");
     while stream.next().await.is_some() {
+        status::refreshed();
         // Only sends this notification if there are package updates
         if !UPDATED.fetch_and(false, Ordering::SeqCst) {
             continue;
         }
+        refresh::written();
         WRITTEN.fetch_or(true, Ordering::SeqCst);
     }
 
     Ok(())
 }
 
+/// Check whether the value can appear in the updates
+fn filter_value_known(kind: filter::Kind, value: &str) -> bool {
+    match kind {
+        filter::Kind::Repo => config::get().repositories.iter().any(|r| r.name == value),
+        filter::Kind::Group => config::get().arch_groups.exists(value),
+        // the components come and go with the topics
+        filter::Kind::Comp => !value.is_empty(),
+        filter::Kind::Arch => config::get().arch_groups.knows(value),
+    }
+}
+
+/// Check whether the sender of the message is allowed to change the layout of the chat
+async fn is_admin(bot: &Bot, message: &Message) -> Result<bool> {
+    is_chat_admin(bot, &message.chat, message.from.as_ref()).await
+}
+
+/// Check whether the user is allowed to change the settings of the chat
+async fn is_chat_admin(bot: &Bot, chat: &Chat, user: Option<&User>) -> Result<bool> {
+    if chat.is_private() {
+        return Ok(true);
+    }
+    let user = match user {
+        Some(user) => user,
+        None => return Ok(false),
+    };
+
+    Ok(bot.get_chat_member(chat.id, user.id).await?.is_privileged())
+}
+
 /// Handle bot commands from Telegram
 async fn answer(
     bot: Bot,
@@ -339,6 +1409,7 @@ async fn answer(
     pool: sqlite::SqlitePool,
 ) -> Result<()> {
     let id = message.chat.id;
+    let lang = settings::get_lang(&pool, id.0).await?;
     match command {
         Command::Help => {
             bot.send_message(id, Command::descriptions().to_string())
@@ -348,49 +1419,817 @@ async fn answer(
             query!("INSERT OR IGNORE INTO subbed (chat_id) VALUES (?)", id.0)
                 .execute(&pool)
                 .await?;
-            bot.send_message(id, "Subscribed to updates.").await?
+            let actor = audit::actor(message.from.as_ref());
+            audit::record(&pool, id.0, &actor, "start", None).await?;
+            bot.send_message(id, tr(&lang, Text::Subscribed)).await?
         }
         Command::Stop => {
             query!("DELETE FROM subbed WHERE chat_id = ?", id.0)
                 .execute(&pool)
                 .await?;
-            bot.send_message(id, "Unsubbed.").await?
+            let actor = audit::actor(message.from.as_ref());
+            audit::record(&pool, id.0, &actor, "stop", None).await?;
+            bot.send_message(id, tr(&lang, Text::Unsubscribed)).await?
         }
-        Command::Ping => bot.send_message(id, "Pong!").await?,
+        Command::Ping => bot.send_message(id, tr(&lang, Text::Pong)).await?,
         Command::ChatID => bot.send_message(id, id.to_string()).await?,
+        Command::Lang(new_lang) => match i18n::find_language(&new_lang) {
+            Some(new_lang) => {
+                settings::set_lang(&pool, id.0, new_lang).await?;
+                bot.send_message(id, tr_with(new_lang, Text::LangChanged, new_lang))
+                    .await?
+            }
+            None => {
+                bot.send_message(
+                    id,
+                    tr_with(&lang, Text::LangUnknown, &i18n::LANGUAGES.join(", ")),
+                )
+                .await?
+            }
+        },
+        Command::Tz(name) => match name.trim() {
+            "" => {
+                let timezone = settings::get_timezone(&pool, id.0).await?;
+                bot.send_message(
+                    id,
+                    tr_with(&lang, Text::TimezoneCurrent, &timezone.to_string()),
+                )
+                .await?
+            }
+            "default" => {
+                settings::set_timezone(&pool, id.0, None).await?;
+                bot.send_message(
+                    id,
+                    tr_with(&lang, Text::TimezoneChanged, timezone::DEFAULT_TIMEZONE),
+                )
+                .await?
+            }
+            name => match timezone::TimeZone::load(name) {
+                Ok(timezone) => {
+                    settings::set_timezone(&pool, id.0, Some(name)).await?;
+                    bot.send_message(
+                        id,
+                        tr_with(&lang, Text::TimezoneChanged, &timezone.to_string()),
+                    )
+                    .await?
+                }
+                Err(_) => {
+                    bot.send_message(id, tr_with(&lang, Text::TimezoneUnknown, name))
+                        .await?
+                }
+            },
+        },
+        Command::Severity(level) => match level.parse::<Severity>() {
+            Ok(level) => {
+                if settings::set_min_severity(&pool, id.0, level).await? {
+                    bot.send_message(
+                        id,
+                        tr_with(&lang, Text::SeverityChanged, &level.to_string()),
+                    )
+                    .await?
+                } else {
+                    bot.send_message(id, tr(&lang, Text::NotSubscribed)).await?
+                }
+            }
+            Err(_) => {
+                let available = severity::SEVERITIES
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                bot.send_message(id, tr_with(&lang, Text::SeverityUnknown, &available))
+                    .await?
+            }
+        },
+        Command::Format(format) => match format.parse::<Format>() {
+            Ok(format) => {
+                settings::set_format(&pool, id.0, format).await?;
+                bot.send_message(id, tr_with(&lang, Text::FormatChanged, &format.to_string()))
+                    .await?
+            }
+            Err(_) => {
+                let available = format::FORMATS
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                bot.send_message(id, tr_with(&lang, Text::FormatUnknown, &available))
+                    .await?
+            }
+        },
+        Command::Group(grouping) => {
+            let grouping = match grouping.trim() {
+                "default" => Ok(None),
+                grouping => grouping.parse::<Grouping>().map(Some),
+            };
+            match grouping {
+                Ok(grouping) => {
+                    settings::set_grouping(&pool, id.0, grouping).await?;
+                    let grouping = grouping.unwrap_or(config::get().batching.group_by);
+                    bot.send_message(
+                        id,
+                        tr_with(&lang, Text::GroupingChanged, &grouping.to_string()),
+                    )
+                    .await?
+                }
+                Err(_) => bot.send_message(id, tr(&lang, Text::GroupingUsage)).await?,
+            }
+        }
+        Command::Template(args) => {
+            if !is_admin(&bot, &message).await? {
+                bot.send_message(id, tr(&lang, Text::AdminOnly)).await?;
+                return Ok(());
+            }
+            let mut template = settings::get_template(&pool, id.0).await?;
+            let args = args.trim();
+            let (part, source) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let source = source.trim();
+            match part {
+                "show" => {
+                    let current = template::PARTS
+                        .iter()
+                        .map(|p| format!("{}: {}", p, template.get(*p).unwrap_or("-")))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    bot.send_message(id, current).await?
+                }
+                "reset" => {
+                    settings::set_template(&pool, id.0, &Default::default()).await?;
+                    bot.send_message(id, tr(&lang, Text::TemplateReset)).await?
+                }
+                _ => match part.parse::<Part>() {
+                    Ok(part) => {
+                        let source = (!source.is_empty()).then(|| source.to_string());
+                        let format = settings::get_format(&pool, id.0).await?;
+                        match template.set(part, source, format) {
+                            Ok(()) => {
+                                settings::set_template(&pool, id.0, &template).await?;
+                                bot.send_message(
+                                    id,
+                                    tr_with(&lang, Text::TemplateChanged, &part.to_string()),
+                                )
+                                .await?
+                            }
+                            Err(e) => {
+                                bot.send_message(
+                                    id,
+                                    tr_with(&lang, Text::TemplateInvalid, &e.to_string()),
+                                )
+                                .await?
+                            }
+                        }
+                    }
+                    Err(_) => bot.send_message(id, tr(&lang, Text::TemplateUsage)).await?,
+                },
+            }
+        }
+        Command::Recent => {
+            let filters = settings::get_filters(&pool, id.0).await?;
+            let layout = (
+                settings::get_format(&pool, id.0).await?,
+                settings::get_template(&pool, id.0).await?,
+                settings::get_grouping(&pool, id.0)
+                    .await?
+                    .unwrap_or(config::get().batching.group_by),
+            );
+            let mut recent = history::recent(&pool, RECENT_LOOKUP)
+                .await?
+                .into_iter()
+                .filter(|p| p.allowed_by(&filters))
+                .take(config::get().batching.max_lines)
+                .collect::<Vec<_>>();
+            classify_messages(&mut recent);
+            // do not ping the maintainers again
+            recent.iter_mut().for_each(|p| p.mentions.clear());
+            match split_into_chunks(&recent, &layout, &lang, 0)
+                .into_iter()
+                .next()
+            {
+                Some((_, content)) => {
+                    send_with_retry(&content, &bot, &pool, id, Target::New, None, layout.0).await?
+                }
+                None => bot.send_message(id, tr(&lang, Text::NoRecent)).await?,
+            }
+        }
+        Command::Pkg(pkg) => {
+            let pkg = pkg.trim();
+            if pkg.is_empty() {
+                bot.send_message(id, tr(&lang, Text::PkgUsage)).await?;
+                return Ok(());
+            }
+            let latest = match versions::fetch(pkg).await {
+                Ok(latest) => latest,
+                Err(e) => {
+                    log::warn!(
+                        "Could not look up {}, using the recorded updates: {}",
+                        pkg,
+                        e
+                    );
+                    history::latest(&pool, pkg).await?
+                }
+            };
+            match versions::render(pkg, &latest) {
+                Some(text) => bot.send_message(id, text).await?,
+                None => {
+                    bot.send_message(id, tr_with(&lang, Text::PkgUnknown, pkg))
+                        .await?
+                }
+            }
+        }
+        Command::Releases(state) => {
+            let text = match state.trim() {
+                "on" => {
+                    settings::set_releases(&pool, id.0, true).await?;
+                    Text::ReleasesOn
+                }
+                "off" => {
+                    settings::set_releases(&pool, id.0, false).await?;
+                    Text::ReleasesOff
+                }
+                _ => Text::ReleasesUsage,
+            };
+            bot.send_message(id, tr(&lang, text)).await?
+        }
+        Command::Status => {
+            let mut report = status::report(&lang);
+            if is_admin(&bot, &message).await? {
+                let receipt = delivery::receipt(&pool, id.0).await?;
+                report = report + "\n" + &status::chat_report(&lang, id.0, receipt);
+            }
+            bot.send_message(id, report).await?
+        }
+        Command::ReportMirror(args) => {
+            let reports = &config::get().mirror_reports;
+            let args = args.trim();
+            let (mirror, details) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let details = details.trim();
+            if mirror.is_empty() || details.is_empty() {
+                bot.send_message(id, tr(&lang, Text::ReportMirrorUsage))
+                    .await?;
+                return Ok(());
+            }
+            let mirror = match reports.find(mirror) {
+                Some(mirror) => mirror,
+                None => {
+                    let available = reports.mirrors.join(", ");
+                    bot.send_message(id, tr_with(&lang, Text::MirrorUnknown, &available))
+                        .await?;
+                    return Ok(());
+                }
+            };
+            let filed = mirror_reports::file(&pool, id.0, &mirror, details).await?;
+            if let Err(e) = mirror_reports::write_probation(&pool).await {
+                log::error!("Could not write the mirrors on probation: {}", e);
+            }
+            match filed {
+                mirror_reports::Filed::New => {
+                    let mentions = reports
+                        .operators
+                        .iter()
+                        .map(|user| format!("@{}", user))
+                        .collect::<Vec<_>>();
+                    let report = format!("{}: {} {}", mirror, details, mentions.join(" "));
+                    notify(
+                        &bot,
+                        &pool,
+                        Severity::Warning,
+                        Text::MirrorReported,
+                        report.trim(),
+                    )
+                    .await?;
+                    bot.send_message(id, tr(&lang, Text::MirrorReportFiled))
+                        .await?
+                }
+                mirror_reports::Filed::Repeated(count) => {
+                    bot.send_message(
+                        id,
+                        tr_with(&lang, Text::MirrorReportCounted, &count.to_string()),
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::ResolveMirror(mirror) => {
+            let reports = &config::get().mirror_reports;
+            let username = message.from.as_ref().and_then(|u| u.username.as_deref());
+            if !username.is_some_and(|u| reports.operators.iter().any(|o| o == u)) {
+                bot.send_message(id, tr(&lang, Text::OperatorOnly)).await?;
+                return Ok(());
+            }
+            let mirror = mirror.trim();
+            if mirror.is_empty() {
+                bot.send_message(id, tr(&lang, Text::ResolveMirrorUsage))
+                    .await?;
+                return Ok(());
+            }
+            let mirror = match reports.find(mirror) {
+                Some(mirror) => mirror,
+                None => {
+                    let available = reports.mirrors.join(", ");
+                    bot.send_message(id, tr_with(&lang, Text::MirrorUnknown, &available))
+                        .await?;
+                    return Ok(());
+                }
+            };
+            if !mirror_reports::resolve(&pool, &mirror).await? {
+                bot.send_message(id, tr_with(&lang, Text::MirrorNotReported, &mirror))
+                    .await?;
+                return Ok(());
+            }
+            if let Err(e) = mirror_reports::write_probation(&pool).await {
+                log::error!("Could not write the mirrors on probation: {}", e);
+            }
+            bot.send_message(id, tr_with(&lang, Text::MirrorResolved, &mirror))
+                .await?
+        }
+        Command::RateLimit(limit) => {
+            if !is_admin(&bot, &message).await? {
+                bot.send_message(id, tr(&lang, Text::AdminOnly)).await?;
+                return Ok(());
+            }
+            let limit = match limit.trim() {
+                "default" => Ok(None),
+                "off" => Ok(Some(0)),
+                limit => limit.parse::<u32>().map(|l| Some(l as i64)),
+            };
+            match limit {
+                Ok(limit) => {
+                    settings::set_rate_limit(&pool, id.0, limit).await?;
+                    let limit = match limit {
+                        Some(limit) => limit.to_string(),
+                        None => config::get().rate_limit.per_hour.to_string(),
+                    };
+                    bot.send_message(id, tr_with(&lang, Text::RateLimitChanged, &limit))
+                        .await?
+                }
+                Err(_) => {
+                    bot.send_message(id, tr(&lang, Text::RateLimitUsage))
+                        .await?
+                }
+            }
+        }
+        Command::Snooze(duration) => {
+            if !is_admin(&bot, &message).await? {
+                bot.send_message(id, tr(&lang, Text::AdminOnly)).await?;
+                return Ok(());
+            }
+            let duration = duration.trim();
+            if duration == "off" {
+                match settings::resume(&pool, id.0).await? {
+                    Some(missed) => send_resumed(&bot, &pool, id.0, missed).await?,
+                    None => {
+                        bot.send_message(id, tr(&lang, Text::NotSnoozed)).await?;
+                    }
+                }
+                return Ok(());
+            }
+            if let Some((hour, minute)) = snooze::parse_clock(duration) {
+                // until the next time the clock of the chat reads so
+                let timezone = settings::get_timezone(&pool, id.0).await?;
+                if let Some(until) = timezone.next_time(snooze::now(), hour, minute) {
+                    settings::set_snooze(&pool, id.0, until).await?;
+                    let until = format!("{} ({})", duration, timezone);
+                    bot.send_message(id, tr_with(&lang, Text::SnoozedUntil, &until))
+                        .await?;
+                    return Ok(());
+                }
+            }
+            match snooze::parse_duration(duration) {
+                Some(length) => {
+                    let until = snooze::now() + length.as_secs() as i64;
+                    settings::set_snooze(&pool, id.0, until).await?;
+                    bot.send_message(id, tr_with(&lang, Text::Snoozed, duration))
+                        .await?
+                }
+                None => bot.send_message(id, tr(&lang, Text::SnoozeUsage)).await?,
+            }
+        }
+        Command::Quiet(window) => {
+            if !is_admin(&bot, &message).await? {
+                bot.send_message(id, tr(&lang, Text::AdminOnly)).await?;
+                return Ok(());
+            }
+            match window.trim() {
+                "" => {
+                    let timezone = settings::get_timezone(&pool, id.0).await?;
+                    match settings::get_quiet_hours(&pool, id.0).await? {
+                        Some(quiet_hours) => {
+                            let current = format!("{} ({})", quiet_hours, timezone);
+                            bot.send_message(id, tr_with(&lang, Text::QuietHoursCurrent, &current))
+                                .await?
+                        }
+                        None => {
+                            bot.send_message(id, tr(&lang, Text::QuietHoursUsage))
+                                .await?
+                        }
+                    }
+                }
+                "off" => {
+                    settings::set_quiet_hours(&pool, id.0, None).await?;
+                    // the held updates go out on the next cycle
+                    delivery::queue().release(id.0);
+                    bot.send_message(id, tr(&lang, Text::QuietHoursOff)).await?
+                }
+                window => match window.parse::<snooze::QuietHours>() {
+                    Ok(quiet_hours) => {
+                        settings::set_quiet_hours(&pool, id.0, Some(quiet_hours)).await?;
+                        let timezone = settings::get_timezone(&pool, id.0).await?;
+                        let set = format!("{} ({})", quiet_hours, timezone);
+                        bot.send_message(id, tr_with(&lang, Text::QuietHoursSet, &set))
+                            .await?
+                    }
+                    Err(_) => {
+                        bot.send_message(id, tr(&lang, Text::QuietHoursUsage))
+                            .await?
+                    }
+                },
+            }
+        }
+        Command::Setup => {
+            if !is_admin(&bot, &message).await? {
+                bot.send_message(id, tr(&lang, Text::AdminOnly)).await?;
+                return Ok(());
+            }
+            let mut comps = history::components(&pool, SETUP_COMPS).await?;
+            if comps.is_empty() {
+                comps.push("stable".to_string());
+            }
+            let arches = config::get().arch_groups.arches();
+            let wizard = setup::Wizard::new(comps, arches.iter().map(|a| a.to_string()).collect());
+            let (text, keyboard) = (wizard.text(&lang), wizard.keyboard(&lang));
+            setup::start(id.0, wizard);
+            bot.send_message(id, text).reply_markup(keyboard).await?
+        }
+        Command::Mute(pkg) => answer_mute(&bot, &pool, id, &lang, &pkg, true).await?,
+        Command::Unmute(pkg) => answer_mute(&bot, &pool, id, &lang, &pkg, false).await?,
+        Command::Filter(args) => {
+            let mut args = args.split_whitespace();
+            match args.next().map(|k| k.parse::<filter::Kind>()) {
+                None => {
+                    let filters = settings::get_filters(&pool, id.0).await?;
+                    if filters.is_empty() {
+                        bot.send_message(id, tr(&lang, Text::NoFilters)).await?
+                    } else {
+                        bot.send_message(id, filters.to_string()).await?
+                    }
+                }
+                Some(Ok(kind)) => {
+                    let values = args.collect::<Vec<_>>();
+                    if let Some(unknown) = values.iter().find(|v| !filter_value_known(kind, v)) {
+                        bot.send_message(id, tr_with(&lang, Text::FilterUnknown, unknown))
+                            .await?;
+                        return Ok(());
+                    }
+                    settings::set_filter(&pool, id.0, kind, &values).await?;
+                    let actor = audit::actor(message.from.as_ref());
+                    let detail = format!("{} {}", kind, values.join(" "));
+                    audit::record(&pool, id.0, &actor, "filter", Some(detail.trim_end())).await?;
+                    if values.is_empty() {
+                        bot.send_message(id, tr_with(&lang, Text::FilterRemoved, &kind.to_string()))
+                            .await?
+                    } else {
+                        bot.send_message(id, tr_with(&lang, Text::FilterChanged, &kind.to_string()))
+                            .await?
+                    }
+                }
+                Some(Err(_)) => bot.send_message(id, tr(&lang, Text::FilterUsage)).await?,
+            }
+        }
     };
 
     Ok(())
 }
 
+/// Mute or unmute the updates of a package for the chat
+async fn answer_mute(
+    bot: &Bot,
+    pool: &sqlite::SqlitePool,
+    id: ChatId,
+    lang: &str,
+    pkg: &str,
+    muted: bool,
+) -> Result<Message> {
+    let pkg = pkg.trim();
+    if pkg.is_empty() || pkg.contains(char::is_whitespace) {
+        return Ok(bot.send_message(id, tr(lang, Text::MuteUsage)).await?);
+    }
+    let changed = settings::set_muted(pool, id.0, pkg, muted).await?;
+    let text = match (muted, changed) {
+        (true, _) => Text::Muted,
+        (false, true) => Text::Unmuted,
+        (false, false) => Text::NotMuted,
+    };
+
+    Ok(bot.send_message(id, tr_with(lang, text, pkg)).await?)
+}
+
+/// Describe the settings of the chat, `None` if it is not subscribed
+async fn describe_settings(
+    pool: &sqlite::SqlitePool,
+    chat_id: i64,
+    lang: &str,
+) -> Result<Option<String>> {
+    let sub = match settings::subscribers(pool)
+        .await?
+        .into_iter()
+        .find(|s| s.chat_id == chat_id)
+    {
+        Some(sub) => sub,
+        None => return Ok(None),
+    };
+    let severity = Severity::from_level(sub.min_severity)
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| sub.min_severity.to_string());
+    let filters = if sub.filters.is_empty() {
+        tr(lang, Text::NoFilters).to_string()
+    } else {
+        sub.filters.to_string().trim_end().to_string()
+    };
+    let timezone = settings::get_timezone(pool, chat_id).await?;
+    let mut text = tr(lang, Text::Settings).to_string();
+    for value in [
+        sub.lang,
+        severity,
+        sub.format.to_string(),
+        timezone.to_string(),
+        filters,
+    ] {
+        text = text.replacen("{}", &value, 1);
+    }
+
+    Ok(Some(text))
+}
+
+/// Handle the buttons managing the subscription under the updates
+async fn answer_button(
+    bot: Bot,
+    query: CallbackQuery,
+    pool: sqlite::SqlitePool,
+    action: buttons::Action,
+) -> Result<()> {
+    let chat = match query.message.as_ref() {
+        Some(message) => message.chat().clone(),
+        None => {
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    let id = chat.id;
+    let lang = settings::get_lang(&pool, id.0).await?;
+    if action != buttons::Action::Settings && !is_chat_admin(&bot, &chat, Some(&query.from)).await?
+    {
+        bot.answer_callback_query(query.id)
+            .text(tr(&lang, Text::AdminOnly))
+            .await?;
+        return Ok(());
+    }
+    match action {
+        buttons::Action::Mute => {
+            let until = snooze::now() + buttons::mute_duration().as_secs() as i64;
+            settings::set_snooze(&pool, id.0, until).await?;
+            bot.send_message(id, tr_with(&lang, Text::Snoozed, buttons::MUTE_FOR))
+                .await?;
+        }
+        buttons::Action::Settings => {
+            let text = describe_settings(&pool, id.0, &lang)
+                .await?
+                .unwrap_or_else(|| tr(&lang, Text::NotSubscribed).to_string());
+            bot.send_message(id, text).await?;
+        }
+        buttons::Action::Unsubscribe => {
+            query!("DELETE FROM subbed WHERE chat_id = ?", id.0)
+                .execute(&pool)
+                .await?;
+            let actor = audit::actor(Some(&query.from));
+            audit::record(&pool, id.0, &actor, "stop", Some("button")).await?;
+            bot.send_message(id, tr(&lang, Text::Unsubscribed)).await?;
+        }
+    }
+    bot.answer_callback_query(query.id).await?;
+
+    Ok(())
+}
+
+/// Handle the buttons of /setup, saving the filters and the grouping when done
+async fn answer_setup(
+    bot: Bot,
+    query: CallbackQuery,
+    pool: sqlite::SqlitePool,
+    callback: setup::Callback,
+) -> Result<()> {
+    let message = match query.message.as_ref() {
+        Some(message) => message,
+        None => {
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    let chat = message.chat();
+    let lang = settings::get_lang(&pool, chat.id.0).await?;
+    if !is_chat_admin(&bot, chat, Some(&query.from)).await? {
+        bot.answer_callback_query(query.id)
+            .text(tr(&lang, Text::AdminOnly))
+            .await?;
+        return Ok(());
+    }
+    match setup::answer(chat.id.0, callback, &lang) {
+        None => {
+            bot.edit_message_text(chat.id, message.id(), tr(&lang, Text::SetupExpired))
+                .await?;
+        }
+        Some(setup::Outcome::Continue(text, keyboard)) => {
+            bot.edit_message_text(chat.id, message.id(), text)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        Some(setup::Outcome::Cancelled) => {
+            bot.edit_message_text(chat.id, message.id(), tr(&lang, Text::SetupCancelled))
+                .await?;
+        }
+        Some(setup::Outcome::Done(wizard, grouping)) => {
+            settings::set_filter(&pool, chat.id.0, filter::Kind::Comp, &wizard.comps()).await?;
+            settings::set_filter(&pool, chat.id.0, filter::Kind::Arch, &wizard.arches()).await?;
+            settings::set_grouping(&pool, chat.id.0, Some(grouping)).await?;
+            let actor = audit::actor(Some(&query.from));
+            let detail = format!(
+                "comp {}; arch {}; group {}",
+                wizard.comps().join(" "),
+                wizard.arches().join(" "),
+                grouping
+            );
+            audit::record(&pool, chat.id.0, &actor, "setup", Some(&detail)).await?;
+            let filters = settings::get_filters(&pool, chat.id.0).await?;
+            let filters = if filters.is_empty() {
+                tr(&lang, Text::NoFilters).to_string()
+            } else {
+                filters.to_string()
+            };
+            bot.edit_message_text(
+                chat.id,
+                message.id(),
+                tr_with(&lang, Text::SetupDone, filters.trim_end()),
+            )
+            .await?;
+        }
+    }
+    bot.answer_callback_query(query.id).await?;
+
+    Ok(())
+}
+
+/// Handle the "Show more" button of the paginated batches, the buttons managing the
+/// subscription and the ones of /setup
+async fn answer_callback(bot: Bot, query: CallbackQuery, pool: sqlite::SqlitePool) -> Result<()> {
+    if let Some(action) = query.data.as_deref().and_then(buttons::parse_callback) {
+        return answer_button(bot, query, pool, action).await;
+    }
+    if let Some(callback) = query.data.as_deref().and_then(setup::parse_callback) {
+        return answer_setup(bot, query, pool, callback).await;
+    }
+    let page = query.data.as_deref().and_then(pages::parse_callback);
+    let (message, (batch, page)) = match (query.message.as_ref(), page) {
+        (Some(message), Some(page)) => (message, page),
+        _ => {
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    let chat_id = message.chat().id;
+    let lang = settings::get_lang(&pool, chat_id.0).await?;
+    let format = settings::get_format(&pool, chat_id.0).await?;
+    let (content, remaining) = match pages::get(batch, page, chat_id.0) {
+        Some(content) => content,
+        None => {
+            bot.answer_callback_query(query.id)
+                .text(tr(&lang, Text::BatchExpired))
+                .await?;
+            return Ok(());
+        }
+    };
+    // remove the button from the previous page
+    bot.edit_message_reply_markup(chat_id, message.id()).await?;
+    let with_buttons = config::get().buttons(chat_id.0);
+    if remaining > 0 {
+        let content = content + &pages::footer(remaining, &lang, format);
+        let mut keyboard = pages::keyboard(batch, page + 1, &lang);
+        if with_buttons {
+            keyboard = keyboard.append_row(buttons::row(&lang));
+        }
+        send_with_retry(
+            &content,
+            &bot,
+            &pool,
+            chat_id,
+            Target::New,
+            Some(&keyboard),
+            format,
+        )
+        .await?;
+    } else {
+        let keyboard = with_buttons.then(|| buttons::keyboard(&lang));
+        send_with_retry(
+            &content,
+            &bot,
+            &pool,
+            chat_id,
+            Target::New,
+            keyboard.as_ref(),
+            format,
+        )
+        .await?;
+    }
+    bot.answer_callback_query(query.id).await?;
+
+    Ok(())
+}
+
 async fn run() -> Result<()> {
-    let pool = sqlite::SqlitePool::connect(&std::env::var("DATABASE_URL").unwrap()).await?;
-    migrate!().run(&pool).await?;
-    let redis_addr =
-        std::env::var("REDIS_ENDPOINT").expect("Please set REDIS_ENDPOINT environment variable!");
     pretty_env_logger::init();
+    config::load()?;
+    let pool = config::get()
+        .database
+        .connect(&std::env::var("DATABASE_URL").unwrap())
+        .await?;
+    migrate!().run(&pool).await?;
+    // tell about the dry run right away
+    dry_run::mode();
+    let repositories = &config::get().repositories;
+    let sources = if repositories.is_empty() {
+        let redis_addr = std::env::var("REDIS_ENDPOINT")
+            .expect("Please set REDIS_ENDPOINT environment variable!");
+        vec![(None, redis_addr)]
+    } else {
+        repositories
+            .iter()
+            .map(|r| (Some(r.name.as_str()), r.endpoint.clone()))
+            .collect()
+    };
     log::info!("Starting bot...");
 
-    let rx = connect_redis(&redis_addr)
-        .await
-        .expect("Unable to connect to redis endpoint!");
-    log::info!("Redis connected.");
-    let bot = Bot::from_env();
+    let mut clients = Vec::new();
+    for (repo, endpoint) in sources {
+        let rx = connect_redis(&endpoint)
+            .await
+            .expect("Unable to connect to redis endpoint!");
+        log::info!("Redis connected ({}).", repo.unwrap_or("default"));
+        status::set_connected(repo, false);
+        clients.push((repo, rx));
+    }
+    let bot = bots::with_api_url(Bot::from_env());
+    bots::init();
     log::info!("Bot connected.");
     tokio::try_join!(
         async {
-            teloxide::repl(
-                bot.clone(),
-                move |bot: Bot, msg: Message, cmd: Command, pool_clone: sqlite::SqlitePool| async move {
-                    if let Err(e) = answer(bot, msg, cmd, pool_clone.clone()).await {
-                        log::error!("An error occurred while replying to the user: {}", e);
-                    }
-                    respond(())
-                },
-            ).await;
+            let handler = dptree::entry()
+                .branch(Update::filter_message().filter_command::<Command>().endpoint(
+                    |bot: Bot, msg: Message, cmd: Command, pool: sqlite::SqlitePool| async move {
+                        if let Err(e) = answer(bot, msg, cmd, pool).await {
+                            log::error!("An error occurred while replying to the user: {}", e);
+                        }
+                        respond(())
+                    },
+                ))
+                .branch(Update::filter_callback_query().endpoint(
+                    |bot: Bot, query: CallbackQuery, pool: sqlite::SqlitePool| async move {
+                        if let Err(e) = answer_callback(bot, query, pool).await {
+                            log::error!("An error occurred while handling the callback: {}", e);
+                        }
+                        respond(())
+                    },
+                ));
+            Dispatcher::builder(bot.clone(), handler)
+                .dependencies(dptree::deps![pool.clone()])
+                .default_handler(|_| async {})
+                .enable_ctrlc_handler()
+                .build()
+                .dispatch()
+                .await;
+            Ok(())
+        },
+        futures_util::future::try_join_all(clients.into_iter().map(|(repo, rx)| {
+            let (bot, pool) = (&bot, &pool);
+            async move {
+                let result = monitor_pv(rx, repo, bot, pool).await;
+                status::set_connected(repo, false);
+                result
+            }
+        })),
+        run_jobs(&bot, &pool),
+        sinks::run(),
+        async {
+            if let Ok(path) = std::env::var("LAST_UPDATE") {
+                if let Err(e) = send_missed(&bot, &pool, &path).await {
+                    log::error!("Could not send the missed updates: {}", e);
+                }
+            }
             Ok(())
         },
-        monitor_pv(rx, &bot, &pool),
+        async {
+            match std::env::var("API_LISTEN") {
+                Ok(listen) => api::serve(&listen, pool.clone()).await,
+                Err(_) => Ok(()),
+            }
+        },
         async {
             let path = std::env::var("LAST_UPDATE");
             if let Ok(path) = path {
@@ -407,7 +2246,164 @@ async fn run() -> Result<()> {
     Err(anyhow!("Bot exited due to an error."))
 }
 
+/// Export the subscriptions to the file (or the standard output), or import them from the file
+async fn backup(command: &str, path: Option<&str>) -> Result<()> {
+    config::load()?;
+    let pool = config::get()
+        .database
+        .connect(&std::env::var("DATABASE_URL")?)
+        .await?;
+    migrate!().run(&pool).await?;
+    match (command, path) {
+        ("export", path) => {
+            let backup = serde_json::to_string_pretty(&backup::export(&pool).await?)?;
+            match path {
+                Some(path) => std::fs::write(path, backup + "\n")?,
+                None => println!("{}", backup),
+            }
+        }
+        ("import", Some(path)) => {
+            let backup = backup::Backup::parse(&std::fs::read_to_string(path)?)?;
+            backup::import(&pool, &backup).await?;
+            println!("Imported {} chats.", backup.chats.len());
+        }
+        _ => {
+            return Err(anyhow!(
+                "Usage: repository-notifier [export [FILE] | import FILE]"
+            ))
+        }
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
-    run().await.unwrap();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first() {
+        Some(command) => backup(command, args.get(1).map(|p| p.as_str()))
+            .await
+            .unwrap(),
+        None => run().await.unwrap(),
+    }
+}
+
+#[test]
+fn test_backoff() {
+    assert_eq!(backoff(1), Duration::from_secs(1));
+    assert_eq!(backoff(3), Duration::from_secs(4));
+    assert_eq!(backoff(64), MAX_BACKOFF);
+    // the sender is not blocked for long before giving up
+    let total = (1..SEND_ATTEMPTS).map(backoff).sum::<Duration>();
+    assert_eq!(total, Duration::from_secs(15));
+}
+
+#[test]
+fn test_render_formats() {
+    use notifier_core::{PVError, PVMessageMethod};
+
+    let message = PVMessage {
+        comp: "stable".to_string(),
+        pkg: "gtk-3".to_string(),
+        arch: "amd64".to_string(),
+        method: PVMessageMethod::New(b'^'),
+        from_ver: Some("3.24.1".to_string()),
+        to_ver: Some("3.24.2".to_string()),
+        error: None,
+        repo: None,
+        security: false,
+        mentions: Vec::new(),
+        description: None,
+    };
+    assert_eq!(
+        message.render(Format::Markdown),
+        r"` ^` [gtk\-3](https://packages.aosc.io/packages/gtk-3) `3.24.1` ⇒ `3.24.2`"
+    );
+    assert_eq!(message.render(Format::Plain), " ^ gtk-3 3.24.1 ⇒ 3.24.2");
+    let security = PVMessage {
+        security: true,
+        ..message.clone()
+    };
+    assert_eq!(security.render(Format::Plain), "🔒 ^ gtk-3 3.24.1 ⇒ 3.24.2");
+    let mentioned = PVMessage {
+        mentions: vec!["gtk_maintainer".to_string()],
+        ..message.clone()
+    };
+    assert!(mentioned
+        .render(Format::Markdown)
+        .ends_with(r" @gtk\_maintainer"));
+    let described = PVMessage {
+        description: Some("GTK+ toolkit".to_string()),
+        ..message.clone()
+    };
+    assert!(described
+        .render(Format::Html)
+        .ends_with(" — <i>GTK+ toolkit</i>"));
+    let failed = PVMessage {
+        method: PVMessageMethod::New(b'i'),
+        error: Some(PVError {
+            stage: Some("scan".to_string()),
+            message: "bad <control> file".to_string(),
+        }),
+        ..message.clone()
+    };
+    assert_eq!(
+        failed.render(Format::Html),
+        "<code> i</code> gtk-3 failed at scan: <code>bad &lt;control&gt; file</code>"
+    );
+    assert_eq!(
+        failed.render(Format::Plain),
+        " i gtk-3 failed at scan: bad <control> file"
+    );
+    let chunks = split_into_chunks(
+        &[message],
+        &(Format::Markdown, Default::default(), Grouping::Arch),
+        "en",
+        0,
+    );
+    assert_eq!(chunks.len(), 1);
+    assert!(chunks[0].1.starts_with("*stable* amd64\n"));
+}
+
+#[tokio::test]
+async fn test_mock_api() {
+    let api = mock_api::MockApi::start().await.unwrap();
+    let pool = sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    migrate!().run(&pool).await.unwrap();
+    query!("INSERT INTO subbed (chat_id) VALUES (-1001)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let mut last_sequence = None;
+    let mut pending = Vec::new();
+    for fixture in [
+        &include_bytes!("../tests/fixtures/legacy.json")[..],
+        &include_bytes!("../tests/fixtures/v3.bin")[..],
+    ] {
+        parse_message(fixture, None, &mut last_sequence, &mut pending)
+            .await
+            .unwrap();
+    }
+    assert_eq!(last_sequence, Some(1));
+    assert_eq!(pending.len(), 4);
+
+    // the first attempt is rate limited, then retried
+    api.rate_limit(1);
+    send_all_pending_messages(&mut pending, last_sequence, &api.bot(), &pool)
+        .await
+        .unwrap();
+    let requests = api.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests.iter().all(|(method, _)| method == "SendMessage"));
+    assert_eq!(requests[1].1["chat_id"], -1001);
+    let text = requests[1].1["text"].as_str().unwrap();
+    assert!(text.contains("<b>stable</b> amd64\n") && text.contains("<b>stable</b> arm64\n"));
+    assert!(text.contains(r#"<a href="https://packages.aosc.io/packages/gtk-3">gtk-3</a>"#));
+    assert!(text.contains("<code> -</code>"));
+    assert_eq!(history::recent(&pool, 10).await.unwrap().len(), 4);
+    api.stop().await;
 }
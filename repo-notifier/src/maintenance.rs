@@ -0,0 +1,54 @@
+//! Maintenance of the database: the old history and audit entries are pruned (`[retention]` in
+//! the configuration), then the file is compacted
+use anyhow::Result;
+use sqlx::{query, sqlite::SqlitePool};
+
+use crate::{config, snooze};
+
+const DAY: i64 = 24 * 3600;
+
+/// Entries older than this UNIX timestamp are pruned, `None` to keep them forever
+fn cutoff(now: i64, days: i64) -> Option<i64> {
+    if days <= 0 {
+        return None;
+    }
+
+    Some(now - days * DAY)
+}
+
+/// Prune the entries past their retention, then reclaim the space and refresh the statistics
+/// of the query planner
+pub async fn run(pool: &SqlitePool) -> Result<()> {
+    let retention = &config::get().retention;
+    let now = snooze::now();
+    let mut history = 0;
+    if let Some(cutoff) = cutoff(now, retention.history_days) {
+        history = query!("DELETE FROM history WHERE timestamp < ?", cutoff)
+            .execute(pool)
+            .await?
+            .rows_affected();
+    }
+    let mut audit = 0;
+    if let Some(cutoff) = cutoff(now, retention.audit_days) {
+        audit = query!("DELETE FROM audit WHERE timestamp < ?", cutoff)
+            .execute(pool)
+            .await?
+            .rows_affected();
+    }
+    query!("VACUUM").execute(pool).await?;
+    query!("ANALYZE").execute(pool).await?;
+    log::info!(
+        "Pruned {} updates and {} audit entries, database compacted.",
+        history,
+        audit
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_cutoff() {
+    assert_eq!(cutoff(100 * DAY, 30), Some(70 * DAY));
+    assert_eq!(cutoff(100 * DAY, 0), None);
+    assert_eq!(cutoff(100 * DAY, -1), None);
+}
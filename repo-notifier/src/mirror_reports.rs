@@ -0,0 +1,123 @@
+//! Mirror issues reported by the users, and the probation of the reported mirrors in
+//! repo-redirect until they are resolved
+use anyhow::Result;
+use sqlx::{query, sqlite::SqlitePool};
+use std::collections::BTreeMap;
+
+use crate::{config, snooze};
+
+const HOUR: i64 = 3600;
+
+/// Outcome of a report
+#[derive(Debug, PartialEq, Eq)]
+pub enum Filed {
+    /// The first report of an issue, the operators should be notified
+    New,
+    /// Added to the open report, with this many reports in total
+    Repeated(i64),
+}
+
+/// File a report of the mirror, or count it in the open one reported recently
+pub async fn file(pool: &SqlitePool, chat_id: i64, mirror: &str, details: &str) -> Result<Filed> {
+    let now = snooze::now();
+    let since = now - config::get().mirror_reports.dedup_hours * HOUR;
+    let mut tx = pool.begin().await?;
+    let open = query!(
+        "SELECT id, reports FROM mirror_reports
+        WHERE mirror = ? AND resolved IS NULL AND last_reported >= ? ORDER BY id DESC LIMIT 1",
+        mirror,
+        since
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let filed = match open {
+        Some(report) => {
+            query!(
+                "UPDATE mirror_reports SET reports = reports + 1, last_reported = ? WHERE id = ?",
+                now,
+                report.id
+            )
+            .execute(&mut *tx)
+            .await?;
+            Filed::Repeated(report.reports + 1)
+        }
+        None => {
+            query!(
+                "INSERT INTO mirror_reports (mirror, chat_id, details, first_reported, last_reported)
+                VALUES (?, ?, ?, ?, ?)",
+                mirror,
+                chat_id,
+                details,
+                now,
+                now
+            )
+            .execute(&mut *tx)
+            .await?;
+            Filed::New
+        }
+    };
+    tx.commit().await?;
+
+    Ok(filed)
+}
+
+/// Resolve the open reports of the mirror.
+///
+/// Returns `false` if there was none.
+pub async fn resolve(pool: &SqlitePool, mirror: &str) -> Result<bool> {
+    let now = snooze::now();
+    let result = query!(
+        "UPDATE mirror_reports SET resolved = ? WHERE mirror = ? AND resolved IS NULL",
+        now,
+        mirror
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Get the mirrors on probation and when it ends (UNIX timestamp)
+pub async fn probation(pool: &SqlitePool) -> Result<BTreeMap<String, i64>> {
+    let length = config::get().mirror_reports.probation_hours * HOUR;
+    if length < 1 {
+        return Ok(BTreeMap::new());
+    }
+    let since = snooze::now() - length;
+    let rows = query!(
+        r#"SELECT mirror AS "mirror!", MAX(last_reported) AS "last_reported!: i64" FROM mirror_reports
+        WHERE resolved IS NULL AND last_reported > ? GROUP BY mirror"#,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.mirror, r.last_reported + length))
+        .collect())
+}
+
+/// Write the mirrors on probation to the file read by repo-redirect (if configured)
+pub async fn write_probation(pool: &SqlitePool) -> Result<()> {
+    let path = match config::get().mirror_reports.probation_file.as_deref() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let probation = serde_json::to_string(&probation(pool).await?)?;
+    // replace the file at once so that repo-redirect never reads a partial one
+    let temp = format!("{}.tmp", path);
+    std::fs::write(&temp, probation)?;
+    std::fs::rename(&temp, path)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_find_mirror() {
+    let mut reports = config::MirrorReports::default();
+    assert_eq!(reports.find("anything").as_deref(), Some("anything"));
+    reports.mirrors = vec!["origin".to_string(), "TUNA".to_string()];
+    assert_eq!(reports.find("tuna").as_deref(), Some("TUNA"));
+    assert_eq!(reports.find("bfsu"), None);
+}
@@ -0,0 +1,105 @@
+//! Stand-in for the Telegram Bot API in the end-to-end tests of the batching and the retries:
+//! it answers `sendMessage` and `editMessageText` with the message as sent, everything else
+//! with `true`, and records the requests (teloxide names the methods like `SendMessage`)
+use actix_web::{dev::ServerHandle, post, web, App, HttpResponse, HttpServer};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use teloxide::Bot;
+
+#[derive(Default)]
+struct State {
+    /// Method and parameters of each request, in the order they arrived
+    requests: Vec<(String, Value)>,
+    /// Number of the next requests answered with `429 Too Many Requests`
+    rate_limited: usize,
+    last_message_id: i32,
+}
+
+type Shared = Arc<Mutex<State>>;
+
+pub struct MockApi {
+    state: Shared,
+    url: reqwest::Url,
+    handle: ServerHandle,
+}
+
+#[post("/bot{token}/{method}")]
+async fn call(
+    state: web::Data<Shared>,
+    path: web::Path<(String, String)>,
+    params: web::Json<Value>,
+) -> HttpResponse {
+    let (_, method) = path.into_inner();
+    let params = params.into_inner();
+    let mut state = state.lock().unwrap();
+    state.requests.push((method.clone(), params.clone()));
+    if state.rate_limited > 0 {
+        state.rate_limited -= 1;
+        return HttpResponse::TooManyRequests().json(json!({
+            "ok": false,
+            "error_code": 429,
+            "description": "Too Many Requests: retry after 1",
+            "parameters": { "retry_after": 1 },
+        }));
+    }
+    let result = match method.to_ascii_lowercase().as_str() {
+        "sendmessage" | "editmessagetext" => {
+            let message_id = match params.get("message_id") {
+                Some(id) => id.as_i64().unwrap_or_default() as i32,
+                None => {
+                    state.last_message_id += 1;
+                    state.last_message_id
+                }
+            };
+            json!({
+                "message_id": message_id,
+                "date": 0,
+                "chat": { "id": params["chat_id"], "type": "private" },
+                "text": params["text"],
+            })
+        }
+        _ => json!(true),
+    };
+
+    HttpResponse::Ok().json(json!({ "ok": true, "result": result }))
+}
+
+impl MockApi {
+    /// Serve the mock API on a free local port
+    pub async fn start() -> std::io::Result<MockApi> {
+        let state = Shared::default();
+        let data = web::Data::new(state.clone());
+        let server = HttpServer::new(move || App::new().app_data(data.clone()).service(call))
+            .workers(1)
+            .bind(("127.0.0.1", 0))?;
+        let url = format!("http://{}/", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        Ok(MockApi {
+            state,
+            url: reqwest::Url::parse(&url).expect("Invalid address of the mock API"),
+            handle,
+        })
+    }
+
+    /// A bot talking to the mock API
+    pub fn bot(&self) -> Bot {
+        Bot::new("1234:mock").set_api_url(self.url.clone())
+    }
+
+    /// Answer the next `count` requests with `429 Too Many Requests` (retry after 1 second)
+    pub fn rate_limit(&self, count: usize) {
+        self.state.lock().unwrap().rate_limited = count;
+    }
+
+    /// Method and parameters of the requests received so far
+    pub fn requests(&self) -> Vec<(String, Value)> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub async fn stop(self) {
+        self.handle.stop(false).await;
+    }
+}
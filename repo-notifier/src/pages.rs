@@ -0,0 +1,89 @@
+use once_cell::sync::Lazy;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::{
+    format::Format,
+    i18n::{tr, tr_with, Text},
+};
+
+// Number of batches whose pages are kept around for the "Show more" button
+const KEEP_BATCHES: usize = 32;
+
+/// A page of the batch (number of updates and the formatted content)
+type Page = (usize, String);
+/// Pages of a batch for each chat along with its ID
+type Batch = (u64, HashMap<i64, Arc<Vec<Page>>>);
+
+/// Pages of the recent batches
+static PAGES: Lazy<Mutex<VecDeque<Batch>>> =
+    Lazy::new(|| Mutex::new(VecDeque::with_capacity(KEEP_BATCHES)));
+static NEXT_BATCH: AtomicU64 = AtomicU64::new(0);
+
+/// Merge the chunks (number of updates and the formatted content) into pages
+/// no longer than `max_length`
+pub fn paginate(chunks: Vec<Page>, max_length: usize) -> Vec<Page> {
+    let mut pages: Vec<Page> = Vec::new();
+    for (count, chunk) in chunks {
+        match pages.last_mut() {
+            Some(page) if page.1.len() + chunk.len() <= max_length => {
+                page.0 += count;
+                page.1 += &chunk;
+            }
+            _ => pages.push((count, chunk)),
+        }
+    }
+
+    pages
+}
+
+/// Store the pages of a batch (rendered for each chat) and return the ID of the batch
+pub fn store(pages: HashMap<i64, Arc<Vec<Page>>>) -> u64 {
+    let batch = NEXT_BATCH.fetch_add(1, Ordering::SeqCst);
+    let mut stored = PAGES.lock().unwrap();
+    if stored.len() >= KEEP_BATCHES {
+        stored.pop_front();
+    }
+    stored.push_back((batch, pages));
+
+    batch
+}
+
+/// Get the content of the given page and the number of updates in the pages after it
+pub fn get(batch: u64, page: usize, chat_id: i64) -> Option<(String, usize)> {
+    let stored = PAGES.lock().unwrap();
+    let (_, pages) = stored.iter().find(|(id, _)| *id == batch)?;
+    let pages = pages.get(&chat_id)?;
+    let content = pages.get(page)?.1.clone();
+    let remaining = pages.iter().skip(page + 1).map(|p| p.0).sum();
+
+    Some((content, remaining))
+}
+
+/// Text appended to a page when there are more pages to show
+pub fn footer(remaining: usize, lang: &str, format: Format) -> String {
+    format.italic(&format.escape(&tr_with(lang, Text::MoreUpdates, &remaining.to_string())))
+}
+
+/// Inline keyboard showing the given page when tapped
+pub fn keyboard(batch: u64, page: usize, lang: &str) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+        tr(lang, Text::ShowMore),
+        format!("more:{}:{}", batch, page),
+    )]])
+}
+
+/// Parse the callback data of the "Show more" button
+pub fn parse_callback(data: &str) -> Option<(u64, usize)> {
+    let mut splitted = data.strip_prefix("more:")?.split(':');
+    let batch = splitted.next()?.parse().ok()?;
+    let page = splitted.next()?.parse().ok()?;
+
+    Some((batch, page))
+}
@@ -0,0 +1,140 @@
+//! Per-chat token buckets limiting the messages sent with the updates, the chunks appended to
+//! the previous message of the batch (edited) are free
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+/// Token bucket of a chat, refilled at `per_hour` tokens per hour
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Number of updates not sent because of the limit
+    overflow: usize,
+}
+
+/// Token buckets of the chats
+#[derive(Default)]
+pub struct Buckets {
+    buckets: Mutex<HashMap<i64, Bucket>>,
+}
+
+static BUCKETS: Lazy<Buckets> = Lazy::new(Buckets::default);
+
+impl Buckets {
+    /// Decide how many of the messages (with `counts[i]` updates each) can be sent to the chat
+    /// at `now`.
+    ///
+    /// Returns the number of the leading messages to send, and the number of the updates to
+    /// summarize in a single message (if the summary can be sent now). The updates in the
+    /// remaining messages are added to the summary. A limit of 0 means unlimited.
+    pub fn acquire(
+        &self,
+        chat_id: i64,
+        per_hour: u32,
+        counts: &[usize],
+        now: Instant,
+    ) -> (usize, Option<usize>) {
+        if per_hour == 0 {
+            return (counts.len(), None);
+        }
+        let capacity = per_hour as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(chat_id).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            overflow: 0,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * capacity / 3600.0).min(capacity);
+        bucket.updated = now;
+
+        let available = bucket.tokens.floor() as usize;
+        let send = if bucket.overflow == 0 && counts.len() <= available {
+            counts.len()
+        } else {
+            // keep a message for the summary
+            counts.len().min(available.saturating_sub(1))
+        };
+        bucket.tokens -= send as f64;
+        bucket.overflow += counts[send..].iter().sum::<usize>();
+        let summary = if bucket.overflow > 0 && bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Some(std::mem::take(&mut bucket.overflow))
+        } else {
+            None
+        };
+
+        (send, summary)
+    }
+
+    /// Chats with updates waiting to be summarized
+    pub fn overflowed(&self) -> Vec<i64> {
+        self.buckets
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, b)| b.overflow > 0)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+/// Decide how many of the messages (with `counts[i]` updates each) can be sent to the chat now,
+/// see [`Buckets::acquire`]
+pub fn acquire(chat_id: i64, per_hour: u32, counts: &[usize]) -> (usize, Option<usize>) {
+    BUCKETS.acquire(chat_id, per_hour, counts, Instant::now())
+}
+
+/// Chats with updates waiting to be summarized
+pub fn overflowed() -> Vec<i64> {
+    BUCKETS.overflowed()
+}
+
+/// Group the chunks (number of updates and text) into the messages sent, as the chunks are
+/// appended to the previous message while it stays under `max_length`.
+///
+/// Returns the number of the chunks and of the updates in each message.
+pub fn messages(chunks: &[(usize, String)], max_length: usize) -> Vec<(usize, usize)> {
+    let mut messages = Vec::new();
+    let mut length = 0;
+    for (count, text) in chunks {
+        match messages.last_mut() {
+            Some((chunks, updates)) if length + text.len() <= max_length => {
+                *chunks += 1;
+                *updates += count;
+                length += text.len();
+            }
+            _ => {
+                messages.push((1, *count));
+                length = text.len();
+            }
+        }
+    }
+
+    messages
+}
+
+#[test]
+fn test_rate_limit() {
+    let buckets = Buckets::default();
+    let now = Instant::now();
+    assert_eq!(buckets.acquire(1, 0, &[1, 2, 3], now), (3, None));
+    assert_eq!(buckets.acquire(2, 3, &[1, 2], now), (2, None));
+    // one message left, used for the summary
+    assert_eq!(buckets.acquire(2, 3, &[4, 5], now), (0, Some(9)));
+    assert_eq!(buckets.acquire(2, 3, &[6], now), (0, None));
+    assert_eq!(buckets.overflowed(), vec![2]);
+    // refilled at 3 messages per hour
+    let later = now + std::time::Duration::from_secs(1200);
+    assert_eq!(buckets.acquire(2, 3, &[], later), (0, Some(6)));
+    assert!(buckets.overflowed().is_empty());
+
+    let chunk = |count, length| (count, "x".repeat(length));
+    let chunks = [
+        chunk(3, 1500),
+        chunk(2, 1500),
+        chunk(4, 1500),
+        chunk(1, 3000),
+    ];
+    assert_eq!(messages(&chunks, 4000), [(2, 5), (1, 4), (1, 1)]);
+    assert!(messages(&[], 4000).is_empty());
+}
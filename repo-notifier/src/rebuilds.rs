@@ -0,0 +1,212 @@
+use notifier_core::PVMessage;
+use std::collections::{HashMap, HashSet};
+
+use crate::format::Format;
+use crate::grouping::Grouping;
+use crate::i18n::{tr, Text};
+
+/// Longest list of the rebuilt packages under a collapsed line, the rest are only counted
+const MAX_LIST_LENGTH: usize = 1500;
+
+/// Get the version without the package release, e.g. `1.2.3` of `1.2.3-1`
+fn upstream_version(version: &str) -> &str {
+    match version.rsplit_once('-') {
+        Some((upstream, rel)) if !rel.is_empty() && rel.bytes().all(|c| c.is_ascii_digit()) => {
+            upstream
+        }
+        _ => version,
+    }
+}
+
+/// Whether the update only bumps the package release (a rebuild of the same upstream version),
+/// the security fixes are never considered rebuilds
+fn is_rebuild(p: &PVMessage) -> bool {
+    if p.method.as_new_type() != b'^' || p.security {
+        return false;
+    }
+    match (p.from_ver.as_deref(), p.to_ver.as_deref()) {
+        (Some(from), Some(to)) => from != to && upstream_version(from) == upstream_version(to),
+        _ => false,
+    }
+}
+
+/// An update or the collapsed rebuilds in the same repository, component and architecture
+/// (or only component when grouped by package)
+pub enum Entry<'a> {
+    Single(&'a PVMessage),
+    /// The same update on several architectures
+    Arches(Vec<&'a PVMessage>),
+    Rebuilds {
+        packages: Vec<&'a PVMessage>,
+        /// The only other new or upgraded package next to the rebuilds, likely their cause
+        against: Option<&'a PVMessage>,
+    },
+}
+
+type Group<'a> = (Option<&'a str>, &'a str, Option<&'a str>);
+
+fn group_of(p: &PVMessage, grouping: Grouping) -> Group<'_> {
+    let arch = match grouping {
+        Grouping::Arch => Some(p.arch.as_str()),
+        Grouping::Package => None,
+    };
+
+    (p.repo.as_deref(), &p.comp, arch)
+}
+
+/// Names of the packages, without the repeated ones (rebuilt on several architectures)
+fn names<'a>(packages: &[&'a PVMessage]) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+
+    packages
+        .iter()
+        .map(|p| p.pkg.as_str())
+        .filter(|pkg| seen.insert(*pkg))
+        .collect()
+}
+
+impl<'a> Entry<'a> {
+    /// Number of the updates in the entry
+    pub fn count(&self) -> usize {
+        match self {
+            Entry::Single(_) => 1,
+            Entry::Arches(packages) => packages.len(),
+            Entry::Rebuilds { packages, .. } => packages.len(),
+        }
+    }
+
+    /// The (first) update in the entry
+    pub fn first(&self) -> &'a PVMessage {
+        match self {
+            Entry::Single(p) => p,
+            Entry::Arches(packages) | Entry::Rebuilds { packages, .. } => packages[0],
+        }
+    }
+}
+
+/// Render the collapsed rebuilds as a line and an expandable list of the packages
+pub fn render(
+    packages: &[&PVMessage],
+    against: Option<&PVMessage>,
+    lang: &str,
+    format: Format,
+) -> String {
+    let names = names(packages);
+    let count = names.len().to_string();
+    let text = match against {
+        Some(p) => tr(lang, Text::RebuiltAgainst)
+            .replacen("{}", &count, 1)
+            .replacen(
+                "{}",
+                &format!("{} {}", p.pkg, p.to_ver.as_deref().unwrap_or("?")),
+                1,
+            ),
+        None => tr(lang, Text::Rebuilt).replacen("{}", &count, 1),
+    };
+    let line = match format {
+        Format::Html => format!("<code> ^</code> {}", format.escape(&text)),
+        Format::Markdown => format!("` ^` {}", format.escape(&text)),
+        Format::Plain => format!(" ^ {}", text),
+    };
+    let mut list = String::new();
+    let mut listed = 0;
+    for pkg in names.iter() {
+        if list.len() + pkg.len() > MAX_LIST_LENGTH {
+            break;
+        }
+        if listed > 0 {
+            list += ", ";
+        }
+        list += pkg;
+        listed += 1;
+    }
+    if listed < names.len() {
+        list += &format!(" (+{})", names.len() - listed);
+    }
+
+    format!("{}\n{}", line, format.expandable(&format.escape(&list)))
+}
+
+/// Collapse the rebuilds in each repository, component and architecture (or component when
+/// grouped by package) with at least `threshold` of them (0: never), the collapsed entry
+/// takes the place of the first rebuild
+pub fn collapse(messages: &[PVMessage], threshold: usize, grouping: Grouping) -> Vec<Entry<'_>> {
+    let mut rebuilds: HashMap<Group, Vec<&PVMessage>> = HashMap::new();
+    let mut others: HashMap<Group, Vec<&PVMessage>> = HashMap::new();
+    for p in messages {
+        if is_rebuild(p) {
+            rebuilds.entry(group_of(p, grouping)).or_default().push(p);
+        } else if matches!(p.method.as_new_type(), b'^' | b'+') {
+            others.entry(group_of(p, grouping)).or_default().push(p);
+        }
+    }
+    rebuilds.retain(|_, packages| threshold > 0 && names(packages).len() >= threshold);
+    let collapsed = rebuilds.keys().copied().collect::<HashSet<_>>();
+    let mut entries = Vec::new();
+    for p in messages {
+        let group = group_of(p, grouping);
+        if !is_rebuild(p) || !collapsed.contains(&group) {
+            entries.push(Entry::Single(p));
+            continue;
+        }
+        // the rest of the group was collapsed into the first one
+        if let Some(packages) = rebuilds.remove(&group) {
+            let against = others.get(&group).filter(|o| o.len() == 1).map(|o| o[0]);
+            entries.push(Entry::Rebuilds { packages, against });
+        }
+    }
+
+    entries
+}
+
+#[test]
+fn test_collapse() {
+    let message = |pkg: &str, arch: &str, from: &str, to: &str| {
+        PVMessage::sample(pkg, arch, b'^', Some(from), Some(to))
+    };
+    assert_eq!(upstream_version("1:1.2.3-4"), "1:1.2.3");
+    assert_eq!(upstream_version("1.2.3"), "1.2.3");
+    assert_eq!(upstream_version("1.2.3-rc1"), "1.2.3-rc1");
+    let messages = vec![
+        message("gtk-3", "amd64", "3.24.1", "3.24.1-1"),
+        message("libfoo", "amd64", "1.0", "2.0"),
+        message("bar", "amd64", "1.0-1", "1.0-2"),
+        message("baz", "amd64", "2.1-3", "2.1-4"),
+        message("qux", "arm64", "2.1-3", "2.1-4"),
+    ];
+    let entries = collapse(&messages, 3, Grouping::Arch);
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries.iter().map(Entry::count).sum::<usize>(), 5);
+    match &entries[0] {
+        Entry::Rebuilds { packages, against } => {
+            assert_eq!(packages.len(), 3);
+            assert_eq!(against.map(|p| p.pkg.as_str()), Some("libfoo"));
+            assert_eq!(
+                render(packages, *against, "en", Format::Plain),
+                " ^ 3 packages rebuilt against libfoo 2.0\ngtk-3, bar, baz"
+            );
+        }
+        _ => panic!("rebuilds not collapsed"),
+    }
+    assert_eq!(entries[1].first().pkg, "libfoo");
+    assert_eq!(entries[2].first().pkg, "qux");
+    assert_eq!(collapse(&messages, 0, Grouping::Arch).len(), 5);
+    assert_eq!(collapse(&messages, 4, Grouping::Arch).len(), 5);
+    // rebuilt on several architectures, counted once
+    let entries = collapse(&messages, 4, Grouping::Package);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].first().pkg, "libfoo");
+    let messages = vec![
+        message("bar", "amd64", "1.0-1", "1.0-2"),
+        message("bar", "arm64", "1.0-1", "1.0-2"),
+        message("baz", "amd64", "2.1-3", "2.1-4"),
+    ];
+    assert_eq!(collapse(&messages, 3, Grouping::Package).len(), 3);
+    match &collapse(&messages, 2, Grouping::Package)[0] {
+        Entry::Rebuilds { packages, against } => assert_eq!(
+            render(packages, *against, "en", Format::Plain),
+            " ^ 2 packages rebuilt\nbar, baz"
+        ),
+        _ => panic!("rebuilds not collapsed"),
+    }
+}
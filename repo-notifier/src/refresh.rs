@@ -0,0 +1,143 @@
+//! Statistics of the repository refreshes: from the first update after the previous refresh
+//! to the write of the `last_update` file
+use once_cell::sync::Lazy;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use notifier_core::PVMessage;
+use teloxide::types::{ChatId, MessageId};
+
+use crate::i18n::{tr, Text};
+use crate::snooze;
+use crate::status;
+use crate::summary::{self, Counts};
+
+/// Number of the completed refreshes kept for the dashboard
+const KEEP_RECENT: usize = 20;
+
+/// A completed refresh
+#[derive(Clone)]
+pub struct Refresh {
+    /// When `last_update` was written (UNIX timestamp)
+    pub finished: i64,
+    pub duration: Duration,
+    pub counts: Counts,
+}
+
+impl Refresh {
+    /// Describe the refresh, e.g. `🔄 Repository refreshed in 3m 12s: 1 new, 12 upgraded.`
+    pub fn describe(&self, lang: &str) -> String {
+        tr(lang, Text::RefreshedIn)
+            .replacen("{}", &status::elapsed(self.duration.as_secs() as i64), 1)
+            .replacen("{}", &summary::describe(&self.counts, lang), 1)
+    }
+
+    /// The refreshes combined, from the start of this one to the end of the next one
+    fn followed_by(&self, next: &Refresh) -> Refresh {
+        let gap = Duration::from_secs((next.finished - self.finished).max(0) as u64);
+        let mut counts = self.counts;
+        for (count, more) in counts.iter_mut().zip(next.counts.iter()) {
+            *count += more;
+        }
+
+        Refresh {
+            finished: next.finished,
+            duration: (self.duration + gap).max(next.duration),
+            counts,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Cycle {
+    started: Option<Instant>,
+    counts: Counts,
+}
+
+static CURRENT: Lazy<Mutex<Cycle>> = Lazy::new(|| Mutex::new(Cycle::default()));
+static COMPLETED: Lazy<Mutex<Option<Refresh>>> = Lazy::new(|| Mutex::new(None));
+static RECENT: Lazy<Mutex<VecDeque<Refresh>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+/// Last batch message sent to each chat since the previous refresh notice, which replies to it
+static THREADS: Lazy<Mutex<HashMap<i64, (ChatId, MessageId)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Count the updates received from p-vector
+pub fn received(updates: &[PVMessage]) {
+    if updates.is_empty() {
+        return;
+    }
+    let mut cycle = CURRENT.lock().unwrap();
+    cycle.started.get_or_insert_with(Instant::now);
+    for p in updates {
+        summary::count(&mut cycle.counts, p);
+    }
+}
+
+/// Complete the refresh once `last_update` is written
+pub fn written() {
+    let cycle = std::mem::take(&mut *CURRENT.lock().unwrap());
+    if let Some(started) = cycle.started {
+        let refresh = Refresh {
+            finished: snooze::now(),
+            duration: started.elapsed(),
+            counts: cycle.counts,
+        };
+        let mut recent = RECENT.lock().unwrap();
+        recent.push_front(refresh.clone());
+        recent.truncate(KEEP_RECENT);
+        let mut completed = COMPLETED.lock().unwrap();
+        *completed = Some(match completed.take() {
+            // not announced yet (the batches were combined), both are announced together
+            Some(previous) => previous.followed_by(&refresh),
+            None => refresh,
+        });
+    }
+}
+
+/// Take the statistics of the last completed refresh
+pub fn take() -> Option<Refresh> {
+    COMPLETED.lock().unwrap().take()
+}
+
+/// Remember the last message of the batch sent to the chat
+pub fn batch_sent(chat_id: i64, real_id: ChatId, message_id: MessageId) {
+    THREADS
+        .lock()
+        .unwrap()
+        .insert(chat_id, (real_id, message_id));
+}
+
+/// Take the last batch messages of the chats, for the refresh notice to reply to
+pub fn take_threads() -> HashMap<i64, (ChatId, MessageId)> {
+    std::mem::take(&mut *THREADS.lock().unwrap())
+}
+
+/// The recently completed refreshes, the latest first
+pub fn recent() -> Vec<Refresh> {
+    RECENT.lock().unwrap().iter().cloned().collect()
+}
+
+#[test]
+fn test_refresh() {
+    let refresh = Refresh {
+        finished: 0,
+        duration: Duration::from_secs(192),
+        counts: [1, 12, 0, 0, 0],
+    };
+    assert_eq!(
+        refresh.describe("en"),
+        "🔄 Repository refreshed in 3m 12s: 1 new, 12 upgraded."
+    );
+    let next = Refresh {
+        finished: 300,
+        duration: Duration::from_secs(60),
+        counts: [0, 3, 1, 0, 0],
+    };
+    assert_eq!(
+        refresh.followed_by(&next).describe("en"),
+        "🔄 Repository refreshed in 8m 12s: 1 new, 15 upgraded, 1 removed."
+    );
+}
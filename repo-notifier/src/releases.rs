@@ -0,0 +1,174 @@
+//! New releases of AOSC OS: the tarballs and the images added to the manifests written by
+//! repo-manifest (`[releases]` in the configuration), announced to the chats opting in
+use anyhow::Result;
+use serde::Deserialize;
+use sqlx::{query, sqlite::SqlitePool};
+use std::{collections::BTreeMap, path::Path};
+
+use crate::i18n::{tr, Text};
+use crate::snooze;
+
+/// A tarball or an image in the manifests
+#[derive(Debug, PartialEq)]
+pub struct Release {
+    pub variant: String,
+    pub arch: String,
+    pub date: String,
+    pub path: String,
+}
+
+#[derive(Deserialize)]
+struct File {
+    arch: String,
+    date: String,
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct Variant {
+    name: String,
+    #[serde(default)]
+    tarballs: Vec<File>,
+    #[serde(default)]
+    squashfs: Vec<File>,
+}
+
+/// `recipe.json`
+#[derive(Deserialize)]
+struct Recipe {
+    variants: Vec<Variant>,
+}
+
+#[derive(Deserialize)]
+struct Image {
+    variant: String,
+    #[serde(flatten)]
+    file: File,
+}
+
+/// `livekit.json`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LiveKit {
+    Images {
+        images: Vec<Image>,
+    },
+    /// The legacy format, a plain list of the images
+    Legacy(Vec<File>),
+}
+
+fn release(variant: &str, file: File) -> Release {
+    Release {
+        variant: variant.to_string(),
+        arch: file.arch,
+        date: file.date,
+        path: file.path,
+    }
+}
+
+fn parse_recipe(data: &[u8]) -> Result<Vec<Release>> {
+    let recipe: Recipe = serde_json::from_slice(data)?;
+
+    Ok(recipe
+        .variants
+        .into_iter()
+        .flat_map(|v| {
+            let name = v.name;
+            v.tarballs
+                .into_iter()
+                .chain(v.squashfs)
+                .map(move |f| release(&name, f))
+        })
+        .collect())
+}
+
+fn parse_livekit(data: &[u8]) -> Result<Vec<Release>> {
+    Ok(match serde_json::from_slice(data)? {
+        LiveKit::Images { images } => images
+            .into_iter()
+            .map(|i| release(&i.variant, i.file))
+            .collect(),
+        LiveKit::Legacy(files) => files.into_iter().map(|f| release("livekit", f)).collect(),
+    })
+}
+
+/// Read the manifests and remember the files not seen before, which are returned unless the
+/// manifests are read for the first time
+pub async fn check(pool: &SqlitePool, manifest_dir: &Path) -> Result<Vec<Release>> {
+    let mut releases = parse_recipe(&std::fs::read(manifest_dir.join("recipe.json"))?)?;
+    releases.extend(parse_livekit(&std::fs::read(
+        manifest_dir.join("livekit.json"),
+    )?)?);
+    let now = snooze::now();
+    let mut tx = pool.begin().await?;
+    let first = query!(r#"SELECT COUNT(*) AS "count!: i64" FROM releases"#)
+        .fetch_one(&mut *tx)
+        .await?
+        .count
+        == 0;
+    let mut new = Vec::new();
+    for release in releases {
+        let inserted = query!(
+            "INSERT OR IGNORE INTO releases (path, seen) VALUES (?, ?)",
+            release.path,
+            now
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if inserted > 0 && !first {
+            new.push(release);
+        }
+    }
+    tx.commit().await?;
+
+    Ok(new)
+}
+
+/// Describe the releases, a line for each variant and date, e.g.
+/// `💿 New AOSC OS release: Base amd64, arm64 20240101`
+pub fn describe(releases: &[Release], lang: &str) -> String {
+    let mut arches: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+    for r in releases {
+        let arches = arches.entry((&r.date, &r.variant)).or_default();
+        if !arches.contains(&r.arch.as_str()) {
+            arches.push(&r.arch);
+        }
+    }
+
+    arches
+        .into_iter()
+        .map(|((date, variant), arches)| {
+            tr(lang, Text::Release)
+                .replacen("{}", variant, 1)
+                .replacen("{}", &arches.join(", "), 1)
+                .replacen("{}", date, 1)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn test_releases() {
+    let recipe = br#"{"version": 1, "variants": [{"name": "Base", "retro": false,
+        "tarballs": [
+            {"arch": "amd64", "date": "20240101", "path": "os-amd64/base/aosc-os_base_20240101_amd64.tar.xz"},
+            {"arch": "arm64", "date": "20240101", "path": "os-arm64/base/aosc-os_base_20240101_arm64.tar.xz"}
+        ],
+        "squashfs": [
+            {"arch": "amd64", "date": "20240101", "path": "os-amd64/base/aosc-os_base_20240101_amd64.squashfs"}
+        ]}]}"#;
+    let mut releases = parse_recipe(recipe).unwrap();
+    assert_eq!(releases.len(), 3);
+    assert_eq!(releases[1].arch, "arm64");
+    let livekit = br#"{"version": 2, "images": [{"variant": "livekit", "type": "iso", "retro": false,
+        "arch": "amd64", "date": "20240102", "path": "os-amd64/livekit/aosc-os_livekit_20240102_amd64.iso"}]}"#;
+    releases.extend(parse_livekit(livekit).unwrap());
+    let legacy = br#"[{"arch": "loongson3", "date": "20210614", "path": "os-loongson3/livekit/aosc-os_livekit_20210614_loongson3.iso"}]"#;
+    assert_eq!(parse_livekit(legacy).unwrap()[0].variant, "livekit");
+    assert_eq!(
+        describe(&releases, "en"),
+        "💿 New AOSC OS release: Base amd64, arm64 20240101\n\
+         💿 New AOSC OS release: livekit amd64 20240102"
+    );
+}
@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Timelike, Utc};
+use serde::Deserialize;
+use std::{convert::TryFrom, fmt, future::Future, str::FromStr};
+
+/// Cron expression with the 5 standard fields (minute, hour, day of month, month and
+/// day of week), evaluated in UTC. The `@hourly`, `@daily`, `@weekly` and `@monthly`
+/// shorthands are accepted too.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Schedule {
+    expression: String,
+    /// Bit masks of the allowed values of each field
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// When both the day of month and the day of week are restricted, either one matches
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+/// Parse a field like `*`, `*/15`, `1-5`, `0-30/10` or `1,15`, returns the bit mask
+/// of the allowed values and whether the field is restricted (not `*`)
+fn parse_field(field: &str, min: u32, max: u32) -> Result<(u64, bool)> {
+    let mut mask = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            return Err(anyhow!("Invalid step in {}", field));
+        }
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse()?, end.parse()?),
+                // `5/10` means from 5 to the end
+                None if step > 1 => (range.parse()?, max),
+                None => {
+                    let value = range.parse()?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(anyhow!("{} is out of range ({}-{})", item, min, max));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok((mask, field != "*"))
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expanded = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            s => s,
+        };
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(anyhow!("Expected 5 fields in the cron expression {:?}", s));
+        }
+        let error = |e: anyhow::Error| anyhow!("Invalid cron expression {:?}: {}", s, e);
+        let (minutes, _) = parse_field(fields[0], 0, 59).map_err(error)?;
+        let (hours, _) = parse_field(fields[1], 0, 23).map_err(error)?;
+        let (days, days_restricted) = parse_field(fields[2], 1, 31).map_err(error)?;
+        let (months, _) = parse_field(fields[3], 1, 12).map_err(error)?;
+        let (mut weekdays, weekdays_restricted) = parse_field(fields[4], 0, 7).map_err(error)?;
+        // both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Schedule {
+            expression: s.trim().to_string(),
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            days_restricted,
+            weekdays_restricted,
+        })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Debug for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl Schedule {
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// Get the first matching time after `time`
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut next = time.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        // every matching time appears within a few years (e.g. February 29)
+        let limit = time + Duration::days(366 * 8);
+        while next < limit {
+            if self.months & (1 << next.month()) == 0 {
+                // skip to the first day of the next month
+                let (year, month) = if next.month() == 12 {
+                    (next.year() + 1, 1)
+                } else {
+                    (next.year(), next.month() + 1)
+                };
+                next = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+                continue;
+            }
+            if !self.day_matches(&next) {
+                next = (next + Duration::days(1))
+                    .duration_trunc(Duration::days(1))
+                    .ok()?;
+                continue;
+            }
+            if self.hours & (1 << next.hour()) == 0 {
+                next = (next + Duration::hours(1))
+                    .duration_trunc(Duration::hours(1))
+                    .ok()?;
+                continue;
+            }
+            if self.minutes & (1 << next.minute()) == 0 {
+                next += Duration::minutes(1);
+                continue;
+            }
+
+            return Some(next);
+        }
+
+        None
+    }
+
+    /// Approximate interval between the runs from now on
+    pub fn interval(&self) -> Duration {
+        let first = self.next_after(Utc::now());
+        let second = first.and_then(|t| self.next_after(t));
+        match (first, second) {
+            (Some(first), Some(second)) => second - first,
+            _ => Duration::days(1),
+        }
+    }
+}
+
+/// Run the job whenever the schedule matches, the errors are logged
+pub async fn run<F, Fut>(name: &str, schedule: &Schedule, mut job: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    log::info!("Scheduled {} at {}.", name, schedule);
+    loop {
+        let now = Utc::now();
+        let next = schedule
+            .next_after(now)
+            .ok_or_else(|| anyhow!("{} ({}) will never run", name, schedule))?;
+        let wait = (next - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+        if let Err(e) = job().await {
+            log::error!("Job {} failed: {}", name, e);
+        }
+    }
+}
+
+#[test]
+fn test_schedule() {
+    let time = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+    let every_10 = "*/10 * * * *".parse::<Schedule>().unwrap();
+    assert_eq!(
+        every_10.next_after(time("2026-10-16T08:03:20Z")),
+        Some(time("2026-10-16T08:10:00Z"))
+    );
+    assert_eq!(
+        every_10.next_after(time("2026-10-16T08:10:00Z")),
+        Some(time("2026-10-16T08:20:00Z"))
+    );
+    // Mondays at 09:30
+    let weekly = "30 9 * * 1".parse::<Schedule>().unwrap();
+    assert_eq!(
+        weekly.next_after(time("2026-10-16T08:00:00Z")),
+        Some(time("2026-10-19T09:30:00Z"))
+    );
+    let leap_day = "0 0 29 2 *".parse::<Schedule>().unwrap();
+    assert_eq!(
+        leap_day.next_after(time("2026-10-16T08:00:00Z")),
+        Some(time("2028-02-29T00:00:00Z"))
+    );
+    // either the 1st or Sundays
+    let either = "0 12 1 * 7".parse::<Schedule>().unwrap();
+    assert_eq!(
+        either.next_after(time("2026-10-16T08:00:00Z")),
+        Some(time("2026-10-18T12:00:00Z"))
+    );
+    assert_eq!(
+        "@daily".parse::<Schedule>().unwrap().interval(),
+        Duration::days(1)
+    );
+    assert!("* * *".parse::<Schedule>().is_err());
+    assert!("60 * * * *".parse::<Schedule>().is_err());
+    assert!("*/0 * * * *".parse::<Schedule>().is_err());
+    assert!("0 0 31 2 *"
+        .parse::<Schedule>()
+        .unwrap()
+        .next_after(time("2026-10-16T08:00:00Z"))
+        .is_none());
+}
@@ -0,0 +1,663 @@
+use anyhow::Result;
+use sqlx::{query, sqlite::SqlitePool};
+use std::collections::HashMap;
+
+use crate::{
+    config,
+    filter::{Filters, Kind},
+    format::Format,
+    grouping::Grouping,
+    i18n::DEFAULT_LANG,
+    severity::Severity,
+    snooze::{self, QuietHours},
+    template::{Layout, Template},
+    timezone::TimeZone,
+};
+
+/// A subscribed chat along with its settings
+pub struct Subscriber {
+    pub chat_id: i64,
+    pub lang: String,
+    pub min_severity: i64,
+    /// Whether the minimum severity was chosen for the chat, the operational alerts are only
+    /// sent to the subscribers who did
+    pub severity_chosen: bool,
+    pub format: Format,
+    pub template: Template,
+    /// Grouping of the updates set for the chat
+    pub grouping: Option<Grouping>,
+    pub filters: Filters,
+    /// Maximum number of messages per hour set for the chat
+    pub rate_limit: Option<i64>,
+    /// Notifications are paused until this time (UNIX timestamp)
+    pub snoozed_until: Option<i64>,
+    pub timezone: TimeZone,
+    /// Daily window when the updates are held, in the time zone of the chat
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Subscriber {
+    /// Maximum number of messages per hour (0: unlimited)
+    pub fn messages_per_hour(&self) -> u32 {
+        match self.rate_limit {
+            Some(limit) => limit.clamp(0, u32::MAX as i64) as u32,
+            None => config::get().rate_limit.per_hour,
+        }
+    }
+
+    pub fn is_snoozed(&self) -> bool {
+        self.snoozed_until.is_some_and(|t| t > snooze::now())
+    }
+
+    /// When the quiet hours of the chat in progress at the time end, `None` outside of them
+    pub fn quiet_until(&self, now: i64) -> Option<i64> {
+        self.quiet_hours?.end_after(&self.timezone, now)
+    }
+
+    pub fn grouping(&self) -> Grouping {
+        self.grouping.unwrap_or(config::get().batching.group_by)
+    }
+
+    pub fn layout(&self) -> Layout {
+        (self.format, self.template.clone(), self.grouping())
+    }
+}
+
+/// Get all the subscribed chats
+pub async fn subscribers(pool: &SqlitePool) -> Result<Vec<Subscriber>> {
+    let rows = query!(
+        r#"SELECT subbed.chat_id, COALESCE(chat_settings.lang, 'en') AS "lang!: String",
+        subbed.lvl AS min_severity, subbed.severity_chosen AS "severity_chosen: bool",
+        COALESCE(chat_settings.format, 'html') AS "format!: Format",
+        chat_settings.template_header, chat_settings.template_line, chat_settings.template_footer,
+        chat_settings.grouping AS "grouping: Grouping", chat_settings.rate_limit,
+        chat_settings.snoozed_until, chat_settings.timezone, chat_settings.quiet_hours
+        FROM subbed LEFT JOIN chat_settings ON subbed.chat_id = chat_settings.chat_id"#
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut filters = all_filters(pool).await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| Subscriber {
+            filters: filters.remove(&r.chat_id).unwrap_or_default(),
+            grouping: r.grouping,
+            rate_limit: r.rate_limit,
+            snoozed_until: r.snoozed_until,
+            timezone: load_timezone(r.chat_id, r.timezone.as_deref()),
+            quiet_hours: load_quiet_hours(r.chat_id, r.quiet_hours.as_deref()),
+            chat_id: r.chat_id,
+            lang: r.lang,
+            min_severity: r.min_severity,
+            severity_chosen: r.severity_chosen,
+            format: r.format,
+            template: Template {
+                header: r.template_header,
+                line: r.template_line,
+                footer: r.template_footer,
+            },
+        })
+        .collect())
+}
+
+/// Get the chats that should receive the messages of the given severity
+pub async fn recipients(pool: &SqlitePool, severity: Severity) -> Result<Vec<Subscriber>> {
+    let routed = config::get()
+        .severity
+        .channels
+        .get(&severity)
+        .cloned()
+        .unwrap_or_default();
+    // operational alerts with dedicated channels are only sent to those
+    let dedicated = severity.is_operational() && !routed.is_empty();
+    let mut recipients = if dedicated {
+        Vec::new()
+    } else {
+        let mut subs = subscribers(pool).await?;
+        // the operational alerts are opt-in for the subscribers
+        subs.retain(|s| {
+            s.min_severity <= severity.as_level()
+                && (s.severity_chosen || !severity.is_operational())
+        });
+        subs
+    };
+    for chat_id in routed {
+        if recipients.iter().any(|r| r.chat_id == chat_id) {
+            continue;
+        }
+        recipients.push(Subscriber {
+            chat_id,
+            lang: get_lang(pool, chat_id).await?,
+            min_severity: 0,
+            severity_chosen: true,
+            format: get_format(pool, chat_id).await?,
+            template: get_template(pool, chat_id).await?,
+            grouping: get_grouping(pool, chat_id).await?,
+            filters: get_filters(pool, chat_id).await?,
+            // dedicated channels are not limited
+            rate_limit: Some(0),
+            snoozed_until: None,
+            timezone: get_timezone(pool, chat_id).await?,
+            quiet_hours: None,
+        });
+    }
+    // the channels of the configuration, replacing their interactive subscriptions
+    if !dedicated {
+        for channel in config::get()
+            .channels
+            .iter()
+            .filter(|c| c.severity <= severity)
+        {
+            recipients.retain(|r| r.chat_id != channel.chat_id);
+            recipients.push(Subscriber {
+                chat_id: channel.chat_id,
+                lang: channel.lang.clone(),
+                min_severity: channel.severity.as_level(),
+                severity_chosen: true,
+                format: channel.format,
+                template: Template::default(),
+                grouping: None,
+                filters: channel.filters(),
+                rate_limit: Some(0),
+                snoozed_until: None,
+                timezone: TimeZone::utc(),
+                quiet_hours: None,
+            });
+        }
+    }
+
+    Ok(recipients)
+}
+
+/// Set the minimum severity of the messages the chat receives, opting in to the operational
+/// alerts at or above it.
+///
+/// Returns `false` if the chat is not subscribed.
+pub async fn set_min_severity(pool: &SqlitePool, chat_id: i64, severity: Severity) -> Result<bool> {
+    let level = severity.as_level();
+    let result = query!(
+        "UPDATE subbed SET lvl = ?, severity_chosen = 1 WHERE chat_id = ?",
+        level,
+        chat_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Get the language configured for the chat
+pub async fn get_lang(pool: &SqlitePool, chat_id: i64) -> Result<String> {
+    let lang = query!("SELECT lang FROM chat_settings WHERE chat_id = ?", chat_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(lang
+        .map(|r| r.lang)
+        .unwrap_or_else(|| DEFAULT_LANG.to_string()))
+}
+
+pub async fn set_lang(pool: &SqlitePool, chat_id: i64, lang: &str) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, lang) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET lang = excluded.lang",
+        chat_id,
+        lang
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the message format configured for the chat
+pub async fn get_format(pool: &SqlitePool, chat_id: i64) -> Result<Format> {
+    let format = query!(
+        r#"SELECT format AS "format: Format" FROM chat_settings WHERE chat_id = ?"#,
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(format.map(|r| r.format).unwrap_or_default())
+}
+
+pub async fn set_format(pool: &SqlitePool, chat_id: i64, format: Format) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, format) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET format = excluded.format",
+        chat_id,
+        format
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the grouping of the updates set for the chat (`None`: the default in the configuration)
+pub async fn get_grouping(pool: &SqlitePool, chat_id: i64) -> Result<Option<Grouping>> {
+    let grouping = query!(
+        r#"SELECT grouping AS "grouping: Grouping" FROM chat_settings WHERE chat_id = ?"#,
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(grouping.and_then(|r| r.grouping))
+}
+
+pub async fn set_grouping(
+    pool: &SqlitePool,
+    chat_id: i64,
+    grouping: Option<Grouping>,
+) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, grouping) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET grouping = excluded.grouping",
+        chat_id,
+        grouping
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the time zone of the chat, UTC if not set or no longer known
+pub async fn get_timezone(pool: &SqlitePool, chat_id: i64) -> Result<TimeZone> {
+    let timezone = query!(
+        "SELECT timezone FROM chat_settings WHERE chat_id = ?",
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .and_then(|r| r.timezone);
+
+    Ok(load_timezone(chat_id, timezone.as_deref()))
+}
+
+fn load_timezone(chat_id: i64, name: Option<&str>) -> TimeZone {
+    match name {
+        Some(name) => TimeZone::load(name).unwrap_or_else(|e| {
+            log::warn!("Time zone of {} is no longer valid: {}", chat_id, e);
+            TimeZone::utc()
+        }),
+        None => TimeZone::utc(),
+    }
+}
+
+pub async fn set_timezone(pool: &SqlitePool, chat_id: i64, timezone: Option<&str>) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, timezone) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET timezone = excluded.timezone",
+        chat_id,
+        timezone
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the quiet hours of the chat, if any
+pub async fn get_quiet_hours(pool: &SqlitePool, chat_id: i64) -> Result<Option<QuietHours>> {
+    let quiet_hours = query!(
+        "SELECT quiet_hours FROM chat_settings WHERE chat_id = ?",
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .and_then(|r| r.quiet_hours);
+
+    Ok(load_quiet_hours(chat_id, quiet_hours.as_deref()))
+}
+
+fn load_quiet_hours(chat_id: i64, quiet_hours: Option<&str>) -> Option<QuietHours> {
+    let quiet_hours = quiet_hours?;
+    quiet_hours
+        .parse()
+        .map_err(|_| log::warn!("Invalid quiet hours of {}: {}", chat_id, quiet_hours))
+        .ok()
+}
+
+pub async fn set_quiet_hours(
+    pool: &SqlitePool,
+    chat_id: i64,
+    quiet_hours: Option<QuietHours>,
+) -> Result<()> {
+    let quiet_hours = quiet_hours.map(|q| q.to_string());
+    query!(
+        "INSERT INTO chat_settings (chat_id, quiet_hours) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET quiet_hours = excluded.quiet_hours",
+        chat_id,
+        quiet_hours
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Set whether the chat is told about the new releases
+pub async fn set_releases(pool: &SqlitePool, chat_id: i64, releases: bool) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, releases) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET releases = excluded.releases",
+        chat_id,
+        releases
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the chats told about the new releases, with their languages and formats
+pub async fn release_chats(pool: &SqlitePool) -> Result<Vec<(i64, String, Format)>> {
+    let rows = query!(
+        r#"SELECT chat_id, lang, COALESCE(format, 'html') AS "format!: Format" FROM chat_settings
+        WHERE releases != 0"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.chat_id, r.lang, r.format))
+        .collect())
+}
+
+/// Get the custom templates of the chat
+pub async fn get_template(pool: &SqlitePool, chat_id: i64) -> Result<Template> {
+    let template = query!(
+        "SELECT template_header, template_line, template_footer FROM chat_settings WHERE chat_id = ?",
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(template
+        .map(|r| Template {
+            header: r.template_header,
+            line: r.template_line,
+            footer: r.template_footer,
+        })
+        .unwrap_or_default())
+}
+
+pub async fn set_template(pool: &SqlitePool, chat_id: i64, template: &Template) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, template_header, template_line, template_footer) VALUES (?, ?, ?, ?)
+        ON CONFLICT(chat_id) DO UPDATE SET template_header = excluded.template_header,
+        template_line = excluded.template_line, template_footer = excluded.template_footer",
+        chat_id,
+        template.header,
+        template.line,
+        template.footer
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the filters of all the chats
+async fn all_filters(pool: &SqlitePool) -> Result<HashMap<i64, Filters>> {
+    let rows = query!("SELECT chat_id, kind, value FROM filters")
+        .fetch_all(pool)
+        .await?;
+    let mut filters: HashMap<i64, Filters> = HashMap::new();
+    for row in rows {
+        if let Ok(kind) = row.kind.parse() {
+            filters.entry(row.chat_id).or_default().add(kind, row.value);
+        }
+    }
+    let mutes = query!("SELECT chat_id, pkg FROM mutes")
+        .fetch_all(pool)
+        .await?;
+    for row in mutes {
+        filters.entry(row.chat_id).or_default().mute(row.pkg);
+    }
+
+    Ok(filters)
+}
+
+/// Get the filters of the chat
+pub async fn get_filters(pool: &SqlitePool, chat_id: i64) -> Result<Filters> {
+    let rows = query!("SELECT kind, value FROM filters WHERE chat_id = ?", chat_id)
+        .fetch_all(pool)
+        .await?;
+    let mut filters = Filters::default();
+    for row in rows {
+        if let Ok(kind) = row.kind.parse() {
+            filters.add(kind, row.value);
+        }
+    }
+    let mutes = query!("SELECT pkg FROM mutes WHERE chat_id = ?", chat_id)
+        .fetch_all(pool)
+        .await?;
+    for row in mutes {
+        filters.mute(row.pkg);
+    }
+
+    Ok(filters)
+}
+
+/// Replace the values of the given kind of filter (an empty list removes the filter)
+pub async fn set_filter(
+    pool: &SqlitePool,
+    chat_id: i64,
+    kind: Kind,
+    values: &[&str],
+) -> Result<()> {
+    let kind = kind.to_string();
+    let mut tx = pool.begin().await?;
+    query!(
+        "DELETE FROM filters WHERE chat_id = ? AND kind = ?",
+        chat_id,
+        kind
+    )
+    .execute(&mut *tx)
+    .await?;
+    for value in values {
+        query!(
+            "INSERT OR IGNORE INTO filters (chat_id, kind, value) VALUES (?, ?, ?)",
+            chat_id,
+            kind,
+            value
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Mute or unmute the updates of the package, returns `false` if nothing changed
+pub async fn set_muted(pool: &SqlitePool, chat_id: i64, pkg: &str, muted: bool) -> Result<bool> {
+    let result = if muted {
+        query!(
+            "INSERT OR IGNORE INTO mutes (chat_id, pkg) VALUES (?, ?)",
+            chat_id,
+            pkg
+        )
+        .execute(pool)
+        .await?
+    } else {
+        query!(
+            "DELETE FROM mutes WHERE chat_id = ? AND pkg = ?",
+            chat_id,
+            pkg
+        )
+        .execute(pool)
+        .await?
+    };
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Move the subscription and the settings of a group to its new ID after it was upgraded
+/// to a supergroup
+pub async fn migrate_chat(pool: &SqlitePool, old_id: i64, new_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    // the new chat may have been set up already, keep its settings in that case
+    query!(
+        "UPDATE OR IGNORE subbed SET chat_id = ? WHERE chat_id = ?",
+        new_id,
+        old_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("DELETE FROM subbed WHERE chat_id = ?", old_id)
+        .execute(&mut *tx)
+        .await?;
+    query!(
+        "UPDATE OR IGNORE chat_settings SET chat_id = ? WHERE chat_id = ?",
+        new_id,
+        old_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("DELETE FROM chat_settings WHERE chat_id = ?", old_id)
+        .execute(&mut *tx)
+        .await?;
+    query!(
+        "UPDATE OR IGNORE filters SET chat_id = ? WHERE chat_id = ?",
+        new_id,
+        old_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("DELETE FROM filters WHERE chat_id = ?", old_id)
+        .execute(&mut *tx)
+        .await?;
+    query!(
+        "UPDATE OR IGNORE mutes SET chat_id = ? WHERE chat_id = ?",
+        new_id,
+        old_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("DELETE FROM mutes WHERE chat_id = ?", old_id)
+        .execute(&mut *tx)
+        .await?;
+    query!(
+        "UPDATE OR IGNORE chat_health SET chat_id = ? WHERE chat_id = ?",
+        new_id,
+        old_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("DELETE FROM chat_health WHERE chat_id = ?", old_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Set the maximum number of messages per hour (`None` to use the default)
+pub async fn set_rate_limit(pool: &SqlitePool, chat_id: i64, limit: Option<i64>) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, rate_limit) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET rate_limit = excluded.rate_limit",
+        chat_id,
+        limit
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Pause the notifications of the chat until the given time
+pub async fn set_snooze(pool: &SqlitePool, chat_id: i64, until: i64) -> Result<()> {
+    query!(
+        "INSERT INTO chat_settings (chat_id, snoozed_until) VALUES (?, ?) ON CONFLICT(chat_id) DO UPDATE SET snoozed_until = excluded.snoozed_until",
+        chat_id,
+        until
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Count the updates not sent to the chat while it is snoozed
+pub async fn add_snoozed_updates(pool: &SqlitePool, chat_id: i64, count: i64) -> Result<()> {
+    query!(
+        "UPDATE chat_settings SET snoozed_updates = snoozed_updates + ? WHERE chat_id = ?",
+        count,
+        chat_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Get the snoozed chats whose snooze has ended
+pub async fn expired_snoozes(pool: &SqlitePool, now: i64) -> Result<Vec<i64>> {
+    let rows = query!(
+        "SELECT chat_id FROM chat_settings WHERE snoozed_until <= ?",
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| r.chat_id).collect())
+}
+
+/// Resume the notifications of the chat.
+///
+/// Returns the number of the updates not sent while snoozed, or `None` if the chat
+/// was not snoozed.
+pub async fn resume(pool: &SqlitePool, chat_id: i64) -> Result<Option<i64>> {
+    let mut tx = pool.begin().await?;
+    let row = query!(
+        "SELECT snoozed_updates FROM chat_settings WHERE chat_id = ? AND snoozed_until IS NOT NULL",
+        chat_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    query!(
+        "UPDATE chat_settings SET snoozed_until = NULL, snoozed_updates = 0 WHERE chat_id = ?",
+        chat_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(row.map(|r| r.snoozed_updates))
+}
+
+#[tokio::test]
+async fn test_recipients() {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+    query!("INSERT INTO subbed (chat_id) VALUES (-100), (-200), (-300)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(set_min_severity(&pool, -200, Severity::Heartbeat)
+        .await
+        .unwrap());
+    assert!(set_min_severity(&pool, -300, Severity::Critical)
+        .await
+        .unwrap());
+    let pool = &pool;
+    let chats = |severity| async move {
+        let mut chats = recipients(pool, severity)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.chat_id)
+            .collect::<Vec<_>>();
+        chats.sort_unstable();
+        chats
+    };
+    assert_eq!(chats(Severity::Routine).await, vec![-200, -100]);
+    // the chats which did not choose a minimum severity get no operational alerts
+    assert_eq!(chats(Severity::Warning).await, vec![-200]);
+    assert_eq!(chats(Severity::Critical).await, vec![-300, -200]);
+}
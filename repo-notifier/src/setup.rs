@@ -0,0 +1,278 @@
+//! Interactive setup of the filters (`/setup`): the chat picks the components, then the
+//! architectures with the buttons under the same message, then the digest mode (how the updates
+//! are grouped in the messages). Setups left alone for a while are dropped.
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Mutex};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::grouping::{Grouping, GROUPINGS};
+use crate::i18n::{tr, Text};
+use crate::snooze::now;
+
+/// Number of the options in each row of the keyboard
+const ROW_LENGTH: usize = 3;
+/// Seconds after the last button pressed before a setup expires
+const TIMEOUT: i64 = 15 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Comps,
+    Arches,
+    Digest,
+}
+
+/// Options offered in a step and whether they are chosen
+type Options = Vec<(String, bool)>;
+
+/// Setup in progress in a chat
+#[derive(Debug)]
+pub struct Wizard {
+    step: Step,
+    comps: Options,
+    arches: Options,
+    /// When the setup was started or last advanced (UNIX timestamp)
+    touched: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Callback {
+    /// Choose the n-th option of the step, or not anymore
+    Toggle(usize),
+    Next,
+    Digest(Grouping),
+    Cancel,
+}
+
+pub enum Outcome {
+    /// Text and buttons of the next step
+    Continue(String, InlineKeyboardMarkup),
+    Done(Wizard, Grouping),
+    Cancelled,
+}
+
+/// Setups in progress in each chat
+#[derive(Default)]
+pub struct Wizards {
+    wizards: Mutex<HashMap<i64, Wizard>>,
+}
+
+static WIZARDS: Lazy<Wizards> = Lazy::new(Wizards::default);
+
+fn chosen(options: &Options) -> Vec<&str> {
+    options
+        .iter()
+        .filter(|(_, chosen)| *chosen)
+        .map(|(option, _)| option.as_str())
+        .collect()
+}
+
+impl Wizard {
+    pub fn new(comps: Vec<String>, arches: Vec<String>) -> Self {
+        let options = |values: Vec<String>| values.into_iter().map(|v| (v, false)).collect();
+        Wizard {
+            step: Step::Comps,
+            comps: options(comps),
+            arches: options(arches),
+            touched: 0,
+        }
+    }
+
+    /// Chosen components, none means all of them
+    pub fn comps(&self) -> Vec<&str> {
+        chosen(&self.comps)
+    }
+
+    /// Chosen architectures, none means all of them
+    pub fn arches(&self) -> Vec<&str> {
+        chosen(&self.arches)
+    }
+
+    fn options(&mut self) -> Option<&mut Options> {
+        match self.step {
+            Step::Comps => Some(&mut self.comps),
+            Step::Arches => Some(&mut self.arches),
+            Step::Digest => None,
+        }
+    }
+
+    pub fn text(&self, lang: &str) -> String {
+        let text = match self.step {
+            Step::Comps => Text::SetupComps,
+            Step::Arches => Text::SetupArches,
+            Step::Digest => Text::SetupDigest,
+        };
+
+        tr(lang, text).to_string()
+    }
+
+    pub fn keyboard(&self, lang: &str) -> InlineKeyboardMarkup {
+        let cancel = InlineKeyboardButton::callback(tr(lang, Text::ButtonCancel), "setup:cancel");
+        let options = match self.step {
+            Step::Comps => &self.comps,
+            Step::Arches => &self.arches,
+            Step::Digest => {
+                let groupings = GROUPINGS.iter().map(|g| {
+                    let text = match g {
+                        Grouping::Arch => Text::ButtonByArch,
+                        Grouping::Package => Text::ButtonByPackage,
+                    };
+                    InlineKeyboardButton::callback(tr(lang, text), format!("setup:digest:{}", g))
+                });
+                return InlineKeyboardMarkup::new([groupings.collect(), vec![cancel]]);
+            }
+        };
+        let buttons = options
+            .iter()
+            .enumerate()
+            .map(|(i, (option, chosen))| {
+                let text = if *chosen {
+                    format!("✅ {}", option)
+                } else {
+                    option.clone()
+                };
+                InlineKeyboardButton::callback(text, format!("setup:toggle:{}", i))
+            })
+            .collect::<Vec<_>>();
+        let next = InlineKeyboardButton::callback(tr(lang, Text::ButtonNext), "setup:next");
+
+        InlineKeyboardMarkup::new(buttons.chunks(ROW_LENGTH).map(|row| row.to_vec()))
+            .append_row(vec![cancel, next])
+    }
+
+    /// Apply the button pressed, returns the digest mode when done
+    fn advance(&mut self, callback: Callback) -> Option<Grouping> {
+        match callback {
+            Callback::Toggle(i) => {
+                if let Some((_, chosen)) = self.options().and_then(|o| o.get_mut(i)) {
+                    *chosen = !*chosen;
+                }
+            }
+            Callback::Next => {
+                self.step = match self.step {
+                    Step::Comps => Step::Arches,
+                    _ => Step::Digest,
+                }
+            }
+            Callback::Digest(grouping) if self.step == Step::Digest => return Some(grouping),
+            Callback::Digest(_) | Callback::Cancel => (),
+        }
+
+        None
+    }
+}
+
+/// Parse the callback data of the buttons of the setup
+pub fn parse_callback(data: &str) -> Option<Callback> {
+    let data = data.strip_prefix("setup:")?;
+    if let Some(i) = data.strip_prefix("toggle:") {
+        return i.parse().ok().map(Callback::Toggle);
+    }
+    if let Some(grouping) = data.strip_prefix("digest:") {
+        return grouping.parse().ok().map(Callback::Digest);
+    }
+    match data {
+        "next" => Some(Callback::Next),
+        "cancel" => Some(Callback::Cancel),
+        _ => None,
+    }
+}
+
+impl Wizards {
+    /// Start the setup in the chat at `now`, replacing the one in progress
+    pub fn start(&self, chat_id: i64, mut wizard: Wizard, now: i64) {
+        let mut wizards = self.wizards.lock().unwrap();
+        wizards.retain(|_, w| now - w.touched < TIMEOUT);
+        wizard.touched = now;
+        wizards.insert(chat_id, wizard);
+    }
+
+    /// Apply the button pressed at `now` to the setup in progress in the chat, `None` if there
+    /// is none or it expired
+    pub fn answer(
+        &self,
+        chat_id: i64,
+        callback: Callback,
+        lang: &str,
+        now: i64,
+    ) -> Option<Outcome> {
+        let mut wizards = self.wizards.lock().unwrap();
+        wizards.retain(|_, w| now - w.touched < TIMEOUT);
+        if callback == Callback::Cancel {
+            return wizards.remove(&chat_id).map(|_| Outcome::Cancelled);
+        }
+        let wizard = wizards.get_mut(&chat_id)?;
+        wizard.touched = now;
+        match wizard.advance(callback) {
+            Some(grouping) => wizards
+                .remove(&chat_id)
+                .map(|wizard| Outcome::Done(wizard, grouping)),
+            None => Some(Outcome::Continue(wizard.text(lang), wizard.keyboard(lang))),
+        }
+    }
+}
+
+/// Start the setup in the chat, replacing the one in progress
+pub fn start(chat_id: i64, wizard: Wizard) {
+    WIZARDS.start(chat_id, wizard, now());
+}
+
+/// Apply the button pressed to the setup in progress in the chat, `None` if there is none
+pub fn answer(chat_id: i64, callback: Callback, lang: &str) -> Option<Outcome> {
+    WIZARDS.answer(chat_id, callback, lang, now())
+}
+
+#[test]
+fn test_wizard() {
+    let mut wizard = Wizard::new(
+        vec!["stable".to_string(), "testing".to_string()],
+        vec![
+            "amd64".to_string(),
+            "arm64".to_string(),
+            "riscv64".to_string(),
+        ],
+    );
+    assert_eq!(parse_callback("setup:toggle:1"), Some(Callback::Toggle(1)));
+    assert_eq!(
+        parse_callback("setup:digest:package"),
+        Some(Callback::Digest(Grouping::Package))
+    );
+    assert_eq!(parse_callback("setup:next"), Some(Callback::Next));
+    assert_eq!(parse_callback("manage:stop"), None);
+
+    assert_eq!(wizard.advance(Callback::Toggle(0)), None);
+    assert_eq!(wizard.advance(Callback::Toggle(7)), None);
+    // picking the digest mode is the last step
+    assert_eq!(wizard.advance(Callback::Digest(Grouping::Arch)), None);
+    assert_eq!(wizard.advance(Callback::Next), None);
+    assert_eq!(wizard.keyboard("en").inline_keyboard[0][0].text, "amd64");
+    wizard.advance(Callback::Toggle(2));
+    wizard.advance(Callback::Toggle(0));
+    wizard.advance(Callback::Toggle(0));
+    wizard.advance(Callback::Next);
+    assert_eq!(wizard.keyboard("en").inline_keyboard.len(), 2);
+    assert_eq!(
+        wizard.advance(Callback::Digest(Grouping::Package)),
+        Some(Grouping::Package)
+    );
+    assert_eq!(wizard.comps(), ["stable"]);
+    assert_eq!(wizard.arches(), ["riscv64"]);
+
+    let wizards = Wizards::default();
+    let new = || Wizard::new(vec!["stable".to_string()], vec!["amd64".to_string()]);
+    wizards.start(1, new(), 1000);
+    wizards.start(2, new(), 1000);
+    assert!(matches!(
+        wizards.answer(1, Callback::Next, "en", 1000 + TIMEOUT - 1),
+        Some(Outcome::Continue(..))
+    ));
+    // the first one was advanced since
+    assert!(wizards
+        .answer(2, Callback::Next, "en", 1000 + TIMEOUT)
+        .is_none());
+    assert!(wizards
+        .answer(1, Callback::Next, "en", 1000 + TIMEOUT)
+        .is_some());
+    // abandoned ones are dropped when another setup starts
+    wizards.start(3, new(), 1000 + 3 * TIMEOUT);
+    assert_eq!(wizards.wizards.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,65 @@
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+
+/// Severity of the outbound messages, chats only receive messages at or above their
+/// configured minimum severity (the `lvl` column of the `subbed` table)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// "Repository refreshed" messages
+    Heartbeat = 0,
+    /// Package updates
+    Routine = 1,
+    /// Recoverable errors (e.g. invalid messages from p-vector)
+    Warning = 2,
+    /// The notifier stopped working
+    Critical = 3,
+}
+
+pub const SEVERITIES: &[Severity] = &[
+    Severity::Heartbeat,
+    Severity::Routine,
+    Severity::Warning,
+    Severity::Critical,
+];
+
+impl Severity {
+    /// Whether this is an operational alert instead of a user-facing update
+    pub fn is_operational(self) -> bool {
+        self >= Severity::Warning
+    }
+
+    pub fn as_level(self) -> i64 {
+        self as i64
+    }
+
+    /// The severity stored as the given level, if valid
+    pub fn from_level(level: i64) -> Option<Severity> {
+        SEVERITIES.iter().find(|s| s.as_level() == level).copied()
+    }
+}
+
+impl FromStr for Severity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SEVERITIES
+            .iter()
+            .find(|x| x.to_string().eq_ignore_ascii_case(s.trim()))
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Heartbeat => "heartbeat",
+            Severity::Routine => "routine",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        };
+
+        f.write_str(name)
+    }
+}
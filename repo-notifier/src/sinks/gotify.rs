@@ -0,0 +1,133 @@
+//! Gotify sink pushing a compact summary of each batch to a self-hosted server
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{send_with_retry, Event, CLIENT};
+use crate::format::Format;
+use crate::i18n::update_count;
+use crate::severity::Severity;
+use crate::summary;
+
+#[derive(Deserialize, Debug)]
+pub struct Gotify {
+    /// URL of the server, e.g. `https://gotify.example.org`
+    server: String,
+    /// Token of the application the messages are sent as
+    token: String,
+    #[serde(default)]
+    priorities: Priorities,
+}
+
+/// Priority of each kind of the messages (0 to 10)
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Priorities {
+    updates: u8,
+    /// Batches carrying security fixes
+    security: u8,
+    heartbeat: u8,
+    warning: u8,
+    critical: u8,
+}
+
+impl Default for Priorities {
+    fn default() -> Self {
+        Priorities {
+            updates: 4,
+            security: 8,
+            heartbeat: 1,
+            warning: 6,
+            critical: 10,
+        }
+    }
+}
+
+impl Gotify {
+    pub fn validate(&self) -> Result<()> {
+        if self.server.is_empty() || self.token.is_empty() {
+            return Err(anyhow!("The Gotify sink needs a server and a token"));
+        }
+        let p = &self.priorities;
+        for priority in [p.updates, p.security, p.heartbeat, p.warning, p.critical] {
+            if priority > 10 {
+                return Err(anyhow!(
+                    "Gotify priorities are between 0 and 10, got {}",
+                    priority
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Message sent to the server
+    fn message(&self, event: &Event<'_>) -> Value {
+        let p = &self.priorities;
+        match event {
+            Event::Updates(messages, lang) => json!({
+                "title": update_count(lang, messages.len()),
+                "message": summary::summarize(messages, lang, Format::Plain),
+                "priority": if messages.iter().any(|p| p.security) {
+                    p.security
+                } else {
+                    p.updates
+                },
+            }),
+            Event::Notice(severity, text) => json!({
+                "message": text,
+                "priority": match severity {
+                    Severity::Heartbeat => p.heartbeat,
+                    Severity::Routine => p.updates,
+                    Severity::Warning => p.warning,
+                    Severity::Critical => p.critical,
+                },
+            }),
+        }
+    }
+
+    pub async fn send(&self, event: &Event<'_>) -> Result<()> {
+        let message = self.message(event);
+        let url = format!("{}/message", self.server.trim_end_matches('/'));
+        send_with_retry(|| {
+            CLIENT
+                .post(&url)
+                .header("X-Gotify-Key", &self.token)
+                .json(&message)
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_message() {
+    use notifier_core::PVMessage;
+
+    let gotify: Gotify = toml::from_str(
+        r#"
+        server = "https://gotify.example.org/"
+        token = "A0"
+        priorities = { security = 9 }
+        "#,
+    )
+    .unwrap();
+    assert!(gotify.validate().is_ok());
+    let update = |security| PVMessage {
+        security,
+        ..PVMessage::sample("openssl", "amd64", b'^', Some("3.0.1"), Some("3.0.2"))
+    };
+    let messages = [update(true)];
+    let message = gotify.message(&Event::Updates(&messages, "en"));
+    assert_eq!(message["title"], "1 package update");
+    assert_eq!(message["priority"], 9);
+    let messages = [update(false)];
+    assert_eq!(
+        gotify.message(&Event::Updates(&messages, "en"))["priority"],
+        4
+    );
+    let message = gotify.message(&Event::Notice(Severity::Warning, "Invalid message"));
+    assert_eq!(message["priority"], 6);
+    assert_eq!(message["message"], "Invalid message");
+}
@@ -0,0 +1,263 @@
+//! Destinations of the updates besides the Telegram chats (`[[sinks]]` in the config), fed by
+//! the same batching pipeline. The messages are queued and sent in order by [`run`], so a slow
+//! sink does not hold up the Telegram chats.
+use anyhow::Result;
+use notifier_core::PVMessage;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::{sync::Mutex, time::Duration};
+use tokio::sync::mpsc;
+
+use crate::filter::{Filters, Kind};
+use crate::severity::Severity;
+use crate::PVMessageExt;
+
+mod gotify;
+mod ntfy;
+mod slack;
+mod xmpp;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Number of the attempts to send each request to a sink
+const ATTEMPTS: u64 = 3;
+/// Number of the messages waiting for the sinks, the later ones are dropped
+const QUEUE_SIZE: usize = 64;
+
+/// What is waiting to be sent to the sinks
+enum Job {
+    Updates(Vec<PVMessage>),
+    /// The text rendered for each sink accepting the severity, in the order of `[[sinks]]`
+    Notice(Severity, Vec<Option<String>>),
+}
+
+type Queue = (mpsc::Sender<Job>, Mutex<Option<mpsc::Receiver<Job>>>);
+
+static QUEUE: Lazy<Queue> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+    (sender, Mutex::new(Some(receiver)))
+});
+
+fn enqueue(job: Job) {
+    if let Err(e) = QUEUE.0.try_send(job) {
+        log::warn!("Dropped a message for the sinks: {}", e);
+    }
+}
+
+/// Send the request built by `request`, retrying when rate limited, on server errors or when
+/// the sink can not be reached
+async fn send_with_retry<F: Fn() -> reqwest::RequestBuilder>(
+    request: F,
+) -> Result<reqwest::Response> {
+    let mut attempt = 1;
+    loop {
+        let error = match request().send().await.and_then(|r| r.error_for_status()) {
+            Ok(response) => return Ok(response),
+            Err(e) => e,
+        };
+        let retry = error
+            .status()
+            .is_none_or(|s| s == reqwest::StatusCode::TOO_MANY_REQUESTS || s.is_server_error());
+        if !retry || attempt >= ATTEMPTS {
+            return Err(error.into());
+        }
+        tokio::time::sleep(Duration::from_secs(5 * attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// What is delivered to a sink
+pub enum Event<'a> {
+    /// A batch of the package updates (sorted and classified), and the language of the sink
+    Updates(&'a [PVMessage], &'a str),
+    /// A message of the bot such as "Repository refreshed", rendered in the language of the sink
+    Notice(Severity, &'a str),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Backend {
+    Slack(slack::Slack),
+    Ntfy(ntfy::Ntfy),
+    Gotify(gotify::Gotify),
+    Xmpp(xmpp::Xmpp),
+}
+
+/// A destination listed in `[[sinks]]`
+#[derive(Deserialize, Debug)]
+pub struct Sink {
+    /// Name of the sink in the logs
+    #[serde(default)]
+    name: Option<String>,
+    /// Minimum severity of the messages sent to the sink, the updates are `routine`
+    #[serde(default = "default_severity")]
+    severity: Severity,
+    /// Language of the messages of the bot
+    #[serde(default = "default_lang")]
+    lang: String,
+    /// Only send the updates of these repositories (all of them if empty)
+    #[serde(default)]
+    repos: Vec<String>,
+    /// Only send the updates of these architecture groups (all of them if empty)
+    #[serde(default)]
+    groups: Vec<String>,
+    #[serde(flatten)]
+    backend: Backend,
+}
+
+fn default_severity() -> Severity {
+    Severity::Routine
+}
+
+fn default_lang() -> String {
+    "en".to_string()
+}
+
+impl Sink {
+    pub fn validate(&self) -> Result<()> {
+        match &self.backend {
+            Backend::Slack(slack) => slack.validate(),
+            Backend::Ntfy(ntfy) => ntfy.validate(),
+            Backend::Gotify(gotify) => gotify.validate(),
+            Backend::Xmpp(xmpp) => xmpp.validate(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(match &self.backend {
+            Backend::Slack(_) => "slack",
+            Backend::Ntfy(_) => "ntfy",
+            Backend::Gotify(_) => "gotify",
+            Backend::Xmpp(_) => "xmpp",
+        })
+    }
+
+    fn filters(&self) -> Filters {
+        let mut filters = Filters::default();
+        for repo in self.repos.iter() {
+            filters.add(Kind::Repo, repo.clone());
+        }
+        for group in self.groups.iter() {
+            filters.add(Kind::Group, group.clone());
+        }
+
+        filters
+    }
+
+    async fn send(&self, event: &Event<'_>) -> Result<()> {
+        if crate::dry_run::mode().is_some() {
+            log::info!("Dry run, not sending to {}.", self.name());
+            return Ok(());
+        }
+        match &self.backend {
+            Backend::Slack(slack) => slack.send(event).await,
+            Backend::Ntfy(ntfy) => ntfy.send(event).await,
+            Backend::Gotify(gotify) => gotify.send(event).await,
+            Backend::Xmpp(xmpp) => xmpp.send(event).await,
+        }
+    }
+}
+
+/// Queue the batch of updates for the sinks
+pub fn updates(messages: &[PVMessage]) {
+    if !crate::config::get().sinks.is_empty() {
+        enqueue(Job::Updates(messages.to_vec()));
+    }
+}
+
+/// Queue the message of the bot for the sinks accepting its severity
+pub fn notice<F: Fn(&str) -> String>(severity: Severity, render: F) {
+    let texts = crate::config::get()
+        .sinks
+        .iter()
+        .map(|sink| (severity >= sink.severity).then(|| render(&sink.lang)))
+        .collect::<Vec<_>>();
+    if texts.iter().any(Option::is_some) {
+        enqueue(Job::Notice(severity, texts));
+    }
+}
+
+/// Send the queued messages to the sinks, one at a time
+pub async fn run() -> Result<()> {
+    let receiver = QUEUE.1.lock().unwrap().take();
+    let mut receiver = match receiver {
+        Some(receiver) => receiver,
+        None => return Ok(()),
+    };
+    while let Some(job) = receiver.recv().await {
+        match job {
+            Job::Updates(messages) => send_updates(&messages).await,
+            Job::Notice(severity, texts) => send_notice(severity, &texts).await,
+        }
+    }
+
+    Ok(())
+}
+
+async fn send_updates(messages: &[PVMessage]) {
+    for sink in crate::config::get().sinks.iter() {
+        if sink.severity > Severity::Routine {
+            continue;
+        }
+        let filters = sink.filters();
+        let messages = messages
+            .iter()
+            .filter(|p| p.allowed_by(&filters))
+            .cloned()
+            .collect::<Vec<_>>();
+        if messages.is_empty() {
+            continue;
+        }
+        if let Err(e) = sink.send(&Event::Updates(&messages, &sink.lang)).await {
+            log::error!("Could not send the updates to {}: {}", sink.name(), e);
+        }
+    }
+}
+
+async fn send_notice(severity: Severity, texts: &[Option<String>]) {
+    for (sink, text) in crate::config::get().sinks.iter().zip(texts) {
+        let text = match text {
+            Some(text) => text,
+            None => continue,
+        };
+        if let Err(e) = sink.send(&Event::Notice(severity, text)).await {
+            log::error!("Could not send the message to {}: {}", sink.name(), e);
+        }
+    }
+}
+
+#[test]
+fn test_sinks_config() {
+    #[derive(Deserialize)]
+    struct Config {
+        sinks: Vec<Sink>,
+    }
+    let config: Config = toml::from_str(
+        r#"
+        [[sinks]]
+        kind = "slack"
+        webhook = "https://hooks.slack.com/services/T0/B0/X"
+        groups = ["retro"]
+
+        [[sinks]]
+        kind = "slack"
+        name = "security"
+        severity = "warning"
+        token = "xoxb-0"
+        "#,
+    )
+    .unwrap();
+    let (updates, security) = (&config.sinks[0], &config.sinks[1]);
+    assert_eq!(updates.name(), "slack");
+    assert_eq!(updates.severity, Severity::Routine);
+    assert_eq!(updates.filters().to_string(), "group: retro\n");
+    assert!(updates.validate().is_ok());
+    assert_eq!(security.name(), "security");
+    // no channel to post to
+    assert!(security.validate().is_err());
+}
@@ -0,0 +1,136 @@
+//! ntfy sink pushing a compact summary of each batch to a topic, so that the users can follow
+//! the updates on their phones without any chat platform
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{send_with_retry, Event, CLIENT};
+use crate::format::Format;
+use crate::i18n::update_count;
+use crate::severity::Severity;
+use crate::summary;
+
+#[derive(Deserialize, Debug)]
+pub struct Ntfy {
+    #[serde(default = "default_server")]
+    server: String,
+    topic: String,
+    /// Access token of a protected topic
+    token: Option<String>,
+    /// Priority of the batches (1 to 5)
+    #[serde(default = "default_priority")]
+    priority: u8,
+    /// Priority of the batches carrying security fixes
+    #[serde(default = "default_security_priority")]
+    security_priority: u8,
+}
+
+fn default_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+fn default_priority() -> u8 {
+    3
+}
+
+fn default_security_priority() -> u8 {
+    5
+}
+
+impl Ntfy {
+    pub fn validate(&self) -> Result<()> {
+        if self.topic.is_empty() {
+            return Err(anyhow!("The ntfy sink needs a topic"));
+        }
+        for priority in [self.priority, self.security_priority] {
+            if !(1..=5).contains(&priority) {
+                return Err(anyhow!(
+                    "ntfy priorities are between 1 and 5, got {}",
+                    priority
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Message published to the topic
+    fn publication(&self, event: &Event<'_>) -> Value {
+        let (title, message, priority, tag) = match event {
+            Event::Updates(messages, lang) => {
+                let security = messages.iter().any(|p| p.security);
+                (
+                    Some(update_count(lang, messages.len())),
+                    summary::summarize(messages, lang, Format::Plain),
+                    if security {
+                        self.security_priority
+                    } else {
+                        self.priority
+                    },
+                    if security { "lock" } else { "package" },
+                )
+            }
+            Event::Notice(severity, text) => {
+                let (priority, tag) = match severity {
+                    Severity::Heartbeat => (2, "arrows_counterclockwise"),
+                    Severity::Routine => (3, "package"),
+                    Severity::Warning => (4, "warning"),
+                    Severity::Critical => (5, "rotating_light"),
+                };
+                (None, text.to_string(), priority, tag)
+            }
+        };
+        let mut publication = json!({
+            "topic": self.topic,
+            "message": message,
+            "priority": priority,
+            "tags": [tag],
+        });
+        if let Some(title) = title {
+            publication["title"] = json!(title);
+        }
+
+        publication
+    }
+
+    pub async fn send(&self, event: &Event<'_>) -> Result<()> {
+        let publication = self.publication(event);
+        // publishing as JSON to the root allows titles out of ASCII, unlike the headers
+        let url = self.server.trim_end_matches('/');
+        send_with_retry(|| {
+            let request = CLIENT.post(url).json(&publication);
+            match &self.token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_publication() {
+    use notifier_core::PVMessage;
+
+    let ntfy: Ntfy = toml::from_str(r#"topic = "aosc""#).unwrap();
+    assert!(ntfy.validate().is_ok());
+    let update = |security| PVMessage {
+        security,
+        ..PVMessage::sample("openssl", "amd64", b'^', Some("3.0.1"), Some("3.0.2"))
+    };
+    let messages = [update(false), update(true)];
+    let publication = ntfy.publication(&Event::Updates(&messages, "en"));
+    assert_eq!(publication["title"], "2 package updates");
+    assert_eq!(publication["priority"], 5);
+    assert_eq!(publication["tags"], json!(["lock"]));
+    let publication = ntfy.publication(&Event::Updates(&messages[..1], "en"));
+    assert_eq!(publication["title"], "1 package update");
+    assert_eq!(publication["priority"], 3);
+    let publication = ntfy.publication(&Event::Notice(Severity::Critical, "Stopped"));
+    assert_eq!(publication["priority"], 5);
+    assert_eq!(publication.get("title"), None);
+    let ntfy: Ntfy = toml::from_str("topic = \"aosc\"\npriority = 9").unwrap();
+    assert!(ntfy.validate().is_err());
+}
@@ -0,0 +1,203 @@
+//! Slack sink, posting with an incoming webhook or `chat.postMessage`, the package lists are
+//! formatted with Block Kit
+use anyhow::{anyhow, Result};
+use notifier_core::PVMessage;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{send_with_retry, Event, CLIENT};
+use crate::i18n::update_count;
+use crate::{severity::Severity, PVMessageExt};
+
+const POST_MESSAGE: &str = "https://slack.com/api/chat.postMessage";
+/// Slack takes no more than 50 blocks in a message
+const MAX_BLOCKS: usize = 50;
+/// and no more than 3000 characters in a section
+const MAX_SECTION: usize = 3000;
+
+#[derive(Deserialize, Debug)]
+pub struct Slack {
+    /// URL of the incoming webhook
+    webhook: Option<String>,
+    /// Bot token posting to `channel` with `chat.postMessage`, instead of a webhook
+    token: Option<String>,
+    channel: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    ok: bool,
+    error: Option<String>,
+}
+
+/// Escape the control characters of Slack's mrkdwn
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Line of an update in mrkdwn
+fn line(p: &PVMessage) -> String {
+    let code = |v: &Option<String>| format!("`{}`", escape(v.as_deref().unwrap_or("?")));
+    let method = p.method.as_new_type();
+    let link = format!(
+        "<https://packages.aosc.io/packages/{}|{}>",
+        p.pkg,
+        escape(&p.pkg)
+    );
+    let mut line = match method {
+        b'+' => format!("`+` {} {}", link, code(&p.to_ver)),
+        b'^' => format!("`^` {} {} ⇒ {}", link, code(&p.from_ver), code(&p.to_ver)),
+        b'-' | b'*' => format!("`{}` {} {}", method as char, link, code(&p.from_ver)),
+        b'i' => match p.failure() {
+            Some((what, message)) => format!(
+                "`i` {} {}: `{}`",
+                escape(&p.pkg),
+                escape(&what),
+                escape(&message)
+            ),
+            None => format!("`i` {}", escape(&p.pkg)),
+        },
+        _ => format!("`?` {} Unknown operation", link),
+    };
+    if p.security {
+        line.insert(0, '🔒');
+    }
+    if let Some(url) = p.changelog_url() {
+        line += &format!(" <{}|changelog>", url);
+    }
+    if let Some(description) = p.description.as_deref() {
+        line += &format!(" — _{}_", escape(description));
+    }
+
+    line
+}
+
+/// Render the updates as the blocks of one or more messages
+fn blocks(messages: &[PVMessage]) -> Vec<Vec<Value>> {
+    // group the updates by repository, component and architecture in the order they appear
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for p in messages {
+        let header = match p.repo.as_deref() {
+            Some(repo) => format!("*[{}] {} {}*", escape(repo), escape(&p.comp), p.arch),
+            None => format!("*{} {}*", escape(&p.comp), p.arch),
+        };
+        match groups.iter_mut().find(|(h, _)| *h == header) {
+            Some((_, lines)) => lines.push(line(p)),
+            None => groups.push((header, vec![line(p)])),
+        }
+    }
+    let mut sections = Vec::new();
+    for (header, lines) in groups {
+        let mut text = header.clone();
+        for line in lines {
+            if text.len() + line.len() + 1 > MAX_SECTION {
+                sections.push(std::mem::replace(&mut text, header.clone()));
+            }
+            text.push('\n');
+            text += &line;
+        }
+        sections.push(text);
+    }
+
+    sections
+        .chunks(MAX_BLOCKS)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(
+                    |text| json!({ "type": "section", "text": { "type": "mrkdwn", "text": text } }),
+                )
+                .collect()
+        })
+        .collect()
+}
+
+impl Slack {
+    pub fn validate(&self) -> Result<()> {
+        match (&self.webhook, &self.token, &self.channel) {
+            (Some(_), None, _) | (None, Some(_), Some(_)) => Ok(()),
+            _ => Err(anyhow!(
+                "A Slack sink needs either a webhook, or a token and a channel"
+            )),
+        }
+    }
+
+    async fn post(&self, text: &str, blocks: Option<Vec<Value>>) -> Result<()> {
+        let mut body = json!({ "text": text });
+        if let Some(blocks) = blocks {
+            body["blocks"] = json!(blocks);
+        }
+        if let Some(webhook) = &self.webhook {
+            send_with_retry(|| CLIENT.post(webhook).json(&body)).await?;
+            return Ok(());
+        }
+        body["channel"] = json!(self.channel);
+        let token = self.token.as_deref().unwrap_or_default();
+        let response: Response =
+            send_with_retry(|| CLIENT.post(POST_MESSAGE).bearer_auth(token).json(&body))
+                .await?
+                .json()
+                .await?;
+        if !response.ok {
+            return Err(anyhow!(
+                "Slack refused the message: {}",
+                response.error.unwrap_or_default()
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub async fn send(&self, event: &Event<'_>) -> Result<()> {
+        match event {
+            Event::Updates(messages, lang) => {
+                // the text is only shown in the notifications
+                let text = update_count(lang, messages.len());
+                for blocks in blocks(messages) {
+                    self.post(&text, Some(blocks)).await?;
+                }
+                Ok(())
+            }
+            Event::Notice(severity, text) => {
+                let icon = match severity {
+                    Severity::Warning => ":warning: ",
+                    Severity::Critical => ":rotating_light: ",
+                    _ => "",
+                };
+                self.post(&format!("{}{}", icon, escape(text)), None).await
+            }
+        }
+    }
+}
+
+#[test]
+fn test_blocks() {
+    let update =
+        |pkg: &str, arch: &str| PVMessage::sample(pkg, arch, b'^', Some("1.0"), Some("1.1"));
+    let mut described = update("libsigc++", "arm64");
+    described.description = Some("Callbacks for <C++>".to_string());
+    let messages = [
+        update("gtk-3", "amd64"),
+        described,
+        update("gtk-4", "amd64"),
+    ];
+    let rendered = blocks(&messages);
+    assert_eq!(rendered.len(), 1);
+    assert_eq!(
+        rendered[0][0]["text"]["text"],
+        "*stable amd64*\n`^` <https://packages.aosc.io/packages/gtk-3|gtk-3> `1.0` ⇒ `1.1`\n`^` <https://packages.aosc.io/packages/gtk-4|gtk-4> `1.0` ⇒ `1.1`"
+    );
+    assert_eq!(
+        rendered[0][1]["text"]["text"],
+        "*stable arm64*\n`^` <https://packages.aosc.io/packages/libsigc++|libsigc++> `1.0` ⇒ `1.1` — _Callbacks for &lt;C++&gt;_"
+    );
+    let many = (0..200)
+        .map(|i| update("gtk-3", &format!("arch{}", i)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        blocks(&many).iter().map(|b| b.len()).collect::<Vec<_>>(),
+        [50, 50, 50, 50]
+    );
+}
@@ -0,0 +1,313 @@
+//! XMPP sink announcing the updates in a multi-user chat room (XEP-0045).
+//!
+//! This is a minimal client (STARTTLS and SASL PLAIN only) keeping a connection to the server,
+//! which is established again whenever the server drops it.
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use notifier_core::PVMessage;
+use serde::Deserialize;
+use std::{fmt, time::Duration};
+use tokio::{
+    io::{split, AsyncRead, AsyncReadExt, AsyncWriteExt, WriteHalf},
+    net::TcpStream,
+    sync::Mutex,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tokio_native_tls::{native_tls, TlsConnector, TlsStream};
+
+use super::{Event, ATTEMPTS};
+use crate::{format::Format, grouping::Grouping, PVMessageExt};
+
+const DEFAULT_PORT: u16 = 5222;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maximum length of each message in the room
+const MAX_LENGTH: usize = 4000;
+
+#[derive(Deserialize, Debug)]
+pub struct Xmpp {
+    /// Bare JID of the bot, e.g. `notifier@example.org`
+    jid: String,
+    password: String,
+    /// Address of the room, e.g. `updates@conference.example.org`
+    room: String,
+    #[serde(default = "default_nick")]
+    nick: String,
+    /// Server to connect to (`host:port`), the domain of the JID on port 5222 by default
+    server: Option<String>,
+    #[serde(skip)]
+    connection: Mutex<Option<Connection>>,
+}
+
+fn default_nick() -> String {
+    "repo-notifier".to_string()
+}
+
+struct Connection {
+    writer: WriteHalf<TlsStream<TcpStream>>,
+    /// Drains what the server sends, finishes when the connection is lost
+    reader: JoinHandle<()>,
+}
+
+impl Connection {
+    fn is_alive(&self) -> bool {
+        !self.reader.is_finished()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Connection")
+    }
+}
+
+/// Escape the text for the XML stream
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+/// Render the updates in plain text, split into the messages posted to the room
+fn messages(updates: &[PVMessage]) -> Vec<String> {
+    // group the updates by repository, component and architecture in the order they appear
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for p in updates {
+        let header = p.header(Format::Plain, Grouping::Arch);
+        let line = p.render(Format::Plain);
+        match groups.iter_mut().find(|(h, _)| *h == header) {
+            Some((_, lines)) => lines.push(line),
+            None => groups.push((header, vec![line])),
+        }
+    }
+    let mut messages = vec![String::new()];
+    for (header, lines) in groups {
+        for (i, line) in lines.iter().enumerate() {
+            let text = messages.last_mut().unwrap();
+            if !text.is_empty() && text.len() + header.len() + line.len() + 3 > MAX_LENGTH {
+                messages.push(String::new());
+            }
+            let text = messages.last_mut().unwrap();
+            if i == 0 || text.is_empty() {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                *text += &header;
+                text.push('\n');
+            }
+            *text += line;
+            text.push('\n');
+        }
+    }
+    messages.retain(|m| !m.is_empty());
+
+    messages
+        .into_iter()
+        .map(|m| m.trim_end().to_string())
+        .collect()
+}
+
+/// Read from the stream until one of the patterns arrives, returning what was read
+async fn read_until<S: AsyncRead + Unpin>(stream: &mut S, patterns: &[&str]) -> Result<String> {
+    let mut received = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let text = String::from_utf8_lossy(&received);
+        if patterns.iter().any(|p| text.contains(p)) {
+            return Ok(text.into_owned());
+        }
+        let n = stream.read(&mut buffer).await?;
+        if n == 0 {
+            return Err(anyhow!("The XMPP server closed the connection"));
+        }
+        received.extend_from_slice(&buffer[..n]);
+    }
+}
+
+impl Xmpp {
+    pub fn validate(&self) -> Result<()> {
+        match self.jid.split_once('@') {
+            Some((user, domain)) if !user.is_empty() && !domain.is_empty() => Ok(()),
+            _ => Err(anyhow!("Invalid JID of the XMPP sink: {}", self.jid)),
+        }
+    }
+
+    fn stream_header(&self, domain: &str) -> String {
+        format!(
+            "<?xml version='1.0'?><stream:stream to='{}' version='1.0' xmlns='jabber:client' \
+            xmlns:stream='http://etherx.jabber.org/streams'>",
+            escape(domain)
+        )
+    }
+
+    /// Log in and join the room
+    async fn connect(&self) -> Result<Connection> {
+        let (user, domain) = self
+            .jid
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Invalid JID: {}", self.jid))?;
+        let server = self
+            .server
+            .clone()
+            .unwrap_or_else(|| format!("{}:{}", domain, DEFAULT_PORT));
+        let mut tcp = TcpStream::connect(&server).await?;
+        tcp.write_all(self.stream_header(domain).as_bytes()).await?;
+        let features = read_until(&mut tcp, &["</stream:features>"]).await?;
+        if !features.contains("urn:ietf:params:xml:ns:xmpp-tls") {
+            return Err(anyhow!("{} does not offer STARTTLS", server));
+        }
+        tcp.write_all(b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")
+            .await?;
+        if !read_until(&mut tcp, &["<proceed", "<failure"])
+            .await?
+            .contains("<proceed")
+        {
+            return Err(anyhow!("{} refused STARTTLS", server));
+        }
+        let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+        let mut tls = connector.connect(domain, tcp).await?;
+
+        tls.write_all(self.stream_header(domain).as_bytes()).await?;
+        let features = read_until(&mut tls, &["</stream:features>"]).await?;
+        if !features.contains(">PLAIN<") {
+            return Err(anyhow!("{} does not offer SASL PLAIN", server));
+        }
+        let credentials = STANDARD.encode(format!("\0{}\0{}", user, self.password));
+        tls.write_all(
+            format!(
+                "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>{}</auth>",
+                credentials
+            )
+            .as_bytes(),
+        )
+        .await?;
+        if !read_until(&mut tls, &["<success", "<failure"])
+            .await?
+            .contains("<success")
+        {
+            return Err(anyhow!("Could not log in to {} as {}", server, self.jid));
+        }
+
+        tls.write_all(self.stream_header(domain).as_bytes()).await?;
+        read_until(&mut tls, &["</stream:features>"]).await?;
+        tls.write_all(
+            b"<iq type='set' id='bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>\
+            <resource>repo-notifier</resource></bind></iq>",
+        )
+        .await?;
+        let bound = read_until(&mut tls, &["</iq>"]).await?;
+        if bound.contains("type='error'") || bound.contains("type=\"error\"") {
+            return Err(anyhow!("Could not bind a resource on {}", server));
+        }
+        // join the room without its history
+        tls.write_all(
+            format!(
+                "<presence to='{}/{}'><x xmlns='http://jabber.org/protocol/muc'>\
+                <history maxstanzas='0'/></x></presence>",
+                escape(&self.room),
+                escape(&self.nick)
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+        let (mut reader, writer) = split(tls);
+        let reader = tokio::spawn(async move {
+            let mut buffer = [0; 4096];
+            while let Ok(n) = reader.read(&mut buffer).await {
+                if n == 0 {
+                    break;
+                }
+            }
+        });
+        log::info!("Joined the XMPP room {} as {}.", self.room, self.nick);
+
+        Ok(Connection { writer, reader })
+    }
+
+    /// Post the message to the room, connecting again if the connection was lost
+    async fn post(&self, text: &str) -> Result<()> {
+        let stanza = format!(
+            "<message to='{}' type='groupchat'><body>{}</body></message>",
+            escape(&self.room),
+            escape(text)
+        );
+        let mut connection = self.connection.lock().await;
+        let mut error = anyhow!("Not connected");
+        for attempt in 1..=ATTEMPTS {
+            if attempt > 1 {
+                sleep(Duration::from_secs(5 * attempt)).await;
+            }
+            if connection.as_ref().is_none_or(|c| !c.is_alive()) {
+                match timeout(CONNECT_TIMEOUT, self.connect()).await {
+                    Ok(Ok(c)) => *connection = Some(c),
+                    Ok(Err(e)) => {
+                        error = e;
+                        continue;
+                    }
+                    Err(e) => {
+                        error = e.into();
+                        continue;
+                    }
+                }
+            }
+            let writer = match connection.as_mut() {
+                Some(c) => &mut c.writer,
+                None => continue,
+            };
+            match writer.write_all(stanza.as_bytes()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    log::warn!("Lost the connection to the XMPP server: {}", e);
+                    *connection = None;
+                    error = e.into();
+                }
+            }
+        }
+
+        Err(error)
+    }
+
+    pub async fn send(&self, event: &Event<'_>) -> Result<()> {
+        match event {
+            Event::Updates(updates, _) => {
+                for message in messages(updates) {
+                    self.post(&message).await?;
+                }
+                Ok(())
+            }
+            Event::Notice(_, text) => self.post(text).await,
+        }
+    }
+}
+
+#[test]
+fn test_messages() {
+    let update = |pkg: &str, arch: &str| PVMessage::sample(pkg, arch, b'+', None, Some("1.0"));
+    let updates = [
+        update("gtk-3", "amd64"),
+        update("gtk-3", "arm64"),
+        update("gtk-4", "amd64"),
+    ];
+    assert_eq!(
+        messages(&updates),
+        ["stable amd64\n + gtk-3 1.0\n + gtk-4 1.0\n\nstable arm64\n + gtk-3 1.0"]
+    );
+    let many = (0..400)
+        .map(|_| update("gtk-3", "amd64"))
+        .collect::<Vec<_>>();
+    let split = messages(&many);
+    assert_eq!(split.len(), 2);
+    assert!(split
+        .iter()
+        .all(|m| m.starts_with("stable amd64\n") && m.len() <= MAX_LENGTH));
+    assert_eq!(escape("<a href='x'>"), "&lt;a href=&apos;x&apos;&gt;");
+}
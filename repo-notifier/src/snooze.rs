@@ -0,0 +1,145 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::timezone::TimeZone;
+
+// Snoozing for longer than this is probably a mistake, use /stop instead
+const MAX_SNOOZE: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// Current UNIX timestamp
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Parse durations like `30m`, `2h` or `1d12h`
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 24 * 3600,
+            _ => return None,
+        };
+        let value: u64 = number.parse().ok()?;
+        total = total.checked_add(value.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if !number.is_empty() || total == 0 {
+        return None;
+    }
+    let duration = Duration::from_secs(total);
+
+    (duration <= MAX_SNOOZE).then_some(duration)
+}
+
+/// Parse a time of the day like `08:00` or `23:30`
+pub fn parse_clock(s: &str) -> Option<(u32, u32)> {
+    let (hour, minute) = s.trim().split_once(':')?;
+    if minute.len() != 2 {
+        return None;
+    }
+    let (hour, minute) = (hour.parse().ok()?, minute.parse().ok()?);
+
+    (hour < 24 && minute < 60).then_some((hour, minute))
+}
+
+/// Daily window when the updates are held for a chat, in its time zone (e.g. `22:00-07:00`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuietHours {
+    start: (u32, u32),
+    end: (u32, u32),
+}
+
+impl FromStr for QuietHours {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or(())?;
+        let quiet_hours = QuietHours {
+            start: parse_clock(start).ok_or(())?,
+            end: parse_clock(end).ok_or(())?,
+        };
+
+        if quiet_hours.start == quiet_hours.end {
+            return Err(());
+        }
+        Ok(quiet_hours)
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start.0, self.start.1, self.end.0, self.end.1
+        )
+    }
+}
+
+impl QuietHours {
+    /// When the quiet hours in progress at the UNIX timestamp end, `None` outside of them
+    pub fn end_after(&self, timezone: &TimeZone, time: i64) -> Option<i64> {
+        let minutes = |(hour, minute)| hour * 60 + minute;
+        if !timezone.within(time, minutes(self.start), minutes(self.end)) {
+            return None;
+        }
+
+        timezone.next_time(time, self.end.0, self.end.1)
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+    assert_eq!(parse_duration("1d12h"), Some(Duration::from_secs(129600)));
+    assert_eq!(parse_duration(" 30M "), Some(Duration::from_secs(1800)));
+    assert_eq!(parse_duration("90"), None);
+    assert_eq!(parse_duration("h"), None);
+    assert_eq!(parse_duration("0m"), None);
+    assert_eq!(parse_duration("2w"), None);
+    assert_eq!(parse_duration("31d"), None);
+    assert_eq!(parse_duration("99999999999999999999d"), None);
+    assert_eq!(parse_clock("08:00"), Some((8, 0)));
+    assert_eq!(parse_clock("23:30"), Some((23, 30)));
+    assert_eq!(parse_clock("24:00"), None);
+    assert_eq!(parse_clock("8:5"), None);
+    assert_eq!(parse_clock("2h"), None);
+
+    let quiet_hours = "22:00-07:30".parse::<QuietHours>().unwrap();
+    assert_eq!(quiet_hours.to_string(), "22:00-07:30");
+    assert!("22:00-22:00".parse::<QuietHours>().is_err());
+    assert!("22:00".parse::<QuietHours>().is_err());
+    // in the time zone of the chat
+    let shanghai = TimeZone::load("Asia/Shanghai").unwrap();
+    let time = |s: &str| chrono::DateTime::parse_from_rfc3339(s).unwrap().timestamp();
+    assert_eq!(
+        quiet_hours.end_after(&shanghai, time("2026-10-16T15:00:00Z")),
+        Some(time("2026-10-16T23:30:00Z"))
+    );
+    assert_eq!(
+        quiet_hours.end_after(&shanghai, time("2026-10-16T22:00:00Z")),
+        Some(time("2026-10-16T23:30:00Z"))
+    );
+    assert_eq!(
+        quiet_hours.end_after(&shanghai, time("2026-10-16T12:00:00Z")),
+        None
+    );
+    assert_eq!(
+        quiet_hours.end_after(&TimeZone::utc(), time("2026-10-16T15:00:00Z")),
+        None
+    );
+}
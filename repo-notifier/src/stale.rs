@@ -0,0 +1,105 @@
+use anyhow::Result;
+use sqlx::{query, sqlite::SqlitePool};
+use teloxide::{prelude::*, types::ChatId, RequestError};
+
+use crate::{audit, config, snooze};
+
+const DAY: i64 = 24 * 3600;
+
+/// Number of the chats to check in each round, so that every chat is checked once
+/// in `check_days`
+fn batch_size(subscribers: i64, interval: i64, check_days: i64) -> i64 {
+    let period = (check_days * DAY).max(interval);
+
+    (subscribers * interval + period - 1) / period
+}
+
+/// Check the subscribed chats not checked for a while (a few of them in each round) and
+/// unsubscribe the ones that kept failing for the grace period
+pub async fn check_some(bot: &Bot, pool: &SqlitePool, interval: i64) -> Result<()> {
+    let config = &config::get().stale;
+    let now = snooze::now();
+    let subscribers = query!("SELECT COUNT(*) AS count FROM subbed")
+        .fetch_one(pool)
+        .await?
+        .count;
+    let limit = batch_size(subscribers, interval, config.check_days);
+    let due = now - config.check_days * DAY;
+    let chats = query!(
+        "SELECT subbed.chat_id FROM subbed LEFT JOIN chat_health ON subbed.chat_id = chat_health.chat_id
+        WHERE chat_health.checked IS NULL OR chat_health.checked < ?
+        ORDER BY COALESCE(chat_health.checked, 0) LIMIT ?",
+        due,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+    for chat in chats {
+        let chat_id = chat.chat_id;
+        match bot.get_chat(ChatId(chat_id)).await {
+            Ok(_) => {
+                query!(
+                    "INSERT INTO chat_health (chat_id, checked) VALUES (?, ?)
+                    ON CONFLICT(chat_id) DO UPDATE SET checked = excluded.checked,
+                    failing_since = NULL, last_error = NULL",
+                    chat_id,
+                    now
+                )
+                .execute(pool)
+                .await?;
+            }
+            // only the errors reported by Telegram count, not the network issues
+            Err(RequestError::Api(e)) => {
+                let error = e.to_string();
+                log::warn!("Subscribed chat {} is not reachable: {}", chat_id, error);
+                let health = query!(
+                    "INSERT INTO chat_health (chat_id, checked, failing_since, last_error) VALUES (?1, ?2, ?2, ?3)
+                    ON CONFLICT(chat_id) DO UPDATE SET checked = excluded.checked,
+                    failing_since = COALESCE(failing_since, excluded.failing_since), last_error = excluded.last_error
+                    RETURNING failing_since",
+                    chat_id,
+                    now,
+                    error
+                )
+                .fetch_one(pool)
+                .await?;
+                let failing_since = health.failing_since.unwrap_or(now);
+                if now - failing_since >= config.grace_days * DAY {
+                    unsubscribe(pool, chat_id, &error).await?;
+                }
+            }
+            Err(e) => log::warn!("Could not check chat {}: {}", chat_id, e),
+        }
+    }
+
+    Ok(())
+}
+
+async fn unsubscribe(pool: &SqlitePool, chat_id: i64, error: &str) -> Result<()> {
+    log::warn!(
+        "Unsubscribing chat {}, which kept failing: {}",
+        chat_id,
+        error
+    );
+    let mut tx = pool.begin().await?;
+    query!("DELETE FROM subbed WHERE chat_id = ?", chat_id)
+        .execute(&mut *tx)
+        .await?;
+    query!("DELETE FROM chat_health WHERE chat_id = ?", chat_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    audit::record(pool, chat_id, audit::BOT, "unsubscribe", Some(error)).await?;
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_size() {
+    // checking every 10 minutes, 1008 rounds in a week
+    assert_eq!(batch_size(0, 600, 7), 0);
+    assert_eq!(batch_size(1, 600, 7), 1);
+    assert_eq!(batch_size(1008, 600, 7), 1);
+    assert_eq!(batch_size(1009, 600, 7), 2);
+    assert_eq!(batch_size(10, 600, 0), 10);
+}
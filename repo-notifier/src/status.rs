@@ -0,0 +1,226 @@
+//! Health of the pipeline, reported with /status
+use once_cell::sync::Lazy;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::i18n::{tr, Text};
+use crate::snooze::now;
+use crate::{delivery, failures};
+
+/// State of the subscription to a p-vector instance
+#[derive(Default)]
+struct Source {
+    connected: bool,
+    /// When the last message arrived (UNIX timestamp)
+    last_event: Option<i64>,
+    /// Number of the updates waiting to be sent
+    pending: usize,
+    /// Number of the messages dropped for a missing or invalid HMAC
+    rejected: u64,
+    /// Sequence number of the last message
+    sequence: Option<u64>,
+}
+
+/// The state of the sources and of the refreshes of the repository
+#[derive(Default)]
+pub struct Registry {
+    sources: Mutex<BTreeMap<String, Source>>,
+    /// When the `last_update` file was last written (UNIX timestamp, 0: never)
+    last_refresh: AtomicI64,
+}
+
+impl Registry {
+    fn with_source(&self, repo: Option<&str>, f: impl FnOnce(&mut Source)) {
+        let mut sources = self.sources.lock().unwrap();
+        f(sources
+            .entry(repo.unwrap_or("default").to_string())
+            .or_default());
+    }
+
+    pub fn set_connected(&self, repo: Option<&str>, connected: bool) {
+        self.with_source(repo, |s| s.connected = connected);
+    }
+
+    pub fn received(&self, repo: Option<&str>, time: i64) {
+        self.with_source(repo, |s| s.last_event = Some(time));
+    }
+
+    pub fn set_sequence(&self, repo: Option<&str>, sequence: u64) {
+        self.with_source(repo, |s| s.sequence = Some(sequence));
+    }
+
+    pub fn rejected(&self, repo: Option<&str>) {
+        self.with_source(repo, |s| s.rejected += 1);
+    }
+
+    pub fn set_pending(&self, repo: Option<&str>, pending: usize) {
+        self.with_source(repo, |s| s.pending = pending);
+    }
+
+    pub fn refreshed(&self, time: i64) {
+        self.last_refresh.store(time, Ordering::SeqCst);
+    }
+
+    /// Report the state of each source, the chunks waiting to be resent and the last refresh
+    /// of the repository, as of `now`
+    pub fn report(&self, lang: &str, now: i64) -> String {
+        let mut lines = Vec::new();
+        for (repo, source) in self.sources.lock().unwrap().iter() {
+            let state = if source.connected {
+                Text::StatusConnected
+            } else {
+                Text::StatusDisconnected
+            };
+            lines.push(
+                tr(lang, Text::StatusSource)
+                    .replacen("{}", repo, 1)
+                    .replacen("{}", tr(lang, state), 1)
+                    .replacen("{}", &ago(lang, now, source.last_event), 1)
+                    .replacen("{}", &source.pending.to_string(), 1),
+            );
+            if source.rejected > 0 {
+                lines.push(
+                    tr(lang, Text::StatusRejected)
+                        .replacen("{}", repo, 1)
+                        .replacen("{}", &source.rejected.to_string(), 1),
+                );
+            }
+        }
+        lines.push(tr(lang, Text::StatusUndelivered).replacen(
+            "{}",
+            &delivery::queue().count().to_string(),
+            1,
+        ));
+        lines.push(failures::report(lang));
+        let refresh = Some(self.last_refresh.load(Ordering::SeqCst)).filter(|t| *t > 0);
+        lines.push(tr(lang, Text::StatusRefresh).replacen("{}", &ago(lang, now, refresh), 1));
+
+        lines.join("\n")
+    }
+
+    /// Report the last batch delivered to the chat as of `now`, to tell whether it is lagging
+    /// behind the last message of p-vector
+    pub fn chat_report(
+        &self,
+        lang: &str,
+        now: i64,
+        chat_id: i64,
+        receipt: Option<delivery::Receipt>,
+    ) -> String {
+        let last_batch = self
+            .sources
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|s| s.sequence)
+            .max()
+            .map_or_else(|| "?".to_string(), |s| s.to_string());
+        let waiting = delivery::queue().count_for(chat_id).to_string();
+        match receipt {
+            Some(receipt) => tr(lang, Text::StatusChat)
+                .replacen("{}", &receipt.sequence.to_string(), 1)
+                .replacen("{}", &(receipt.chunk + 1).to_string(), 1)
+                .replacen("{}", &ago(lang, now, Some(receipt.time)), 1)
+                .replacen("{}", &last_batch, 1)
+                .replacen("{}", &waiting, 1),
+            None => tr(lang, Text::StatusChatNone)
+                .replacen("{}", &last_batch, 1)
+                .replacen("{}", &waiting, 1),
+        }
+    }
+}
+
+static SOURCES: Lazy<Registry> = Lazy::new(Registry::default);
+
+pub fn set_connected(repo: Option<&str>, connected: bool) {
+    SOURCES.set_connected(repo, connected);
+}
+
+pub fn received(repo: Option<&str>) {
+    SOURCES.received(repo, now());
+}
+
+pub fn set_sequence(repo: Option<&str>, sequence: u64) {
+    SOURCES.set_sequence(repo, sequence);
+}
+
+pub fn rejected(repo: Option<&str>) {
+    SOURCES.rejected(repo);
+}
+
+pub fn set_pending(repo: Option<&str>, pending: usize) {
+    SOURCES.set_pending(repo, pending);
+}
+
+pub fn refreshed() {
+    SOURCES.refreshed(now());
+}
+
+/// Format the time elapsed from the timestamp to `now`, e.g. `1h 5m ago`
+fn ago(lang: &str, now: i64, timestamp: Option<i64>) -> String {
+    let seconds = match timestamp {
+        Some(timestamp) => (now - timestamp).max(0),
+        None => return tr(lang, Text::StatusNever).to_string(),
+    };
+
+    tr(lang, Text::StatusAgo).replacen("{}", &elapsed(seconds), 1)
+}
+
+/// Format the length of time, e.g. `1h 5m`
+pub fn elapsed(seconds: i64) -> String {
+    match (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, m) => format!("{}m {}s", m, seconds % 60),
+        (0, h, m) => format!("{}h {}m", h, m),
+        (d, h, _) => format!("{}d {}h", d, h),
+    }
+}
+
+/// Report the state of the pipeline
+pub fn report(lang: &str) -> String {
+    SOURCES.report(lang, now())
+}
+
+/// Report the last batch delivered to the chat
+pub fn chat_report(lang: &str, chat_id: i64, receipt: Option<delivery::Receipt>) -> String {
+    SOURCES.chat_report(lang, now(), chat_id, receipt)
+}
+
+#[test]
+fn test_report() {
+    let now = 1_700_000_000;
+    assert_eq!(ago("en", now, None), "never");
+    assert_eq!(ago("en", now, Some(now - 42)), "42s ago");
+    assert_eq!(ago("en", now, Some(now - 3725)), "1h 2m ago");
+    assert_eq!(ago("en", now, Some(now - 90000)), "1d 1h ago");
+    let registry = Registry::default();
+    registry.set_connected(Some("stable"), true);
+    registry.set_pending(Some("stable"), 3);
+    registry.rejected(Some("stable"));
+    let report = registry.report("en", now);
+    assert!(report.contains("stable: connected, last event: never, pending updates: 3"));
+    assert!(report.contains("stable: 1 unauthenticated messages dropped"));
+    assert!(report.ends_with("Last repository refresh: never"));
+    registry.received(Some("stable"), now - 5);
+    registry.refreshed(now - 60);
+    let report = registry.report("en", now);
+    assert!(report.contains("stable: connected, last event: 5s ago, pending updates: 3"));
+    assert!(report.ends_with("Last repository refresh: 1m 0s ago"));
+    assert!(registry
+        .chat_report("en", now, -1002, None)
+        .starts_with("This chat: nothing received since the start (last batch: #?)"));
+    registry.set_sequence(Some("stable"), 43);
+    let receipt = delivery::Receipt {
+        sequence: 42,
+        chunk: 2,
+        time: now - 7,
+    };
+    let report = registry.chat_report("en", now, -1001, Some(receipt));
+    assert!(report.starts_with("This chat: batch #42 received up to message 3 7s ago"));
+    assert!(report.contains("(last batch: #43)"));
+}
@@ -0,0 +1,138 @@
+use notifier_core::PVMessage;
+use std::collections::BTreeMap;
+
+use crate::format::Format;
+use crate::i18n::{tr, tr_with, Text};
+
+/// Summaries of more components and architectures than this only show the totals
+const MAX_GROUPS: usize = 3;
+/// Counted operations, everything else is counted as the last one
+const OPERATIONS: [(u8, Text); 5] = [
+    (b'+', Text::SummaryNew),
+    (b'^', Text::SummaryUpgraded),
+    (b'-', Text::SummaryRemoved),
+    (b'*', Text::SummaryOverwritten),
+    (b'?', Text::SummaryOther),
+];
+
+/// Number of the updates of each operation
+pub type Counts = [usize; OPERATIONS.len()];
+
+/// Count the update in the counts of its operation
+pub fn count(counts: &mut Counts, p: &PVMessage) {
+    let method = p.method.as_new_type();
+    let index = OPERATIONS
+        .iter()
+        .position(|(op, _)| *op == method)
+        .unwrap_or(OPERATIONS.len() - 1);
+    counts[index] += 1;
+}
+
+/// Describe the counts, e.g. `3 new, 12 upgraded, 1 removed`
+pub fn describe(counts: &Counts, lang: &str) -> String {
+    counts
+        .iter()
+        .zip(OPERATIONS.iter())
+        .filter(|(count, _)| **count > 0)
+        .map(|(count, (_, text))| tr_with(lang, *text, &count.to_string()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Summarize the updates in each component and architecture in a line,
+/// e.g. `stable/amd64: 3 new, 12 upgraded, 1 removed`
+pub fn summarize(messages: &[PVMessage], lang: &str, format: Format) -> String {
+    let mut groups: BTreeMap<String, Counts> = BTreeMap::new();
+    for p in messages {
+        let name = match p.repo.as_deref() {
+            Some(repo) => format!("[{}] {}/{}", repo, p.comp, p.arch),
+            None => format!("{}/{}", p.comp, p.arch),
+        };
+        count(groups.entry(name).or_default(), p);
+    }
+    let line = if groups.len() > MAX_GROUPS {
+        let mut total = Counts::default();
+        for counts in groups.values() {
+            for (total, count) in total.iter_mut().zip(counts.iter()) {
+                *total += count;
+            }
+        }
+        describe(&total, lang)
+    } else {
+        groups
+            .iter()
+            .map(|(name, counts)| format!("{}: {}", name, describe(counts, lang)))
+            .collect::<Vec<_>>()
+            .join("; ")
+    };
+
+    format.escape(&line)
+}
+
+/// Replace a flood of updates (e.g. a mass rebuild) with a line for each component,
+/// e.g. `📦 512 packages updated in stable (12 new, 500 upgraded)`, and a link to the full list
+pub fn flood(messages: &[PVMessage], lang: &str, format: Format, url: &str) -> String {
+    let mut groups: BTreeMap<String, Counts> = BTreeMap::new();
+    for p in messages {
+        let name = match p.repo.as_deref() {
+            Some(repo) => format!("[{}] {}", repo, p.comp),
+            None => p.comp.clone(),
+        };
+        count(groups.entry(name).or_default(), p);
+    }
+    let mut lines = groups
+        .iter()
+        .map(|(name, counts)| {
+            let line = tr_with(
+                lang,
+                Text::Flooded,
+                &counts.iter().sum::<usize>().to_string(),
+            )
+            .replacen("{}", name, 1);
+            format.escape(&format!("{} ({})", line, describe(counts, lang)))
+        })
+        .collect::<Vec<_>>();
+    if !url.is_empty() {
+        lines.push(format.link(&format.escape(tr(lang, Text::FloodedListing)), url));
+    }
+
+    lines.join("\n")
+}
+
+#[test]
+fn test_summarize() {
+    let message = |comp: &str, arch: &str, method: u8| PVMessage {
+        comp: comp.to_string(),
+        ..PVMessage::sample("gtk-3", arch, method, None, None)
+    };
+    let messages = vec![
+        message("stable", "amd64", b'^'),
+        message("stable", "amd64", b'+'),
+        message("stable", "amd64", b'^'),
+        message("stable", "arm64", b'-'),
+        message("stable", "arm64", b'i'),
+    ];
+    assert_eq!(
+        summarize(&messages, "en", Format::Plain),
+        "stable/amd64: 1 new, 2 upgraded; stable/arm64: 1 removed, 1 other"
+    );
+    assert_eq!(
+        summarize(&messages[..1], "zh-CN", Format::Markdown),
+        r"stable/amd64: 更新 1 个"
+    );
+    let many = ["amd64", "arm64", "loongarch64", "riscv64"]
+        .iter()
+        .map(|arch| message("stable", arch, b'^'))
+        .collect::<Vec<_>>();
+    assert_eq!(summarize(&many, "en", Format::Plain), "4 upgraded");
+    assert_eq!(
+        flood(
+            &messages,
+            "en",
+            Format::Html,
+            "https://packages.aosc.io/updates"
+        ),
+        "📦 5 packages updated in stable (1 new, 2 upgraded, 1 removed, 1 other)\n\
+         <a href=\"https://packages.aosc.io/updates\">See the full list</a>"
+    );
+}
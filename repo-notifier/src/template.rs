@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use handlebars::Handlebars;
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+use crate::{format::Format, grouping::Grouping};
+
+// Templates longer than this are rejected
+const TEMPLATE_MAX_LENGTH: usize = 1024;
+
+/// Parts of the notification layout that can be customized
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Part {
+    /// Heading of each component and architecture (`repo`, `comp` and `arch`, which is null
+    /// when grouped by package)
+    Header,
+    /// Each package (`repo`, `comp`, `arch`, `arches` when grouped by package, `pkg`,
+    /// `method`, `from_ver`, `to_ver`, `url`, `security`, `mentions`, `description` and
+    /// `changelog`)
+    Line,
+    /// Appended to each message (`count`)
+    Footer,
+}
+
+pub const PARTS: &[Part] = &[Part::Header, Part::Line, Part::Footer];
+
+impl FromStr for Part {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PARTS
+            .iter()
+            .find(|x| x.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Part::Header => "header",
+            Part::Line => "line",
+            Part::Footer => "footer",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Custom Handlebars templates of a chat, the default layout is used for the missing parts
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Template {
+    pub header: Option<String>,
+    pub line: Option<String>,
+    pub footer: Option<String>,
+}
+
+impl Template {
+    pub fn get(&self, part: Part) -> Option<&str> {
+        match part {
+            Part::Header => self.header.as_deref(),
+            Part::Line => self.line.as_deref(),
+            Part::Footer => self.footer.as_deref(),
+        }
+    }
+
+    /// Replace (or remove if `source` is `None`) the given part after validating it in the
+    /// format of the chat
+    pub fn set(&mut self, part: Part, source: Option<String>, format: Format) -> Result<()> {
+        if let Some(source) = source.as_deref() {
+            validate(source, format)?;
+        }
+        match part {
+            Part::Header => self.header = source,
+            Part::Line => self.line = source,
+            Part::Footer => self.footer = source,
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that the template is not too long, can be parsed, and renders to a message Telegram
+/// accepts in the format
+pub fn validate(source: &str, format: Format) -> Result<()> {
+    if source.len() > TEMPLATE_MAX_LENGTH {
+        return Err(anyhow!(
+            "template is longer than {} bytes",
+            TEMPLATE_MAX_LENGTH
+        ));
+    }
+    let mut registry = Handlebars::new();
+    registry.register_escape_fn(move |s| format.escape(s));
+    registry.register_template_string("check", source)?;
+    let sample = serde_json::json!({
+        "repo": "stable",
+        "comp": "main",
+        "arch": "amd64",
+        "arches": "amd64, arm64",
+        "pkg": "gtk-3",
+        "method": "^",
+        "from_ver": "3.24.1",
+        "to_ver": "3.24.2",
+        "url": "https://packages.aosc.io/packages/gtk-3",
+        "security": true,
+        "mentions": "@maintainer",
+        "description": "GTK+ graphical user interface library (v3)",
+        "changelog": "https://example.com/gtk-3/compare/3.24.1...3.24.2",
+        "count": 1,
+    });
+    format.check(&registry.render("check", &sample)?)?;
+
+    Ok(())
+}
+
+/// Renders the custom parts of a template, the variables are escaped according to the format
+/// (the text of the template is not, it is checked to be valid in the format of the chat)
+pub struct Renderer {
+    registry: Handlebars<'static>,
+}
+
+impl Renderer {
+    pub fn new(format: Format, template: &Template) -> Self {
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(move |s| format.escape(s));
+        for part in PARTS {
+            if let Some(source) = template.get(*part) {
+                if let Err(e) = registry.register_template_string(&part.to_string(), source) {
+                    log::warn!("Invalid {} template: {}", part, e);
+                }
+            }
+        }
+
+        Renderer { registry }
+    }
+
+    /// Render the given part, returns `None` if the part is not customized (or fails to render)
+    pub fn render<T: Serialize>(&self, part: Part, context: &T) -> Option<String> {
+        let name = part.to_string();
+        if !self.registry.has_template(&name) {
+            return None;
+        }
+        match self.registry.render(&name, context) {
+            Ok(rendered) => Some(rendered),
+            Err(e) => {
+                log::warn!("Unable to render the {} template: {}", part, e);
+                None
+            }
+        }
+    }
+}
+
+/// Format, templates and grouping a message is rendered with
+pub type Layout = (Format, Template, Grouping);
+
+#[test]
+fn test_render_template() {
+    let mut template = Template::default();
+    let set = |template: &mut Template, format, source: &str| {
+        template.set(Part::Line, Some(source.to_string()), format)
+    };
+    set(
+        &mut template,
+        Format::Markdown,
+        r"{{method}} {{pkg}} \({{to_ver}}\)",
+    )
+    .unwrap();
+    assert!(template
+        .set(Part::Footer, Some("{{#if}}".to_string()), Format::Html)
+        .is_err());
+    // the text of the template must be valid in the format
+    let mut other = Template::default();
+    for source in [
+        "{{method}} {{pkg}} ({{to_ver}})",
+        "*{{pkg}} {{to_ver}}",
+        "[{{pkg}}]({{{url}}}",
+        "{{{url}}}",
+    ] {
+        assert!(
+            set(&mut other, Format::Markdown, source).is_err(),
+            "{}",
+            source
+        );
+    }
+    set(&mut other, Format::Markdown, "*{{pkg}}* _\\({{to_ver}}\\)_").unwrap();
+    set(
+        &mut other,
+        Format::Markdown,
+        "[{{pkg}}]({{{url}}}) `{{to_ver}}`",
+    )
+    .unwrap();
+    for source in [
+        "<b>{{pkg}}",
+        "<b>{{pkg}}</i>",
+        "<big>{{pkg}}</big>",
+        "{{pkg}} -> {{to_ver}}",
+        "{{pkg}} & co",
+    ] {
+        assert!(set(&mut other, Format::Html, source).is_err(), "{}", source);
+    }
+    set(
+        &mut other,
+        Format::Html,
+        r#"<b>{{pkg}}</b> &amp; <a href="{{url}}">{{to_ver}}</a>"#,
+    )
+    .unwrap();
+    set(&mut other, Format::Plain, "{{pkg}} ({{to_ver}}) & <co>").unwrap();
+    let renderer = Renderer::new(Format::Markdown, &template);
+    let context = serde_json::json!({ "method": "+", "pkg": "gtk-3", "to_ver": "3.24.2" });
+    assert_eq!(
+        renderer.render(Part::Line, &context).unwrap(),
+        r"\+ gtk\-3 \(3\.24\.2\)"
+    );
+    assert_eq!(renderer.render(Part::Header, &context), None);
+}
@@ -0,0 +1,107 @@
+//! IANA time zones of the chats, from the tz database built into `chrono-tz`
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, TimeZone as _, Timelike};
+use chrono_tz::Tz;
+use std::fmt;
+
+/// Time zone of the chats without one set
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeZone(Tz);
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.name())
+    }
+}
+
+impl TimeZone {
+    /// Load the zone of the given name, e.g. `Asia/Shanghai`
+    pub fn load(name: &str) -> Result<TimeZone> {
+        name.parse()
+            .map(TimeZone)
+            .map_err(|e| anyhow!("Unknown time zone {}: {}", name, e))
+    }
+
+    pub fn utc() -> TimeZone {
+        TimeZone(Tz::UTC)
+    }
+
+    /// Local time at the UNIX timestamp
+    pub fn local(&self, time: i64) -> NaiveDateTime {
+        DateTime::from_timestamp(time, 0)
+            .unwrap_or_default()
+            .with_timezone(&self.0)
+            .naive_local()
+    }
+
+    /// UNIX timestamp of the local time, the later one if it happens twice, an hour later if it
+    /// is skipped by the change to the daylight saving time
+    pub fn timestamp(&self, local: NaiveDateTime) -> Option<i64> {
+        match self.0.from_local_datetime(&local) {
+            LocalResult::Single(t) | LocalResult::Ambiguous(_, t) => Some(t.timestamp()),
+            LocalResult::None => self
+                .0
+                .from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+                .map(|t| t.timestamp()),
+        }
+    }
+
+    /// The next time after the UNIX timestamp when the clock reads `hour:minute`
+    pub fn next_time(&self, after: i64, hour: u32, minute: u32) -> Option<i64> {
+        let today = self.local(after).date();
+        [today, today.succ_opt()?]
+            .iter()
+            .filter_map(|d| d.and_hms_opt(hour, minute, 0))
+            .filter_map(|t| self.timestamp(t))
+            .find(|t| *t > after)
+    }
+
+    /// Whether the clock reads from `start` until `end` (in minutes since the midnight, across
+    /// the midnight if `end` is earlier) at the UNIX timestamp
+    pub fn within(&self, time: i64, start: u32, end: u32) -> bool {
+        let local = self.local(time);
+        let minute = local.hour() * 60 + local.minute();
+        if start <= end {
+            start <= minute && minute < end
+        } else {
+            start <= minute || minute < end
+        }
+    }
+}
+
+#[test]
+fn test_timezone() {
+    let time = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().timestamp();
+    let shanghai = TimeZone::load("Asia/Shanghai").unwrap();
+    assert_eq!(shanghai.to_string(), "Asia/Shanghai");
+    assert_eq!(
+        shanghai.next_time(time("2026-10-16T08:00:00Z"), 8, 0),
+        Some(time("2026-10-17T00:00:00Z"))
+    );
+    assert_eq!(
+        shanghai.next_time(time("2026-10-16T08:00:00Z"), 23, 30),
+        Some(time("2026-10-16T15:30:00Z"))
+    );
+    // 22:00 to 07:00 in Shanghai
+    assert!(shanghai.within(time("2026-10-16T15:00:00Z"), 22 * 60, 7 * 60));
+    assert!(!shanghai.within(time("2026-10-16T23:00:00Z"), 22 * 60, 7 * 60));
+    assert!(shanghai.within(time("2026-10-16T04:00:00Z"), 12 * 60, 13 * 60));
+    let berlin = TimeZone::load("Europe/Berlin").unwrap();
+    // 2026-03-29 02:00 CET is skipped, 2026-10-25 02:00 to 03:00 happens twice
+    assert_eq!(
+        berlin.next_time(time("2026-03-28T12:00:00Z"), 2, 30),
+        Some(time("2026-03-29T01:30:00Z"))
+    );
+    assert_eq!(
+        berlin.next_time(time("2026-10-24T12:00:00Z"), 2, 30),
+        Some(time("2026-10-25T01:30:00Z"))
+    );
+    assert!(berlin.within(time("2026-07-10T20:30:00Z"), 22 * 60, 23 * 60));
+    assert!(!berlin.within(time("2026-01-10T20:30:00Z"), 22 * 60, 23 * 60));
+    assert_eq!(TimeZone::utc().to_string(), DEFAULT_TIMEZONE);
+    assert!(TimeZone::load("../etc/passwd").is_err());
+    assert!(TimeZone::load("Asia/Nowhere").is_err());
+}
@@ -0,0 +1,174 @@
+//! Current versions of a package for `/pkg`, from the packages site or as seen in the recorded
+//! updates
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::{config, history::Event};
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Information of a package on the packages site, with the versions of the packages in each
+/// repository (e.g. `amd64/stable`) from the newest one
+#[derive(Deserialize)]
+struct Info {
+    pkg: Package,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    dpkg_matrix: Vec<Row>,
+}
+
+#[derive(Deserialize)]
+struct Row {
+    version: String,
+    dpkgs: Vec<Option<Dpkg>>,
+}
+
+#[derive(Deserialize)]
+struct Dpkg {
+    reponame: String,
+}
+
+/// Versions in a branch, with the architectures having each
+type Versions<'a> = Vec<(&'a str, Vec<&'a str>)>;
+
+/// Look up the current versions of the package on the packages site, as the latest updates in
+/// each branch and architecture
+pub async fn fetch(pkg: &str) -> Result<Vec<Event>> {
+    let url = &config::get().versions.url;
+    if url.is_empty() {
+        return Err(anyhow!("The packages site is not configured"));
+    }
+    let info: Info = CLIENT
+        .get(url.replace("{}", pkg))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(latest_of(pkg, info))
+}
+
+/// The newest version in each repository
+fn latest_of(pkg: &str, info: Info) -> Vec<Event> {
+    let mut latest: Vec<Event> = Vec::new();
+    for row in info.pkg.dpkg_matrix {
+        for dpkg in row.dpkgs.into_iter().flatten() {
+            let (arch, comp) = match dpkg.reponame.split_once('/') {
+                Some((arch, comp)) => (arch.to_string(), comp.to_string()),
+                None => continue,
+            };
+            if latest.iter().any(|e| e.arch == arch && e.comp == comp) {
+                continue;
+            }
+            latest.push(Event {
+                id: 0,
+                timestamp: 0,
+                repo: None,
+                comp,
+                pkg: pkg.to_string(),
+                arch,
+                method: "^".to_string(),
+                from_ver: None,
+                to_ver: Some(row.version.clone()),
+            });
+        }
+    }
+    latest.sort_by(|a, b| a.comp.cmp(&b.comp));
+
+    latest
+}
+
+/// List the versions of the package in each branch, along with the architectures having them,
+/// `None` if the package is not found in any branch
+pub fn render(pkg: &str, latest: &[Event]) -> Option<String> {
+    let mut branches: Vec<(String, Versions)> = Vec::new();
+    for event in latest {
+        // removed from the branch
+        let version = match (&event.to_ver, event.method.as_str()) {
+            (Some(version), method) if method != "-" => version.as_str(),
+            _ => continue,
+        };
+        let branch = match &event.repo {
+            Some(repo) => format!("[{}] {}", repo, event.comp),
+            None => event.comp.clone(),
+        };
+        let versions = match branches.iter_mut().find(|(b, _)| *b == branch) {
+            Some((_, versions)) => versions,
+            None => {
+                branches.push((branch, Vec::new()));
+                &mut branches.last_mut().unwrap().1
+            }
+        };
+        match versions.iter_mut().find(|(v, _)| *v == version) {
+            Some((_, archs)) => archs.push(&event.arch),
+            None => versions.push((version, vec![&event.arch])),
+        }
+    }
+    if branches.is_empty() {
+        return None;
+    }
+    let mut text = format!("{} (https://packages.aosc.io/packages/{})", pkg, pkg);
+    for (branch, versions) in branches {
+        let versions = versions
+            .iter()
+            .map(|(version, archs)| format!("{} ({})", version, archs.join(", ")))
+            .collect::<Vec<_>>();
+        text += &format!("\n{}: {}", branch, versions.join(", "));
+    }
+
+    Some(text)
+}
+
+#[test]
+fn test_render() {
+    let event = |comp: &str, arch: &str, method: &str, version: &str| Event {
+        id: 0,
+        timestamp: 0,
+        repo: None,
+        comp: comp.to_string(),
+        pkg: "gtk-3".to_string(),
+        arch: arch.to_string(),
+        method: method.to_string(),
+        from_ver: None,
+        to_ver: Some(version.to_string()),
+    };
+    let latest = [
+        event("stable", "amd64", "^", "3.24.2"),
+        event("stable", "arm64", "^", "3.24.2"),
+        event("stable", "riscv64", "+", "3.24.1"),
+        event("stable", "ppc64el", "-", "3.24.1"),
+        event("testing", "amd64", "^", "3.24.3"),
+    ];
+    assert_eq!(
+        render("gtk-3", &latest).unwrap(),
+        "gtk-3 (https://packages.aosc.io/packages/gtk-3)\n\
+        stable: 3.24.2 (amd64, arm64), 3.24.1 (riscv64)\n\
+        testing: 3.24.3 (amd64)"
+    );
+    assert_eq!(render("gtk-3", &latest[3..4]), None);
+
+    let info = serde_json::from_str(
+        r#"{"pkg": {"name": "gtk-3", "dpkg_matrix": [
+            {"version": "3.24.3", "dpkgs": [{"reponame": "amd64/testing"}, null]},
+            {"version": "3.24.2", "dpkgs": [{"reponame": "amd64/stable"}, {"reponame": "arm64/stable"}]},
+            {"version": "3.24.1", "dpkgs": [{"reponame": "amd64/stable"}, null]}
+        ]}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        render("gtk-3", &latest_of("gtk-3", info)).unwrap(),
+        "gtk-3 (https://packages.aosc.io/packages/gtk-3)\n\
+        stable: 3.24.2 (amd64, arm64)\n\
+        testing: 3.24.3 (amd64)"
+    );
+}
@@ -0,0 +1,12 @@
+-- Resolved downloads, for the statistics of the release team
+CREATE TABLE IF NOT EXISTS downloads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    variant TEXT NOT NULL,
+    arch TEXT NOT NULL,
+    -- release date of the tarball or image, YYYYMMDD
+    date TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    -- region of the client (see `region_header` of the mirrors), if known
+    region TEXT
+);
+CREATE INDEX IF NOT EXISTS downloads_timestamp ON downloads (timestamp);
@@ -0,0 +1,89 @@
+//! Access logs as JSON lines on the standard output (`ACCESS_LOG_FORMAT=json`), for Loki or
+//! Elasticsearch, instead of the lines of the actix logger
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    middleware::Next,
+    web, Error, HttpMessage,
+};
+use serde_json::json;
+use std::{
+    io::Write,
+    sync::LazyLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{client_address, mirrors::Mirrors, timing::RequestedEntry};
+
+/// Whether the access logs are written as JSON
+pub static JSON: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("ACCESS_LOG_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("json"))
+});
+
+fn outcome(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        304 => "not_modified",
+        404 => "not_found",
+        s if s >= 500 => "error",
+        s if s >= 400 => "rejected",
+        s if s >= 300 => "redirect",
+        _ => "ok",
+    }
+}
+
+/// Write a JSON line of the request once it is answered
+pub async fn log_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let res = next.call(req).await?;
+    let latency = start.elapsed();
+
+    let request = res.request();
+    let entry = request
+        .extensions()
+        .get::<RequestedEntry>()
+        .map(|e| e.0.clone());
+    let mut parts = entry.as_deref().unwrap_or_default().split('.');
+    let (variant, arch) = (parts.next(), parts.next());
+    let region = request
+        .app_data::<web::Data<Mirrors>>()
+        .and_then(|mirrors| mirrors.region(request));
+    let line = json!({
+        "timestamp": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+        "method": request.method().as_str(),
+        "path": request.path(),
+        "route": request.match_pattern(),
+        "variant": variant.filter(|v| !v.is_empty()),
+        "arch": arch,
+        "status": res.status().as_u16(),
+        "outcome": outcome(res.status()),
+        "latency_ms": latency.as_secs_f64() * 1000.0,
+        "client": client_address(request),
+        "region": region,
+        "user_agent": request
+            .headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|ua| ua.to_str().ok()),
+    });
+    // a single write, so the lines of the workers are not interleaved
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", line).ok();
+
+    Ok(res)
+}
+
+#[test]
+fn test_outcome() {
+    assert_eq!(outcome(StatusCode::FOUND), "redirect");
+    assert_eq!(outcome(StatusCode::NOT_MODIFIED), "not_modified");
+    assert_eq!(outcome(StatusCode::NOT_FOUND), "not_found");
+    assert_eq!(outcome(StatusCode::BAD_REQUEST), "rejected");
+    assert_eq!(outcome(StatusCode::OK), "ok");
+    assert_eq!(outcome(StatusCode::SERVICE_UNAVAILABLE), "error");
+}
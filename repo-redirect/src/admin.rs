@@ -0,0 +1,61 @@
+//! Administrative endpoints, only available with the token in `ADMIN_TOKEN`
+use actix_web::{post, HttpRequest, HttpResponse, Responder};
+use anyhow::Result;
+use subtle::ConstantTimeEq;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::parser;
+
+/// Whether the request carries the token as `Authorization: Bearer <token>`, compared in
+/// constant time
+pub fn authorized(req: &HttpRequest, token: Option<&str>) -> bool {
+    let token = match token {
+        Some(token) if !token.is_empty() => token,
+        _ => return false,
+    };
+    req.headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|given| bool::from(given.as_bytes().ct_eq(token.as_bytes())))
+        .unwrap_or(false)
+}
+
+/// Parse the manifests again, e.g. after they were updated on an NFS mount
+#[post("/admin/reload")]
+async fn reload(req: HttpRequest) -> impl Responder {
+    if !authorized(&req, std::env::var("ADMIN_TOKEN").ok().as_deref()) {
+        return HttpResponse::Forbidden().finish();
+    }
+    parser::force_reload();
+
+    HttpResponse::Accepted().body("Reloading the manifests.")
+}
+
+/// Parse the manifests again on SIGHUP
+pub async fn reload_on_sighup() -> Result<()> {
+    let mut hangups = signal(SignalKind::hangup())?;
+    while hangups.recv().await.is_some() {
+        log::info!("Received SIGHUP");
+        parser::force_reload();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_authorized() {
+    use actix_web::test::TestRequest;
+
+    let req = TestRequest::post()
+        .insert_header(("Authorization", "Bearer secret"))
+        .to_http_request();
+    assert!(authorized(&req, Some("secret")));
+    assert!(!authorized(&req, Some("other")));
+    assert!(!authorized(&req, Some("secrets")));
+    assert!(!authorized(&req, None));
+    assert!(!authorized(
+        &TestRequest::post().to_http_request(),
+        Some("")
+    ));
+}
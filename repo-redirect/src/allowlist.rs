@@ -0,0 +1,63 @@
+//! Hosts the download form may send the visitors to with a `https://` URL, so it cannot be
+//! used as an open redirector
+use actix_web::http::Uri;
+use std::sync::LazyLock;
+
+use crate::mirrors::Mirrors;
+
+/// Patterns of the allowed hosts in `REDIRECT_ALLOWLIST` (comma-separated, e.g.
+/// `aosc.io,*.aosc.io`), the mirrors are always allowed
+static ALLOWLIST: LazyLock<Vec<String>> = LazyLock::new(|| {
+    parse(&std::env::var("REDIRECT_ALLOWLIST").unwrap_or_else(|_| "aosc.io,*.aosc.io".to_string()))
+});
+
+fn parse(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|p| p.trim().to_ascii_lowercase())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Whether the host matches the pattern, `*.example.org` matching the subdomains only
+fn matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+fn allowed_by(patterns: &[String], url: &str, mirrors: &Mirrors) -> bool {
+    let host = match url.parse::<Uri>() {
+        Ok(uri) if uri.scheme_str() == Some("https") => match uri.host() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    patterns.iter().any(|p| matches(p, &host)) || mirrors.serves_host(&host)
+}
+
+/// Whether the visitors may be redirected to the URL
+pub fn allowed(url: &str, mirrors: &Mirrors) -> bool {
+    allowed_by(&ALLOWLIST, url, mirrors)
+}
+
+#[test]
+fn test_allowlist() {
+    let mirrors = Mirrors::from_env().unwrap();
+    let patterns = parse("aosc.io, *.aosc.io,");
+    assert_eq!(patterns, vec!["aosc.io", "*.aosc.io"]);
+    let allowed = |url| allowed_by(&patterns, url, &mirrors);
+    assert!(allowed("https://aosc.io/downloads/"));
+    assert!(allowed("https://Wiki.AOSC.io/"));
+    assert!(allowed("https://releases.aosc.io/os-amd64/"));
+    assert!(!allowed("http://aosc.io/"));
+    assert!(!allowed("https://evilaosc.io/"));
+    assert!(!allowed("https://aosc.io.example.com/"));
+    assert!(!allowed("https://aosc.io@example.com/"));
+    assert!(!allowed("https://example.com/?aosc.io"));
+    assert!(!allowed("not a url"));
+}
@@ -0,0 +1,144 @@
+//! JSON API for the installer and the front-ends, returning the metadata of the latest
+//! tarballs instead of the pages
+use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    caching::Validators,
+    parser::{artifact_key, Tarball},
+    SharedDistMap,
+};
+
+#[derive(Serialize, Debug)]
+pub struct TarballInfo<'a> {
+    /// `<variant>.<arch>`, the LiveKit images which are not ISOs get their type appended
+    key: &'a str,
+    variant: &'a str,
+    arch: &'a str,
+    date: &'a str,
+    /// Path relative to the root of the releases
+    path: &'a str,
+    sha256sum: &'a str,
+    download_size: u64,
+    inst_size: u64,
+    retro: bool,
+}
+
+pub fn tarball_info<'a>(key: &'a str, tarball: &'a Tarball) -> TarballInfo<'a> {
+    TarballInfo {
+        key,
+        variant: key.split('.').next().unwrap_or_default(),
+        arch: &tarball.arch,
+        date: &tarball.date,
+        path: &tarball.path,
+        sha256sum: &tarball.sha256sum,
+        download_size: tarball.download_size,
+        inst_size: tarball.inst_size,
+        retro: tarball.retro,
+    }
+}
+
+#[derive(Deserialize)]
+struct DistroQuery {
+    /// Type of the artifact, `tarball` by default
+    #[serde(rename = "type")]
+    type_: Option<String>,
+}
+
+/// Metadata of the latest tarball (or image, with `?type=iso` for example) of the variant on
+/// the architecture
+#[get("/api/v1/distro/{variant}/{arch}")]
+async fn distro(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<DistroQuery>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+) -> Result<HttpResponse, Error> {
+    let (variant, arch) = path.into_inner();
+    let type_ = query.type_.as_deref().unwrap_or("tarball");
+    let (livekit, key) = artifact_key(&tarballs.0, type_, &variant, &arch);
+    let map = if livekit { &tarballs.1 } else { &tarballs.0 };
+    match map.get(&key) {
+        Some(tarball) => {
+            let mut builder = HttpResponse::Ok();
+            if let Some(validators) = Validators::of(&[livekit], &key) {
+                if validators.fresh(&req) {
+                    return Ok(validators.not_modified());
+                }
+                validators.apply(&mut builder);
+            }
+            Ok(builder.json(tarball_info(&key, &tarball)))
+        }
+        None => Ok(HttpResponse::NotFound()
+            .json(json!({ "error": format!("{} is not available on {}", variant, arch) }))),
+    }
+}
+
+/// Metadata of the latest tarballs (or images) in the manifest, sorted by their keys
+fn list_entries(map: &SharedDistMap) -> Vec<serde_json::Value> {
+    let mut entries = map
+        .iter()
+        .map(|item| {
+            serde_json::to_value(tarball_info(item.key(), item.value())).unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a["key"].as_str().cmp(&b["key"].as_str()));
+
+    entries
+}
+
+/// Every variant and architecture available in the manifests
+#[get("/api/v1/list")]
+async fn list(
+    req: HttpRequest,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+) -> Result<HttpResponse, Error> {
+    let mut builder = HttpResponse::Ok();
+    if let Some(validators) = Validators::of(&[false, true], "list") {
+        if validators.fresh(&req) {
+            return Ok(validators.not_modified());
+        }
+        validators.apply(&mut builder);
+    }
+
+    Ok(builder.json(json!({
+        "recipe": list_entries(&tarballs.0),
+        "livekit": list_entries(&tarballs.1),
+    })))
+}
+
+#[test]
+fn test_tarball_info() {
+    let tarball = Tarball {
+        download_size: 1024,
+        inst_size: 4096,
+        ..Tarball::sample(
+            "amd64",
+            "20240101",
+            "os-amd64/base/aosc-os_base_20240101_amd64.tar.xz",
+        )
+    };
+    let info = serde_json::to_value(tarball_info("base.amd64", &tarball)).unwrap();
+    assert_eq!(
+        info,
+        json!({
+            "key": "base.amd64",
+            "variant": "base",
+            "arch": "amd64",
+            "date": "20240101",
+            "path": "os-amd64/base/aosc-os_base_20240101_amd64.tar.xz",
+            "sha256sum": "00",
+            "download_size": 1024,
+            "inst_size": 4096,
+            "retro": false,
+        })
+    );
+
+    let map: SharedDistMap = std::sync::Arc::new(dashmap::DashMap::new());
+    map.insert("desktop.amd64".to_string(), tarball.clone());
+    map.insert("base.amd64".to_string(), tarball);
+    let entries = list_entries(&map);
+    assert_eq!(entries[0]["key"], "base.amd64");
+    assert_eq!(entries[1]["variant"], "desktop");
+}
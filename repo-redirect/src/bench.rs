@@ -0,0 +1,276 @@
+//! Load test of the handlers against fixture manifests (`repo-redirect --bench`)
+//!
+//! Configured with the environment variables:
+//! - `BENCH_FIXTURES`: directory containing `recipe.json` and `livekit.json` (default: `tests`)
+//! - `BENCH_REQUESTS`: total number of requests (default: 10000)
+//! - `BENCH_CONCURRENCY`: number of concurrent clients (default: 32)
+//! - `BENCH_MIX`: weights of the request kinds (default: `download=70,notfound=20,metrics=10`)
+
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use actix_web::web;
+use dashmap::DashMap;
+
+use crate::{
+    counters, filenames, mirrors, parser, releases, serve, stats, timing, torrent, AppState,
+    SharedDistMap,
+};
+
+const DEFAULT_MIX: &str = "download=70,notfound=20,metrics=10";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Kind {
+    /// Download of an entry in the manifests
+    Download,
+    /// Download of an entry not in the manifests
+    NotFound,
+    /// Query of the metrics endpoint
+    Metrics,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Download => "download",
+            Kind::NotFound => "notfound",
+            Kind::Metrics => "metrics",
+        }
+    }
+}
+
+/// Parse the request mix, e.g. `download=70,notfound=20,metrics=10`
+fn parse_mix(mix: &str) -> Result<Vec<(Kind, usize)>> {
+    let mut weights = Vec::new();
+    for item in mix.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let (name, weight) = item
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid request mix: {}", item))?;
+        let kind = [Kind::Download, Kind::NotFound, Kind::Metrics]
+            .iter()
+            .copied()
+            .find(|k| k.name() == name.trim())
+            .ok_or_else(|| anyhow!("Unknown request kind: {}", name))?;
+        weights.push((kind, weight.trim().parse()?));
+    }
+    if weights.iter().all(|(_, w)| *w == 0) {
+        return Err(anyhow!("The request mix is empty"));
+    }
+
+    Ok(weights)
+}
+
+/// Kind of the `i`-th request, spreading the kinds evenly according to their weights
+fn pick(weights: &[(Kind, usize)], i: usize) -> Kind {
+    let total: usize = weights.iter().map(|(_, w)| w).sum();
+    let mut slot = i % total;
+    for (kind, weight) in weights {
+        if slot < *weight {
+            return *kind;
+        }
+        slot -= weight;
+    }
+
+    weights[0].0
+}
+
+/// Get the `p`-th percentile (0-100) of the sorted samples
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+async fn load_fixture(path: &Path, livekit: bool) -> Result<SharedDistMap> {
+    let map = if livekit {
+        parser::parse_livekit(path).await?
+    } else {
+        parser::parse_recipe(path).await?
+    };
+
+    Ok(Arc::new(map.into_iter().collect::<DashMap<_, _>>()))
+}
+
+/// Start the server against the fixtures and drive the configured request mix against it
+pub async fn run() -> Result<()> {
+    let fixtures = std::env::var("BENCH_FIXTURES").unwrap_or_else(|_| "tests".to_string());
+    let fixtures = Path::new(&fixtures);
+    let requests: usize = env_or("BENCH_REQUESTS", 10000);
+    let concurrency: usize = env_or("BENCH_CONCURRENCY", 32).max(1);
+    let weights = parse_mix(&std::env::var("BENCH_MIX").unwrap_or_else(|_| DEFAULT_MIX.into()))?;
+
+    let recipe = load_fixture(&fixtures.join("recipe.json"), false).await?;
+    let livekit = load_fixture(&fixtures.join("livekit.json"), true).await?;
+    let mut entries = recipe
+        .iter()
+        .map(|e| ("/download/alt", e.key().clone()))
+        .chain(
+            livekit
+                .iter()
+                .map(|e| ("/download/livekit", e.key().clone())),
+        )
+        .collect::<Vec<_>>();
+    entries.sort();
+    if entries.is_empty() {
+        return Err(anyhow!("No entry found in the fixtures"));
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let server = serve(
+        listener,
+        (recipe, livekit),
+        Default::default(),
+        AppState {
+            stats: web::Data::new(stats::Stats::from_env()),
+            mirrors: web::Data::new(mirrors::Mirrors::from_env()?),
+            filenames: web::Data::new(filenames::Filenames::from_env()),
+            counters: web::Data::new(counters::Counters::disabled()),
+            releases: web::Data::new(releases::Releases::from_env(fixtures)),
+        },
+        web::Data::new(timing::Timings::from_env()),
+        web::Data::new(torrent::Torrents::from_env(releases::Releases::from_env(
+            fixtures,
+        ))),
+    )?;
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    println!(
+        "Sending {} requests with {} clients ({} entries in the fixtures)...",
+        requests,
+        concurrency,
+        entries.len()
+    );
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let next = AtomicUsize::new(0);
+    let started = Instant::now();
+    let workers = (0..concurrency).map(|_| async {
+        let mut samples: Vec<(Kind, Duration, bool)> = Vec::new();
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            if i >= requests {
+                break;
+            }
+            let kind = pick(&weights, i);
+            let request = match kind {
+                Kind::Download => {
+                    let (endpoint, key) = &entries[i % entries.len()];
+                    client
+                        .post(format!("{}{}", base, endpoint))
+                        .form(&[("distro-variant", key)])
+                }
+                Kind::NotFound => client
+                    .post(format!("{}/download/alt", base))
+                    .form(&[("distro-variant", format!("missing{}.amd64", i))]),
+                Kind::Metrics => client.get(format!("{}/metrics", base)),
+            };
+            let start = Instant::now();
+            let ok = match request.send().await {
+                Ok(response) => {
+                    let expected = match kind {
+                        Kind::NotFound => reqwest::StatusCode::NOT_FOUND,
+                        _ => reqwest::StatusCode::OK,
+                    };
+                    let status = response.status();
+                    response.bytes().await.is_ok() && status == expected
+                }
+                Err(_) => false,
+            };
+            samples.push((kind, start.elapsed(), ok));
+        }
+        samples
+    });
+    let samples = join_all(workers).await.concat();
+    let elapsed = started.elapsed();
+    handle.stop(true).await;
+
+    let mut by_kind: HashMap<Kind, (Vec<Duration>, usize)> = HashMap::new();
+    for (kind, duration, ok) in samples {
+        let entry = by_kind.entry(kind).or_default();
+        entry.0.push(duration);
+        if !ok {
+            entry.1 += 1;
+        }
+    }
+    println!(
+        "{} requests in {:.2} s ({:.0} requests/s)",
+        requests,
+        elapsed.as_secs_f64(),
+        requests as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "{:<10} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "kind", "count", "errors", "p50 (ms)", "p90 (ms)", "p99 (ms)", "max (ms)"
+    );
+    let mut failed = false;
+    for (kind, _) in weights.iter() {
+        let (durations, errors) = match by_kind.get_mut(kind) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        durations.sort_unstable();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        println!(
+            "{:<10} {:>8} {:>8} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+            kind.name(),
+            durations.len(),
+            errors,
+            ms(percentile(durations, 50.0)),
+            ms(percentile(durations, 90.0)),
+            ms(percentile(durations, 99.0)),
+            ms(*durations.last().unwrap_or(&Duration::ZERO))
+        );
+        failed |= *errors > 0;
+    }
+    if failed {
+        return Err(anyhow!("Some requests failed"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bench_helpers() {
+    let weights = parse_mix("download=2, metrics=1").unwrap();
+    let kinds = (0..6).map(|i| pick(&weights, i)).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            Kind::Download,
+            Kind::Download,
+            Kind::Metrics,
+            Kind::Download,
+            Kind::Download,
+            Kind::Metrics
+        ]
+    );
+    assert!(parse_mix("upload=1").is_err());
+    assert!(parse_mix("download=0").is_err());
+    let samples = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+    assert_eq!(percentile(&samples, 50.0), Duration::from_millis(50));
+    assert_eq!(percentile(&samples, 99.0), Duration::from_millis(99));
+    assert_eq!(percentile(&samples, 100.0), Duration::from_millis(100));
+    assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+}
@@ -0,0 +1,138 @@
+//! Validators of the responses built from the manifests, so the CDNs and the browsers
+//! revalidate the pages and the metadata instead of fetching them again
+use actix_web::{
+    http::header::{
+        CacheControl, CacheDirective, ETag, EntityTag, Header, HttpDate, IfModifiedSince,
+        IfNoneMatch, LastModified,
+    },
+    http::Method,
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use sha1::{Digest, Sha1};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::parser;
+
+pub struct Validators {
+    etag: EntityTag,
+    modified: SystemTime,
+}
+
+impl Validators {
+    /// Validators of the response about `key` from the manifests modified at the times, the
+    /// tags stay the same across the restarts and the instances
+    fn new(times: &[SystemTime], key: &str) -> Option<Self> {
+        let modified = *times.iter().max()?;
+        let mut tag = times
+            .iter()
+            .map(|t| {
+                let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                format!("{:x}", secs)
+            })
+            .collect::<Vec<_>>();
+        tag.push(
+            Sha1::digest(key.as_bytes())[..8]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        );
+
+        Some(Validators {
+            // weak, the mirror in the pages may differ
+            etag: EntityTag::new_weak(tag.join("-")),
+            modified,
+        })
+    }
+
+    /// Validators of the response about `key` from the recipe and/or the LiveKit manifest,
+    /// `None` until they are loaded
+    pub fn of(livekit: &[bool], key: &str) -> Option<Self> {
+        let times = livekit
+            .iter()
+            .map(|&livekit| parser::modified(livekit))
+            .collect::<Option<Vec<_>>>()?;
+
+        Validators::new(&times, key)
+    }
+
+    /// Whether the client already has the response, from `If-None-Match` (or
+    /// `If-Modified-Since` without it). Only `GET` and `HEAD` are revalidated.
+    pub fn fresh(&self, req: &HttpRequest) -> bool {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return false;
+        }
+        if req.headers().contains_key(IfNoneMatch::name()) {
+            return match IfNoneMatch::parse(req) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&self.etag)),
+                Err(_) => false,
+            };
+        }
+        match IfModifiedSince::parse(req) {
+            // HTTP dates are precise to the second
+            Ok(since) => HttpDate::from(self.modified) <= since.0,
+            Err(_) => false,
+        }
+    }
+
+    /// Add the validators to the response, which must be revalidated before being reused
+    pub fn apply(&self, builder: &mut HttpResponseBuilder) {
+        builder
+            .insert_header(ETag(self.etag.clone()))
+            .insert_header(LastModified(self.modified.into()))
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]));
+    }
+
+    pub fn not_modified(&self) -> HttpResponse {
+        let mut builder = HttpResponse::NotModified();
+        self.apply(&mut builder);
+
+        builder.finish()
+    }
+}
+
+#[test]
+fn test_validators() {
+    use actix_web::{http::header, test::TestRequest};
+    use std::time::Duration;
+
+    let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let validators = Validators::new(&[modified], "base.amd64").unwrap();
+    assert!(validators.etag.weak);
+    assert_eq!(validators.etag.tag(), "6553f100-7f3a20ec3e830303");
+    assert!(Validators::new(&[], "base.amd64").is_none());
+    assert_ne!(
+        Validators::new(&[modified], "desktop.amd64").unwrap().etag,
+        validators.etag
+    );
+
+    let etag = validators.etag.to_string();
+    let req = TestRequest::get()
+        .insert_header((header::IF_NONE_MATCH, etag.as_str()))
+        .to_http_request();
+    assert!(validators.fresh(&req));
+    let req = TestRequest::post()
+        .insert_header((header::IF_NONE_MATCH, etag.as_str()))
+        .to_http_request();
+    assert!(!validators.fresh(&req));
+    let req = TestRequest::get()
+        .insert_header((header::IF_NONE_MATCH, r#"W/"other""#))
+        .insert_header((
+            header::IF_MODIFIED_SINCE,
+            HttpDate::from(modified).to_string(),
+        ))
+        .to_http_request();
+    assert!(!validators.fresh(&req));
+    let req = TestRequest::get()
+        .insert_header((
+            header::IF_MODIFIED_SINCE,
+            HttpDate::from(modified).to_string(),
+        ))
+        .to_http_request();
+    assert!(validators.fresh(&req));
+    assert!(!validators.fresh(&TestRequest::get().to_http_request()));
+    assert_eq!(
+        validators.not_modified().status(),
+        actix_web::http::StatusCode::NOT_MODIFIED
+    );
+}
@@ -0,0 +1,73 @@
+//! Failure injection for the resilience tests, only built with the `chaos` feature.
+//!
+//! `CHAOS_FAULTS` sets the probability of each fault, e.g. `parse=0.5,slow_reload=0.2`, it
+//! can be changed at runtime with `PUT /chaos` (with the `ADMIN_TOKEN`). Slow reloads take
+//! `CHAOS_RELOAD_DELAY_SECS` (5 by default) longer.
+use actix_web::{get, put, HttpRequest, HttpResponse, Responder};
+use anyhow::{anyhow, Result};
+use repokit_common::chaos::{self, Chaos};
+use std::{sync::LazyLock, time::Duration};
+
+use crate::admin;
+
+const DEFAULT_RELOAD_DELAY: u64 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fault {
+    /// The manifest can not be parsed
+    ParseFailure,
+    /// The manifest takes long to reload
+    SlowReload,
+}
+
+impl chaos::Fault for Fault {
+    const FAULTS: &'static [(&'static str, Self)] = &[
+        ("parse", Fault::ParseFailure),
+        ("slow_reload", Fault::SlowReload),
+    ];
+}
+
+static CHAOS: LazyLock<Chaos<Fault>> = LazyLock::new(Chaos::from_env);
+
+/// Simulate the faults of reloading a manifest
+pub async fn reload() -> Result<()> {
+    if CHAOS.inject(Fault::SlowReload) {
+        let delay = std::env::var("CHAOS_RELOAD_DELAY_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_RELOAD_DELAY);
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+    }
+    if CHAOS.inject(Fault::ParseFailure) {
+        return Err(anyhow!("Could not parse the manifest (injected)"));
+    }
+
+    Ok(())
+}
+
+/// Current probabilities of the injected faults
+#[get("/chaos")]
+async fn get_chaos() -> impl Responder {
+    CHAOS.describe()
+}
+
+/// Replace the probabilities of the injected faults, the body is in the format of
+/// `CHAOS_FAULTS`
+#[put("/chaos")]
+async fn put_chaos(req: HttpRequest, spec: String) -> impl Responder {
+    if !admin::authorized(&req, std::env::var("ADMIN_TOKEN").ok().as_deref()) {
+        return HttpResponse::Forbidden().finish();
+    }
+    match CHAOS.set(&spec) {
+        Ok(described) => HttpResponse::Ok().body(described),
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
+    }
+}
+
+#[test]
+fn test_faults() {
+    let chaos = Chaos::<Fault>::new("parse=1, slow_reload=0.25").unwrap();
+    assert_eq!(chaos.describe(), "parse=1,slow_reload=0.25");
+    assert!(chaos.inject(Fault::ParseFailure));
+    assert!(Chaos::<Fault>::new("disconnect=0.1").is_err());
+}
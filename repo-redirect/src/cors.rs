@@ -0,0 +1,111 @@
+//! CORS headers on the JSON endpoints, so the websites in `CORS_ALLOWED_ORIGINS` can call them
+//! from the browsers
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, Method},
+    middleware::Next,
+    Error, HttpResponse,
+};
+use std::sync::LazyLock;
+
+/// Allowed origins in `CORS_ALLOWED_ORIGINS` (comma-separated, e.g.
+/// `https://aosc.io,https://packages.aosc.io`, or `*` for any), none by default
+static ALLOWED_ORIGINS: LazyLock<Vec<String>> = LazyLock::new(|| {
+    std::env::var("CORS_ALLOWED_ORIGINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|o| o.trim().trim_end_matches('/').to_string())
+        .filter(|o| !o.is_empty())
+        .collect()
+});
+
+/// Headers of the responses the scripts may read
+const EXPOSED_HEADERS: &str = "ETag, Last-Modified, X-Mirror";
+/// How long the browsers may cache the preflight responses, in seconds
+const PREFLIGHT_MAX_AGE: &str = "86400";
+
+/// The value of `Access-Control-Allow-Origin` for the origin, if it is allowed
+fn allow_origin<'a>(allowed: &[String], origin: &'a str) -> Option<&'a str> {
+    if allowed.iter().any(|o| o == "*") {
+        return Some("*");
+    }
+    allowed
+        .iter()
+        .any(|o| o.eq_ignore_ascii_case(origin))
+        .then_some(origin)
+}
+
+/// The API and the downloads answering with JSON when asked
+fn json_endpoint(path: &str) -> bool {
+    path.starts_with("/api/") || path == "/download" || path.starts_with("/download/")
+}
+
+/// Answer the preflight requests and add the CORS headers to the responses of the allowed
+/// origins
+pub async fn cors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|o| o.to_str().ok())
+        .filter(|_| json_endpoint(req.path()))
+        .and_then(|o| allow_origin(&ALLOWED_ORIGINS, o))
+        .map(|o| o.to_string());
+    let origin = match origin {
+        Some(origin) => origin,
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if preflight {
+        let response = HttpResponse::NoContent()
+            .insert_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD"))
+            .insert_header((
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                "Accept, If-None-Match, If-Modified-Since",
+            ))
+            .insert_header((header::ACCESS_CONTROL_MAX_AGE, PREFLIGHT_MAX_AGE))
+            .insert_header((header::VARY, "Origin"))
+            .finish();
+        return Ok(req.into_response(response));
+    }
+    let mut res = next.call(req).await?;
+    let headers = res.headers_mut();
+    if let Ok(origin) = header::HeaderValue::from_str(&origin) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        header::HeaderValue::from_static(EXPOSED_HEADERS),
+    );
+    headers.append(header::VARY, header::HeaderValue::from_static("Origin"));
+
+    Ok(res.map_into_boxed_body())
+}
+
+#[test]
+fn test_cors() {
+    let allowed = vec![
+        "https://aosc.io".to_string(),
+        "https://packages.aosc.io".to_string(),
+    ];
+    assert_eq!(
+        allow_origin(&allowed, "https://packages.aosc.io"),
+        Some("https://packages.aosc.io")
+    );
+    assert_eq!(allow_origin(&allowed, "https://example.com"), None);
+    assert_eq!(
+        allow_origin(&["*".to_string()], "https://example.com"),
+        Some("*")
+    );
+    assert!(json_endpoint("/api/v1/list"));
+    assert!(json_endpoint("/download/alt"));
+    assert!(!json_endpoint("/metrics"));
+    assert!(!json_endpoint("/admin/reload"));
+}
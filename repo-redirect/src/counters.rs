@@ -0,0 +1,220 @@
+//! Download counters kept in the SQLite database at `DOWNLOAD_DB`, aggregated for the release
+//! team under `/api/v1/stats`
+use actix_web::{get, web, Error, HttpResponse};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{
+    migrate,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool},
+};
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+
+use crate::parser::Tarball;
+
+/// Downloads waiting to be written, the ones beyond are dropped
+const QUEUE_SIZE: usize = 1024;
+
+/// A resolved download, as stored in the database
+struct Download {
+    variant: String,
+    arch: String,
+    date: String,
+    timestamp: i64,
+    region: Option<String>,
+}
+
+/// Records the resolved downloads, disabled if `DOWNLOAD_DB` is not set
+pub struct Counters {
+    pool: Option<SqlitePool>,
+    queue: Option<mpsc::Sender<Download>>,
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    /// Only count the downloads in the last days
+    days: Option<u64>,
+}
+
+impl Counters {
+    /// Open (or create) the database in `DOWNLOAD_DB`
+    pub async fn from_env() -> Result<Self> {
+        let path = match std::env::var("DOWNLOAD_DB") {
+            Ok(path) if !path.is_empty() => path,
+            _ => return Ok(Counters::disabled()),
+        };
+        let options = SqliteConnectOptions::from_str(&path)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(options).await?;
+        migrate!().run(&pool).await?;
+
+        Ok(Counters::with_pool(pool))
+    }
+
+    /// Counters writing to the database from a single task
+    fn with_pool(pool: SqlitePool) -> Self {
+        let (queue, downloads) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(write_downloads(pool.clone(), downloads));
+
+        Counters {
+            pool: Some(pool),
+            queue: Some(queue),
+        }
+    }
+
+    /// Counters not recording anything
+    pub fn disabled() -> Self {
+        Counters {
+            pool: None,
+            queue: None,
+        }
+    }
+
+    /// Record the download of the entry in the background
+    pub fn record(&self, key: &str, tarball: &Tarball, region: Option<String>) {
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return,
+        };
+        let download = Download {
+            variant: key.split('.').next().unwrap_or_default().to_string(),
+            arch: tarball.arch.clone(),
+            date: tarball.date.clone(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default(),
+            region,
+        };
+        if let Err(e) = queue.try_send(download) {
+            log::warn!("Could not queue the download: {}", e);
+        }
+    }
+}
+
+/// Write the queued downloads until the counters are dropped
+async fn write_downloads(pool: SqlitePool, mut downloads: mpsc::Receiver<Download>) {
+    while let Some(download) = downloads.recv().await {
+        let result = sqlx::query!(
+            "INSERT INTO downloads (variant, arch, date, timestamp, region) VALUES (?, ?, ?, ?, ?)",
+            download.variant,
+            download.arch,
+            download.date,
+            download.timestamp,
+            download.region,
+        )
+        .execute(&pool)
+        .await;
+        if let Err(e) = result {
+            log::error!("Could not record the download: {}", e);
+        }
+    }
+}
+
+/// Number of the downloads since `since` (UNIX timestamp) of each variant, architecture and
+/// release date, and of each region
+async fn aggregate(pool: &SqlitePool, since: i64) -> Result<serde_json::Value> {
+    let images = sqlx::query!(
+        r#"SELECT variant, arch, date, COUNT(*) AS "count!: i64" FROM downloads
+        WHERE timestamp >= ? GROUP BY variant, arch, date ORDER BY variant, arch, date"#,
+        since
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        json!({
+            "variant": row.variant,
+            "arch": row.arch,
+            "date": row.date,
+            "count": row.count,
+        })
+    })
+    .collect::<Vec<_>>();
+    let regions = sqlx::query!(
+        r#"SELECT region, COUNT(*) AS "count!: i64" FROM downloads WHERE timestamp >= ?
+        GROUP BY region ORDER BY 2 DESC"#,
+        since
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| json!({ "region": row.region, "count": row.count }))
+    .collect::<Vec<_>>();
+
+    Ok(json!({ "images": images, "regions": regions }))
+}
+
+/// Start (UNIX timestamp) of the last days before the time, since the epoch if not given
+fn since(now: u64, days: Option<u64>) -> i64 {
+    match days {
+        Some(days) => now.saturating_sub(days.saturating_mul(24 * 3600)) as i64,
+        None => 0,
+    }
+}
+
+/// Download numbers of the images, e.g. `/api/v1/stats?days=30`
+#[get("/api/v1/stats")]
+async fn download_stats(
+    query: web::Query<StatsQuery>,
+    counters: web::Data<Counters>,
+) -> Result<HttpResponse, Error> {
+    let pool = match &counters.pool {
+        Some(pool) => pool,
+        None => return Ok(HttpResponse::NotFound().body("The download counters are disabled.")),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    match aggregate(pool, since(now, query.days)).await {
+        Ok(counted) => Ok(HttpResponse::Ok().json(counted)),
+        Err(e) => {
+            log::error!("Could not count the downloads: {}", e);
+            Ok(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_counters() {
+    // a single connection, each one would get its own database otherwise
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    migrate!().run(&pool).await.unwrap();
+    let counters = Counters::with_pool(pool.clone());
+    let tarball = Tarball::sample("amd64", "20240101", "");
+    for region in [Some("CN"), Some("CN"), None] {
+        counters.record("base.amd64", &tarball, region.map(|r| r.to_string()));
+    }
+    // recorded in the background
+    for _ in 0..100 {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM downloads")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        if count == 3 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let counted = aggregate(&pool, 0).await.unwrap();
+    assert_eq!(
+        counted["images"],
+        json!([{ "variant": "base", "arch": "amd64", "date": "20240101", "count": 3 }])
+    );
+    assert_eq!(counted["regions"][0], json!({ "region": "CN", "count": 2 }));
+    let now = 1_700_000_000;
+    assert_eq!(since(now, Some(1)), now as i64 - 86400);
+    assert_eq!(since(now, None), 0);
+    // way too many days count everything instead of overflowing
+    assert_eq!(since(now, Some(u64::MAX)), 0);
+}
@@ -0,0 +1,230 @@
+//! Guess the architecture of the visitor from the User-Agent and the client hints, for
+//! `/download/auto/{variant}`
+use actix_web::{get, http, web, Error, HttpRequest, HttpResponse};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+
+use crate::{not_found, parser::artifact_key, suggest, SharedDistMap};
+
+/// Client hints asked from the browsers supporting them
+const CLIENT_HINTS: &str = "Sec-CH-UA-Arch, Sec-CH-UA-Bitness";
+
+#[derive(TemplateOnce)]
+#[template(path = "chooser.html")]
+#[template(rm_whitespace = true)]
+struct ChooserPage {
+    variant: String,
+    /// Architectures of the variant and the links to their downloads
+    choices: Vec<(String, String)>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AutoQuery {
+    /// Type of the artifact, `tarball` for the variants in the recipe and `iso` for the others
+    /// by default
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    /// Redirect to the file instead of showing the thank-you page
+    #[serde(default)]
+    redirect: bool,
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name)?.to_str().ok()
+}
+
+/// Architecture from the `Sec-CH-UA-Arch` and `Sec-CH-UA-Bitness` hints, e.g. `"arm"` and `"64"`
+fn from_hints(arch: &str, bitness: Option<&str>) -> Option<&'static str> {
+    let arch = arch.trim().trim_matches('"').to_lowercase();
+    if bitness.map(|b| b.trim().trim_matches('"')) == Some("32") {
+        return None;
+    }
+    match arch.as_str() {
+        "x86" => Some("amd64"),
+        "arm" => Some("arm64"),
+        a if a.starts_with("loongarch") => Some("loongarch64"),
+        _ => None,
+    }
+}
+
+/// Architecture from the User-Agent, if exactly one of them is mentioned
+fn from_user_agent(ua: &str) -> Option<&'static str> {
+    const PATTERNS: &[(&str, &[&str])] = &[
+        (
+            "amd64",
+            &["x86_64", "x86-64", "amd64", "win64", "wow64", "x64"],
+        ),
+        ("arm64", &["aarch64", "arm64", "armv8"]),
+        ("loongarch64", &["loongarch64", "loong64"]),
+    ];
+    let ua = ua.to_lowercase();
+    let mut matched = PATTERNS
+        .iter()
+        .filter(|(_, patterns)| patterns.iter().any(|p| ua.contains(p)))
+        .map(|(arch, _)| *arch);
+    match (matched.next(), matched.next()) {
+        (Some(arch), None) => Some(arch),
+        _ => None,
+    }
+}
+
+/// The architecture of the visitor, `None` if ambiguous. The client hints are preferred when
+/// sent, as the User-Agent of the browsers on the ARM Macs and PCs often claims x86.
+pub fn guess_arch(req: &HttpRequest) -> Option<&'static str> {
+    if let Some(arch) = header(req, "Sec-CH-UA-Arch").filter(|a| !a.trim_matches('"').is_empty()) {
+        return from_hints(arch, header(req, "Sec-CH-UA-Bitness"));
+    }
+    let ua = header(req, http::header::USER_AGENT.as_str())?;
+    // Safari on the ARM Macs reports an Intel Mac
+    if ua.contains("Macintosh") {
+        return None;
+    }
+
+    from_user_agent(ua)
+}
+
+/// Download of the variant for the architecture of the visitor, e.g. `/download/auto/base`,
+/// or a page to choose one if it could not be guessed
+#[get("/download/auto/{variant}")]
+async fn auto(
+    req: HttpRequest,
+    variant: web::Path<String>,
+    query: web::Query<AutoQuery>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+) -> Result<HttpResponse, Error> {
+    let variant = variant.into_inner();
+    let prefix = format!("{}.", variant);
+    let type_ = match &query.type_ {
+        Some(type_) => type_.as_str(),
+        None if tarballs.0.iter().any(|i| i.key().starts_with(&prefix)) => "tarball",
+        None => "iso",
+    };
+    let (livekit, _) = artifact_key(&tarballs.0, type_, &variant, "");
+    let (manifest, map) = if livekit {
+        ("livekit", &tarballs.1)
+    } else {
+        ("alt", &tarballs.0)
+    };
+    let link = |arch: &str| {
+        let mut link = format!("/download/{}?variant={}&arch={}", manifest, variant, arch);
+        if query.type_.is_some() {
+            link.push_str(&format!("&type={}", type_));
+        }
+        if query.redirect {
+            link.push_str("&redirect=true");
+        }
+        link
+    };
+    let available =
+        |arch: &str| map.contains_key(&artifact_key(&tarballs.0, type_, &variant, arch).1);
+    if let Some(arch) = guess_arch(&req).filter(|a| available(a)) {
+        return Ok(HttpResponse::Found()
+            .append_header((http::header::LOCATION, link(arch)))
+            .append_header(("Accept-CH", CLIENT_HINTS))
+            .append_header((http::header::VARY, "User-Agent, Sec-CH-UA-Arch"))
+            .finish());
+    }
+    let arches = map
+        .iter()
+        .filter_map(|item| {
+            Some(
+                item.key()
+                    .strip_prefix(&prefix)?
+                    .split('.')
+                    .next()?
+                    .to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut choices = arches
+        .into_iter()
+        .filter(|arch| available(arch))
+        .map(|arch| {
+            let link = link(&arch);
+            (arch, link)
+        })
+        .collect::<Vec<_>>();
+    if choices.is_empty() {
+        let suggestions = suggest::suggest(map, manifest, &variant, "(?)");
+        return Ok(not_found(&req, &variant, "(?)", suggestions));
+    }
+    choices.sort();
+    choices.dedup();
+    let page = ChooserPage { variant, choices }
+        .render_once()
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .append_header((http::header::CONTENT_TYPE, "text/html; charset=utf-8"))
+        .append_header(("Accept-CH", CLIENT_HINTS))
+        .append_header((http::header::VARY, "User-Agent, Sec-CH-UA-Arch"))
+        .body(page))
+}
+
+#[test]
+fn test_guess_arch() {
+    use actix_web::test::TestRequest;
+
+    let guess = |headers: &[(&str, &str)]| {
+        let mut req = TestRequest::get();
+        for header in headers {
+            req = req.insert_header(*header);
+        }
+        guess_arch(&req.to_http_request())
+    };
+    let ua = "User-Agent";
+    assert_eq!(
+        guess(&[(
+            ua,
+            "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"
+        )]),
+        Some("amd64")
+    );
+    assert_eq!(
+        guess(&[(
+            ua,
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36"
+        )]),
+        Some("amd64")
+    );
+    assert_eq!(
+        guess(&[(
+            ua,
+            "Mozilla/5.0 (X11; Linux aarch64; rv:128.0) Gecko/20100101"
+        )]),
+        Some("arm64")
+    );
+    assert_eq!(
+        guess(&[(
+            ua,
+            "Mozilla/5.0 (X11; Linux loongarch64; rv:128.0) Gecko/20100101"
+        )]),
+        Some("loongarch64")
+    );
+    assert_eq!(
+        guess(&[(
+            ua,
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15"
+        )]),
+        None
+    );
+    assert_eq!(guess(&[(ua, "curl/8.9.1")]), None);
+    assert_eq!(
+        guess(&[
+            (
+                ua,
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36"
+            ),
+            ("Sec-CH-UA-Arch", "\"arm\""),
+            ("Sec-CH-UA-Bitness", "\"64\""),
+        ]),
+        Some("arm64")
+    );
+    assert_eq!(
+        guess(&[
+            ("Sec-CH-UA-Arch", "\"x86\""),
+            ("Sec-CH-UA-Bitness", "\"32\"")
+        ]),
+        None
+    );
+}
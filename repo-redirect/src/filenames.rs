@@ -0,0 +1,134 @@
+//! Friendly names of the downloaded files, passed to the mirrors as a query parameter
+//! (e.g. `?filename=` understood by the origin server to set `Content-Disposition`)
+use std::fmt::Write;
+
+use crate::parser::Tarball;
+
+const DEFAULT_TEMPLATE: &str = "AOSC-OS-{variant}-{date}-{arch}{ext}";
+/// Extensions of the tarballs and the images, kept whole in the names
+const EXTENSIONS: &[&str] = &[".tar.xz", ".iso", ".squashfs"];
+
+pub struct Filenames {
+    /// Name of the query parameter, the file names are left alone if not set
+    param: Option<String>,
+    template: String,
+}
+
+impl Filenames {
+    /// Load the settings from `DOWNLOAD_FILENAME_PARAM` and `DOWNLOAD_FILENAME_TEMPLATE`
+    pub fn from_env() -> Self {
+        Filenames {
+            param: std::env::var("DOWNLOAD_FILENAME_PARAM")
+                .ok()
+                .filter(|p| !p.is_empty()),
+            template: std::env::var("DOWNLOAD_FILENAME_TEMPLATE")
+                .unwrap_or_else(|_| DEFAULT_TEMPLATE.to_string()),
+        }
+    }
+
+    /// Friendly name of the file of the tarball
+    pub(crate) fn name(&self, variant: &str, tarball: &Tarball) -> String {
+        let file = tarball.path.rsplit('/').next().unwrap_or_default();
+        // keep compound extensions such as `.tar.xz`, the file names may have dots elsewhere
+        let ext = EXTENSIONS
+            .iter()
+            .find(|ext| file.ends_with(*ext))
+            .copied()
+            .or_else(|| file.rfind('.').map(|i| &file[i..]))
+            .unwrap_or_default();
+        let retro = if tarball.retro { "-retro" } else { "" };
+
+        self.template
+            .replace("{variant}", &format!("{}{}", variant, retro))
+            .replace("{date}", &tarball.date)
+            .replace("{arch}", &tarball.arch)
+            .replace("{ext}", ext)
+    }
+
+    /// Name the tarball is saved as when downloaded from the link
+    pub fn saved_as(&self, variant: &str, tarball: &Tarball) -> String {
+        match &self.param {
+            Some(_) => self.name(variant, tarball),
+            None => tarball
+                .path
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+
+    /// URL of the tarball on the mirror, with the friendly file name if enabled
+    pub fn link(&self, mirror: &str, variant: &str, tarball: &Tarball) -> String {
+        let url = format!("{}/{}", mirror, tarball.path);
+        match &self.param {
+            Some(param) => format!("{}?{}={}", url, param, encode(&self.name(variant, tarball))),
+            None => url,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Filenames {
+    /// Friendly names passed in the query parameter, with the default template
+    pub fn with_param(param: &str) -> Self {
+        Filenames {
+            param: Some(param.to_string()),
+            template: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+/// Percent-encode everything but the unreserved characters
+fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            write!(encoded, "%{:02X}", b).unwrap();
+        }
+    }
+
+    encoded
+}
+
+#[test]
+fn test_link() {
+    let tarball = Tarball::sample(
+        "amd64",
+        "20240101",
+        "os-amd64/base/aosc-os_base_20240101_amd64.tar.xz",
+    );
+    let mut filenames = Filenames {
+        param: None,
+        template: DEFAULT_TEMPLATE.to_string(),
+    };
+    let url = "https://example.com/os-amd64/base/aosc-os_base_20240101_amd64.tar.xz";
+    assert_eq!(filenames.link("https://example.com", "base", &tarball), url);
+    filenames.param = Some("filename".to_string());
+    assert_eq!(
+        filenames.link("https://example.com", "base", &tarball),
+        format!("{}?filename=AOSC-OS-base-20240101-amd64.tar.xz", url)
+    );
+    filenames.template = "AOSC OS {variant} ({arch}){ext}".to_string();
+    assert_eq!(
+        filenames.name("desktop", &tarball),
+        "AOSC OS desktop (amd64).tar.xz"
+    );
+    let image = Tarball::sample(
+        "amd64",
+        "20240101",
+        "livekit/aosc-os_livekit_v1.2_amd64.iso",
+    );
+    assert_eq!(
+        filenames.name("livekit", &image),
+        "AOSC OS livekit (amd64).iso"
+    );
+    let image = Tarball::sample("amd64", "20240101", "livekit/livekit.v1.2.img.zst");
+    assert_eq!(
+        filenames.name("livekit", &image),
+        "AOSC OS livekit (amd64).zst"
+    );
+    assert_eq!(encode("AOSC OS (amd64)"), "AOSC%20OS%20%28amd64%29");
+}
@@ -0,0 +1,165 @@
+// tonic::Status is large, but it is the error type of every RPC
+#![allow(clippy::result_large_err)]
+
+use std::{convert::TryFrom, net::SocketAddr, pin::Pin};
+
+use tokio::sync::watch;
+use tokio_stream::{wrappers::WatchStream, Stream, StreamExt};
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{parser::Tarball, SharedDistMap};
+
+pub mod proto {
+    tonic::include_proto!("aosc.repokit.v1");
+}
+
+use proto::{
+    manifests_server::{Manifests, ManifestsServer},
+    GetLatestMediaRequest, ListVariantsRequest, ListVariantsResponse, Manifest, Media,
+    WatchManifestRequest,
+};
+
+/// A manifest loaded in memory, along with the notifications of its reloads
+pub struct LoadedManifest {
+    pub map: SharedDistMap,
+    pub reloads: watch::Receiver<()>,
+}
+
+/// gRPC service for the internal consumers of the manifests
+pub struct ManifestService {
+    pub recipe: LoadedManifest,
+    pub livekit: LoadedManifest,
+}
+
+fn to_media(key: &str, tarball: &Tarball) -> Media {
+    Media {
+        key: key.to_string(),
+        arch: tarball.arch.clone(),
+        date: tarball.date.clone(),
+        path: tarball.path.clone(),
+        sha256sum: tarball.sha256sum.clone(),
+        retro: tarball.retro,
+    }
+}
+
+/// List the media in the map, sorted by their keys
+fn list_media(map: &SharedDistMap) -> ListVariantsResponse {
+    let mut media = map
+        .iter()
+        .map(|entry| to_media(entry.key(), entry.value()))
+        .collect::<Vec<_>>();
+    media.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+
+    ListVariantsResponse { media }
+}
+
+impl ManifestService {
+    fn manifest(&self, manifest: i32) -> Result<&LoadedManifest, Status> {
+        match Manifest::try_from(manifest) {
+            Ok(Manifest::Recipe) => Ok(&self.recipe),
+            Ok(Manifest::Livekit) => Ok(&self.livekit),
+            Err(_) => Err(Status::invalid_argument("unknown manifest")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Manifests for ManifestService {
+    async fn get_latest_media(
+        &self,
+        request: Request<GetLatestMediaRequest>,
+    ) -> Result<Response<Media>, Status> {
+        let request = request.into_inner();
+        let manifest = self.manifest(request.manifest)?;
+        let key = format!("{}.{}", request.variant, request.arch);
+        match manifest.map.get(&key) {
+            Some(tarball) => Ok(Response::new(to_media(&key, &tarball))),
+            None => Err(Status::not_found(format!("{} not found", key))),
+        }
+    }
+
+    async fn list_variants(
+        &self,
+        request: Request<ListVariantsRequest>,
+    ) -> Result<Response<ListVariantsResponse>, Status> {
+        let manifest = self.manifest(request.into_inner().manifest)?;
+
+        Ok(Response::new(list_media(&manifest.map)))
+    }
+
+    type WatchManifestStream =
+        Pin<Box<dyn Stream<Item = Result<ListVariantsResponse, Status>> + Send>>;
+
+    async fn watch_manifest(
+        &self,
+        request: Request<WatchManifestRequest>,
+    ) -> Result<Response<Self::WatchManifestStream>, Status> {
+        let manifest = self.manifest(request.into_inner().manifest)?;
+        let map = manifest.map.clone();
+        // yields the current state first, then once per reload
+        let stream = WatchStream::new(manifest.reloads.clone()).map(move |_| Ok(list_media(&map)));
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Serve the gRPC service on the given address
+pub async fn serve(addr: SocketAddr, service: ManifestService) -> anyhow::Result<()> {
+    Server::builder()
+        .add_service(ManifestsServer::new(service))
+        .serve(addr)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list_variants() {
+    use std::sync::Arc;
+
+    let tarball = |arch: &str| {
+        let path = format!("os-{}/base/aosc-os_base_20240101_{}.tar.xz", arch, arch);
+        Tarball::sample(arch, "20240101", &path)
+    };
+    let recipe: SharedDistMap = Arc::new(Default::default());
+    recipe.insert("base.arm64".to_string(), tarball("arm64"));
+    recipe.insert("base.amd64".to_string(), tarball("amd64"));
+    let (_, reloads) = watch::channel(());
+    let service = ManifestService {
+        recipe: LoadedManifest {
+            map: recipe,
+            reloads: reloads.clone(),
+        },
+        livekit: LoadedManifest {
+            map: Arc::new(Default::default()),
+            reloads,
+        },
+    };
+    let response = service
+        .list_variants(Request::new(ListVariantsRequest {
+            manifest: Manifest::Recipe as i32,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    let keys = response
+        .media
+        .iter()
+        .map(|m| m.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, vec!["base.amd64", "base.arm64"]);
+    let media = service
+        .get_latest_media(Request::new(GetLatestMediaRequest {
+            manifest: Manifest::Recipe as i32,
+            variant: "base".to_string(),
+            arch: "arm64".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(media.arch, "arm64");
+    assert!(service
+        .list_variants(Request::new(ListVariantsRequest { manifest: 5 }))
+        .await
+        .is_err());
+}
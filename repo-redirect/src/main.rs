@@ -1,13 +1,51 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    path::Path,
+    sync::{Arc, LazyLock},
+};
 
-use actix_web::{get, http, middleware, post, web, App, Error, HttpResponse, HttpServer};
+use actix_web::{
+    dev::Server, get, http, middleware, post, web, App, Error, HttpMessage, HttpRequest,
+    HttpResponse, HttpServer,
+};
 use dashmap::DashMap;
 use sailfish::TemplateOnce;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub type SharedDistMap = Arc<DashMap<String, parser::Tarball>>;
 
+mod access_log;
+mod admin;
+mod allowlist;
+mod api;
+mod bench;
+mod caching;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod cors;
+mod counters;
+mod detect;
+mod filenames;
+mod grpc;
+mod mirrors;
+mod overrides;
+mod pages;
 mod parser;
+mod releases;
+mod signatures;
+mod stats;
+mod suggest;
+mod timing;
+mod torrent;
+
+/// Services recording and resolving the downloads
+#[derive(Clone)]
+struct AppState {
+    stats: web::Data<stats::Stats>,
+    mirrors: web::Data<mirrors::Mirrors>,
+    filenames: web::Data<filenames::Filenames>,
+    counters: web::Data<counters::Counters>,
+    releases: web::Data<releases::Releases>,
+}
 
 #[derive(Deserialize, Debug)]
 struct DownloadRequest {
@@ -15,7 +53,7 @@ struct DownloadRequest {
     distro_variant: String,
 }
 
-#[derive(TemplateOnce)]
+#[derive(TemplateOnce, Serialize)]
 #[template(path = "thank-you.html")]
 #[template(rm_whitespace = true)]
 struct HelpContent {
@@ -23,147 +61,902 @@ struct HelpContent {
     arch: String,
     url: String,
     sha256: String,
+    /// Name of the file, for the `sha256sum -c` snippet
+    filename: String,
+    signatures: Vec<signatures::Signature>,
 }
 
-#[derive(TemplateOnce)]
+#[derive(TemplateOnce, Serialize)]
 #[template(path = "404.html")]
 #[template(rm_whitespace = true)]
 struct NotFoundPage {
     variant: String,
     arch: String,
+    suggestions: Vec<suggest::Suggestion>,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "metalink.meta4")]
+#[template(rm_whitespace = true)]
+struct Metalink {
+    name: String,
+    size: u64,
+    sha256: String,
+    urls: Vec<String>,
+}
+
+#[inline]
+fn client_address(req: &HttpRequest) -> String {
+    req.connection_info()
+        .realip_remote_addr()
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+#[derive(Deserialize, Debug)]
+struct DownloadQuery {
+    variant: Option<String>,
+    arch: Option<String>,
+    /// Type of the artifact: `tarball` (the default of `/download/alt`), `iso` (the default of
+    /// `/download/livekit`), `squashfs`, ...
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    /// Redirect to the file instead of showing the thank-you page
+    #[serde(default)]
+    redirect: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct LatestQuery {
+    /// Type of the artifact, `tarball` for the variants in the recipe and `iso` for the others
+    /// by default
+    #[serde(rename = "type")]
+    type_: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PinnedQuery {
+    /// Type of the artifact, `iso` if the LiveKit manifest lists the release and `tarball`
+    /// otherwise by default
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    /// Redirect to the file instead of showing the thank-you page
+    #[serde(default)]
+    redirect: bool,
+}
+
+/// Download page of the website, `DOWNLOADS_PAGE` if set
+static DOWNLOADS_PAGE: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("DOWNLOADS_PAGE")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| "https://aosc.io/downloads/".to_string())
+});
+
+#[inline]
+fn downloads_page() -> HttpResponse {
+    HttpResponse::Found()
+        .append_header((http::header::LOCATION, DOWNLOADS_PAGE.as_str()))
+        .finish()
+}
+
+/// Whether the client prefers JSON to the pages, e.g. `Accept: application/json`
+fn wants_json(req: &HttpRequest) -> bool {
+    use http::header::Header;
+
+    http::header::Accept::parse(req)
+        .is_ok_and(|accept| accept.preference().essence_str() == "application/json")
+}
+
+fn not_found(
+    req: &HttpRequest,
+    variant: &str,
+    arch: &str,
+    suggestions: Vec<suggest::Suggestion>,
+) -> HttpResponse {
+    if wants_json(req) {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("{} is not available on {}", variant, arch),
+            "suggestions": suggestions,
+        }));
+    }
+    HttpResponse::NotFound()
+        .append_header((http::header::CONTENT_TYPE, "text/html"))
+        .body(
+            overrides::render(
+                "404.html",
+                NotFoundPage {
+                    variant: variant.to_string(),
+                    arch: arch.to_string(),
+                    suggestions,
+                },
+            )
+            .unwrap_or_else(|| "Not Found".to_string()),
+        )
+}
+
+/// Respond with the thank-you page of the entry (or its metadata, if the client prefers JSON),
+/// or a redirect to its file
+fn download(
+    req: &HttpRequest,
+    key: &str,
+    tarball: Option<&parser::Tarball>,
+    livekit: bool,
+    redirect: bool,
+    state: &AppState,
+) -> Option<HttpResponse> {
+    let tarball = tarball?;
+    let json = wants_json(req);
+    // the same file in the same representation, whichever mirror is picked
+    let validators = if redirect {
+        None
+    } else {
+        let representation = if json { "json" } else { "html" };
+        caching::Validators::of(
+            &[livekit],
+            &format!("{}@{}.{}", key, tarball.sha256sum, representation),
+        )
+    };
+    if let Some(validators) = validators.as_ref().filter(|v| v.fresh(req)) {
+        return Some(validators.not_modified());
+    }
+    state.stats.record(&client_address(req), key);
+    let region = state.mirrors.region(req);
+    state.counters.record(key, tarball, region.clone());
+    let variant = key.split('.').next().unwrap_or_default();
+    let mirror = state.mirrors.select(variant, region.as_deref());
+    let url = state.filenames.link(&mirror.url, variant, tarball);
+    if redirect {
+        return Some(
+            HttpResponse::Found()
+                .append_header((http::header::LOCATION, url))
+                .append_header((mirrors::MIRROR_HEADER, mirror.name))
+                .finish(),
+        );
+    }
+    let mut builder = HttpResponse::Ok();
+    builder
+        .append_header((http::header::VARY, "Accept"))
+        .append_header((mirrors::MIRROR_HEADER, mirror.name.clone()));
+    if let Some(validators) = &validators {
+        validators.apply(&mut builder);
+    }
+    if json {
+        let mut info = serde_json::to_value(api::tarball_info(key, tarball)).unwrap_or_default();
+        info["url"] = url.into();
+        info["mirror"] = mirror.name.into();
+        return Some(builder.json(info));
+    }
+    let shown_variant = if !livekit {
+        variant.to_string()
+    } else if tarball.retro {
+        "Livekit (Retro)".to_string()
+    } else {
+        "Livekit".to_string()
+    };
+    let signatures = signatures::find(&state.releases, &mirror.url, &tarball.path);
+    // the origin server may save the file under its friendly name
+    let filename = state.filenames.saved_as(variant, tarball);
+    let help_content = overrides::render(
+        "thank-you.html",
+        HelpContent {
+            variant: shown_variant,
+            arch: tarball.arch.clone(),
+            sha256: tarball.sha256sum.clone(),
+            url: url.clone(),
+            filename,
+            signatures,
+        },
+    )
+    .unwrap_or(url);
+
+    Some(
+        builder
+            .append_header((http::header::CONTENT_TYPE, "text/html"))
+            .body(help_content),
+    )
 }
 
 #[post("/download/alt")]
 async fn download_distribution(
+    req: HttpRequest,
     params: web::Form<DownloadRequest>,
     tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
+    req.extensions_mut()
+        .insert(timing::RequestedEntry(params.distro_variant.clone()));
     if params.distro_variant.starts_with("https://") {
+        if !allowlist::allowed(&params.distro_variant, &state.mirrors) {
+            log::warn!("Refused to redirect to {}", params.distro_variant);
+            return Ok(HttpResponse::BadRequest().body("The URL is not allowed."));
+        }
         return Ok(HttpResponse::Found()
             .append_header((http::header::LOCATION, params.distro_variant.clone()))
             .finish());
     }
-    let mut splitted = params.distro_variant.split('.');
-    let variant_name = splitted.next().unwrap_or("(?)");
-    if let Some(tarball) = tarballs.0.get(&params.distro_variant) {
-        let url = format!("https://releases.aosc.io/{}", tarball.path);
-        let help_content = HelpContent {
-            variant: variant_name.to_string(),
-            arch: tarball.arch.clone(),
-            sha256: tarball.sha256sum.clone(),
-            url: url.clone(),
-        }
-        .render_once()
-        .unwrap_or_else(|_| url.clone());
-
-        Ok(HttpResponse::Ok()
-            .append_header((http::header::CONTENT_TYPE, "text/html"))
-            .body(help_content))
-    } else {
+    let key = &params.distro_variant;
+    Ok(download(
+        &req,
+        key,
+        tarballs.0.get(key).as_deref(),
+        false,
+        false,
+        &state,
+    )
+    .unwrap_or_else(|| {
+        let mut splitted = key.split('.');
+        let variant = splitted.next().unwrap_or("(?)");
         let arch = splitted.next().unwrap_or("(?)");
-        Ok(HttpResponse::NotFound()
-            .append_header((http::header::CONTENT_TYPE, "text/html"))
-            .body(
-                NotFoundPage {
-                    variant: variant_name.to_string(),
-                    arch: arch.to_string(),
-                }
-                .render_once()
-                .unwrap_or_else(|_| "Not Found".to_string()),
-            ))
-    }
+        let suggestions = suggest::suggest(&tarballs.0, "alt", variant, arch);
+        not_found(&req, variant, arch, suggestions)
+    }))
 }
 
 #[post("/download/livekit")]
 async fn download_livekit(
+    req: HttpRequest,
     params: web::Form<DownloadRequest>,
     tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    if let Some(tarball) = tarballs.1.get(&params.distro_variant) {
-        let url = format!("https://releases.aosc.io/{}", tarball.path);
-        let help_content = HelpContent {
-            variant: "Livekit".to_string(),
-            arch: tarball.arch.clone(),
-            sha256: tarball.sha256sum.clone(),
-            url: url.clone(),
-        }
-        .render_once()
-        .unwrap_or_else(|_| url.clone());
+    // the download form of the website only sends the architecture of the classic LiveKit
+    let key = if params.distro_variant.contains('.') {
+        params.distro_variant.clone()
+    } else {
+        format!("livekit.{}", params.distro_variant)
+    };
+    req.extensions_mut()
+        .insert(timing::RequestedEntry(key.clone()));
 
-        Ok(HttpResponse::Ok()
-            .append_header((http::header::CONTENT_TYPE, "text/html"))
-            .body(help_content))
+    Ok(download(
+        &req,
+        &key,
+        tarballs.1.get(&key).as_deref(),
+        true,
+        false,
+        &state,
+    )
+    .unwrap_or_else(|| {
+        let (variant, arch) = key.split_once('.').unwrap_or_default();
+        let suggestions = suggest::suggest(&tarballs.1, "livekit", variant, arch);
+        not_found(&req, "Livekit", &params.distro_variant, suggestions)
+    }))
+}
+
+/// Download (or 404 page) of the artifact of the type, looked up in the manifest listing it
+fn link_artifact(
+    req: &HttpRequest,
+    variant: &str,
+    arch: &str,
+    type_: &str,
+    redirect: bool,
+    tarballs: &(SharedDistMap, SharedDistMap),
+    state: &AppState,
+) -> HttpResponse {
+    let (livekit, key) = parser::artifact_key(&tarballs.0, type_, variant, arch);
+    req.extensions_mut()
+        .insert(timing::RequestedEntry(key.clone()));
+    let (manifest, map) = if livekit {
+        ("livekit", &tarballs.1)
     } else {
-        Ok(HttpResponse::NotFound()
-            .append_header((http::header::CONTENT_TYPE, "text/html"))
-            .body(
-                NotFoundPage {
-                    variant: "Livekit".to_string(),
-                    arch: params.distro_variant.clone(),
-                }
-                .render_once()
-                .unwrap_or_else(|_| "Not Found".to_string()),
-            ))
-    }
+        ("alt", &tarballs.0)
+    };
+
+    download(
+        req,
+        &key,
+        map.get(&key).as_deref(),
+        livekit,
+        redirect,
+        state,
+    )
+    .unwrap_or_else(|| {
+        let suggestions = suggest::suggest(map, manifest, variant, arch);
+        let shown_variant = if livekit && variant == "livekit" {
+            "Livekit"
+        } else {
+            variant
+        };
+        not_found(req, shown_variant, arch, suggestions)
+    })
 }
 
+/// Linkable variant of the form, e.g. `/download/alt?variant=base&arch=amd64` (or
+/// `&type=squashfs` for the images), sending the visitors without the parameters to the
+/// download page of the website
 #[get("/download/alt")]
-async fn fallback_distribution() -> Result<HttpResponse, Error> {
-    Ok(HttpResponse::Found()
-        .append_header((http::header::LOCATION, "https://aosc.io/downloads/"))
-        .finish())
+async fn link_distribution(
+    req: HttpRequest,
+    params: web::Query<DownloadQuery>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (variant, arch) = match (&params.variant, &params.arch) {
+        (Some(variant), Some(arch)) => (variant, arch),
+        _ => return Ok(downloads_page()),
+    };
+
+    Ok(link_artifact(
+        &req,
+        variant,
+        arch,
+        params.type_.as_deref().unwrap_or("tarball"),
+        params.redirect,
+        &tarballs,
+        &state,
+    ))
 }
 
+/// Linkable variant of the form, e.g. `/download/livekit?arch=amd64` (the variant defaults
+/// to `livekit` and the type to `iso`)
 #[get("/download/livekit")]
-async fn fallback_livekit() -> Result<HttpResponse, Error> {
+async fn link_livekit(
+    req: HttpRequest,
+    params: web::Query<DownloadQuery>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let arch = match &params.arch {
+        Some(arch) => arch,
+        None => return Ok(downloads_page()),
+    };
+
+    Ok(link_artifact(
+        &req,
+        params.variant.as_deref().unwrap_or("livekit"),
+        arch,
+        params.type_.as_deref().unwrap_or("iso"),
+        params.redirect,
+        &tarballs,
+        &state,
+    ))
+}
+
+/// Permanent link to the newest release of the variant on the architecture, e.g.
+/// `/latest/base/amd64`, redirecting to its file as the manifests are reloaded
+#[get("/latest/{variant}/{arch}")]
+async fn latest(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<LatestQuery>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (variant, arch) = path.into_inner();
+    let type_ = match &query.type_ {
+        Some(type_) => type_.as_str(),
+        None if tarballs.0.contains_key(&format!("{}.{}", variant, arch)) => "tarball",
+        None => "iso",
+    };
+
+    Ok(link_artifact(
+        &req, &variant, &arch, type_, true, &tarballs, &state,
+    ))
+}
+
+/// Permalink to a release of the variant on the architecture, e.g.
+/// `/download/base/amd64/20240101`, as long as the manifest still lists it
+#[get("/download/{variant}/{arch}/{date}")]
+async fn pinned(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    query: web::Query<PinnedQuery>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    histories: web::Data<(parser::SharedHistory, parser::SharedHistory)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (variant, arch, date) = path.into_inner();
+    let type_ = match &query.type_ {
+        Some(type_) => type_.as_str(),
+        None if parser::release(&histories.1, &format!("{}.{}", variant, arch), &date)
+            .is_some() =>
+        {
+            "iso"
+        }
+        None => "tarball",
+    };
+    let (livekit, key) = parser::artifact_key(&tarballs.0, type_, &variant, &arch);
+    req.extensions_mut()
+        .insert(timing::RequestedEntry(key.clone()));
+
+    Ok(download(
+        &req,
+        &key,
+        parser::release(
+            if livekit { &histories.1 } else { &histories.0 },
+            &key,
+            &date,
+        )
+        .as_ref(),
+        livekit,
+        query.redirect,
+        &state,
+    )
+    .unwrap_or_else(|| {
+        // the latest releases instead
+        let (manifest, map) = if livekit {
+            ("livekit", &tarballs.1)
+        } else {
+            ("alt", &tarballs.0)
+        };
+        let suggestions = suggest::suggest(map, manifest, &variant, &arch);
+        not_found(&req, &variant, &format!("{} ({})", arch, date), suggestions)
+    }))
+}
+
+/// Redirect to the zsync control file of the entry on a mirror, e.g.
+/// `/download/zsync/base.amd64`, for the delta downloads of the new releases. The control
+/// files are published next to the files, e.g. by `zsyncmake` after the tarballs are built.
+#[get("/download/zsync/{key}")]
+async fn zsync(
+    req: HttpRequest,
+    key: web::Path<String>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    mirrors: web::Data<mirrors::Mirrors>,
+    releases: web::Data<releases::Releases>,
+) -> Result<HttpResponse, Error> {
+    let key = key.into_inner();
+    req.extensions_mut()
+        .insert(timing::RequestedEntry(key.clone()));
+    let path = match tarballs.0.get(&key).or_else(|| tarballs.1.get(&key)) {
+        Some(tarball) => format!("{}.zsync", tarball.path),
+        None => return Ok(HttpResponse::NotFound().body(format!("{} is not available.", key))),
+    };
+    if !releases.file(&path).exists() {
+        return Ok(HttpResponse::NotFound().body(format!("No zsync control file of {}.", key)));
+    }
+    let variant = key.split('.').next().unwrap_or_default();
+    let mirror = mirrors.select(variant, mirrors.region(&req).as_deref());
+
     Ok(HttpResponse::Found()
-        .append_header((http::header::LOCATION, "https://aosc.io/downloads/"))
+        .append_header((http::header::LOCATION, format!("{}/{}", mirror.url, path)))
+        .append_header((mirrors::MIRROR_HEADER, mirror.name))
         .finish())
 }
 
+/// Metalink (`.meta4`) or torrent (`.torrent`) of the entry with all the mirrors, e.g.
+/// `/download/alt/base.amd64.meta4`, for the download managers fetching from multiple mirrors
+#[get("/download/{manifest}/{file}")]
+async fn download_file(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    state: web::Data<AppState>,
+    torrents: web::Data<torrent::Torrents>,
+) -> Result<HttpResponse, Error> {
+    let (manifest, file) = path.into_inner();
+    let map = match manifest.as_str() {
+        "alt" => &tarballs.0,
+        "livekit" => &tarballs.1,
+        _ => return Ok(HttpResponse::NotFound().body("Not Found")),
+    };
+    let (key, torrent) = match (file.strip_suffix(".meta4"), file.strip_suffix(".torrent")) {
+        (Some(key), _) => (key, false),
+        (_, Some(key)) => (key, true),
+        _ => return Ok(HttpResponse::NotFound().body("Not Found")),
+    };
+    req.extensions_mut()
+        .insert(timing::RequestedEntry(key.to_string()));
+    let tarball = match map.get(key) {
+        Some(tarball) => tarball.clone(),
+        None => return Ok(HttpResponse::NotFound().body(format!("{} is not available.", key))),
+    };
+    if torrent {
+        // generated in the background once the manifest is loaded
+        return match torrents.read(&tarball, &state.mirrors).await {
+            Some(torrent) => {
+                state.stats.record(&client_address(&req), key);
+                state
+                    .counters
+                    .record(key, &tarball, state.mirrors.region(&req));
+                Ok(HttpResponse::Ok()
+                    .append_header((http::header::CONTENT_TYPE, "application/x-bittorrent"))
+                    .body(torrent))
+            }
+            None => Ok(HttpResponse::NotFound().body(format!("No torrent of {} yet.", key))),
+        };
+    }
+    state.stats.record(&client_address(&req), key);
+    let region = state.mirrors.region(&req);
+    state.counters.record(key, &tarball, region.clone());
+    let variant = key.split('.').next().unwrap_or_default();
+    let urls = state
+        .mirrors
+        .candidates(region.as_deref())
+        .iter()
+        .map(|mirror| state.filenames.link(&mirror.url, variant, &tarball))
+        .collect();
+    let page = Metalink {
+        name: tarball
+            .path
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        size: tarball.download_size,
+        sha256: tarball.sha256sum.clone(),
+        urls,
+    }
+    .render_once()
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .append_header((http::header::CONTENT_TYPE, "application/metalink4+xml"))
+        .body(page))
+}
+
+#[get("/metrics")]
+async fn metrics(
+    stats: web::Data<stats::Stats>,
+    timings: web::Data<timing::Timings>,
+    mirrors: web::Data<mirrors::Mirrors>,
+) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok()
+        .append_header((http::header::CONTENT_TYPE, "text/plain; version=0.0.4"))
+        .body(stats.render_metrics() + &timings.render_metrics() + &mirrors.render_metrics()))
+}
+
+/// Serve the manifests on the listener
+fn serve(
+    listener: tokio::net::TcpListener,
+    maps: (SharedDistMap, SharedDistMap),
+    histories: (parser::SharedHistory, parser::SharedHistory),
+    state: AppState,
+    timings: web::Data<timing::Timings>,
+    torrents: web::Data<torrent::Torrents>,
+) -> std::io::Result<Server> {
+    Ok(HttpServer::new(move || {
+        let app = App::new()
+            .wrap(middleware::from_fn(cors::cors))
+            .wrap(middleware::from_fn(timing::trace_requests))
+            .wrap(middleware::Condition::new(
+                !*access_log::JSON,
+                middleware::Logger::default(),
+            ))
+            .wrap(middleware::Condition::new(
+                *access_log::JSON,
+                middleware::from_fn(access_log::log_requests),
+            ))
+            .app_data(web::Data::new(maps.clone()))
+            .app_data(web::Data::new(histories.clone()))
+            .app_data(web::Data::new(state.clone()))
+            .app_data(state.stats.clone())
+            .app_data(timings.clone())
+            .app_data(state.mirrors.clone())
+            .app_data(torrents.clone())
+            .app_data(state.counters.clone())
+            .app_data(state.releases.clone())
+            .service(download_distribution)
+            .service(download_livekit)
+            .service(link_distribution)
+            .service(link_livekit)
+            .service(latest)
+            .service(metrics)
+            // before `download_file`, which would take `/download/zsync/...` otherwise
+            .service(zsync)
+            .service(detect::auto)
+            .service(download_file)
+            .service(api::distro)
+            .service(api::list)
+            .service(counters::download_stats)
+            .service(admin::reload)
+            .service(pages::picker)
+            .service(pages::plain)
+            .service(pages::plain_download)
+            // after `pages::plain_download`, which takes `/download/plain/...`
+            .service(pinned);
+        #[cfg(feature = "chaos")]
+        let app = app.service(chaos::get_chaos).service(chaos::put_chaos);
+        app
+    })
+    .listen(listener.into_std()?)?
+    .run())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
+    if std::env::args().any(|arg| arg == "--bench") {
+        return bench::run().await.map_err(std::io::Error::other);
+    }
 
     let listen = std::env::var("LISTEN_ADDRESS").expect("LISTEN_ADDRESS not set");
     let manifest_path = std::env::var("MANIFEST_PATH").expect("MANIFEST_PATH not set");
     let manifest_path = Path::new(&manifest_path);
 
+    let stats = web::Data::new(stats::Stats::from_env());
+    let timings = web::Data::new(timing::Timings::from_env());
+    let mirrors = web::Data::new(mirrors::Mirrors::from_env().map_err(std::io::Error::other)?);
+    let filenames = web::Data::new(filenames::Filenames::from_env());
+    let releases = releases::Releases::from_env(manifest_path);
+    let torrents = web::Data::new(torrent::Torrents::from_env(releases.clone()));
+    let counters = web::Data::new(
+        counters::Counters::from_env()
+            .await
+            .map_err(std::io::Error::other)?,
+    );
     let shared_map = Arc::new(DashMap::new());
     let shared_map_lk = Arc::new(DashMap::new());
-    let monitor_worker =
-        parser::monitor_recipe(manifest_path.join("recipe.json"), Arc::clone(&shared_map));
+    let history = parser::SharedHistory::default();
+    let history_lk = parser::SharedHistory::default();
+    let (reloaded, reloads) = tokio::sync::watch::channel(());
+    let (reloaded_lk, reloads_lk) = tokio::sync::watch::channel(());
+    let monitor_worker = parser::monitor_recipe(
+        manifest_path.join("recipe.json"),
+        Arc::clone(&shared_map),
+        Arc::clone(&history),
+        reloaded,
+        mirrors.clone(),
+    );
     let monitor_worker_lk = parser::monitor_livekit(
         manifest_path.join("livekit.json"),
         Arc::clone(&shared_map_lk),
+        Arc::clone(&history_lk),
+        reloaded_lk,
     );
+    // the gRPC service for the internal consumers is optional
+    let grpc_listen = match std::env::var("GRPC_LISTEN_ADDRESS") {
+        Ok(addr) => Some(
+            addr.parse()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        ),
+        Err(_) => None,
+    };
+    let torrent_worker = torrent::monitor(
+        torrents.clone(),
+        (Arc::clone(&shared_map), Arc::clone(&shared_map_lk)),
+        reloads.clone(),
+        reloads_lk.clone(),
+        mirrors.clone(),
+    );
+    let grpc_service = grpc::ManifestService {
+        recipe: grpc::LoadedManifest {
+            map: Arc::clone(&shared_map),
+            reloads,
+        },
+        livekit: grpc::LoadedManifest {
+            map: Arc::clone(&shared_map_lk),
+            reloads: reloads_lk,
+        },
+    };
+    let prune_worker = stats::prune_stats(stats.clone());
+    let probation_worker = mirrors::monitor_probation(mirrors.clone());
+    let health_worker = mirrors::monitor_health(mirrors.clone());
+    let sighup_worker = admin::reload_on_sighup();
 
-    let server = HttpServer::new(move || {
-        App::new()
-            .wrap(middleware::Logger::default())
-            .app_data(web::Data::new((shared_map.clone(), shared_map_lk.clone())))
-            .service(download_distribution)
-            .service(download_livekit)
-            .service(fallback_distribution)
-            .service(fallback_livekit)
-    })
-    .bind(listen)?
-    .run();
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    let state = AppState {
+        stats,
+        mirrors,
+        filenames,
+        counters,
+        releases: web::Data::new(releases),
+    };
+    let server = serve(
+        listener,
+        (shared_map, shared_map_lk),
+        (history, history_lk),
+        state,
+        timings,
+        torrents,
+    )?;
 
     let res = tokio::select! {
         v = server => v,
         v = async {
             monitor_worker
                 .await
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+                .map_err(std::io::Error::other)
         } => v,
         v = async {
             monitor_worker_lk
                 .await
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+                .map_err(std::io::Error::other)
+        } => v,
+        v = async {
+            prune_worker
+                .await
+                .map_err(std::io::Error::other)
+        } => v,
+        v = async {
+            probation_worker
+                .await
+                .map_err(std::io::Error::other)
+        } => v,
+        v = async {
+            health_worker
+                .await
+                .map_err(std::io::Error::other)
+        } => v,
+        v = async {
+            torrent_worker
+                .await
+                .map_err(std::io::Error::other)
+        } => v,
+        v = async {
+            sighup_worker
+                .await
+                .map_err(std::io::Error::other)
+        } => v,
+        v = async {
+            match grpc_listen {
+                Some(addr) => grpc::serve(addr, grpc_service)
+                    .await
+                    .map_err(std::io::Error::other),
+                None => std::future::pending().await,
+            }
         } => v
     };
     res?;
 
     Ok(())
 }
+
+#[actix_web::test]
+async fn test_download_links() {
+    use actix_web::test;
+
+    let tarball = parser::Tarball::sample(
+        "amd64",
+        "20240101",
+        "os-amd64/base/aosc-os_base_20240101_amd64.tar.xz",
+    );
+    let recipe: SharedDistMap = Arc::new(DashMap::new());
+    recipe.insert("base.amd64".to_string(), tarball.clone());
+    let livekit: SharedDistMap = Arc::new(DashMap::new());
+    livekit.insert("livekit.amd64".to_string(), tarball.clone());
+    livekit.insert("livekit.amd64.squashfs".to_string(), tarball);
+    let history = parser::SharedHistory::default();
+    history.write().unwrap().insert(
+        "base.amd64@20231201".to_string(),
+        parser::Tarball::sample(
+            "amd64",
+            "20231201",
+            "os-amd64/base/aosc-os_base_20231201_amd64.tar.xz",
+        ),
+    );
+    let mirrors = web::Data::new(mirrors::Mirrors::from_env().unwrap());
+    let releases = releases::Releases::from_env(Path::new("."));
+    let init = |filenames| {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new((recipe.clone(), livekit.clone())))
+                .app_data(web::Data::new((
+                    history.clone(),
+                    parser::SharedHistory::default(),
+                )))
+                .app_data(web::Data::new(AppState {
+                    stats: web::Data::new(stats::Stats::from_env()),
+                    mirrors: mirrors.clone(),
+                    filenames: web::Data::new(filenames),
+                    counters: web::Data::new(counters::Counters::disabled()),
+                    releases: web::Data::new(releases.clone()),
+                }))
+                .app_data(mirrors.clone())
+                .app_data(web::Data::new(releases.clone()))
+                .app_data(web::Data::new(torrent::Torrents::from_env(
+                    releases.clone(),
+                )))
+                .service(link_distribution)
+                .service(link_livekit)
+                .service(latest)
+                .service(zsync)
+                .service(detect::auto)
+                .service(download_file)
+                .service(pinned),
+        )
+    };
+    let app = init(filenames::Filenames::from_env()).await;
+    let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+    let resp = test::call_service(&app, get("/download/alt?variant=base&arch=amd64")).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains("aosc-os_base_20240101_amd64.tar.xz"));
+    assert!(page.contains(r#"echo "00  aosc-os_base_20240101_amd64.tar.xz" | sha256sum -c"#));
+
+    let resp = test::call_service(&app, get("/download/livekit?arch=amd64&redirect=true")).await;
+    assert_eq!(resp.status(), http::StatusCode::FOUND);
+    assert_eq!(
+        resp.headers().get(mirrors::MIRROR_HEADER).unwrap(),
+        "origin"
+    );
+    let location = resp.headers().get(http::header::LOCATION).unwrap();
+    assert!(location
+        .to_str()
+        .unwrap()
+        .ends_with("/os-amd64/base/aosc-os_base_20240101_amd64.tar.xz"));
+
+    let resp = test::call_service(
+        &app,
+        get("/download/alt?variant=livekit&arch=amd64&type=squashfs&redirect=true"),
+    )
+    .await;
+    assert_eq!(resp.status(), http::StatusCode::FOUND);
+    let resp = test::call_service(&app, get("/latest/livekit/amd64?type=squashfs")).await;
+    assert_eq!(resp.status(), http::StatusCode::FOUND);
+    let resp = test::call_service(&app, get("/latest/base/amd64")).await;
+    assert!(resp
+        .headers()
+        .get(http::header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .ends_with("/aosc-os_base_20240101_amd64.tar.xz"));
+    let resp = test::call_service(&app, get("/download/base/amd64/20231201?redirect=true")).await;
+    assert!(resp
+        .headers()
+        .get(http::header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .ends_with("/aosc-os_base_20231201_amd64.tar.xz"));
+    let resp = test::call_service(&app, get("/download/base/amd64/19700101")).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains(r#"<a href="/download/alt?variant=base&amp;arch=amd64">"#));
+    let resp = test::call_service(&app, get("/download/livekit?arch=amd64&type=tarball")).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/download/alt?variant=base&arch=amd64")
+            .insert_header((http::header::ACCEPT, "application/json"))
+            .to_request(),
+    )
+    .await;
+    let info: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(info["sha256sum"], "00");
+    assert_eq!(info["mirror"], "origin");
+
+    let resp = test::call_service(&app, get("/download/alt?variant=desktop&arch=amd64")).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let resp = test::call_service(&app, get("/download/alt?variant=bsae&arch=amd64")).await;
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains(r#"<a href="/download/alt?variant=base&amp;arch=amd64">"#));
+    let resp = test::call_service(&app, get("/download/alt/base.amd64.meta4")).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains(r#"<file name="aosc-os_base_20240101_amd64.tar.xz">"#));
+    assert!(page.contains(r#"<hash type="sha-256">00</hash>"#));
+    assert!(page.contains(r#"<url priority="1">https://"#));
+
+    // no control file was published
+    let resp = test::call_service(&app, get("/download/zsync/base.amd64")).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let body = test::read_body(resp).await;
+    assert_eq!(body, "No zsync control file of base.amd64.");
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/download/auto/base")
+            .insert_header((http::header::USER_AGENT, "Mozilla/5.0 (X11; Linux x86_64)"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(
+        resp.headers().get(http::header::LOCATION).unwrap(),
+        "/download/alt?variant=base&arch=amd64"
+    );
+    let resp = test::call_service(&app, get("/download/auto/base")).await;
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains(r#"<a href="/download/alt?variant=base&amp;arch=amd64">amd64</a>"#));
+
+    let resp = test::call_service(&app, get("/download/alt")).await;
+    assert_eq!(
+        resp.headers().get(http::header::LOCATION).unwrap(),
+        DOWNLOADS_PAGE.as_str()
+    );
+
+    // the checksum is checked against the friendly name the file is saved as
+    let app = init(filenames::Filenames::with_param("filename")).await;
+    let resp = test::call_service(&app, get("/download/alt?variant=base&arch=amd64")).await;
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains(
+        "aosc-os_base_20240101_amd64.tar.xz?filename=AOSC-OS-base-20240101-amd64.tar.xz"
+    ));
+    assert!(page.contains(r#"echo "00  AOSC-OS-base-20240101-amd64.tar.xz" | sha256sum -c"#));
+}
@@ -0,0 +1,819 @@
+use actix_web::{http::header::HttpDate, HttpRequest};
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::parser::ManifestMirror;
+
+const ORIGIN: &str = "https://releases.aosc.io";
+const DEFAULT_WINDOW: u64 = 60;
+/// Response header naming the mirror the client was sent to
+pub const MIRROR_HEADER: &str = "X-Mirror";
+/// How often the mirrors on probation are reloaded
+const PROBATION_RELOAD: Duration = Duration::from_secs(60);
+/// Country codes (as in `CF-IPCountry`) of the locations of the mirrors listed in the recipe
+const LOCATIONS: &[(&str, &str)] = &[
+    ("China", "CN"),
+    ("Hong Kong", "HK"),
+    ("Taiwan", "TW"),
+    ("Japan", "JP"),
+    ("South Korea", "KR"),
+    ("Singapore", "SG"),
+    ("United States", "US"),
+    ("Germany", "DE"),
+    ("France", "FR"),
+    ("Netherlands", "NL"),
+    ("United Kingdom", "GB"),
+];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Mirror {
+    pub name: String,
+    /// Base URL of the releases on the mirror
+    pub url: String,
+    /// Maximum number of redirects to the mirror within the window
+    pub cap: usize,
+    /// Region of the mirror, matched against the region of the clients (see `region_header`)
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Share of the redirects among the mirrors available in the region of the client
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Periodic checks of the mirrors, taking the failing ones out of the selection
+#[derive(Deserialize, Debug, Clone)]
+pub struct HealthCheck {
+    /// Path of the probe file relative to the base URL of the mirrors, e.g. `manifest/recipe.json`
+    probe: String,
+    #[serde(default = "default_interval")]
+    interval_secs: u64,
+    #[serde(default = "default_timeout")]
+    timeout_secs: u64,
+    /// Consecutive failed checks before the mirror is taken out of the selection
+    #[serde(default = "default_failures")]
+    failures: usize,
+    /// A mirror whose probe file was modified this many seconds before the one on the origin
+    /// server is considered stale
+    #[serde(default)]
+    max_lag_secs: Option<u64>,
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+fn default_failures() -> usize {
+    2
+}
+
+/// Fair-queuing configuration (the JSON file pointed to by `MIRRORS_CONFIG`)
+#[derive(Deserialize, Debug)]
+struct MirrorsConfig {
+    /// Length of the window (in seconds) the utilization of the mirrors is estimated over
+    #[serde(default)]
+    window_secs: Option<u64>,
+    /// The origin server, the first choice when multiple mirrors are equally busy
+    origin: Mirror,
+    #[serde(default)]
+    mirrors: Vec<Mirror>,
+    /// Maximum number of redirects of a single variant to each mirror within the window
+    #[serde(default)]
+    variant_cap: Option<usize>,
+    #[serde(default)]
+    health: Option<HealthCheck>,
+    /// Use the mirrors listed in the recipe as well, each one with this cap
+    #[serde(default)]
+    manifest_cap: Option<usize>,
+    /// Regions of the locations of the mirrors in the recipe not known as countries, e.g.
+    /// `{"Europe": "NL"}`
+    #[serde(default)]
+    manifest_regions: HashMap<String, String>,
+    /// Request header carrying the region of the client (e.g. `CF-IPCountry`), the clients are
+    /// sent to the mirrors in their regions by weighted round-robin
+    #[serde(default)]
+    region_header: Option<String>,
+}
+
+struct Target {
+    mirror: Mirror,
+    /// Recent redirects to the mirror and their variants
+    recent: VecDeque<(Instant, String)>,
+    /// Whether the mirror comes from the recipe, replaced when it is reloaded
+    listed: bool,
+    /// Current weight of the smooth weighted round-robin in the region
+    current_weight: i64,
+}
+
+impl Target {
+    fn utilization(&self) -> f64 {
+        self.recent.len() as f64 / self.mirror.cap.max(1) as f64
+    }
+
+    fn variant_count(&self, variant: &str) -> usize {
+        self.recent.iter().filter(|(_, v)| v == variant).count()
+    }
+}
+
+/// Spreads the downloads across the mirrors by their recent redirect counts
+pub struct Mirrors {
+    targets: Mutex<Vec<Target>>,
+    window: Duration,
+    variant_cap: Option<usize>,
+    /// Mirrors reported by the users (through repo-notifier) and when their probation ends
+    /// (UNIX timestamp), they are only used when every other mirror is saturated
+    probation: Mutex<HashMap<String, u64>>,
+    health: Option<HealthCheck>,
+    /// Consecutive failed health checks of the mirrors
+    failures: Mutex<HashMap<String, usize>>,
+    manifest_cap: Option<usize>,
+    /// The mirrors in the recipe are only offered in the Metalinks and the torrents, the
+    /// redirects never go to them
+    listed_only_for_seeds: bool,
+    manifest_regions: HashMap<String, String>,
+    region_header: Option<String>,
+}
+
+/// Pick one of the candidates by smooth weighted round-robin
+fn weighted_pick(targets: &mut [Target], candidates: &[usize]) -> usize {
+    let mut total = 0;
+    for &i in candidates {
+        let weight = targets[i].mirror.weight as i64;
+        targets[i].current_weight += weight;
+        total += weight;
+    }
+    // the first one of the equally weighted mirrors wins
+    let picked = candidates
+        .iter()
+        .copied()
+        .max_by_key(|&i| (targets[i].current_weight, std::cmp::Reverse(i)))
+        .unwrap_or(0);
+    targets[picked].current_weight -= total;
+
+    picked
+}
+
+#[inline]
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl Mirrors {
+    fn new(
+        origin: Mirror,
+        mirrors: Vec<Mirror>,
+        window: Duration,
+        variant_cap: Option<usize>,
+    ) -> Self {
+        let targets = std::iter::once(origin)
+            .chain(mirrors)
+            .map(|mut mirror| {
+                mirror.url = mirror.url.trim_end_matches('/').to_string();
+                Target {
+                    mirror,
+                    recent: VecDeque::new(),
+                    listed: false,
+                    current_weight: 0,
+                }
+            })
+            .collect();
+
+        Mirrors {
+            targets: Mutex::new(targets),
+            window,
+            variant_cap,
+            probation: Mutex::new(HashMap::new()),
+            health: None,
+            failures: Mutex::new(HashMap::new()),
+            manifest_cap: None,
+            listed_only_for_seeds: false,
+            manifest_regions: HashMap::new(),
+            region_header: None,
+        }
+    }
+
+    /// Region of a mirror in the recipe from its location, e.g. `CN` for `China`
+    fn region_of(&self, loc: &str) -> Option<String> {
+        if let Some(region) = self.manifest_regions.get(loc) {
+            return Some(region.clone());
+        }
+        if loc.len() == 2 && loc.bytes().all(|c| c.is_ascii_alphabetic()) {
+            return Some(loc.to_ascii_uppercase());
+        }
+
+        LOCATIONS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(loc))
+            .map(|(_, code)| code.to_string())
+    }
+
+    /// Region of the client, from the header in `region_header`
+    pub fn region(&self, req: &HttpRequest) -> Option<String> {
+        req.headers()
+            .get(self.region_header.as_deref()?)?
+            .to_str()
+            .ok()
+            .map(|r| r.trim().to_string())
+            .filter(|r| !r.is_empty())
+    }
+
+    /// Replace the mirrors from the recipe with the ones it lists now (unless `MIRRORS_CONFIG`
+    /// leaves out `manifest_cap`), keeping the recent redirects of the ones still listed
+    pub fn update_from_manifest(&self, listed: &[ManifestMirror]) {
+        let cap = match self.manifest_cap {
+            Some(cap) => cap,
+            None => return,
+        };
+        let mut targets = self.targets.lock().unwrap();
+        let mut previous = HashMap::new();
+        targets.retain_mut(|t| {
+            if t.listed {
+                previous.insert(t.mirror.name.clone(), std::mem::take(&mut t.recent));
+            }
+            !t.listed
+        });
+        for mirror in listed {
+            let url = mirror.url.trim_end_matches('/');
+            let name = mirror.short_name();
+            // the configured mirrors take precedence
+            if targets
+                .iter()
+                .any(|t| t.mirror.url == url || t.mirror.name == name)
+            {
+                continue;
+            }
+            targets.push(Target {
+                mirror: Mirror {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                    cap,
+                    region: self.region_of(&mirror.loc),
+                    weight: default_weight(),
+                },
+                recent: previous.remove(name).unwrap_or_default(),
+                listed: true,
+                current_weight: 0,
+            });
+        }
+    }
+
+    /// Replace the mirrors on probation with the ones in the file written by repo-notifier
+    pub fn load_probation(&self, path: &str) -> Result<()> {
+        let probation: HashMap<String, u64> = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| anyhow!("Could not parse {}: {}", path, e))?;
+        *self.probation.lock().unwrap() = probation;
+
+        Ok(())
+    }
+
+    /// Names of the mirrors which failed the last health checks
+    fn down(&self) -> Vec<String> {
+        let threshold = self.health.as_ref().map_or(1, |h| h.failures.max(1));
+        self.failures
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, failures)| **failures >= threshold)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Record the result of the health check of the mirror
+    fn record_check(&self, name: &str, healthy: bool) {
+        let threshold = self.health.as_ref().map_or(1, |h| h.failures.max(1));
+        let mut failures = self.failures.lock().unwrap();
+        let count = failures.entry(name.to_string()).or_default();
+        if healthy {
+            if *count >= threshold {
+                log::info!("Mirror {} is back", name);
+            }
+            *count = 0;
+        } else {
+            *count += 1;
+            if *count == threshold {
+                log::warn!("Mirror {} failed {} checks, not using it", name, threshold);
+            }
+        }
+    }
+
+    /// Names of the mirrors whose probation has not ended
+    fn on_probation(&self) -> Vec<String> {
+        let now = unix_now();
+        self.probation
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Load the configuration in `MIRRORS_CONFIG`, all the downloads go to the origin
+    /// server if it is not set. `RELEASES_BASE_URL` replaces the URL of the origin server,
+    /// e.g. for the staging environments.
+    pub fn from_env() -> Result<Self> {
+        let base_url = std::env::var("RELEASES_BASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty());
+        let path = match std::env::var("MIRRORS_CONFIG") {
+            Ok(path) => path,
+            Err(_) => {
+                let origin = Mirror {
+                    name: "origin".to_string(),
+                    url: base_url.unwrap_or_else(|| ORIGIN.to_string()),
+                    cap: usize::MAX,
+                    region: None,
+                    weight: default_weight(),
+                };
+                // the origin server stays the first choice, the mirrors in the recipe are
+                // offered in the Metalinks and the torrents
+                return Ok(Mirrors {
+                    manifest_cap: Some(usize::MAX),
+                    listed_only_for_seeds: true,
+                    ..Mirrors::new(
+                        origin,
+                        Vec::new(),
+                        Duration::from_secs(DEFAULT_WINDOW),
+                        None,
+                    )
+                });
+            }
+        };
+        let mut config: MirrorsConfig = serde_json::from_slice(&std::fs::read(&path)?)
+            .map_err(|e| anyhow!("Could not parse {}: {}", path, e))?;
+        if let Some(url) = base_url {
+            config.origin.url = url;
+        }
+
+        Ok(Mirrors {
+            health: config.health,
+            manifest_cap: config.manifest_cap,
+            manifest_regions: config.manifest_regions,
+            region_header: config.region_header,
+            ..Mirrors::new(
+                config.origin,
+                config.mirrors,
+                Duration::from_secs(config.window_secs.unwrap_or(DEFAULT_WINDOW)),
+                config.variant_cap,
+            )
+        })
+    }
+
+    /// Pick the mirror to download the variant from: the least utilized mirror below its cap
+    /// (and the variant below its quota on that mirror), healthy and not on probation. The
+    /// available mirrors in the region of the client take turns by their weights. When every
+    /// mirror is saturated, the least utilized healthy one is still used, preferring the ones
+    /// not on probation. The failing mirrors are only used when all of them are failing.
+    pub fn select(&self, variant: &str, region: Option<&str>) -> Mirror {
+        let probation = self.on_probation();
+        let down = self.down();
+        let on_probation = |t: &Target| probation.contains(&t.mirror.name);
+        let selectable = |t: &Target| !(self.listed_only_for_seeds && t.listed);
+        let healthy = |t: &Target| !down.contains(&t.mirror.name);
+        let now = Instant::now();
+        let mut targets = self.targets.lock().unwrap();
+        for target in targets.iter_mut() {
+            while target
+                .recent
+                .front()
+                .is_some_and(|(t, _)| now.duration_since(*t) >= self.window)
+            {
+                target.recent.pop_front();
+            }
+        }
+        let available = |t: &Target| {
+            selectable(t)
+                && healthy(t)
+                && !on_probation(t)
+                && t.recent.len() < t.mirror.cap
+                && self
+                    .variant_cap
+                    .is_none_or(|cap| t.variant_count(variant) < cap)
+        };
+        let least_utilized = |candidates: &mut dyn Iterator<Item = (usize, &Target)>| {
+            // `min_by` keeps the first one of the equally utilized mirrors
+            candidates
+                .min_by(|a, b| a.1.utilization().total_cmp(&b.1.utilization()))
+                .map(|(i, _)| i)
+        };
+        let regional = targets
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| {
+                available(t)
+                    && region.is_some_and(|r| {
+                        t.mirror
+                            .region
+                            .as_deref()
+                            .is_some_and(|m| m.eq_ignore_ascii_case(r))
+                    })
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let index = if !regional.is_empty() {
+            weighted_pick(&mut targets, &regional)
+        } else {
+            least_utilized(&mut targets.iter().enumerate().filter(|(_, t)| available(t)))
+                .or_else(|| {
+                    least_utilized(
+                        &mut targets
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, t)| selectable(t) && healthy(t) && !on_probation(t)),
+                    )
+                })
+                .or_else(|| {
+                    least_utilized(
+                        &mut targets
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, t)| selectable(t) && healthy(t)),
+                    )
+                })
+                .or_else(|| {
+                    least_utilized(&mut targets.iter().enumerate().filter(|(_, t)| selectable(t)))
+                })
+                .unwrap_or(0)
+        };
+        let target = &mut targets[index];
+        target.recent.push_back((now, variant.to_string()));
+
+        target.mirror.clone()
+    }
+
+    /// Whether one of the mirrors (or the origin server) is on the host
+    pub fn serves_host(&self, host: &str) -> bool {
+        let targets = self.targets.lock().unwrap();
+        targets.iter().any(|t| {
+            t.mirror
+                .url
+                .parse::<actix_web::http::Uri>()
+                .ok()
+                .and_then(|uri| uri.host().map(|h| h.eq_ignore_ascii_case(host)))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Every mirror and the origin server, including the ones on probation or down
+    pub fn all(&self) -> Vec<Mirror> {
+        let targets = self.targets.lock().unwrap();
+
+        targets.iter().map(|t| t.mirror.clone()).collect()
+    }
+
+    /// All the mirrors to offer for a download, e.g. in a Metalink: the healthy ones not on
+    /// probation, starting with the ones in the region of the client
+    pub fn candidates(&self, region: Option<&str>) -> Vec<Mirror> {
+        let probation = self.on_probation();
+        let down = self.down();
+        let targets = self.targets.lock().unwrap();
+        let mut candidates = targets
+            .iter()
+            .filter(|t| !probation.contains(&t.mirror.name) && !down.contains(&t.mirror.name))
+            .map(|t| t.mirror.clone())
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            candidates = targets.iter().map(|t| t.mirror.clone()).collect();
+        }
+        // stable, so the configured order is kept otherwise
+        candidates.sort_by_key(|m| {
+            !region.is_some_and(|r| {
+                m.region
+                    .as_deref()
+                    .is_some_and(|m| m.eq_ignore_ascii_case(r))
+            })
+        });
+
+        candidates
+    }
+
+    /// Render the recent redirect counts and the mirrors on probation in the Prometheus
+    /// text format
+    pub fn render_metrics(&self) -> String {
+        let probation = self.on_probation();
+        let targets = self.targets.lock().unwrap();
+        let mut output = String::new();
+        output += "# TYPE repo_redirect_mirror_recent_redirects gauge\n";
+        for target in targets.iter() {
+            writeln!(
+                output,
+                "repo_redirect_mirror_recent_redirects{{mirror=\"{}\"}} {}",
+                target.mirror.name,
+                target.recent.len()
+            )
+            .ok();
+        }
+        output += "# TYPE repo_redirect_mirror_probation gauge\n";
+        for target in targets.iter() {
+            writeln!(
+                output,
+                "repo_redirect_mirror_probation{{mirror=\"{}\"}} {}",
+                target.mirror.name,
+                probation.contains(&target.mirror.name) as u8
+            )
+            .ok();
+        }
+        let down = self.down();
+        output += "# TYPE repo_redirect_mirror_up gauge\n";
+        for target in targets.iter() {
+            writeln!(
+                output,
+                "repo_redirect_mirror_up{{mirror=\"{}\"}} {}",
+                target.mirror.name,
+                !down.contains(&target.mirror.name) as u8
+            )
+            .ok();
+        }
+
+        output
+    }
+}
+
+/// Whether the probe file on the mirror was modified more than `max_lag` before the one on
+/// the origin server
+fn lagging(origin: Option<SystemTime>, mirror: Option<SystemTime>, max_lag: Duration) -> bool {
+    match (origin, mirror) {
+        (Some(origin), Some(mirror)) => {
+            origin.duration_since(mirror).is_ok_and(|lag| lag > max_lag)
+        }
+        _ => false,
+    }
+}
+
+/// Request the probe file on the mirror, returning its modification time if the mirror is up
+async fn probe(client: &reqwest::Client, url: &str) -> Result<Option<SystemTime>> {
+    let resp = client.head(url).send().await?.error_for_status()?;
+    let modified = resp
+        .headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<HttpDate>().ok())
+        .map(SystemTime::from);
+
+    Ok(modified)
+}
+
+/// Periodically check the probe file on each mirror, taking the ones which are down or stale
+/// out of the selection (configured in `health` of `MIRRORS_CONFIG`)
+pub async fn monitor_health(mirrors: actix_web::web::Data<Mirrors>) -> Result<()> {
+    let health = match &mirrors.health {
+        Some(health) => health.clone(),
+        None => return std::future::pending().await,
+    };
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(health.timeout_secs))
+        .build()?;
+    let mut interval = tokio::time::interval(Duration::from_secs(health.interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let targets = mirrors
+            .targets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|t| t.mirror.clone())
+            .collect::<Vec<_>>();
+        let results = join_all(targets.iter().map(|mirror| {
+            let url = format!("{}/{}", mirror.url, health.probe.trim_start_matches('/'));
+            let client = &client;
+            async move { probe(client, &url).await }
+        }))
+        .await;
+        // the first target is the origin server
+        let origin = results
+            .first()
+            .and_then(|r| r.as_ref().ok().copied().flatten());
+        for (mirror, result) in targets.iter().zip(results) {
+            let healthy = match result {
+                Ok(modified) => !health
+                    .max_lag_secs
+                    .is_some_and(|lag| lagging(origin, modified, Duration::from_secs(lag))),
+                Err(e) => {
+                    log::debug!("Health check of {} failed: {}", mirror.name, e);
+                    false
+                }
+            };
+            mirrors.record_check(&mirror.name, healthy);
+        }
+    }
+}
+
+/// Periodically reload the mirrors on probation from the file in `MIRROR_PROBATION`
+pub async fn monitor_probation(mirrors: actix_web::web::Data<Mirrors>) -> Result<()> {
+    let path = match std::env::var("MIRROR_PROBATION") {
+        Ok(path) => path,
+        Err(_) => return std::future::pending().await,
+    };
+    let mut interval = tokio::time::interval(PROBATION_RELOAD);
+    loop {
+        interval.tick().await;
+        // the file only exists once a mirror was reported
+        if std::path::Path::new(&path).exists() {
+            if let Err(e) = mirrors.load_probation(&path) {
+                log::warn!("{}", e);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_select_mirror() {
+    let mirror = |name: &str, cap| Mirror {
+        name: name.to_string(),
+        url: format!("https://{}.example.com/", name),
+        cap,
+        region: None,
+        weight: 1,
+    };
+    let window = Duration::from_secs(60);
+    let mirrors = Mirrors::new(
+        mirror("origin", 4),
+        vec![mirror("a", 2), mirror("b", 2)],
+        window,
+        None,
+    );
+    let picks = (0..8)
+        .map(|_| mirrors.select("base", None).url)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        picks,
+        vec![
+            "https://origin.example.com",
+            "https://a.example.com",
+            "https://b.example.com",
+            "https://origin.example.com",
+            "https://origin.example.com",
+            "https://a.example.com",
+            "https://b.example.com",
+            "https://origin.example.com",
+        ]
+    );
+    // everything is saturated, the least utilized one is still used
+    assert_eq!(
+        mirrors.select("base", None).url,
+        "https://origin.example.com"
+    );
+    assert!(mirrors
+        .render_metrics()
+        .contains("repo_redirect_mirror_recent_redirects{mirror=\"a\"} 2\n"));
+
+    // the variant quota keeps a single variant from taking over the biggest mirror
+    let mirrors = Mirrors::new(
+        mirror("origin", 10),
+        vec![mirror("a", 1000)],
+        window,
+        Some(2),
+    );
+    let picks = (0..4)
+        .map(|_| mirrors.select("base", None).url)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        picks,
+        vec![
+            "https://origin.example.com",
+            "https://a.example.com",
+            "https://a.example.com",
+            "https://origin.example.com",
+        ]
+    );
+    assert_eq!(mirrors.select("desktop", None).url, "https://a.example.com");
+
+    // the mirrors on probation are only used when the others are saturated
+    let mirrors = Mirrors::new(
+        mirror("origin", 1),
+        vec![mirror("a", 10), mirror("b", 10)],
+        window,
+        None,
+    );
+    mirrors.probation.lock().unwrap().extend([
+        ("a".to_string(), unix_now() + 3600),
+        ("b".to_string(), unix_now() - 1),
+    ]);
+    let picks = (0..3)
+        .map(|_| mirrors.select("base", None).url)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        picks,
+        vec![
+            "https://origin.example.com",
+            "https://b.example.com",
+            "https://b.example.com",
+        ]
+    );
+    assert!(mirrors
+        .render_metrics()
+        .contains("repo_redirect_mirror_probation{mirror=\"a\"} 1\n"));
+
+    // the failing mirrors are left out after the configured number of failed checks
+    let mirrors = Mirrors::new(mirror("origin", 10), vec![mirror("a", 10)], window, None);
+    mirrors.record_check("origin", false);
+    assert_eq!(mirrors.select("base", None).url, "https://a.example.com");
+    assert_eq!(mirrors.select("base", None).url, "https://a.example.com");
+    assert!(mirrors
+        .render_metrics()
+        .contains("repo_redirect_mirror_up{mirror=\"origin\"} 0\n"));
+    mirrors.record_check("origin", true);
+    assert_eq!(
+        mirrors.select("base", None).url,
+        "https://origin.example.com"
+    );
+    let now = SystemTime::now();
+    let hour = Duration::from_secs(3600);
+    assert!(lagging(Some(now), Some(now - 2 * hour), hour));
+    assert!(!lagging(Some(now), Some(now - hour / 2), hour));
+    assert!(!lagging(None, Some(now - 2 * hour), hour));
+
+    // the mirrors in the recipe are added after the configured ones
+    let mirrors = Mirrors {
+        manifest_cap: Some(1),
+        ..Mirrors::new(mirror("origin", 1), vec![mirror("a", 1)], window, None)
+    };
+    let listed = |name: &str| ManifestMirror {
+        name: name.to_uppercase(),
+        name_tr: format!("{}-name", name),
+        loc: "China".to_string(),
+        url: format!("https://{}.example.com", name),
+    };
+    mirrors.update_from_manifest(&[listed("a"), listed("tuna")]);
+    let picks = (0..3)
+        .map(|_| mirrors.select("base", None).url)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        picks,
+        vec![
+            "https://origin.example.com",
+            "https://a.example.com",
+            "https://tuna.example.com",
+        ]
+    );
+    mirrors.update_from_manifest(&[]);
+    assert!(!mirrors.render_metrics().contains("tuna"));
+    // the location is matched against the country of the client
+    let mirrors = Mirrors {
+        manifest_cap: Some(100),
+        manifest_regions: HashMap::from([("Europe".to_string(), "NL".to_string())]),
+        ..Mirrors::new(mirror("origin", 100), vec![], window, None)
+    };
+    let nluug = ManifestMirror {
+        loc: "Europe".to_string(),
+        ..listed("nluug")
+    };
+    mirrors.update_from_manifest(&[listed("tuna"), nluug]);
+    assert_eq!(mirrors.select("base", Some("CN")).name, "tuna");
+    assert_eq!(mirrors.select("base", Some("NL")).name, "nluug");
+    assert_eq!(mirrors.select("base", Some("JP")).name, "origin");
+    // without `MIRRORS_CONFIG` the mirrors in the recipe are only offered for the seeds
+    let mirrors = Mirrors {
+        manifest_cap: Some(usize::MAX),
+        listed_only_for_seeds: true,
+        ..Mirrors::new(mirror("origin", usize::MAX), vec![], window, None)
+    };
+    mirrors.update_from_manifest(&[listed("tuna"), listed("nluug")]);
+    assert_eq!(mirrors.select("base", None).name, "origin");
+    assert_eq!(mirrors.select("base", Some("CN")).name, "origin");
+    assert_eq!(mirrors.candidates(Some("CN")).len(), 3);
+
+    // the mirrors in the region of the client take turns by their weights
+    let regional = |name: &str, weight| Mirror {
+        region: Some("CN".to_string()),
+        weight,
+        ..mirror(name, 100)
+    };
+    let mirrors = Mirrors::new(
+        mirror("origin", 100),
+        vec![regional("a", 2), regional("b", 1)],
+        window,
+        None,
+    );
+    let picks = (0..4)
+        .map(|_| mirrors.select("base", Some("cn")).name)
+        .collect::<Vec<_>>();
+    assert_eq!(picks, vec!["a", "b", "a", "a"]);
+    assert_eq!(mirrors.select("base", Some("JP")).name, "origin");
+    let names = |region| {
+        mirrors
+            .candidates(region)
+            .into_iter()
+            .map(|m| m.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(Some("CN")), vec!["a", "b", "origin"]);
+    assert_eq!(names(None), vec!["origin", "a", "b"]);
+}
@@ -0,0 +1,80 @@
+//! Pages loaded at runtime from `TEMPLATE_DIR` (as Handlebars templates, e.g. `thank-you.html`
+//! using `{{variant}}`, `{{arch}}`, `{{url}}`, `{{sha256}}`, `{{filename}}` and `{{signatures}}`),
+//! replacing the built-in ones
+use handlebars::Handlebars;
+use sailfish::TemplateOnce;
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+/// Names of the pages which may be replaced
+const PAGES: &[&str] = &["thank-you.html", "404.html"];
+
+static TEMPLATE_DIR: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
+    std::env::var("TEMPLATE_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+});
+
+static REGISTRY: LazyLock<Handlebars<'static>> = LazyLock::new(Handlebars::new);
+
+/// Render the page with the template in `TEMPLATE_DIR` if there is one, otherwise with the
+/// built-in one
+pub fn render<T: TemplateOnce + Serialize>(name: &str, page: T) -> Option<String> {
+    render_with(TEMPLATE_DIR.as_deref(), name, page)
+}
+
+/// The template is looked up on each render, so it can be added, changed or removed without
+/// restarting
+fn render_with<T: TemplateOnce + Serialize>(
+    dir: Option<&Path>,
+    name: &str,
+    page: T,
+) -> Option<String> {
+    let path = dir
+        .filter(|_| PAGES.contains(&name))
+        .map(|dir| dir.join(name))
+        .filter(|path| path.exists());
+    if let Some(path) = path {
+        match std::fs::read_to_string(&path) {
+            Ok(template) => match REGISTRY.render_template(&template, &page) {
+                Ok(rendered) => return Some(rendered),
+                Err(e) => log::error!("Could not render {}: {}", path.display(), e),
+            },
+            Err(e) => log::error!("Could not load {}: {}", path.display(), e),
+        }
+    }
+
+    page.render_once().ok()
+}
+
+#[test]
+fn test_overrides() {
+    #[derive(TemplateOnce, Serialize)]
+    #[template(path = "404.html")]
+    struct Page {
+        variant: String,
+        arch: String,
+        suggestions: Vec<crate::suggest::Suggestion>,
+    }
+
+    let page = || Page {
+        variant: "<base>".to_string(),
+        arch: "amd64".to_string(),
+        suggestions: Vec::new(),
+    };
+    let dir = std::env::temp_dir().join(format!("repo-redirect-templates-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    assert!(render_with(Some(&dir), "404.html", page())
+        .unwrap()
+        .contains("&lt;base&gt;, amd64"));
+
+    // picked up without reloading
+    std::fs::write(dir.join("404.html"), "{{variant}} is not on {{arch}}").unwrap();
+    let rendered = render_with(Some(&dir), "404.html", page()).unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(rendered, "&lt;base&gt; is not on amd64");
+}
@@ -0,0 +1,167 @@
+//! Download pages working without JavaScript: a variant picker posting plain forms and a
+//! text-only listing for terminal browsers
+use actix_web::{get, http, web, Error, HttpMessage, HttpRequest, HttpResponse};
+use sailfish::TemplateOnce;
+use std::collections::BTreeMap;
+
+use crate::{client_address, mirrors, timing, AppState, SharedDistMap};
+
+/// An entry of the manifests as listed on the pages
+pub struct Entry {
+    pub key: String,
+    pub arch: String,
+    pub date: String,
+    pub sha256: String,
+    pub retro: bool,
+}
+
+/// Entries of a variant, sorted by their architectures
+pub type Group = (String, Vec<Entry>);
+
+#[derive(TemplateOnce)]
+#[template(path = "picker.html")]
+#[template(rm_whitespace = true)]
+struct PickerPage {
+    recipe: Vec<Group>,
+    livekit: Vec<Group>,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "plain.html")]
+#[template(rm_whitespace = true)]
+struct PlainPage {
+    recipe: Vec<Group>,
+    livekit: Vec<Group>,
+}
+
+/// Group the entries of the manifest (keyed by `<variant>.<arch>`) by their variants
+pub fn group_entries(map: &SharedDistMap) -> Vec<Group> {
+    let mut groups: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+    for item in map.iter() {
+        let variant = item.key().split('.').next().unwrap_or_default();
+        let tarball = item.value();
+        groups.entry(variant.to_string()).or_default().push(Entry {
+            key: item.key().clone(),
+            arch: tarball.arch.clone(),
+            date: tarball.date.clone(),
+            sha256: tarball.sha256sum.clone(),
+            retro: tarball.retro,
+        });
+    }
+    for entries in groups.values_mut() {
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+    }
+
+    groups.into_iter().collect()
+}
+
+#[inline]
+fn html(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .append_header((http::header::CONTENT_TYPE, "text/html; charset=utf-8"))
+        .body(body)
+}
+
+#[get("/download")]
+async fn picker(
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+) -> Result<HttpResponse, Error> {
+    let page = PickerPage {
+        recipe: group_entries(&tarballs.0),
+        livekit: group_entries(&tarballs.1),
+    }
+    .render_once()
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(html(page))
+}
+
+#[get("/download/plain")]
+async fn plain(tarballs: web::Data<(SharedDistMap, SharedDistMap)>) -> Result<HttpResponse, Error> {
+    let page = PlainPage {
+        recipe: group_entries(&tarballs.0),
+        livekit: group_entries(&tarballs.1),
+    }
+    .render_once()
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(html(page))
+}
+
+/// Redirect to the file of the entry, for the links on the text-only page
+#[get("/download/plain/{manifest}/{key}")]
+async fn plain_download(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    tarballs: web::Data<(SharedDistMap, SharedDistMap)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (manifest, key) = path.into_inner();
+    req.extensions_mut()
+        .insert(timing::RequestedEntry(key.clone()));
+    let map = match manifest.as_str() {
+        "alt" => &tarballs.0,
+        "livekit" => &tarballs.1,
+        _ => return Ok(HttpResponse::NotFound().body("Not Found")),
+    };
+    match map.get(&key) {
+        Some(tarball) => {
+            state.stats.record(&client_address(&req), &key);
+            let region = state.mirrors.region(&req);
+            state.counters.record(&key, &tarball, region.clone());
+            let variant = key.split('.').next().unwrap_or_default();
+            let mirror = state.mirrors.select(variant, region.as_deref());
+            let url = state.filenames.link(&mirror.url, variant, &tarball);
+
+            Ok(HttpResponse::Found()
+                .append_header((http::header::LOCATION, url))
+                .append_header((mirrors::MIRROR_HEADER, mirror.name))
+                .finish())
+        }
+        None => Ok(HttpResponse::NotFound().body(format!("{} is not available.", key))),
+    }
+}
+
+#[test]
+fn test_pages() {
+    use crate::parser::Tarball;
+    use dashmap::DashMap;
+    use std::sync::Arc;
+
+    let tarball = |arch: &str| {
+        let path = format!("os-{}/base/aosc-os_base_20240101_{}.tar.xz", arch, arch);
+        Tarball::sample(arch, "20240101", &path)
+    };
+    let map: SharedDistMap = Arc::new(DashMap::new());
+    map.insert("desktop.amd64".to_string(), tarball("amd64"));
+    map.insert("base.arm64".to_string(), tarball("arm64"));
+    map.insert("base.amd64".to_string(), tarball("amd64"));
+    let groups = group_entries(&map);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].0, "base");
+    assert_eq!(
+        groups[0]
+            .1
+            .iter()
+            .map(|e| e.key.as_str())
+            .collect::<Vec<_>>(),
+        vec!["base.amd64", "base.arm64"]
+    );
+
+    let page = PickerPage {
+        recipe: group_entries(&map),
+        livekit: Vec::new(),
+    }
+    .render_once()
+    .unwrap();
+    assert!(page.contains(r#"<form method="post" action="/download/alt">"#));
+    assert!(page.contains(r#"<option value="base.arm64">"#));
+    assert!(!page.contains("<script"));
+    let page = PlainPage {
+        recipe: group_entries(&map),
+        livekit: Vec::new(),
+    }
+    .render_once()
+    .unwrap();
+    assert!(page.contains(r#"<a href="/download/plain/alt/base.amd64">"#));
+}
@@ -6,13 +6,73 @@ use serde::Deserialize;
 use std::collections::HashMap;
 use std::future::Future;
 use std::path::Path;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, LazyLock, RwLock,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
+use tokio::sync::watch;
 use tokio::task::spawn_blocking;
 
-use crate::SharedDistMap;
+use crate::{mirrors::Mirrors, SharedDistMap};
 
 type TarballMap = HashMap<String, Tarball>;
+/// Every release listed in a manifest, keyed by `<key>@<date>` with the keys of the map of the
+/// latest releases
+pub type History = HashMap<String, Tarball>;
+pub type SharedHistory = Arc<RwLock<History>>;
+
+/// Number of manifests being reloaded
+static RELOADING: AtomicUsize = AtomicUsize::new(0);
+
+/// Forced reloads of the manifests, for the file systems without inotify events (e.g. NFS)
+static FORCED: LazyLock<watch::Sender<()>> = LazyLock::new(|| watch::channel(()).0);
+
+/// Parse the manifests again, even if they were not reported as changed
+pub fn force_reload() {
+    FORCED.send_replace(());
+}
+
+/// Modification times (UNIX timestamps) of the recipe and the LiveKit manifest as loaded
+static RECIPE_MODIFIED: AtomicU64 = AtomicU64::new(0);
+static LIVEKIT_MODIFIED: AtomicU64 = AtomicU64::new(0);
+
+/// Modification time of the recipe (or the LiveKit manifest) when it was last loaded
+pub fn modified(livekit: bool) -> Option<SystemTime> {
+    let modified = if livekit {
+        &LIVEKIT_MODIFIED
+    } else {
+        &RECIPE_MODIFIED
+    };
+    match modified.load(Ordering::SeqCst) {
+        0 => None,
+        secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+    }
+}
+
+/// The release of the entry (e.g. `base.amd64`) on the date, if the manifest still lists it
+pub fn release(history: &RwLock<History>, key: &str, date: &str) -> Option<Tarball> {
+    history
+        .read()
+        .unwrap()
+        .get(&format!("{}@{}", key, date))
+        .cloned()
+}
+
+fn record_history(history: &RwLock<History>, releases: &[(String, Tarball)]) {
+    *history.write().unwrap() = releases
+        .iter()
+        .filter(|(_, tarball)| tarball.date != "latest")
+        .map(|(key, tarball)| (format!("{}@{}", key, tarball.date), tarball.clone()))
+        .collect();
+}
+
+/// Whether a manifest is being reloaded right now
+pub fn reload_in_progress() -> bool {
+    RELOADING.load(Ordering::SeqCst) > 0
+}
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Tarball {
@@ -20,6 +80,30 @@ pub struct Tarball {
     pub date: String,
     pub path: String,
     pub sha256sum: String,
+    /// Size of the file, in bytes
+    #[serde(rename = "downloadSize", default)]
+    pub download_size: u64,
+    /// Size of the installed system, in bytes
+    #[serde(rename = "instSize", default)]
+    pub inst_size: u64,
+    #[serde(default)]
+    pub retro: bool,
+}
+
+#[cfg(test)]
+impl Tarball {
+    /// Entry of the tests, with a `00` checksum and empty sizes
+    pub fn sample(arch: &str, date: &str, path: &str) -> Self {
+        Tarball {
+            arch: arch.to_string(),
+            date: date.to_string(),
+            path: path.to_string(),
+            sha256sum: "00".to_string(),
+            download_size: 0,
+            inst_size: 0,
+            retro: false,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -27,6 +111,49 @@ pub struct Variant {
     #[serde(rename = "description-tr")]
     description_id: String,
     tarballs: Vec<Tarball>,
+    #[serde(default)]
+    squashfs: Vec<Tarball>,
+}
+
+#[derive(Deserialize)]
+struct LiveKitImage {
+    variant: String,
+    #[serde(rename = "type")]
+    type_: String,
+    retro: bool,
+    #[serde(flatten)]
+    tarball: Tarball,
+}
+
+/// AOSC OS LiveKit manifest structure, the legacy format is just a list of images
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LiveKitManifest {
+    Versioned { images: Vec<LiveKitImage> },
+    Legacy(Vec<Tarball>),
+}
+
+/// A mirror listed in the recipe by repo-manifest
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestMirror {
+    pub name: String,
+    /// Translation key of the name, e.g. `tuna-name`
+    #[serde(rename = "name-tr", default)]
+    pub name_tr: String,
+    /// Location of the mirror, e.g. `China`, mapped to its region (`CN`)
+    #[serde(default)]
+    pub loc: String,
+    pub url: String,
+}
+
+impl ManifestMirror {
+    /// Short name of the mirror, e.g. `tuna`, as used for the probation
+    pub fn short_name(&self) -> &str {
+        match self.name_tr.strip_suffix("-name") {
+            Some(name) if !name.is_empty() => name,
+            _ => &self.name,
+        }
+    }
 }
 
 /// AOSC OS Tarball Recipe structure
@@ -34,6 +161,8 @@ pub struct Variant {
 pub struct Recipe {
     pub version: usize,
     variants: Vec<Variant>,
+    #[serde(default)]
+    mirrors: Vec<ManifestMirror>,
 }
 
 #[inline]
@@ -44,6 +173,8 @@ async fn monitor_recipe_inner<
 >(
     path: &'a Path,
     shared_map: SharedDistMap,
+    reloaded: watch::Sender<()>,
+    modified: &AtomicU64,
     parser: F,
 ) -> Result<()> {
     let inotify = Inotify::init()?;
@@ -53,22 +184,45 @@ async fn monitor_recipe_inner<
         WatchMask::CREATE | WatchMask::MODIFY | WatchMask::CLOSE_WRITE,
     )?;
     let mut stream = inotify.into_event_stream(buffer)?;
+    let mut forced = FORCED.subscribe();
 
     loop {
-        match parser(path).await {
+        RELOADING.fetch_add(1, Ordering::SeqCst);
+        #[cfg(feature = "chaos")]
+        let result = match crate::chaos::reload().await {
+            Ok(()) => parser(path).await,
+            Err(e) => Err(e),
+        };
+        #[cfg(not(feature = "chaos"))]
+        let result = parser(path).await;
+        match result {
             Ok(new_map) => {
+                let mtime = tokio::fs::metadata(path)
+                    .await
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
                 shared_map.retain(|k, _| new_map.contains_key(k));
                 for (k, variant) in new_map.into_iter() {
                     shared_map.insert(k, variant);
                 }
+                // once the entries are replaced, for the ETags of the responses
+                modified.store(mtime, Ordering::SeqCst);
+                reloaded.send_replace(());
             }
             Err(err) => error!("Error parsing recipe: {}", err),
         }
+        RELOADING.fetch_sub(1, Ordering::SeqCst);
 
-        if stream.next().await.is_some() {
-            continue;
-        } else {
-            break;
+        tokio::select! {
+            event = stream.next() => {
+                if event.is_none() {
+                    break;
+                }
+            }
+            _ = forced.changed() => log::info!("Reloading {}", path.display()),
         }
     }
 
@@ -82,67 +236,216 @@ fn get_variant_id(description: &str) -> Option<&str> {
     splitted.next()
 }
 
-pub async fn monitor_recipe<P: AsRef<Path>>(path: P, shared_map: SharedDistMap) -> Result<()> {
-    monitor_recipe_inner(path.as_ref(), shared_map, parse_recipe).await
+/// Keep the map and the history up to date with the recipe, along with the mirrors listed in it
+pub async fn monitor_recipe<P: AsRef<Path>>(
+    path: P,
+    shared_map: SharedDistMap,
+    history: SharedHistory,
+    reloaded: watch::Sender<()>,
+    mirrors: actix_web::web::Data<Mirrors>,
+) -> Result<()> {
+    monitor_recipe_inner(
+        path.as_ref(),
+        shared_map,
+        reloaded,
+        &RECIPE_MODIFIED,
+        |path| {
+            let mirrors = mirrors.clone();
+            let history = Arc::clone(&history);
+            async move {
+                let (releases, listed) = parse_recipe_releases(path).await?;
+                mirrors.update_from_manifest(&listed);
+                record_history(&history, &releases);
+                Ok(latest(releases))
+            }
+        },
+    )
+    .await
 }
 
-pub async fn monitor_livekit<P: AsRef<Path>>(path: P, shared_map: SharedDistMap) -> Result<()> {
-    monitor_recipe_inner(path.as_ref(), shared_map, parse_livekit).await
+pub async fn monitor_livekit<P: AsRef<Path>>(
+    path: P,
+    shared_map: SharedDistMap,
+    history: SharedHistory,
+    reloaded: watch::Sender<()>,
+) -> Result<()> {
+    monitor_recipe_inner(
+        path.as_ref(),
+        shared_map,
+        reloaded,
+        &LIVEKIT_MODIFIED,
+        |path| {
+            let history = Arc::clone(&history);
+            async move {
+                let releases = parse_livekit_releases(path).await?;
+                record_history(&history, &releases);
+                Ok(latest(releases))
+            }
+        },
+    )
+    .await
 }
 
-pub async fn parse_livekit<P: AsRef<Path>>(path: P) -> Result<TarballMap> {
-    let mut f = File::open(path).await?;
-    let mut content = Vec::new();
+/// Get the key of the LiveKit image in the map: `<variant>.<arch>`, with the image type
+/// appended if the image is not an ISO
+#[inline]
+fn get_livekit_id(variant: &str, arch: &str, type_: &str) -> String {
+    if type_ == "iso" {
+        format!("{}.{}", variant, arch)
+    } else {
+        format!("{}.{}.{}", variant, arch, type_)
+    }
+}
+
+/// Where the artifacts of the type (`?type=` of the download endpoints) are listed: the
+/// tarballs and the squashfs images of the variants in the recipe, the other images (`iso`,
+/// `squashfs` of the LiveKit, ...) in the LiveKit manifest.
+/// Returns whether it is the LiveKit manifest, and the key of the artifact there.
+pub fn artifact_key(
+    recipe: &SharedDistMap,
+    type_: &str,
+    variant: &str,
+    arch: &str,
+) -> (bool, String) {
+    let prefix = format!("{}.", variant);
+    match type_ {
+        "tarball" => (false, format!("{}.{}", variant, arch)),
+        "squashfs" if recipe.iter().any(|item| item.key().starts_with(&prefix)) => {
+            (false, format!("{}.{}.squashfs", variant, arch))
+        }
+        _ => (true, get_livekit_id(variant, arch, type_)),
+    }
+}
+
+/// Keep the latest release of each entry
+fn latest(releases: Vec<(String, Tarball)>) -> TarballMap {
     let mut new_map: TarballMap = HashMap::new();
-    f.read_to_end(&mut content).await?;
-    let content: Vec<Tarball> = spawn_blocking(move || serde_json::from_slice(&content)).await??;
-    // get the latest tarball for each variant
-    for tarball in content {
-        let option_id = &tarball.arch;
-        if let Some(existing_tarball) = new_map.get(option_id) {
+    for (option_id, tarball) in releases {
+        if let Some(existing_tarball) = new_map.get(&option_id) {
             // ignore the one with the date "latest"
             if tarball.date == "latest" || tarball.date < existing_tarball.date {
                 continue;
             }
         }
-        new_map.insert(option_id.to_string(), tarball);
+        new_map.insert(option_id, tarball);
     }
 
-    Ok(new_map)
+    new_map
+}
+
+/// Parse every image listed in the LiveKit manifest, along with their keys
+async fn parse_livekit_releases<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Tarball)>> {
+    let mut f = File::open(path).await?;
+    let mut content = Vec::new();
+    f.read_to_end(&mut content).await?;
+    let content: LiveKitManifest =
+        spawn_blocking(move || serde_json::from_slice(&content)).await??;
+    let images = match content {
+        LiveKitManifest::Versioned { images } => images,
+        LiveKitManifest::Legacy(tarballs) => tarballs
+            .into_iter()
+            .map(|tarball| LiveKitImage {
+                variant: "livekit".to_string(),
+                type_: "iso".to_string(),
+                retro: false,
+                tarball,
+            })
+            .collect(),
+    };
+
+    Ok(images
+        .into_iter()
+        .map(|image| {
+            let option_id = get_livekit_id(&image.variant, &image.tarball.arch, &image.type_);
+            let mut tarball = image.tarball;
+            tarball.retro = image.retro;
+            (option_id, tarball)
+        })
+        .collect())
+}
+
+pub async fn parse_livekit<P: AsRef<Path>>(path: P) -> Result<TarballMap> {
+    Ok(latest(parse_livekit_releases(path).await?))
 }
 
 pub async fn parse_recipe<P: AsRef<Path>>(path: P) -> Result<TarballMap> {
+    Ok(parse_recipe_with_mirrors(path).await?.0)
+}
+
+/// Parse the recipe, returning the latest tarballs and the mirrors listed in it
+pub async fn parse_recipe_with_mirrors<P: AsRef<Path>>(
+    path: P,
+) -> Result<(TarballMap, Vec<ManifestMirror>)> {
+    let (releases, mirrors) = parse_recipe_releases(path).await?;
+
+    Ok((latest(releases), mirrors))
+}
+
+/// Parse every tarball listed in the recipe along with their keys, and the mirrors
+async fn parse_recipe_releases<P: AsRef<Path>>(
+    path: P,
+) -> Result<(Vec<(String, Tarball)>, Vec<ManifestMirror>)> {
     let mut f = File::open(path).await?;
     let mut content = Vec::new();
-    let mut new_map: TarballMap = HashMap::new();
     f.read_to_end(&mut content).await?;
     let content: Recipe = spawn_blocking(move || serde_json::from_slice(&content)).await??;
+    let mut releases = Vec::new();
     for variant in content.variants {
-        let variant_id = get_variant_id(&variant.description_id);
-        if variant_id.is_none() {
-            continue;
-        }
-        let variant_id = variant_id.unwrap();
-        // get the latest tarball for each variant
+        let variant_id = match get_variant_id(&variant.description_id) {
+            Some(variant_id) => variant_id.to_string(),
+            None => continue,
+        };
         for tarball in variant.tarballs {
-            let option_id = format!("{}.{}", variant_id, tarball.arch);
-            if let Some(existing_tarball) = new_map.get(&option_id) {
-                // ignore the one with the date "latest"
-                if tarball.date == "latest" || tarball.date < existing_tarball.date {
-                    continue;
-                }
-            }
-            new_map.insert(option_id, tarball);
+            releases.push((format!("{}.{}", variant_id, tarball.arch), tarball));
+        }
+        for image in variant.squashfs {
+            releases.push((format!("{}.{}.squashfs", variant_id, image.arch), image));
         }
     }
 
-    Ok(new_map)
+    Ok((releases, content.mirrors))
 }
 
 #[tokio::test]
 async fn test_parsing() {
-    let map = parse_recipe("./tests/recipe.json").await.unwrap();
+    let (map, mirrors) = parse_recipe_with_mirrors("./tests/recipe.json")
+        .await
+        .unwrap();
     dbg!(map);
+    assert_eq!(mirrors[3].short_name(), "tuna");
+
+    let (releases, _) = parse_recipe_releases("./tests/recipe.json").await.unwrap();
+    let history = RwLock::default();
+    record_history(&history, &releases);
+    let (key, tarball) = &releases[0];
+    assert_eq!(
+        release(&history, key, &tarball.date).unwrap().sha256sum,
+        tarball.sha256sum
+    );
+    assert!(release(&history, key, "19700101").is_none());
+}
+
+#[tokio::test]
+async fn test_artifact_key() {
+    let recipe: SharedDistMap = std::sync::Arc::new(Default::default());
+    for (key, tarball) in parse_recipe("./tests/recipe.json").await.unwrap() {
+        recipe.insert(key, tarball);
+    }
+    assert_eq!(
+        artifact_key(&recipe, "tarball", "base", "amd64"),
+        (false, "base.amd64".to_string())
+    );
+    let (livekit, key) = artifact_key(&recipe, "squashfs", "base", "amd64");
+    assert!(!livekit);
+    assert!(recipe.get(&key).unwrap().path.ends_with(".squashfs"));
+    assert_eq!(
+        artifact_key(&recipe, "iso", "livekit", "amd64"),
+        (true, "livekit.amd64".to_string())
+    );
+    assert_eq!(
+        artifact_key(&recipe, "squashfs", "livekit", "amd64"),
+        (true, "livekit.amd64.squashfs".to_string())
+    );
 }
 
 #[tokio::test]
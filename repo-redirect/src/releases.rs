@@ -0,0 +1,25 @@
+//! The files of the releases on this host, e.g. to check for the signatures and the zsync
+//! control files published next to the tarballs
+use std::path::{Path, PathBuf};
+
+#[derive(Clone)]
+pub struct Releases {
+    /// The paths in the manifests are relative to it
+    root: PathBuf,
+}
+
+impl Releases {
+    /// Load the root from `RELEASES_PATH`, the parent of the manifests by default
+    pub fn from_env(manifest_path: &Path) -> Self {
+        Releases {
+            root: std::env::var("RELEASES_PATH")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| manifest_path.join("..")),
+        }
+    }
+
+    /// Path of a file of the releases, relative to `RELEASES_PATH`
+    pub fn file(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+}
@@ -0,0 +1,44 @@
+//! Detached signatures published next to the files, linked from the thank-you page
+use serde::Serialize;
+
+use crate::releases::Releases;
+
+/// Extensions of the signature files and the tools verifying them
+const SIGNATURES: &[(&str, &str)] = &[("asc", "GPG"), ("sig", "GPG"), ("minisig", "minisign")];
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Signature {
+    /// Tool verifying the signature, e.g. `GPG`
+    pub kind: &'static str,
+    pub url: String,
+}
+
+/// Signatures of the file at `path` (relative to `RELEASES_PATH`) found on this host, linked on
+/// the mirror at `base_url`
+pub fn find(releases: &Releases, base_url: &str, path: &str) -> Vec<Signature> {
+    SIGNATURES
+        .iter()
+        .filter(|(ext, _)| releases.file(&format!("{}.{}", path, ext)).exists())
+        .map(|(ext, kind)| Signature {
+            kind,
+            url: format!("{}/{}.{}", base_url, path, ext),
+        })
+        .collect()
+}
+
+#[test]
+fn test_signatures() {
+    let dir = std::env::temp_dir().join(format!("repo-redirect-sigs-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("manifest")).unwrap();
+    std::fs::write(dir.join("base.tar.xz.minisig"), "").unwrap();
+    let releases = Releases::from_env(&dir.join("manifest"));
+    let found = find(&releases, "https://example.com", "base.tar.xz");
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(
+        found,
+        vec![Signature {
+            kind: "minisign",
+            url: "https://example.com/base.tar.xz.minisig".to_string(),
+        }]
+    );
+}
@@ -0,0 +1,208 @@
+use dashmap::DashMap;
+use log::{error, warn};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+// A client requesting the same entry this many times within the window is considered
+// to be re-downloading it (probably because the previous download was corrupted)
+const REPEAT_COUNT: usize = 3;
+const REPEAT_WINDOW: Duration = Duration::from_secs(10 * 60);
+// Number of re-downloading clients within the window that triggers an alert
+const DEFAULT_ALERT_THRESHOLD: usize = 5;
+const ALERT_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize)]
+struct Alert<'a> {
+    entry: &'a str,
+    clients: usize,
+    message: String,
+}
+
+/// Statistics of the recent download requests
+pub struct Stats {
+    /// Recent requests of each client to each entry
+    recent: DashMap<(String, String), VecDeque<Instant>>,
+    /// Recent times when a client was found re-downloading the entry
+    repeats: DashMap<String, VecDeque<Instant>>,
+    alerted: DashMap<String, Instant>,
+    downloads: DashMap<String, u64>,
+    repeated_downloads: DashMap<String, u64>,
+    alert_threshold: usize,
+    webhook: Option<String>,
+}
+
+impl Stats {
+    pub fn from_env() -> Self {
+        Stats {
+            recent: DashMap::new(),
+            repeats: DashMap::new(),
+            alerted: DashMap::new(),
+            downloads: DashMap::new(),
+            repeated_downloads: DashMap::new(),
+            alert_threshold: std::env::var("REPEAT_ALERT_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_ALERT_THRESHOLD),
+            webhook: std::env::var("ALERT_WEBHOOK").ok(),
+        }
+    }
+
+    /// Record a resolved download request from `client`.
+    ///
+    /// Returns the number of clients re-downloading this entry recently.
+    pub fn record(&self, client: &str, entry: &str) -> usize {
+        let now = Instant::now();
+        *self.downloads.entry(entry.to_string()).or_insert(0) += 1;
+        let count = {
+            let mut times = self
+                .recent
+                .entry((client.to_string(), entry.to_string()))
+                .or_default();
+            times.retain(|t| now.duration_since(*t) < REPEAT_WINDOW);
+            times.push_back(now);
+            times.len()
+        };
+        if count < REPEAT_COUNT {
+            return 0;
+        }
+        *self
+            .repeated_downloads
+            .entry(entry.to_string())
+            .or_insert(0) += 1;
+        let clients = {
+            let mut repeats = self.repeats.entry(entry.to_string()).or_default();
+            repeats.retain(|t| now.duration_since(*t) < REPEAT_WINDOW);
+            // only count each client once
+            if count == REPEAT_COUNT {
+                repeats.push_back(now);
+            }
+            repeats.len()
+        };
+        if clients >= self.alert_threshold {
+            self.alert(entry, clients, now);
+        }
+
+        clients
+    }
+
+    fn alert(&self, entry: &str, clients: usize, now: Instant) {
+        if let Some(last) = self.alerted.get(entry) {
+            if now.duration_since(*last) < ALERT_COOLDOWN {
+                return;
+            }
+        }
+        self.alerted.insert(entry.to_string(), now);
+        let message = format!(
+            "{} clients re-downloaded {} within {} minutes, the file or the mirror may be broken",
+            clients,
+            entry,
+            REPEAT_WINDOW.as_secs() / 60
+        );
+        warn!("{}", message);
+        if let Some(webhook) = self.webhook.clone() {
+            let alert = serde_json::to_string(&Alert {
+                entry,
+                clients,
+                message,
+            });
+            tokio::spawn(async move {
+                let client = reqwest::Client::new();
+                let result = match alert {
+                    Ok(alert) => client
+                        .post(webhook)
+                        .header("Content-Type", "application/json")
+                        .body(alert)
+                        .send()
+                        .await
+                        .and_then(|r| r.error_for_status())
+                        .map(|_| ())
+                        .map_err(anyhow::Error::from),
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = result {
+                    error!("Could not send the alert: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Forget about the requests outside of the window
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.recent.retain(|_, times| {
+            times.retain(|t| now.duration_since(*t) < REPEAT_WINDOW);
+            !times.is_empty()
+        });
+        self.repeats.retain(|_, times| {
+            times.retain(|t| now.duration_since(*t) < REPEAT_WINDOW);
+            !times.is_empty()
+        });
+    }
+
+    /// Render the counters in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let mut output = String::new();
+        output += "# TYPE repo_redirect_downloads_total counter\n";
+        for entry in self.downloads.iter() {
+            writeln!(
+                output,
+                "repo_redirect_downloads_total{{entry=\"{}\"}} {}",
+                entry.key(),
+                entry.value()
+            )
+            .ok();
+        }
+        output += "# TYPE repo_redirect_repeated_downloads_total counter\n";
+        for entry in self.repeated_downloads.iter() {
+            writeln!(
+                output,
+                "repo_redirect_repeated_downloads_total{{entry=\"{}\"}} {}",
+                entry.key(),
+                entry.value()
+            )
+            .ok();
+        }
+        output += "# TYPE repo_redirect_repeating_clients gauge\n";
+        for entry in self.repeats.iter() {
+            writeln!(
+                output,
+                "repo_redirect_repeating_clients{{entry=\"{}\"}} {}",
+                entry.key(),
+                entry.value().len()
+            )
+            .ok();
+        }
+
+        output
+    }
+}
+
+/// Periodically prune the statistics
+pub async fn prune_stats(stats: actix_web::web::Data<Stats>) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(REPEAT_WINDOW);
+    loop {
+        interval.tick().await;
+        stats.prune();
+    }
+}
+
+#[test]
+fn test_repeated_downloads() {
+    let stats = Stats {
+        alert_threshold: 2,
+        webhook: None,
+        ..Stats::from_env()
+    };
+    assert_eq!(stats.record("a", "base.amd64"), 0);
+    assert_eq!(stats.record("a", "base.amd64"), 0);
+    assert_eq!(stats.record("a", "base.amd64"), 1);
+    assert_eq!(stats.record("a", "base.amd64"), 1);
+    assert_eq!(stats.record("b", "base.amd64"), 0);
+    assert_eq!(stats.record("b", "base.amd64"), 0);
+    assert_eq!(stats.record("b", "base.amd64"), 2);
+    assert_eq!(stats.record("a", "kde.amd64"), 0);
+}
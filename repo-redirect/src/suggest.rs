@@ -0,0 +1,111 @@
+//! Suggestions of the entries close to the one requested, for the 404 page
+use serde::Serialize;
+
+use crate::SharedDistMap;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Suggestion {
+    pub label: String,
+    /// Link to the download of the entry
+    pub href: String,
+}
+
+/// Edit distance between the two names (counting the swapped letters as one edit), ignoring
+/// the case
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<_>>();
+    let b = b.to_lowercase().chars().collect::<Vec<_>>();
+    // d[i][j]: distance between the first i letters of a and the first j letters of b
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            d[i][j] = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// The other architectures of the variant if it exists, otherwise the variants with similar
+/// names (on the same architecture if available). `manifest` is `alt` or `livekit`.
+pub fn suggest(map: &SharedDistMap, manifest: &str, variant: &str, arch: &str) -> Vec<Suggestion> {
+    let entries = map
+        .iter()
+        .filter_map(|item| {
+            let (v, a) = item.key().split_once('.')?;
+            Some((v.to_string(), a.to_string()))
+        })
+        .collect::<Vec<_>>();
+    let mut matched = entries
+        .iter()
+        .filter(|(v, _)| v == variant)
+        .collect::<Vec<_>>();
+    if matched.is_empty() {
+        let max_distance = (variant.chars().count() / 3).max(1);
+        matched = entries
+            .iter()
+            .filter(|(v, _)| distance(v, variant) <= max_distance)
+            .collect();
+        if matched.iter().any(|(_, a)| a == arch) {
+            matched.retain(|(_, a)| a == arch);
+        }
+    }
+    matched.sort();
+
+    matched
+        .into_iter()
+        .map(|(v, a)| Suggestion {
+            label: format!("{} ({})", v, a),
+            href: format!("/download/{}?variant={}&arch={}", manifest, v, a),
+        })
+        .collect()
+}
+
+#[test]
+fn test_suggest() {
+    use crate::parser::Tarball;
+    use dashmap::DashMap;
+    use std::sync::Arc;
+
+    assert_eq!(distance("desktop", "Destkop"), 1);
+    assert_eq!(distance("base", "base"), 0);
+    let map: SharedDistMap = Arc::new(DashMap::new());
+    for key in [
+        "base.amd64",
+        "base.arm64",
+        "desktop.amd64",
+        "desktop.riscv64",
+    ] {
+        let tarball = Tarball::sample(key.split('.').nth(1).unwrap(), "20240101", "");
+        map.insert(key.to_string(), tarball);
+    }
+    let labels = |variant, arch| {
+        suggest(&map, "alt", variant, arch)
+            .into_iter()
+            .map(|s| s.label)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(labels("base", "i486"), vec!["base (amd64)", "base (arm64)"]);
+    assert_eq!(labels("dekstop", "amd64"), vec!["desktop (amd64)"]);
+    assert_eq!(
+        labels("dekstop", "i486"),
+        vec!["desktop (amd64)", "desktop (riscv64)"]
+    );
+    assert!(labels("server", "amd64").is_empty());
+    assert_eq!(
+        suggest(&map, "alt", "bsae", "arm64")[0].href,
+        "/download/alt?variant=base&arch=arm64"
+    );
+}
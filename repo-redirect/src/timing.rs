@@ -0,0 +1,181 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error, HttpMessage,
+};
+use dashmap::DashMap;
+use log::warn;
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use crate::SharedDistMap;
+
+const DEFAULT_SLOW_THRESHOLD: Duration = Duration::from_millis(500);
+// Upper bounds (in seconds) of the histogram buckets
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Entry requested by the client, set by the handlers for the slow request and access logs
+pub struct RequestedEntry(pub String);
+
+#[derive(Default)]
+struct Histogram {
+    /// Number of requests in each bucket (not cumulative), the last one is `+Inf`
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; BUCKETS.len() + 1];
+        }
+        let index = BUCKETS
+            .iter()
+            .position(|b| seconds <= *b)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[index] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Handler timings of each endpoint
+pub struct Timings {
+    histograms: DashMap<(String, String), Histogram>,
+    slow_threshold: Duration,
+}
+
+impl Timings {
+    pub fn from_env() -> Self {
+        Timings {
+            histograms: DashMap::new(),
+            slow_threshold: std::env::var("SLOW_REQUEST_THRESHOLD_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_SLOW_THRESHOLD),
+        }
+    }
+
+    fn observe(&self, method: &str, endpoint: &str, elapsed: Duration) {
+        self.histograms
+            .entry((method.to_string(), endpoint.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Render the histograms in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let mut output = String::new();
+        output += "# TYPE repo_redirect_request_duration_seconds histogram\n";
+        for entry in self.histograms.iter() {
+            let (method, endpoint) = entry.key();
+            let histogram = entry.value();
+            let labels = format!("method=\"{}\",endpoint=\"{}\"", method, endpoint);
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                writeln!(
+                    output,
+                    "repo_redirect_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                )
+                .ok();
+            }
+            writeln!(
+                output,
+                "repo_redirect_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            )
+            .ok();
+            writeln!(
+                output,
+                "repo_redirect_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            )
+            .ok();
+            writeln!(
+                output,
+                "repo_redirect_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            )
+            .ok();
+        }
+
+        output
+    }
+}
+
+/// Middleware recording the handler timings and logging the slow requests
+pub async fn trace_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let reloading_before = crate::parser::reload_in_progress();
+    let res = next.call(req).await?;
+    let elapsed = start.elapsed();
+
+    let request = res.request();
+    let method = request.method().to_string();
+    let endpoint = request
+        .match_pattern()
+        .unwrap_or_else(|| "(unmatched)".to_string());
+    let timings = match request.app_data::<web::Data<Timings>>() {
+        Some(timings) => timings,
+        None => return Ok(res),
+    };
+    timings.observe(&method, &endpoint, elapsed);
+    if elapsed >= timings.slow_threshold {
+        let entry = request
+            .extensions()
+            .get::<RequestedEntry>()
+            .map(|e| e.0.clone())
+            .unwrap_or_else(|| "-".to_string());
+        let (recipe_size, livekit_size) = request
+            .app_data::<web::Data<(SharedDistMap, SharedDistMap)>>()
+            .map(|maps| (maps.0.len(), maps.1.len()))
+            .unwrap_or_default();
+        warn!(
+            "Slow request: {} {} took {} ms (status: {}, entry: {}, recipe entries: {}, livekit entries: {}, reload in progress: {})",
+            method,
+            endpoint,
+            elapsed.as_millis(),
+            res.status().as_u16(),
+            entry,
+            recipe_size,
+            livekit_size,
+            reloading_before || crate::parser::reload_in_progress()
+        );
+    }
+
+    Ok(res)
+}
+
+#[test]
+fn test_histogram() {
+    let timings = Timings::from_env();
+    timings.observe("POST", "/download/alt", Duration::from_millis(3));
+    timings.observe("POST", "/download/alt", Duration::from_millis(700));
+    timings.observe("POST", "/download/alt", Duration::from_secs(20));
+    let metrics = timings.render_metrics();
+    let labels = "method=\"POST\",endpoint=\"/download/alt\"";
+    assert!(metrics.contains(&format!(
+        "repo_redirect_request_duration_seconds_bucket{{{},le=\"0.005\"}} 1\n",
+        labels
+    )));
+    assert!(metrics.contains(&format!(
+        "repo_redirect_request_duration_seconds_bucket{{{},le=\"1\"}} 2\n",
+        labels
+    )));
+    assert!(metrics.contains(&format!(
+        "repo_redirect_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 3\n",
+        labels
+    )));
+}
@@ -0,0 +1,300 @@
+//! .torrent files of the tarballs and images with the mirrors as their web seeds (BEP 19),
+//! generated in `TORRENT_DIR` from the files in `RELEASES_PATH` whenever a manifest is reloaded
+use anyhow::Result;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::HashSet,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use tokio::{sync::watch, task::spawn_blocking};
+
+use crate::{mirrors::Mirrors, parser::Tarball, releases::Releases, SharedDistMap};
+
+const MIN_PIECE_LENGTH: u64 = 256 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+/// The pieces are made bigger until there are no more than this many of them
+const MAX_PIECES: u64 = 2000;
+
+enum Bencode {
+    Int(u64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(Vec<(&'static str, Bencode)>),
+}
+
+impl Bencode {
+    fn str(s: &str) -> Self {
+        Bencode::Bytes(s.as_bytes().to_vec())
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(i) => out.extend(format!("i{}e", i).as_bytes()),
+            Bencode::Bytes(b) => {
+                out.extend(format!("{}:", b.len()).as_bytes());
+                out.extend(b);
+            }
+            Bencode::List(items) => {
+                out.push(b'l');
+                items.iter().for_each(|i| i.encode(out));
+                out.push(b'e');
+            }
+            Bencode::Dict(entries) => {
+                // the keys must be sorted
+                let mut entries = entries.iter().collect::<Vec<_>>();
+                entries.sort_by_key(|(k, _)| *k);
+                out.push(b'd');
+                for (k, v) in entries {
+                    Bencode::str(k).encode(out);
+                    v.encode(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+}
+
+fn piece_length(size: u64) -> u64 {
+    let mut length = MIN_PIECE_LENGTH;
+    while size / length > MAX_PIECES && length < MAX_PIECE_LENGTH {
+        length *= 2;
+    }
+
+    length
+}
+
+/// The web seeds of the tarball, its URLs on every mirror
+fn seeds(mirrors: &Mirrors, tarball: &Tarball) -> Vec<String> {
+    mirrors
+        .all()
+        .iter()
+        .map(|m| format!("{}/{}", m.url.trim_end_matches('/'), tarball.path))
+        .collect()
+}
+
+/// Hash the file and build the torrent with the web seeds (and the trackers, if any)
+fn build(file: &Path, seeds: &[String], trackers: &[String]) -> Result<Vec<u8>> {
+    let mut f = std::fs::File::open(file)?;
+    let length = f.metadata()?.len();
+    let piece_length = piece_length(length);
+    let mut pieces = Vec::new();
+    let mut buffer = Vec::with_capacity(piece_length as usize);
+    loop {
+        buffer.clear();
+        let read = (&mut f).take(piece_length).read_to_end(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        pieces.extend(Sha1::digest(&buffer));
+    }
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut torrent = vec![
+        ("created by", Bencode::str("repo-redirect")),
+        (
+            "info",
+            Bencode::Dict(vec![
+                ("length", Bencode::Int(length)),
+                ("name", Bencode::str(&name)),
+                ("piece length", Bencode::Int(piece_length)),
+                ("pieces", Bencode::Bytes(pieces)),
+            ]),
+        ),
+        (
+            "url-list",
+            Bencode::List(seeds.iter().map(|s| Bencode::str(s)).collect()),
+        ),
+    ];
+    if let Some(tracker) = trackers.first() {
+        torrent.push(("announce", Bencode::str(tracker)));
+        torrent.push((
+            "announce-list",
+            Bencode::List(
+                trackers
+                    .iter()
+                    .map(|t| Bencode::List(vec![Bencode::str(t)]))
+                    .collect(),
+            ),
+        ));
+    }
+    let mut out = Vec::new();
+    Bencode::Dict(torrent).encode(&mut out);
+
+    Ok(out)
+}
+
+/// Where the torrents are kept, disabled if `TORRENT_DIR` is not set
+pub struct Torrents {
+    dir: Option<PathBuf>,
+    releases: Releases,
+    trackers: Vec<String>,
+}
+
+impl Torrents {
+    /// Load the settings from `TORRENT_DIR` and `TORRENT_TRACKERS` (comma-separated announce
+    /// URLs), the torrents are generated from the files in `releases`
+    pub fn from_env(releases: Releases) -> Self {
+        Torrents {
+            dir: std::env::var("TORRENT_DIR")
+                .ok()
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from),
+            releases,
+            trackers: std::env::var("TORRENT_TRACKERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Where the torrent of the tarball with the web seeds is kept, a new one is generated
+    /// when the mirrors change
+    fn path_of(&self, tarball: &Tarball, seeds: &[String]) -> Option<PathBuf> {
+        let mut hasher = Sha1::new();
+        for seed in seeds {
+            hasher.update(seed.as_bytes());
+            hasher.update(b"\n");
+        }
+        let seeds = hasher.finalize()[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        Some(
+            self.dir
+                .as_ref()?
+                .join(format!("{}-{}.torrent", tarball.sha256sum, seeds)),
+        )
+    }
+
+    /// The torrent of the tarball with the current mirrors, if it was generated
+    pub async fn read(&self, tarball: &Tarball, mirrors: &Mirrors) -> Option<Vec<u8>> {
+        tokio::fs::read(self.path_of(tarball, &seeds(mirrors, tarball))?)
+            .await
+            .ok()
+    }
+
+    /// Generate the missing torrents of the entries and remove the ones no longer listed
+    async fn refresh(&self, maps: &(SharedDistMap, SharedDistMap), mirrors: &Mirrors) {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let tarballs = maps
+            .0
+            .iter()
+            .chain(maps.1.iter())
+            .map(|item| item.value().clone())
+            .collect::<Vec<_>>();
+        let mut wanted = HashSet::new();
+        for tarball in tarballs {
+            let seeds = seeds(mirrors, &tarball);
+            let path = match self.path_of(&tarball, &seeds) {
+                Some(path) => path,
+                None => return,
+            };
+            wanted.insert(path.clone());
+            if path.exists() {
+                continue;
+            }
+            let file = self.releases.file(&tarball.path);
+            let trackers = self.trackers.clone();
+            let built = spawn_blocking(move || build(&file, &seeds, &trackers)).await;
+            let written = match built {
+                Ok(Ok(torrent)) => {
+                    // written under another name first, so a partial file is never served
+                    let partial = path.with_extension("part");
+                    match tokio::fs::write(&partial, torrent).await {
+                        Ok(()) => tokio::fs::rename(&partial, &path).await,
+                        Err(e) => Err(e),
+                    }
+                    .map_err(anyhow::Error::from)
+                }
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(e.into()),
+            };
+            match written {
+                Ok(()) => log::info!("Generated the torrent of {}", tarball.path),
+                Err(e) => log::warn!("Could not generate the torrent of {}: {}", tarball.path, e),
+            }
+        }
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) => return log::warn!("Could not list {}: {}", dir.display(), e),
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "torrent") && !wanted.contains(&path) {
+                tokio::fs::remove_file(&path).await.ok();
+            }
+        }
+    }
+}
+
+/// Generate the torrents each time one of the manifests is reloaded
+pub async fn monitor(
+    torrents: actix_web::web::Data<Torrents>,
+    maps: (SharedDistMap, SharedDistMap),
+    mut reloads: watch::Receiver<()>,
+    mut reloads_lk: watch::Receiver<()>,
+    mirrors: actix_web::web::Data<Mirrors>,
+) -> Result<()> {
+    if torrents.dir.is_none() {
+        return std::future::pending().await;
+    }
+    loop {
+        tokio::select! {
+            v = reloads.changed() => v?,
+            v = reloads_lk.changed() => v?,
+        }
+        torrents.refresh(&maps, &mirrors).await;
+    }
+}
+
+#[test]
+fn test_torrent() {
+    let mut out = Vec::new();
+    Bencode::Dict(vec![
+        (
+            "spam",
+            Bencode::List(vec![Bencode::str("a"), Bencode::Int(42)]),
+        ),
+        ("cow", Bencode::str("moo")),
+    ])
+    .encode(&mut out);
+    assert_eq!(out, b"d3:cow3:moo4:spaml1:ai42eee");
+    assert_eq!(piece_length(1 << 20), MIN_PIECE_LENGTH);
+    assert_eq!(piece_length(4 << 30), 4 << 20);
+    assert_eq!(piece_length(1 << 40), MAX_PIECE_LENGTH);
+
+    let file = std::env::temp_dir().join(format!("repo-redirect-{}.tar.xz", std::process::id()));
+    std::fs::write(&file, vec![0u8; MIN_PIECE_LENGTH as usize + 1]).unwrap();
+    let torrent = build(&file, &["https://example.com/a.tar.xz".to_string()], &[]).unwrap();
+    std::fs::remove_file(&file).ok();
+    let torrent = String::from_utf8_lossy(&torrent);
+    assert!(torrent.contains(&format!("6:lengthi{}e", MIN_PIECE_LENGTH + 1)));
+    // two pieces of 20 bytes
+    assert!(torrent.contains("6:pieces40:"));
+    assert!(torrent.ends_with("8:url-listl28:https://example.com/a.tar.xzee"));
+
+    // a new torrent once the mirrors change
+    let torrents = Torrents {
+        dir: Some(PathBuf::from("/var/cache/torrents")),
+        releases: Releases::from_env(Path::new(".")),
+        trackers: Vec::new(),
+    };
+    let tarball = Tarball::sample("amd64", "20240101", "os-amd64/a.tar.xz");
+    let seeds = seeds(&Mirrors::from_env().unwrap(), &tarball);
+    assert!(seeds[0].ends_with(".io/os-amd64/a.tar.xz"));
+    let path = torrents.path_of(&tarball, &seeds).unwrap();
+    assert!(path
+        .to_string_lossy()
+        .starts_with("/var/cache/torrents/00-"));
+    assert_ne!(torrents.path_of(&tarball, &seeds[..0]).unwrap(), path);
+}
@@ -0,0 +1,157 @@
+//! Failure injection for the resilience tests of the services, only built with the `chaos`
+//! feature.
+//!
+//! `CHAOS_FAULTS` sets the probability of each fault by its name, e.g.
+//! `parse=0.5,slow_reload=0.2`. The services list their faults by implementing [`Fault`].
+use anyhow::{anyhow, Result};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Faults a service can inject
+pub trait Fault: Copy + Debug + Ord + 'static {
+    /// Every fault along with its name in `CHAOS_FAULTS`
+    const FAULTS: &'static [(&'static str, Self)];
+}
+
+type Probabilities<F> = BTreeMap<F, f64>;
+
+static SEED: LazyLock<AtomicU64> = LazyLock::new(|| {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    AtomicU64::new(nanos | 1)
+});
+
+/// Parse the probability of each fault, e.g. `parse=0.5,slow_reload=0.2`
+fn parse<F: Fault>(spec: &str) -> Result<Probabilities<F>> {
+    let mut probabilities = Probabilities::new();
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, value) = item
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected name=probability, got {}", item))?;
+        let fault = F::FAULTS
+            .iter()
+            .find(|(n, _)| *n == name.trim())
+            .map(|(_, f)| *f)
+            .ok_or_else(|| anyhow!("Unknown fault {}", name))?;
+        let probability: f64 = value.trim().parse()?;
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(anyhow!("Probability of {} out of range: {}", name, value));
+        }
+        probabilities.insert(fault, probability);
+    }
+
+    Ok(probabilities)
+}
+
+fn describe_with<F: Fault>(probabilities: &Probabilities<F>) -> String {
+    F::FAULTS
+        .iter()
+        .filter_map(|(name, fault)| probabilities.get(fault).map(|p| format!("{}={}", name, p)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A uniformly distributed number in [0, 1) (xorshift, good enough for dice rolls)
+fn roll() -> f64 {
+    let mut x = SEED.load(Ordering::Relaxed);
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    SEED.store(x, Ordering::Relaxed);
+
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Probabilities of the faults of a service
+pub struct Chaos<F: Fault> {
+    probabilities: Mutex<Probabilities<F>>,
+}
+
+impl<F: Fault> Chaos<F> {
+    /// The faults in the format of `CHAOS_FAULTS`
+    pub fn new(spec: &str) -> Result<Self> {
+        Ok(Chaos {
+            probabilities: Mutex::new(parse(spec)?),
+        })
+    }
+
+    /// The faults in `CHAOS_FAULTS`, none if it is invalid
+    pub fn from_env() -> Self {
+        let spec = std::env::var("CHAOS_FAULTS").unwrap_or_default();
+        let chaos = Chaos::new(&spec).unwrap_or_else(|e| {
+            log::error!("Invalid CHAOS_FAULTS: {}", e);
+            Chaos {
+                probabilities: Mutex::new(Probabilities::new()),
+            }
+        });
+        log::warn!("Failure injection is enabled: {}", chaos.describe());
+
+        chaos
+    }
+
+    /// Replace the probabilities of the faults, returning them in the format of `CHAOS_FAULTS`
+    pub fn set(&self, spec: &str) -> Result<String> {
+        let probabilities = parse(spec)?;
+        let described = describe_with(&probabilities);
+        *self.probabilities.lock().unwrap() = probabilities;
+
+        Ok(described)
+    }
+
+    /// Current probabilities of the faults, in the format of `CHAOS_FAULTS`
+    pub fn describe(&self) -> String {
+        describe_with(&self.probabilities.lock().unwrap())
+    }
+
+    /// Whether the fault should happen this time
+    pub fn inject(&self, fault: F) -> bool {
+        let probability = self
+            .probabilities
+            .lock()
+            .unwrap()
+            .get(&fault)
+            .copied()
+            .unwrap_or_default();
+        let injected = probability > 0.0 && roll() < probability;
+        if injected {
+            log::warn!("Injecting {:?}", fault);
+        }
+
+        injected
+    }
+}
+
+#[test]
+fn test_chaos() {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    enum TestFault {
+        Parse,
+        Disconnect,
+    }
+    impl Fault for TestFault {
+        const FAULTS: &'static [(&'static str, Self)] = &[
+            ("parse", TestFault::Parse),
+            ("disconnect", TestFault::Disconnect),
+        ];
+    }
+
+    let chaos = Chaos::<TestFault>::new("disconnect=1, parse=0.5").unwrap();
+    assert_eq!(chaos.describe(), "parse=0.5,disconnect=1");
+    assert!(chaos.inject(TestFault::Disconnect));
+    assert_eq!(chaos.set("parse=0").unwrap(), "parse=0");
+    assert!(!chaos.inject(TestFault::Parse));
+    assert!(!chaos.inject(TestFault::Disconnect));
+    assert!(chaos.set("parse=2").is_err());
+    assert!(Chaos::<TestFault>::new("meteor=0.1").is_err());
+    assert!(Chaos::<TestFault>::new("").unwrap().describe().is_empty());
+    assert!((0..100).map(|_| roll()).all(|r| (0.0..1.0).contains(&r)));
+}
@@ -0,0 +1,7 @@
+//! Types shared by the AOSC repository tools and the image build pipeline
+
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod media_name;
+
+pub use media_name::{MediaDate, MediaName, MediaNameBuilder, NameError};
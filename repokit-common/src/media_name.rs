@@ -0,0 +1,363 @@
+//! File names of the AOSC OS installation media.
+//!
+//! The names have the following grammar:
+//!
+//! ```text
+//! aosc-os_<variant>[+<flavor>]_<date>_<arch>[_<device>].<ext>
+//! ```
+//!
+//! e.g. `aosc-os_base_20200526_amd64.tar.xz`, `aosc-os_desktop+nvidia_20240101_amd64.iso`
+//! or `aosc-os_base_20240101_arm64_rpi64.img.xz`. The date is either `YYYYMMDD` or `latest`.
+
+use chrono::NaiveDate;
+use std::{fmt, str::FromStr};
+
+pub const PREFIX: &str = "aosc-os";
+const DATE_FORMAT: &str = "%Y%m%d";
+
+/// Errors of parsing or building a media name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NameError {
+    /// The name does not start with `aosc-os_`
+    MissingPrefix,
+    /// The named component is missing
+    Missing(&'static str),
+    /// The named component contains invalid characters
+    Invalid(&'static str, String),
+    /// The date is neither `YYYYMMDD` nor `latest`
+    InvalidDate(String),
+    /// There are more components than the grammar allows
+    TooManyComponents,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::MissingPrefix => write!(f, "name does not start with `{}_`", PREFIX),
+            NameError::Missing(component) => write!(f, "missing {}", component),
+            NameError::Invalid(component, value) => write!(f, "invalid {}: {:?}", component, value),
+            NameError::InvalidDate(value) => write!(f, "invalid date: {:?}", value),
+            NameError::TooManyComponents => write!(f, "too many components"),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// Build date of the media
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MediaDate {
+    Date(NaiveDate),
+    /// Always points at the latest build
+    Latest,
+}
+
+impl FromStr for MediaDate {
+    type Err = NameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "latest" {
+            return Ok(MediaDate::Latest);
+        }
+        if s.len() != 8 || !s.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(NameError::InvalidDate(s.to_string()));
+        }
+
+        NaiveDate::parse_from_str(s, DATE_FORMAT)
+            .map(MediaDate::Date)
+            .map_err(|_| NameError::InvalidDate(s.to_string()))
+    }
+}
+
+impl fmt::Display for MediaDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaDate::Date(date) => write!(f, "{}", date.format(DATE_FORMAT)),
+            MediaDate::Latest => f.write_str("latest"),
+        }
+    }
+}
+
+/// Parsed (and validated) file name of an installation medium
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MediaName {
+    pub variant: String,
+    /// Flavor of the variant (e.g. `nvidia`)
+    pub flavor: Option<String>,
+    pub date: MediaDate,
+    pub arch: String,
+    /// Device the image is built for (e.g. `rpi64`)
+    pub device: Option<String>,
+    /// File extension, e.g. `tar.xz`, `squashfs` or `iso`
+    pub ext: String,
+}
+
+#[inline]
+fn is_valid_component(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-')
+}
+
+#[inline]
+fn is_valid_ext(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with('.')
+        && !s.ends_with('.')
+        && !s.contains("..")
+        && s.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'.')
+}
+
+fn check(component: &'static str, value: &str) -> Result<(), NameError> {
+    if is_valid_component(value) {
+        Ok(())
+    } else {
+        Err(NameError::Invalid(component, value.to_string()))
+    }
+}
+
+impl MediaName {
+    pub fn builder() -> MediaNameBuilder {
+        MediaNameBuilder::default()
+    }
+
+    pub fn parse(name: &str) -> Result<Self, NameError> {
+        let rest = name
+            .strip_prefix(PREFIX)
+            .and_then(|r| r.strip_prefix('_'))
+            .ok_or(NameError::MissingPrefix)?;
+        let mut splitted = rest.split('_');
+        let variant = splitted.next().ok_or(NameError::Missing("variant"))?;
+        let date = splitted.next().ok_or(NameError::Missing("date"))?;
+        let mut last = splitted.next().ok_or(NameError::Missing("architecture"))?;
+        let mut arch = None;
+        if let Some(device) = splitted.next() {
+            arch = Some(last);
+            last = device;
+        }
+        if splitted.next().is_some() {
+            return Err(NameError::TooManyComponents);
+        }
+        let (last, ext) = last
+            .split_once('.')
+            .ok_or(NameError::Missing("extension"))?;
+        let (arch, device) = match arch {
+            Some(arch) => (arch, Some(last)),
+            None => (last, None),
+        };
+        let (variant, flavor) = match variant.split_once('+') {
+            Some((variant, flavor)) => (variant, Some(flavor)),
+            None => (variant, None),
+        };
+
+        let mut builder = MediaName::builder()
+            .variant(variant)
+            .date(date.parse()?)
+            .arch(arch)
+            .ext(ext);
+        if let Some(flavor) = flavor {
+            builder = builder.flavor(flavor);
+        }
+        if let Some(device) = device {
+            builder = builder.device(device);
+        }
+
+        builder.build()
+    }
+
+    /// Variant with its flavor, e.g. `desktop+nvidia`, as keyed in the manifests
+    pub fn full_variant(&self) -> String {
+        match self.flavor.as_deref() {
+            Some(flavor) => format!("{}+{}", self.variant, flavor),
+            None => self.variant.clone(),
+        }
+    }
+
+    /// Architecture with the device, e.g. `arm64_rpi64`, as keyed in the manifests
+    pub fn target(&self) -> String {
+        match self.device.as_deref() {
+            Some(device) => format!("{}_{}", self.arch, device),
+            None => self.arch.clone(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), NameError> {
+        check("variant", &self.variant)?;
+        if let Some(flavor) = self.flavor.as_deref() {
+            check("flavor", flavor)?;
+        }
+        check("architecture", &self.arch)?;
+        if let Some(device) = self.device.as_deref() {
+            check("device", device)?;
+        }
+        if !is_valid_ext(&self.ext) {
+            return Err(NameError::Invalid("extension", self.ext.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for MediaName {
+    type Err = NameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MediaName::parse(s)
+    }
+}
+
+impl fmt::Display for MediaName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", PREFIX, self.variant)?;
+        if let Some(flavor) = self.flavor.as_deref() {
+            write!(f, "+{}", flavor)?;
+        }
+        write!(f, "_{}_{}", self.date, self.arch)?;
+        if let Some(device) = self.device.as_deref() {
+            write!(f, "_{}", device)?;
+        }
+
+        write!(f, ".{}", self.ext)
+    }
+}
+
+/// Builder of the media names, e.g. for naming the outputs of the image builds
+#[derive(Clone, Debug, Default)]
+pub struct MediaNameBuilder {
+    variant: Option<String>,
+    flavor: Option<String>,
+    date: Option<MediaDate>,
+    arch: Option<String>,
+    device: Option<String>,
+    ext: Option<String>,
+}
+
+impl MediaNameBuilder {
+    pub fn variant<S: Into<String>>(mut self, variant: S) -> Self {
+        self.variant = Some(variant.into());
+        self
+    }
+
+    pub fn flavor<S: Into<String>>(mut self, flavor: S) -> Self {
+        self.flavor = Some(flavor.into());
+        self
+    }
+
+    pub fn date(mut self, date: MediaDate) -> Self {
+        self.date = Some(date);
+        self
+    }
+
+    pub fn arch<S: Into<String>>(mut self, arch: S) -> Self {
+        self.arch = Some(arch.into());
+        self
+    }
+
+    pub fn device<S: Into<String>>(mut self, device: S) -> Self {
+        self.device = Some(device.into());
+        self
+    }
+
+    pub fn ext<S: Into<String>>(mut self, ext: S) -> Self {
+        self.ext = Some(ext.into());
+        self
+    }
+
+    pub fn build(self) -> Result<MediaName, NameError> {
+        let name = MediaName {
+            variant: self.variant.ok_or(NameError::Missing("variant"))?,
+            flavor: self.flavor,
+            date: self.date.ok_or(NameError::Missing("date"))?,
+            arch: self.arch.ok_or(NameError::Missing("architecture"))?,
+            device: self.device,
+            ext: self.ext.ok_or(NameError::Missing("extension"))?,
+        };
+        name.validate()?;
+
+        Ok(name)
+    }
+}
+
+#[test]
+fn test_split_name() {
+    let names = MediaName::parse("aosc-os_base_20200526_amd64.tar.xz").unwrap();
+    assert_eq!(
+        names,
+        MediaName {
+            variant: "base".to_string(),
+            flavor: None,
+            date: MediaDate::Date(NaiveDate::from_ymd_opt(2020, 5, 26).unwrap()),
+            arch: "amd64".to_string(),
+            device: None,
+            ext: "tar.xz".to_string(),
+        }
+    );
+    let names = MediaName::parse("aosc-os_server_20230714_loongarch64.squashfs").unwrap();
+    assert_eq!(
+        names,
+        MediaName {
+            variant: "server".to_string(),
+            flavor: None,
+            date: MediaDate::Date(NaiveDate::from_ymd_opt(2023, 7, 14).unwrap()),
+            arch: "loongarch64".to_string(),
+            device: None,
+            ext: "squashfs".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_parse_media_name() {
+    let name = MediaName::parse("aosc-os_desktop+nvidia_latest_arm64_rpi64.img.xz").unwrap();
+    assert_eq!(name.flavor.as_deref(), Some("nvidia"));
+    assert_eq!(name.date, MediaDate::Latest);
+    assert_eq!(name.device.as_deref(), Some("rpi64"));
+    assert_eq!(name.ext, "img.xz");
+    assert_eq!(
+        MediaName::parse("aosc-os_base_20201326_amd64.iso"),
+        Err(NameError::InvalidDate("20201326".to_string()))
+    );
+    assert_eq!(
+        MediaName::parse("base_20200526_amd64.iso"),
+        Err(NameError::MissingPrefix)
+    );
+    assert_eq!(
+        MediaName::parse("aosc-os_base_20200526_amd64"),
+        Err(NameError::Missing("extension"))
+    );
+    assert!(MediaName::builder().variant("base").build().is_err());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_media_name_round_trip(
+        variant in "[a-z0-9][a-z0-9-]{0,12}",
+        flavor in proptest::option::of("[a-z0-9-]{1,8}"),
+        days in 0i64..36500,
+        latest in proptest::bool::ANY,
+        arch in "[a-z0-9]{1,12}",
+        device in proptest::option::of("[a-z0-9-]{1,8}"),
+        ext in "(tar\\.xz|squashfs|iso|img|img\\.xz|[a-z0-9]{1,5})",
+    ) {
+        let date = if latest {
+            MediaDate::Latest
+        } else {
+            MediaDate::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap() + chrono::Duration::days(days))
+        };
+        let mut builder = MediaName::builder().variant(variant).date(date).arch(arch).ext(ext);
+        if let Some(flavor) = flavor {
+            builder = builder.flavor(flavor);
+        }
+        if let Some(device) = device {
+            builder = builder.device(device);
+        }
+        let name = builder.build().unwrap();
+        proptest::prop_assert_eq!(MediaName::parse(&name.to_string()), Ok(name));
+    }
+
+    #[test]
+    fn test_media_name_no_panic(name in "\\PC*") {
+        if let Ok(parsed) = MediaName::parse(&name) {
+            proptest::prop_assert_eq!(parsed.to_string(), name);
+        }
+    }
+}